@@ -0,0 +1,631 @@
+//! Level metering, voice activity detection and onset (clap/tap) detection.
+//!
+//! `Analyzer::process` consumes interleaved stereo samples, e.g. the upper 16 bits of those
+//! returned by `AudioInput::read`. Detected events are queued and can be fetched with
+//! `Analyzer::next_event`.
+
+use collections::VecDeque;
+use math;
+use super::dsp;
+
+/// Lowest reported level in dB.
+pub const FLOOR_DB: f32 = -120.0;
+
+/// Maximum number of queued events. The oldest events are dropped first.
+const MAX_EVENTS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ballistics {
+    /// Time constant for rising levels.
+    pub attack_ms: f32,
+    /// Time constant for falling levels.
+    pub release_ms: f32,
+}
+
+impl Ballistics {
+    fn coefficients(&self, sample_rate: u32) -> (f32, f32) {
+        (time_constant(self.attack_ms, sample_rate), time_constant(self.release_ms, sample_rate))
+    }
+}
+
+/// Smoothing coefficient of a one pole filter with the given time constant.
+fn time_constant(ms: f32, sample_rate: u32) -> f32 {
+    if ms <= 0.0 {
+        0.0
+    } else {
+        math::exp(-1000.0 / (ms * sample_rate as f32))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnalyzerConfig {
+    pub sample_rate: u32,
+    /// Ballistics of the rms meter.
+    pub rms: Ballistics,
+    /// Ballistics of the peak meter.
+    pub peak: Ballistics,
+    /// Length of a voice activity frame.
+    pub vad_frame_ms: u32,
+    /// Frame energy above the noise floor that counts as voice.
+    pub vad_threshold_db: f32,
+    /// Frames below this level never count as voice.
+    pub vad_min_level_db: f32,
+    /// Time that voice activity is held after the level dropped.
+    pub vad_hangover_ms: u32,
+    /// Length of an onset detection frame.
+    pub onset_frame_ms: u32,
+    /// Required ratio between the frame energy and the average energy in dB.
+    pub onset_threshold_db: f32,
+    /// Frames below this level never trigger an onset.
+    pub onset_min_level_db: f32,
+    /// Minimum time between two onsets.
+    pub onset_refractory_ms: u32,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> AnalyzerConfig {
+        AnalyzerConfig {
+            sample_rate: 16000,
+            rms: Ballistics {
+                attack_ms: 300.0,
+                release_ms: 300.0,
+            },
+            peak: Ballistics {
+                attack_ms: 0.0,
+                release_ms: 1500.0,
+            },
+            vad_frame_ms: 10,
+            vad_threshold_db: 9.0,
+            vad_min_level_db: -60.0,
+            vad_hangover_ms: 300,
+            onset_frame_ms: 5,
+            onset_threshold_db: 15.0,
+            onset_min_level_db: -40.0,
+            onset_refractory_ms: 150,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    VoiceStart { time_ms: u32 },
+    VoiceEnd { time_ms: u32 },
+    /// A sudden rise of the level, e.g. a clap or a tap on the board.
+    Onset { time_ms: u32, level_db: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+    pub rms_db: f32,
+    pub peak_db: f32,
+}
+
+/// RMS and peak meter for a single channel.
+#[derive(Debug, Clone)]
+pub struct LevelMeter {
+    rms_coefficients: (f32, f32),
+    peak_coefficients: (f32, f32),
+    mean_square: f32,
+    peak: f32,
+}
+
+impl LevelMeter {
+    pub fn new(rms: Ballistics, peak: Ballistics, sample_rate: u32) -> LevelMeter {
+        LevelMeter {
+            rms_coefficients: rms.coefficients(sample_rate),
+            peak_coefficients: peak.coefficients(sample_rate),
+            mean_square: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) {
+        self.mean_square = follow(self.mean_square, sample * sample, self.rms_coefficients);
+        self.peak = follow(self.peak, math::abs(sample), self.peak_coefficients);
+    }
+
+    /// RMS level in dB relative to full scale.
+    pub fn rms_db(&self) -> f32 {
+        dsp::power_to_db(self.mean_square, FLOOR_DB)
+    }
+
+    /// Peak level in dB relative to full scale.
+    pub fn peak_db(&self) -> f32 {
+        dsp::amplitude_to_db(self.peak, FLOOR_DB)
+    }
+
+    pub fn level(&self) -> ChannelLevel {
+        ChannelLevel {
+            rms_db: self.rms_db(),
+            peak_db: self.peak_db(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.mean_square = 0.0;
+        self.peak = 0.0;
+    }
+}
+
+/// Envelope follower with separate attack and release coefficients.
+fn follow(envelope: f32, input: f32, (attack, release): (f32, f32)) -> f32 {
+    let coefficient = if input > envelope { attack } else { release };
+    coefficient * envelope + (1.0 - coefficient) * input
+}
+
+/// Sums up the energy of fixed length frames.
+#[derive(Debug, Clone)]
+struct FrameEnergy {
+    len: u32,
+    count: u32,
+    sum: f32,
+}
+
+impl FrameEnergy {
+    fn new(len: u32) -> FrameEnergy {
+        FrameEnergy {
+            len: if len == 0 { 1 } else { len },
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Returns the mean square of the frame if the sample completed it.
+    fn process(&mut self, sample: f32) -> Option<f32> {
+        self.sum += sample * sample;
+        self.count += 1;
+        if self.count < self.len {
+            return None;
+        }
+        let energy = self.sum / self.len as f32;
+        self.count = 0;
+        self.sum = 0.0;
+        Some(energy)
+    }
+}
+
+/// Energy based voice activity detector with an adaptive noise floor.
+#[derive(Debug, Clone)]
+pub struct VoiceActivityDetector {
+    frame: FrameEnergy,
+    threshold_db: f32,
+    min_level_db: f32,
+    hangover_frames: u32,
+    noise_floor_db: f32,
+    remaining_hangover: u32,
+    active: bool,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: &AnalyzerConfig) -> VoiceActivityDetector {
+        let frame_ms = if config.vad_frame_ms == 0 { 1 } else { config.vad_frame_ms };
+        VoiceActivityDetector {
+            frame: FrameEnergy::new(config.sample_rate * frame_ms / 1000),
+            threshold_db: config.vad_threshold_db,
+            min_level_db: config.vad_min_level_db,
+            hangover_frames: config.vad_hangover_ms / frame_ms,
+            noise_floor_db: config.vad_min_level_db,
+            remaining_hangover: 0,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Current estimate of the background noise level in dB.
+    pub fn noise_floor_db(&self) -> f32 {
+        self.noise_floor_db
+    }
+
+    /// Returns `Some(true)` when voice activity starts and `Some(false)` when it ends.
+    pub fn process(&mut self, sample: f32) -> Option<bool> {
+        let energy = match self.frame.process(sample) {
+            Some(energy) => energy,
+            None => return None,
+        };
+        let level_db = dsp::power_to_db(energy, FLOOR_DB);
+
+        // the noise floor follows falling levels immediately and rises slowly (~1 dB/s)
+        if level_db < self.noise_floor_db {
+            self.noise_floor_db = level_db;
+        } else if !self.active {
+            self.noise_floor_db += 0.01;
+        }
+
+        let voice = level_db > self.noise_floor_db + self.threshold_db &&
+                    level_db > self.min_level_db;
+        if voice {
+            self.remaining_hangover = self.hangover_frames;
+            if !self.active {
+                self.active = true;
+                return Some(true);
+            }
+        } else if self.active {
+            if self.remaining_hangover == 0 {
+                self.active = false;
+                return Some(false);
+            }
+            self.remaining_hangover -= 1;
+        }
+        None
+    }
+}
+
+/// Detects sudden rises of the frame energy compared to the recent average.
+#[derive(Debug, Clone)]
+pub struct OnsetDetector {
+    frame: FrameEnergy,
+    threshold_db: f32,
+    min_level_db: f32,
+    refractory_frames: u32,
+    /// Moving average of the level, `None` until the first frame is complete.
+    average_db: Option<f32>,
+    frames_since_onset: u32,
+}
+
+impl OnsetDetector {
+    pub fn new(config: &AnalyzerConfig) -> OnsetDetector {
+        let frame_ms = if config.onset_frame_ms == 0 { 1 } else { config.onset_frame_ms };
+        let refractory_frames = config.onset_refractory_ms / frame_ms;
+        OnsetDetector {
+            frame: FrameEnergy::new(config.sample_rate * frame_ms / 1000),
+            threshold_db: config.onset_threshold_db,
+            min_level_db: config.onset_min_level_db,
+            refractory_frames: refractory_frames,
+            average_db: None,
+            frames_since_onset: refractory_frames,
+        }
+    }
+
+    /// Returns the level in dB if the sample completed a frame with an onset.
+    pub fn process(&mut self, sample: f32) -> Option<f32> {
+        let energy = match self.frame.process(sample) {
+            Some(energy) => energy,
+            None => return None,
+        };
+        let level_db = dsp::power_to_db(energy, FLOOR_DB);
+
+        // the first frame only seeds the average, so that a loud start is no onset
+        let average_db = match self.average_db {
+            Some(average_db) => average_db,
+            None => {
+                self.average_db = Some(level_db);
+                return None;
+            }
+        };
+
+        let onset = level_db > average_db + self.threshold_db && level_db > self.min_level_db &&
+                    self.frames_since_onset >= self.refractory_frames;
+
+        // slow moving average of the level
+        self.average_db = Some(0.9 * average_db + 0.1 * level_db);
+
+        if onset {
+            self.frames_since_onset = 0;
+            Some(level_db)
+        } else {
+            self.frames_since_onset = self.frames_since_onset.saturating_add(1);
+            None
+        }
+    }
+}
+
+/// Level meters for both channels plus voice activity and onset detection on the mono mix.
+pub struct Analyzer {
+    sample_rate: u32,
+    left: LevelMeter,
+    right: LevelMeter,
+    vad: VoiceActivityDetector,
+    onsets: OnsetDetector,
+    frames: u64,
+    events: VecDeque<Event>,
+}
+
+impl Analyzer {
+    pub fn new(config: AnalyzerConfig) -> Analyzer {
+        Analyzer {
+            sample_rate: config.sample_rate,
+            left: LevelMeter::new(config.rms, config.peak, config.sample_rate),
+            right: LevelMeter::new(config.rms, config.peak, config.sample_rate),
+            vad: VoiceActivityDetector::new(&config),
+            onsets: OnsetDetector::new(&config),
+            frames: 0,
+            events: VecDeque::with_capacity(MAX_EVENTS),
+        }
+    }
+
+    /// Processes stereo samples (left and right interleaved).
+    pub fn process(&mut self, samples: &[i16]) {
+        for frame in samples.chunks(2) {
+            let left = dsp::sample_to_f32(frame[0]);
+            let right = frame.get(1).map(|&s| dsp::sample_to_f32(s)).unwrap_or(left);
+            self.left.process(left);
+            self.right.process(right);
+
+            let mono = (left + right) / 2.0;
+            let time_ms = self.time_ms();
+            match self.vad.process(mono) {
+                Some(true) => self.push_event(Event::VoiceStart { time_ms: time_ms }),
+                Some(false) => self.push_event(Event::VoiceEnd { time_ms: time_ms }),
+                None => {}
+            }
+            if let Some(level_db) = self.onsets.process(mono) {
+                self.push_event(Event::Onset {
+                                    time_ms: time_ms,
+                                    level_db: level_db,
+                                });
+            }
+
+            self.frames += 1;
+        }
+    }
+
+    fn push_event(&mut self, event: Event) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Returns the oldest unhandled event.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Time since the analyzer was created, based on the number of processed samples.
+    pub fn time_ms(&self) -> u32 {
+        (self.frames * 1000 / u64::from(self.sample_rate)) as u32
+    }
+
+    pub fn left(&self) -> ChannelLevel {
+        self.left.level()
+    }
+
+    pub fn right(&self) -> ChannelLevel {
+        self.right.level()
+    }
+
+    pub fn voice_active(&self) -> bool {
+        self.vad.is_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::wav;
+    use collections::Vec;
+    use core::f32::consts::PI;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    /// `ms` milliseconds of a 1 kHz tone with the given amplitude, as interleaved stereo.
+    fn tone(ms: u32, amplitude: f32) -> Vec<i16> {
+        let len = SAMPLE_RATE * ms / 1000;
+        let mut samples = Vec::with_capacity(2 * len as usize);
+        for i in 0..len {
+            let x = amplitude * math::sin(2.0 * PI * 1000.0 * i as f32 / SAMPLE_RATE as f32);
+            let sample = (x * 32767.0) as i16;
+            samples.push(sample);
+            samples.push(sample);
+        }
+        samples
+    }
+
+    fn silence(ms: u32) -> Vec<i16> {
+        vec![0; 2 * (SAMPLE_RATE * ms / 1000) as usize]
+    }
+
+    fn events(analyzer: &mut Analyzer) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = analyzer.next_event() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn onsets(events: &[Event]) -> usize {
+        events.iter()
+            .filter(|e| match **e {
+                        Event::Onset { .. } => true,
+                        _ => false,
+                    })
+            .count()
+    }
+
+    #[test]
+    fn level_meter_of_full_scale_sine() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&tone(3000, 1.0));
+        let level = analyzer.left();
+        // the rms of a sine is 3 dB below its peak
+        assert!(math::abs(level.rms_db + 3.01) < 0.1, "{:?}", level);
+        assert!(math::abs(level.peak_db) < 0.1, "{:?}", level);
+        assert_eq!(analyzer.left(), analyzer.right());
+    }
+
+    #[test]
+    fn level_meter_releases() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&tone(1000, 0.5));
+        let loud = analyzer.left();
+        analyzer.process(&silence(1000));
+        let quiet = analyzer.left();
+        assert!(quiet.rms_db < loud.rms_db - 10.0);
+        assert!(quiet.peak_db < loud.peak_db);
+        assert!(quiet.peak_db > FLOOR_DB);
+    }
+
+    #[test]
+    fn voice_activity_with_hangover() {
+        let config = AnalyzerConfig::default();
+        let mut analyzer = Analyzer::new(config);
+        analyzer.process(&silence(500));
+        assert!(events(&mut analyzer).is_empty());
+
+        analyzer.process(&tone(500, 0.1));
+        assert!(analyzer.voice_active());
+        let start = events(&mut analyzer);
+        // the first voice frame ends 10 ms after the tone started
+        assert!(start.contains(&Event::VoiceStart { time_ms: 509 }), "{:?}", start);
+
+        // the activity is held for the hangover time
+        analyzer.process(&silence(config.vad_hangover_ms - 50));
+        assert!(analyzer.voice_active());
+        analyzer.process(&silence(100));
+        assert!(!analyzer.voice_active());
+        match events(&mut analyzer).last() {
+            Some(&Event::VoiceEnd { time_ms }) => {
+                assert!(time_ms >= 1000 + config.vad_hangover_ms && time_ms <= 1350, "{}", time_ms)
+            }
+            other => panic!("expected VoiceEnd, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quiet_signals_are_no_voice() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&silence(200));
+        analyzer.process(&tone(500, 0.0005)); // -66 dBFS
+        assert!(!analyzer.voice_active());
+    }
+
+    #[test]
+    fn no_onset_on_first_frame() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&tone(100, 0.8));
+        assert_eq!(onsets(&events(&mut analyzer)), 0);
+    }
+
+    #[test]
+    fn clap_is_an_onset() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&tone(200, 0.001));
+        analyzer.process(&tone(20, 0.8));
+        let events = events(&mut analyzer);
+        assert_eq!(onsets(&events), 1, "{:?}", events);
+        match events.iter().find(|e| match **e {
+                                     Event::Onset { .. } => true,
+                                     _ => false,
+                                 }) {
+            Some(&Event::Onset { time_ms, level_db }) => {
+                assert!(time_ms >= 200 && time_ms < 210, "{}", time_ms);
+                assert!(level_db > -10.0, "{}", level_db);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn onsets_respect_refractory_period() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&silence(200));
+        analyzer.process(&tone(10, 0.8));
+        analyzer.process(&silence(50));
+        analyzer.process(&tone(10, 0.8)); // within 150 ms
+        assert_eq!(onsets(&events(&mut analyzer)), 1);
+
+        analyzer.process(&silence(300));
+        analyzer.process(&tone(10, 0.8));
+        assert_eq!(onsets(&events(&mut analyzer)), 1);
+    }
+
+    #[test]
+    fn event_queue_drops_oldest() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        for _ in 0..MAX_EVENTS + 5 {
+            analyzer.push_event(Event::VoiceEnd { time_ms: 0 });
+        }
+        analyzer.push_event(Event::VoiceStart { time_ms: 1 });
+        let events = events(&mut analyzer);
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events.last(), Some(&Event::VoiceStart { time_ms: 1 }));
+    }
+
+    #[test]
+    fn time_follows_samples() {
+        let mut analyzer = Analyzer::new(AnalyzerConfig::default());
+        analyzer.process(&silence(1500));
+        assert_eq!(analyzer.time_ms(), 1500);
+    }
+
+    /// Runs the analyzer over a mono WAV fixture, with the samples copied to both channels.
+    fn analyze_fixture(image: &[u8]) -> Vec<Event> {
+        let wav = wav::parse(image).unwrap();
+        assert_eq!(wav.format.channels, 1);
+        let mut analyzer = Analyzer::new(AnalyzerConfig {
+                                             sample_rate: wav.format.sample_rate,
+                                             ..Default::default()
+                                         });
+        let samples: Vec<i16> = wav.samples().flat_map(|sample| vec![sample, sample]).collect();
+        // in blocks, like the main loop
+        for block in samples.chunks(2 * 32) {
+            analyzer.process(block);
+        }
+        assert_eq!(analyzer.time_ms(), wav.duration_ms());
+        events(&mut analyzer)
+    }
+
+    fn onset_times(events: &[Event]) -> Vec<u32> {
+        events.iter()
+            .filter_map(|e| match *e {
+                            Event::Onset { time_ms, .. } => Some(time_ms),
+                            _ => None,
+                        })
+            .collect()
+    }
+
+    #[test]
+    fn silence_fixture_has_no_events() {
+        // room noise at about -60 dBFS
+        let events = analyze_fixture(include_bytes!("testdata/silence.wav"));
+        assert!(events.is_empty(), "{:?}", events);
+    }
+
+    #[test]
+    fn clap_fixture() {
+        // a 40 ms noise burst at 250 ms
+        let events = analyze_fixture(include_bytes!("testdata/clap.wav"));
+        assert_eq!(onset_times(&events).len(), 1, "{:?}", events);
+        match events[0] {
+            Event::Onset { time_ms, level_db } => {
+                assert!(time_ms >= 250 && time_ms < 260, "{}", time_ms);
+                assert!(level_db > -20.0, "{}", level_db);
+            }
+            other => panic!("expected an onset first, got {:?}", other),
+        }
+        // the burst is also voice, which is still held when the fixture ends
+        assert_eq!(events[1], Event::VoiceStart { time_ms: 259 });
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn speech_fixture() {
+        // three 150 ms syllables at 300, 500 and 700 ms, the fixture ends at 1300 ms
+        let events = analyze_fixture(include_bytes!("testdata/speech.wav"));
+        let voice: Vec<Event> = events.iter()
+            .cloned()
+            .filter(|e| match *e {
+                        Event::Onset { .. } => false,
+                        _ => true,
+                    })
+            .collect();
+        // the hangover bridges the 50 ms pauses between the syllables
+        assert_eq!(voice.len(), 2, "{:?}", events);
+        match (voice[0], voice[1]) {
+            (Event::VoiceStart { time_ms: start }, Event::VoiceEnd { time_ms: end }) => {
+                assert!(start >= 300 && start < 320, "{}", start);
+                assert!(end >= 850 + 300 && end < 1200, "{}", end);
+            }
+            other => panic!("expected VoiceStart and VoiceEnd, got {:?}", other),
+        }
+        // each syllable rises from the room noise by more than the onset threshold
+        let onsets = onset_times(&events);
+        assert_eq!(onsets.len(), 3, "{:?}", events);
+        for (&time_ms, &syllable_ms) in onsets.iter().zip(&[300, 500, 700]) {
+            assert!(time_ms >= syllable_ms && time_ms < syllable_ms + 30, "{}", time_ms);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Sample rate, word length and slot configuration for SAI2 and the WM8994.
+
+/// Frequency of the external oscillator (HSE) that feeds the PLLs.
+const HSE_FREQUENCY: u32 = 25_000_000;
+/// Largest value of the 4 bit SAI `MCKDIV` field.
+const MAX_MASTER_CLOCK_DIVIDER: u64 = 0xf;
+/// Largest frame length in bits that fits into the 8 bit SAI `FRL` field.
+const MAX_FRAME_LENGTH: u16 = 256;
+/// Largest number of slots of the 4 bit SAI `NBSLOT` field.
+const MAX_SLOTS: u8 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The number of slots is zero, above 16 or the frame is longer than 256 bits.
+    FrameLength,
+    /// The SAI clock can't be divided down to 256 × fs with the 4 bit `MCKDIV` field.
+    MasterClockDivider,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioConfig {
+    pub sample_rate: SampleRate,
+    pub word_length: WordLength,
+    pub slots: SlotConfig,
+    pub input_device: InputDevice,
+    pub output_device: OutputDevice,
+    /// Output volume in percent.
+    pub output_volume: u8,
+}
+
+impl AudioConfig {
+    /// Bit mask of the slots received by block B, which are those the codec sends the input
+    /// device in.
+    pub fn input_slots(&self) -> u16 {
+        self.input_device.sai_slots()
+    }
+
+    /// Checks that the configuration can be set up by `audio::init_sai_2`.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let slots = self.slots.number_of_slots;
+        if slots == 0 || slots > MAX_SLOTS ||
+           self.slots.frame_length(self.word_length) > MAX_FRAME_LENGTH {
+            return Err(ConfigError::FrameLength);
+        }
+        Ok(())
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> AudioConfig {
+        AudioConfig {
+            sample_rate: SampleRate::Hz16000,
+            word_length: WordLength::Bits16,
+            slots: SlotConfig::default(),
+            input_device: InputDevice::DigitalMic2,
+            output_device: OutputDevice::Headphone,
+            output_volume: 70,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRate {
+    Hz8000,
+    Hz11025,
+    Hz16000,
+    Hz22050,
+    Hz32000,
+    Hz44100,
+    Hz48000,
+    Hz96000,
+}
+
+impl SampleRate {
+    pub fn hz(&self) -> u32 {
+        match *self {
+            SampleRate::Hz8000 => 8000,
+            SampleRate::Hz11025 => 11025,
+            SampleRate::Hz16000 => 16000,
+            SampleRate::Hz22050 => 22050,
+            SampleRate::Hz32000 => 32000,
+            SampleRate::Hz44100 => 44100,
+            SampleRate::Hz48000 => 48000,
+            SampleRate::Hz96000 => 96000,
+        }
+    }
+
+    /// Whether the rate is a multiple of 11.025 kHz (instead of 8 kHz).
+    pub fn is_44k1_family(&self) -> bool {
+        match *self {
+            SampleRate::Hz11025 | SampleRate::Hz22050 | SampleRate::Hz44100 => true,
+            _ => false,
+        }
+    }
+
+    /// PLLI2S settings that give a SAI clock which is an integer multiple of 512 × fs.
+    pub fn pll_i2s_config(&self) -> PllI2sConfig {
+        if self.is_44k1_family() {
+            // 429 MHz / 2 / 19 = 11.289 MHz
+            PllI2sConfig { n: 429, q: 2, div_q: 19 }
+        } else {
+            // 344 MHz / 7 / 1 = 49.142 MHz
+            PllI2sConfig { n: 344, q: 7, div_q: 1 }
+        }
+    }
+
+    /// Value of the WM8994 AIF1 rate register (0x210) with AIF1CLK = 256 × fs.
+    pub fn wm8994_aif1_rate(&self) -> u16 {
+        let sample_rate_bits = match *self {
+            SampleRate::Hz8000 => 0x0,
+            SampleRate::Hz11025 => 0x1,
+            SampleRate::Hz16000 => 0x3,
+            SampleRate::Hz22050 => 0x4,
+            SampleRate::Hz32000 => 0x6,
+            SampleRate::Hz44100 => 0x7,
+            SampleRate::Hz48000 => 0x8,
+            SampleRate::Hz96000 => 0xA,
+        };
+        sample_rate_bits << 4 | 0x3 // ratio 256
+    }
+}
+
+/// Word length of the samples.
+///
+/// `AudioInput` and `AudioOutput` transfer whole words for all of them and scale the samples
+/// to the full `i32` range, so their users don't depend on the word length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordLength {
+    Bits16,
+    Bits24,
+    Bits32,
+}
+
+impl WordLength {
+    pub fn bits(&self) -> u8 {
+        match *self {
+            WordLength::Bits16 => 16,
+            WordLength::Bits24 => 24,
+            WordLength::Bits32 => 32,
+        }
+    }
+
+    /// Number of bits of a SAI slot that holds a word of this length.
+    pub fn slot_bits(&self) -> u8 {
+        match *self {
+            WordLength::Bits16 => 16,
+            WordLength::Bits24 | WordLength::Bits32 => 32,
+        }
+    }
+
+    /// Value of the SAI `DS` field.
+    pub fn sai_data_size(&self) -> u8 {
+        match *self {
+            WordLength::Bits16 => 0b100,
+            WordLength::Bits24 => 0b110,
+            WordLength::Bits32 => 0b111,
+        }
+    }
+
+    /// The word length for a value of the SAI `DS` field, `None` for the 8, 10 and 20 bit
+    /// sizes that are not used by `audio::init_sai_2`.
+    pub fn from_sai_data_size(data_size: u8) -> Option<WordLength> {
+        match data_size {
+            0b100 => Some(WordLength::Bits16),
+            0b110 => Some(WordLength::Bits24),
+            0b111 => Some(WordLength::Bits32),
+            _ => None,
+        }
+    }
+
+    /// Number of unused low bits when a word is scaled to the `i32` range.
+    ///
+    /// The SAI data register holds the words right aligned, so received words are shifted
+    /// left by this amount and words to send are shifted right.
+    pub fn sample_shift(&self) -> u32 {
+        32 - u32::from(self.bits())
+    }
+
+    /// Value of the SAI `SLOTSZ` field.
+    pub fn sai_slot_size(&self) -> u8 {
+        match *self {
+            WordLength::Bits16 => 0b00, // DataSize
+            WordLength::Bits24 | WordLength::Bits32 => 0b10, // 32 bits
+        }
+    }
+
+    /// Value of the WM8994 AIF1 control register (0x300) for I2S format.
+    pub fn wm8994_aif1_control(&self) -> u16 {
+        let word_length_bits = match *self {
+            WordLength::Bits16 => 0b00,
+            WordLength::Bits24 => 0b10,
+            WordLength::Bits32 => 0b11,
+        };
+        0x4010 | word_length_bits << 5
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDevice {
+    None,
+    DigitalMic1,
+    DigitalMic2,
+    LineIn,
+}
+
+impl InputDevice {
+    /// SAI slots of a four slot frame that carry the input. The digital microphone 2 is
+    /// recorded in AIF1 ADC timeslot 1, the other inputs in timeslot 0.
+    pub fn sai_slots(&self) -> u16 {
+        match *self {
+            InputDevice::None => 0,
+            InputDevice::DigitalMic2 => 1 << 1 | 1 << 3,
+            InputDevice::DigitalMic1 | InputDevice::LineIn => 1 << 0 | 1 << 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputDevice {
+    None,
+    Headphone,
+    Speaker,
+    Both,
+}
+
+impl OutputDevice {
+    pub fn headphone(&self) -> bool {
+        match *self {
+            OutputDevice::Headphone | OutputDevice::Both => true,
+            _ => false,
+        }
+    }
+
+    pub fn speaker(&self) -> bool {
+        match *self {
+            OutputDevice::Speaker | OutputDevice::Both => true,
+            _ => false,
+        }
+    }
+}
+
+/// Layout of the TDM frame.
+///
+/// By default the output is sent in slots 0 and 2 (AIF1 DAC timeslot 0) of a four slot frame.
+/// The slots received by block B follow from the input device, see `AudioConfig::input_slots`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotConfig {
+    pub number_of_slots: u8,
+    /// Bit mask of the slots sent by block A.
+    pub output_slots: u16,
+}
+
+impl SlotConfig {
+    /// Frame length in bits.
+    pub fn frame_length(&self, word_length: WordLength) -> u16 {
+        u16::from(self.number_of_slots) * u16::from(word_length.slot_bits())
+    }
+}
+
+impl Default for SlotConfig {
+    fn default() -> SlotConfig {
+        SlotConfig {
+            number_of_slots: 4,
+            output_slots: 1 << 0 | 1 << 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllI2sConfig {
+    pub n: u16,
+    pub q: u8,
+    pub div_q: u8,
+}
+
+impl PllI2sConfig {
+    /// SAI clock (PLLI2S_Q / PLLI2SDIVQ) for the given main PLL input divider.
+    pub fn sai_clock(&self, pllm: u8) -> u32 {
+        let vco_input = HSE_FREQUENCY / u32::from(pllm);
+        vco_input * u32::from(self.n) / u32::from(self.q) / u32::from(self.div_q)
+    }
+}
+
+/// Computes the SAI `MCKDIV` value so that MCLK = SAI_CK / (MCKDIV × 2) = 256 × fs.
+///
+/// An `MCKDIV` of 0 means that the SAI clock is not divided. Fails if the divider doesn't fit
+/// into the 4 bit field.
+pub fn master_clock_divider(sai_clock: u32, sample_rate: u32) -> Result<u8, ConfigError> {
+    // (sai_clock x 10) to keep significant digits
+    let tmpclock = (u64::from(sai_clock) * 10) / (u64::from(sample_rate) * 512);
+
+    let mut mckdiv = tmpclock / 10;
+
+    // round result to the nearest integer
+    if (tmpclock % 10) > 8 {
+        mckdiv += 1;
+    }
+
+    if mckdiv > MAX_MASTER_CLOCK_DIVIDER {
+        Err(ConfigError::MasterClockDivider)
+    } else {
+        Ok(mckdiv as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATES: [SampleRate; 8] = [SampleRate::Hz8000,
+                                    SampleRate::Hz11025,
+                                    SampleRate::Hz16000,
+                                    SampleRate::Hz22050,
+                                    SampleRate::Hz32000,
+                                    SampleRate::Hz44100,
+                                    SampleRate::Hz48000,
+                                    SampleRate::Hz96000];
+
+    /// The main PLL input divider set by `system_clock::init`.
+    const PLLM: u8 = 25;
+
+    #[test]
+    fn pll_i2s_sai_clocks() {
+        assert_eq!(SampleRate::Hz48000.pll_i2s_config().sai_clock(PLLM), 49_142_857);
+        assert_eq!(SampleRate::Hz44100.pll_i2s_config().sai_clock(PLLM), 11_289_473);
+    }
+
+    #[test]
+    fn pll_i2s_vco_in_range() {
+        for rate in RATES.iter() {
+            let config = rate.pll_i2s_config();
+            let vco = HSE_FREQUENCY / u32::from(PLLM) * u32::from(config.n);
+            assert!(vco >= 100_000_000 && vco <= 432_000_000, "{:?}: {}", rate, vco);
+            assert!(config.q >= 2 && config.q <= 15);
+            assert!(config.div_q >= 1 && config.div_q <= 32);
+        }
+    }
+
+    #[test]
+    fn master_clock_dividers() {
+        let expected = [12, 2, 6, 1, 3, 0, 2, 1];
+        for (rate, &mckdiv) in RATES.iter().zip(expected.iter()) {
+            let sai_clock = rate.pll_i2s_config().sai_clock(PLLM);
+            assert_eq!(master_clock_divider(sai_clock, rate.hz()), Ok(mckdiv), "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn master_clock_within_0_1_percent() {
+        for rate in RATES.iter() {
+            let sai_clock = rate.pll_i2s_config().sai_clock(PLLM);
+            let mckdiv = master_clock_divider(sai_clock, rate.hz()).unwrap();
+            let divider = if mckdiv == 0 { 1 } else { 2 * u64::from(mckdiv) };
+            let fs = u64::from(sai_clock) * 1000 / divider / 256;
+            let target = u64::from(rate.hz()) * 1000;
+            let error = if fs > target { fs - target } else { target - fs };
+            assert!(error * 1000 < target, "{:?}: {}", rate, fs);
+        }
+    }
+
+    #[test]
+    fn master_clock_divider_overflow() {
+        assert_eq!(master_clock_divider(49_142_857, 4000),
+                   Err(ConfigError::MasterClockDivider));
+        assert_eq!(master_clock_divider(49_142_857, 7000), Ok(13));
+    }
+
+    #[test]
+    fn all_word_lengths_are_valid() {
+        let mut config = AudioConfig::default();
+        for &word_length in [WordLength::Bits16, WordLength::Bits24, WordLength::Bits32].iter() {
+            config.word_length = word_length;
+            assert_eq!(config.validate(), Ok(()), "{:?}", word_length);
+        }
+    }
+
+    #[test]
+    fn frame_length_limits() {
+        let mut config = AudioConfig::default();
+        config.word_length = WordLength::Bits32;
+        config.slots.number_of_slots = 8;
+        assert_eq!(config.validate(), Ok(()));
+        config.slots.number_of_slots = 9;
+        assert_eq!(config.validate(), Err(ConfigError::FrameLength));
+        config.word_length = WordLength::Bits16;
+        assert_eq!(config.validate(), Ok(()));
+        config.slots.number_of_slots = 17;
+        assert_eq!(config.validate(), Err(ConfigError::FrameLength));
+        config.slots.number_of_slots = 0;
+        assert_eq!(config.validate(), Err(ConfigError::FrameLength));
+    }
+
+    #[test]
+    fn word_length_registers() {
+        let slots = SlotConfig::default();
+
+        assert_eq!(WordLength::Bits16.sai_data_size(), 0b100);
+        assert_eq!(WordLength::Bits16.sai_slot_size(), 0b00);
+        assert_eq!(slots.frame_length(WordLength::Bits16), 64);
+        assert_eq!(WordLength::Bits16.wm8994_aif1_control(), 0x4010);
+
+        assert_eq!(WordLength::Bits24.sai_data_size(), 0b110);
+        assert_eq!(WordLength::Bits24.sai_slot_size(), 0b10);
+        assert_eq!(slots.frame_length(WordLength::Bits24), 128);
+        assert_eq!(WordLength::Bits24.wm8994_aif1_control(), 0x4050);
+
+        assert_eq!(WordLength::Bits32.sai_data_size(), 0b111);
+        assert_eq!(WordLength::Bits32.sai_slot_size(), 0b10);
+        assert_eq!(slots.frame_length(WordLength::Bits32), 128);
+        assert_eq!(WordLength::Bits32.wm8994_aif1_control(), 0x4070);
+    }
+
+    #[test]
+    fn word_length_from_data_size() {
+        for &word_length in [WordLength::Bits16, WordLength::Bits24, WordLength::Bits32].iter() {
+            assert_eq!(WordLength::from_sai_data_size(word_length.sai_data_size()),
+                       Some(word_length));
+        }
+        assert_eq!(WordLength::from_sai_data_size(0b010), None);
+        assert_eq!(WordLength::Bits16.sample_shift(), 16);
+        assert_eq!(WordLength::Bits24.sample_shift(), 8);
+        assert_eq!(WordLength::Bits32.sample_shift(), 0);
+    }
+
+    #[test]
+    fn master_clock_divider_independent_of_word_length() {
+        // MCLK stays 256 × fs, the longer slots only raise the bit clock to 128 × fs
+        let expected = [12, 2, 6, 1, 3, 0, 2, 1];
+        for &word_length in [WordLength::Bits24, WordLength::Bits32].iter() {
+            let mut config = AudioConfig::default();
+            config.word_length = word_length;
+            for (&rate, &mckdiv) in RATES.iter().zip(expected.iter()) {
+                config.sample_rate = rate;
+                assert_eq!(config.validate(), Ok(()));
+                let sai_clock = rate.pll_i2s_config().sai_clock(PLLM);
+                assert_eq!(master_clock_divider(sai_clock, rate.hz()), Ok(mckdiv));
+                let bit_clock = u32::from(config.slots.frame_length(word_length)) * rate.hz();
+                assert_eq!(bit_clock * 2, 256 * rate.hz());
+            }
+        }
+    }
+}
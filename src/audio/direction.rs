@@ -0,0 +1,384 @@
+//! Direction of arrival estimation with the two digital microphones.
+//!
+//! The delay between the left and the right channel is found with the generalized cross
+//! correlation with phase transform (GCC-PHAT). The cross spectrum is zero padded before the
+//! inverse FFT to get sub-sample resolution, which matters because the microphones are close
+//! together: at 16 kHz a sound from the side only arrives about one sample earlier at one of
+//! them.
+
+use collections::{Vec, VecDeque};
+use math;
+use super::dsp::{self, Complex};
+
+/// Speed of sound in air at 20 °C in m/s.
+pub const SPEED_OF_SOUND: f32 = 343.0;
+
+/// Maximum number of queued estimates. The oldest estimates are dropped first.
+const MAX_ESTIMATES: usize = 16;
+
+/// Bins of the cross spectrum below this fraction of the strongest bin (-40 dB) are ignored.
+const MIN_BIN_MAGNITUDE: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionConfig {
+    pub sample_rate: u32,
+    /// Distance between the two microphones in millimeters.
+    pub mic_spacing_mm: f32,
+    /// Number of samples per channel that are correlated. Must be a power of two.
+    pub frame_len: usize,
+    /// Zero padding factor of the cross spectrum. Must be a power of two.
+    pub interpolation: usize,
+    /// Frames below this level (mean of both channels) don't produce estimates.
+    pub min_level_db: f32,
+}
+
+impl Default for DirectionConfig {
+    fn default() -> DirectionConfig {
+        DirectionConfig {
+            sample_rate: 16000,
+            mic_spacing_mm: 21.0,
+            frame_len: 512,
+            interpolation: 8,
+            min_level_db: -50.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Angle of the sound source in degrees. 0 is straight ahead (perpendicular to the line
+    /// between the microphones), positive values are on the left side.
+    pub angle: f32,
+    /// Peak of the normalized cross correlation, from 0 (no correlation) to 1.
+    pub confidence: f32,
+    /// Delay of the right channel relative to the left channel in samples.
+    pub delay: f32,
+}
+
+pub struct DirectionEstimator {
+    config: DirectionConfig,
+    left: Vec<f32>,
+    right: Vec<f32>,
+    left_spectrum: Vec<Complex>,
+    right_spectrum: Vec<Complex>,
+    correlation: Vec<Complex>,
+    estimates: VecDeque<Estimate>,
+}
+
+impl DirectionEstimator {
+    pub fn new(config: DirectionConfig) -> DirectionEstimator {
+        assert!(config.frame_len.is_power_of_two() && config.frame_len >= 4,
+                "frame length must be a power of two");
+        assert!(config.interpolation.is_power_of_two(),
+                "interpolation must be a power of two");
+
+        let bins = config.frame_len / 2 + 1;
+        DirectionEstimator {
+            config: config,
+            left: Vec::with_capacity(config.frame_len),
+            right: Vec::with_capacity(config.frame_len),
+            left_spectrum: vec![Complex::default(); bins],
+            right_spectrum: vec![Complex::default(); bins],
+            correlation: vec![Complex::default(); config.frame_len * config.interpolation],
+            estimates: VecDeque::with_capacity(MAX_ESTIMATES),
+        }
+    }
+
+    /// Largest possible delay between the channels in samples.
+    pub fn max_delay(&self) -> f32 {
+        self.config.mic_spacing_mm / 1000.0 / SPEED_OF_SOUND * self.config.sample_rate as f32
+    }
+
+    /// Adds stereo samples (left and right interleaved).
+    pub fn push_samples(&mut self, samples: &[i16]) {
+        for frame in samples.chunks(2) {
+            if frame.len() < 2 {
+                break;
+            }
+            self.left.push(dsp::sample_to_f32(frame[0]));
+            self.right.push(dsp::sample_to_f32(frame[1]));
+
+            if self.left.len() == self.config.frame_len {
+                if let Some(estimate) = self.estimate() {
+                    if self.estimates.len() >= MAX_ESTIMATES {
+                        self.estimates.pop_front();
+                    }
+                    self.estimates.push_back(estimate);
+                }
+                self.left.clear();
+                self.right.clear();
+            }
+        }
+    }
+
+    /// Returns the oldest unhandled estimate.
+    pub fn next_estimate(&mut self) -> Option<Estimate> {
+        self.estimates.pop_front()
+    }
+
+    fn estimate(&mut self) -> Option<Estimate> {
+        let n = self.config.frame_len;
+        let len = self.correlation.len();
+
+        let energy = self.left.iter().chain(self.right.iter()).map(|s| s * s).sum::<f32>();
+        let level_db = dsp::power_to_db(energy / (2 * n) as f32, -120.0);
+        if level_db < self.config.min_level_db {
+            return None;
+        }
+
+        dsp::hann_window(&mut self.left);
+        dsp::hann_window(&mut self.right);
+        dsp::real_fft(&self.left, &mut self.left_spectrum);
+        dsp::real_fft(&self.right, &mut self.right_spectrum);
+
+        for value in self.correlation.iter_mut() {
+            *value = Complex::default();
+        }
+        for k in 1..n / 2 {
+            self.correlation[k] = self.left_spectrum[k] * self.right_spectrum[k].conj();
+        }
+        let max_magnitude = self.correlation[1..n / 2]
+            .iter()
+            .map(|c| c.norm())
+            .fold(0.0, |max, m| if m > max { m } else { max });
+
+        // phase transform: keep only the phase of the cross spectrum. Bins far below the
+        // strongest one only contain noise, whose random phase would blur the peak. The dc and
+        // nyquist bins carry no phase information and stay zero, everything above nyquist is
+        // zero padding.
+        let mut used_bins = 0;
+        for k in 1..n / 2 {
+            let cross = self.correlation[k];
+            let magnitude = cross.norm();
+            if magnitude > 1e-12 && magnitude > max_magnitude * MIN_BIN_MAGNITUDE {
+                let phase = cross.scale(1.0 / magnitude);
+                self.correlation[k] = phase;
+                self.correlation[len - k] = phase.conj();
+                used_bins += 2;
+            } else {
+                self.correlation[k] = Complex::default();
+            }
+        }
+        if used_bins == 0 {
+            return None;
+        }
+        dsp::ifft(&mut self.correlation);
+
+        // search the peak within the physically possible delays (plus one sample of margin)
+        let interpolation = self.config.interpolation as f32;
+        let max_lag = (self.max_delay() * interpolation) as usize + self.config.interpolation;
+        let max_lag = if max_lag >= len / 2 { len / 2 - 1 } else { max_lag };
+        let value = |lag: isize| -> f32 {
+            let index = if lag < 0 { (len as isize + lag) as usize } else { lag as usize };
+            self.correlation[index].re
+        };
+        let mut peak_lag = 0isize;
+        let mut peak = value(0);
+        for lag in 1..(max_lag as isize + 1) {
+            for &lag in &[lag, -lag] {
+                if value(lag) > peak {
+                    peak = value(lag);
+                    peak_lag = lag;
+                }
+            }
+        }
+
+        // parabolic interpolation between the neighbours of the peak
+        let (before, after) = (value(peak_lag - 1), value(peak_lag + 1));
+        let denominator = before - 2.0 * peak + after;
+        let offset = if denominator < 0.0 {
+            0.5 * (before - after) / denominator
+        } else {
+            0.0
+        };
+
+        // a peak at lag k means left[t + k] matches right[t], so the right channel lags by -k
+        let delay = -(peak_lag as f32 + offset) / interpolation;
+        // with all used bins in phase the peak is used_bins / len
+        let confidence = peak * len as f32 / used_bins as f32;
+
+        Some(Estimate {
+                 angle: self.delay_to_angle(delay),
+                 confidence: if confidence > 1.0 { 1.0 } else { confidence },
+                 delay: delay,
+             })
+    }
+
+    fn delay_to_angle(&self, delay: f32) -> f32 {
+        let max_delay = self.max_delay();
+        let sin = if max_delay > 0.0 { delay / max_delay } else { 0.0 };
+        let sin = if sin > 1.0 {
+            1.0
+        } else if sin < -1.0 {
+            -1.0
+        } else {
+            sin
+        };
+        math::asin(sin) * 180.0 / ::core::f32::consts::PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    /// Xorshift, for reproducible noise.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        /// Uniform in -1 to 1.
+        fn uniform(&mut self) -> f64 {
+            f64::from(self.next()) / f64::from(u32::max_value()) * 2.0 - 1.0
+        }
+    }
+
+    fn interleave(left: &[f64], right: &[f64]) -> Vec<i16> {
+        left.iter()
+            .zip(right.iter())
+            .flat_map(|(&l, &r)| vec![l as i16, r as i16])
+            .collect()
+    }
+
+    /// White noise in which the right channel lags the left one by `delay` whole samples.
+    fn delayed_noise(frames: usize, delay: isize, seed: u32) -> Vec<i16> {
+        let mut random = Random(seed);
+        let margin = 16;
+        let noise: Vec<f64> = (0..frames + 2 * margin).map(|_| 8000.0 * random.uniform()).collect();
+        let left: Vec<f64> = (0..frames).map(|t| noise[t + margin]).collect();
+        let right: Vec<f64> =
+            (0..frames).map(|t| noise[(t as isize + margin as isize - delay) as usize]).collect();
+        interleave(&left, &right)
+    }
+
+    /// A sum of sines with random frequencies and phases in which the right channel lags the
+    /// left one by `delay` samples, which needn't be a whole number.
+    fn delayed_sines(frames: usize, delay: f64, sample_rate: u32) -> Vec<i16> {
+        let mut random = Random(0x2545_f491);
+        let sines: Vec<(f64, f64)> = (0..40)
+            .map(|_| (3100.0 + 2900.0 * random.uniform(), 3.2 * random.uniform()))
+            .collect();
+        let signal = |t: f64| -> f64 {
+            sines.iter()
+                .map(|&(frequency, phase)| {
+                    let omega = 2.0 * ::std::f64::consts::PI * frequency / f64::from(sample_rate);
+                    600.0 * (omega * t + phase).sin()
+                })
+                .sum()
+        };
+        let left: Vec<f64> = (0..frames).map(|t| signal(t as f64)).collect();
+        let right: Vec<f64> = (0..frames).map(|t| signal(t as f64 - delay)).collect();
+        interleave(&left, &right)
+    }
+
+    fn estimates(estimator: &mut DirectionEstimator) -> Vec<Estimate> {
+        let mut estimates = Vec::new();
+        while let Some(estimate) = estimator.next_estimate() {
+            estimates.push(estimate);
+        }
+        estimates
+    }
+
+    #[test]
+    fn whole_sample_delays() {
+        // 10 cm give a maximum delay of 4.7 samples
+        let config = DirectionConfig { mic_spacing_mm: 100.0, ..DirectionConfig::default() };
+        for &delay in &[-4, -1, 0, 1, 2, 4] {
+            let mut estimator = DirectionEstimator::new(config);
+            estimator.push_samples(&delayed_noise(2 * config.frame_len, delay, 0x1234_5678));
+            let estimates = estimates(&mut estimator);
+            assert_eq!(estimates.len(), 2);
+            for estimate in estimates {
+                assert!((estimate.delay - delay as f32).abs() < 0.1,
+                        "delay {}: {:?}",
+                        delay,
+                        estimate);
+                assert!(estimate.confidence > 0.8, "delay {}: {:?}", delay, estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn fractional_delays() {
+        let config = DirectionConfig::default();
+        let mut estimator = DirectionEstimator::new(config);
+        assert!((estimator.max_delay() - 0.98).abs() < 0.01);
+        for &delay in &[-0.9, -0.5, -0.25, 0.0, 0.3, 0.75] {
+            estimator.push_samples(&delayed_sines(config.frame_len, delay, config.sample_rate));
+            let estimate = estimator.next_estimate().unwrap();
+            assert!((f64::from(estimate.delay) - delay).abs() < 0.1,
+                    "delay {}: {:?}",
+                    delay,
+                    estimate);
+            let expected_angle = (delay / f64::from(estimator.max_delay())).asin().to_degrees();
+            assert!((f64::from(estimate.angle) - expected_angle).abs() < 8.0,
+                    "delay {}: {:?}",
+                    delay,
+                    estimate);
+            assert!(estimate.confidence > 0.8);
+        }
+    }
+
+    #[test]
+    fn left_side_is_positive() {
+        // the sound reaches the left microphone first
+        let config = DirectionConfig::default();
+        let mut estimator = DirectionEstimator::new(config);
+        estimator.push_samples(&delayed_sines(config.frame_len, 0.9, config.sample_rate));
+        assert!(estimator.next_estimate().unwrap().angle > 45.0);
+    }
+
+    #[test]
+    fn uncorrelated_channels() {
+        let config = DirectionConfig::default();
+        let mut estimator = DirectionEstimator::new(config);
+        let mut random = Random(0xdead_beef);
+        let samples: Vec<i16> =
+            (0..2 * config.frame_len).map(|_| (8000.0 * random.uniform()) as i16).collect();
+        estimator.push_samples(&samples);
+        let estimate = estimator.next_estimate().unwrap();
+        assert!(estimate.confidence < 0.4, "{:?}", estimate);
+    }
+
+    #[test]
+    fn quiet_frames_are_ignored() {
+        let config = DirectionConfig::default();
+        let mut estimator = DirectionEstimator::new(config);
+        estimator.push_samples(&vec![0; 2 * config.frame_len]);
+        // about -70 dB
+        let mut random = Random(7);
+        let samples: Vec<i16> =
+            (0..2 * config.frame_len).map(|_| (10.0 * random.uniform()) as i16).collect();
+        estimator.push_samples(&samples);
+        assert_eq!(estimator.next_estimate(), None);
+    }
+
+    #[test]
+    fn split_pushes_and_queue_limit() {
+        let config = DirectionConfig { frame_len: 64, ..DirectionConfig::default() };
+        let mut estimator = DirectionEstimator::new(config);
+        let samples = delayed_noise(20 * 64, 0, 99);
+        // odd chunks split frames and even sample pairs
+        for chunk in samples.chunks(37) {
+            estimator.push_samples(chunk);
+        }
+        assert_eq!(estimates(&mut estimator).len(), MAX_ESTIMATES);
+    }
+
+    #[test]
+    fn angle_is_clamped() {
+        let estimator = DirectionEstimator::new(DirectionConfig::default());
+        let max_delay = estimator.max_delay();
+        assert_eq!(estimator.delay_to_angle(0.0), 0.0);
+        assert!((estimator.delay_to_angle(2.0 * max_delay) - 90.0).abs() < 1e-3);
+        assert!((estimator.delay_to_angle(-max_delay) + 90.0).abs() < 1e-3);
+        assert!((estimator.delay_to_angle(max_delay / 2.0) - 30.0).abs() < 1e-2);
+    }
+}
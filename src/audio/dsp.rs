@@ -0,0 +1,333 @@
+//! Signal processing for audio samples: FFT, windowing and level conversion.
+
+use core::f32::consts::PI;
+use core::ops::{Add, Sub, Mul};
+use math;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Complex {
+        Complex { re: re, im: im }
+    }
+
+    /// `e^(i * angle)`
+    pub fn from_angle(angle: f32) -> Complex {
+        Complex::new(math::cos(angle), math::sin(angle))
+    }
+
+    pub fn conj(&self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn norm_sqr(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn norm(&self) -> f32 {
+        math::sqrt(self.norm_sqr())
+    }
+
+    pub fn scale(&self, factor: f32) -> Complex {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self.re * other.re - self.im * other.im,
+                     self.re * other.im + self.im * other.re)
+    }
+}
+
+/// In-place radix-2 FFT. The length of `buffer` must be a power of two.
+pub fn fft(buffer: &mut [Complex]) {
+    transform(buffer, false);
+}
+
+/// In-place inverse FFT including the 1/N scaling.
+pub fn ifft(buffer: &mut [Complex]) {
+    transform(buffer, true);
+    let scale = 1.0 / buffer.len() as f32;
+    for value in buffer.iter_mut() {
+        *value = value.scale(scale);
+    }
+}
+
+fn transform(buffer: &mut [Complex], inverse: bool) {
+    let n = buffer.len();
+    assert!(n.is_power_of_two(), "fft length must be a power of two");
+    if n <= 1 {
+        return;
+    }
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let step = Complex::from_angle(sign * 2.0 * PI / len as f32);
+        for start in (0..n).filter(|i| i % len == 0) {
+            let mut twiddle = Complex::new(1.0, 0.0);
+            for k in 0..len / 2 {
+                let even = buffer[start + k];
+                let odd = buffer[start + k + len / 2] * twiddle;
+                buffer[start + k] = even + odd;
+                buffer[start + k + len / 2] = even - odd;
+                twiddle = twiddle * step;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// FFT of `input.len()` real samples.
+///
+/// `output` must hold `input.len() / 2 + 1` values and receives the bins from 0 to the Nyquist
+/// frequency. The transform uses a complex FFT of half the length.
+pub fn real_fft(input: &[f32], output: &mut [Complex]) {
+    let n = input.len();
+    let half = n / 2;
+    assert!(n >= 2, "real fft needs at least two samples");
+    assert_eq!(output.len(), half + 1);
+
+    // pack even samples into the real and odd samples into the imaginary part
+    for (i, value) in output[..half].iter_mut().enumerate() {
+        *value = Complex::new(input[2 * i], input[2 * i + 1]);
+    }
+    fft(&mut output[..half]);
+
+    // split the spectrum of the packed signal into the spectrum of the real signal
+    let z0 = output[0];
+    output[0] = Complex::new(z0.re + z0.im, 0.0);
+    output[half] = Complex::new(z0.re - z0.im, 0.0);
+    for k in 1..(half / 2 + 1) {
+        let z_k = output[k];
+        let z_mirror = output[half - k];
+
+        let x_k = split(z_k, z_mirror, k, n);
+        let x_mirror = split(z_mirror, z_k, half - k, n);
+        output[k] = x_k;
+        output[half - k] = x_mirror;
+    }
+}
+
+/// Computes bin `k` of the real FFT from bin `k` and `N/2 - k` of the packed FFT.
+fn split(z_k: Complex, z_mirror: Complex, k: usize, n: usize) -> Complex {
+    let even = (z_k + z_mirror.conj()).scale(0.5);
+    // (z_k - conj(z_mirror)) / 2i
+    let diff = z_k - z_mirror.conj();
+    let odd = Complex::new(diff.im, -diff.re).scale(0.5);
+    even + Complex::from_angle(-2.0 * PI * k as f32 / n as f32) * odd
+}
+
+/// Multiplies the samples with a Hann window.
+pub fn hann_window(samples: &mut [f32]) {
+    let n = samples.len();
+    if n < 2 {
+        return;
+    }
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let w = 0.5 - 0.5 * math::cos(2.0 * PI * i as f32 / (n - 1) as f32);
+        *sample *= w;
+    }
+}
+
+/// Converts an amplitude ratio to dB. Returns `floor` for (nearly) silent input.
+pub fn amplitude_to_db(amplitude: f32, floor: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return floor;
+    }
+    let db = 20.0 * math::log10(amplitude);
+    if db < floor { floor } else { db }
+}
+
+/// Converts a power ratio to dB. Returns `floor` for (nearly) silent input.
+pub fn power_to_db(power: f32, floor: f32) -> f32 {
+    if power <= 0.0 {
+        return floor;
+    }
+    let db = 10.0 * math::log10(power);
+    if db < floor { floor } else { db }
+}
+
+/// Computes the magnitude of each bin in dB relative to a full scale sine wave.
+///
+/// `fft_len` is the number of real samples that were transformed. The result assumes that a
+/// Hann window (coherent gain 0.5) was applied.
+pub fn magnitudes_db(spectrum: &[Complex], fft_len: usize, output: &mut [f32], floor: f32) {
+    // a full scale sine results in a bin magnitude of N/2 * 0.5 (window gain)
+    let full_scale = fft_len as f32 / 4.0;
+    for (bin, db) in spectrum.iter().zip(output.iter_mut()) {
+        *db = amplitude_to_db(bin.norm() / full_scale, floor);
+    }
+}
+
+/// Converts a 16 bit sample to the range [-1, 1).
+pub fn sample_to_f32(sample: i16) -> f32 {
+    f32::from(sample) / 32768.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    const TOLERANCE: f32 = 1e-3;
+
+    fn assert_close(actual: Complex, expected: Complex) {
+        assert!((actual - expected).norm() < TOLERANCE,
+                "{:?} != {:?}",
+                actual,
+                expected);
+    }
+
+    /// A sine with `cycles` periods in `n` samples.
+    fn sine(n: usize, cycles: usize, amplitude: f32) -> Vec<f32> {
+        (0..n)
+            .map(|i| amplitude * math::sin(2.0 * PI * (cycles * i) as f32 / n as f32))
+            .collect()
+    }
+
+    /// Direct evaluation of the DFT for comparison.
+    fn dft(input: &[Complex]) -> Vec<Complex> {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input.iter().enumerate().fold(Complex::default(), |sum, (i, &x)| {
+                    let angle = -2.0 * PI * ((k * i) % n) as f32 / n as f32;
+                    sum + x * Complex::from_angle(angle)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fft_of_impulse_is_flat() {
+        let mut buffer = vec![Complex::default(); 16];
+        buffer[0] = Complex::new(1.0, 0.0);
+        fft(&mut buffer);
+        for &bin in buffer.iter() {
+            assert_close(bin, Complex::new(1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn fft_matches_dft() {
+        let input: Vec<Complex> = (0..32)
+            .map(|i| Complex::new(math::sin(i as f32 * 0.7), math::cos(i as f32 * 1.3)))
+            .collect();
+        let mut buffer = input.clone();
+        fft(&mut buffer);
+        for (&actual, &expected) in buffer.iter().zip(dft(&input).iter()) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn ifft_inverts_fft() {
+        let input: Vec<Complex> = (0..64).map(|i| Complex::new(i as f32 / 64.0, 0.5)).collect();
+        let mut buffer = input.clone();
+        fft(&mut buffer);
+        ifft(&mut buffer);
+        for (&actual, &expected) in buffer.iter().zip(input.iter()) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn fft_rejects_other_lengths() {
+        fft(&mut [Complex::default(); 12]);
+    }
+
+    #[test]
+    fn real_fft_matches_complex_fft() {
+        let input = sine(64, 5, 0.8);
+        let mut complex: Vec<Complex> = input.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        fft(&mut complex);
+        let mut output = vec![Complex::default(); 33];
+        real_fft(&input, &mut output);
+        for (&actual, &expected) in output.iter().zip(complex.iter()) {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn sine_peaks_in_its_bin() {
+        let mut input = sine(256, 10, 1.0);
+        hann_window(&mut input);
+        let mut spectrum = vec![Complex::default(); 129];
+        real_fft(&input, &mut spectrum);
+        let mut db = vec![0.0; 129];
+        magnitudes_db(&spectrum, 256, &mut db, -120.0);
+
+        // a full scale sine is at 0 dB, the neighbours of the Hann main lobe at -6 dB
+        assert!(math::abs(db[10]) < 0.1, "{}", db[10]);
+        assert!(math::abs(db[9] + 6.02) < 0.1, "{}", db[9]);
+        assert!(math::abs(db[11] + 6.02) < 0.1, "{}", db[11]);
+        assert!(db[20] < -60.0, "{}", db[20]);
+    }
+
+    #[test]
+    fn hann_window_shape() {
+        let mut samples = vec![1.0; 9];
+        hann_window(&mut samples);
+        assert!(math::abs(samples[0]) < 1e-6);
+        assert!(math::abs(samples[8]) < 1e-6);
+        assert!(math::abs(samples[4] - 1.0) < 1e-6);
+        assert!(math::abs(samples[2] - 0.5) < 1e-5);
+        for i in 0..4 {
+            assert!(math::abs(samples[i] - samples[8 - i]) < 1e-5);
+        }
+
+        let mut single = [0.7];
+        hann_window(&mut single);
+        assert_eq!(single[0], 0.7);
+    }
+
+    #[test]
+    fn db_conversions() {
+        assert!(math::abs(amplitude_to_db(0.5, -120.0) + 6.0206) < 1e-3);
+        assert!(math::abs(power_to_db(0.5, -120.0) + 3.0103) < 1e-3);
+        assert_eq!(amplitude_to_db(0.0, -90.0), -90.0);
+        assert_eq!(amplitude_to_db(1e-9, -90.0), -90.0);
+        assert_eq!(power_to_db(-1.0, -90.0), -90.0);
+        assert_eq!(sample_to_f32(i16::min_value()), -1.0);
+        assert_eq!(sample_to_f32(16384), 0.5);
+    }
+}
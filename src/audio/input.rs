@@ -0,0 +1,169 @@
+//! DMA driven microphone capture from SAI2 block B.
+//!
+//! DMA2 stream 7 copies the samples into a circular buffer. Its half transfer and transfer
+//! complete interrupts copy the finished half into a larger ring buffer, from which the main
+//! loop reads with `AudioInput::read`.
+
+use board::dma;
+use board::sai::Sai;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use interrupts;
+use super::config::WordLength;
+use super::dma_2;
+use super::ring::SampleRing;
+
+/// Number of samples (left and right interleaved) in each half of the DMA buffer.
+const BLOCK_LEN: usize = 256;
+
+static mut DMA_BUFFER: [u32; 2 * BLOCK_LEN] = [0; 2 * BLOCK_LEN];
+static RING: SampleRing = SampleRing::new();
+// `WordLength::sample_shift` of the configured word length
+static SAMPLE_SHIFT: AtomicUsize = AtomicUsize::new(16);
+
+// number of blocks that were dropped because the ring buffer was full
+static OVERRUNS: AtomicUsize = AtomicUsize::new(0);
+// number of DMA transfer errors
+static DMA_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Stereo sample stream of the two digital microphones.
+pub struct AudioInput {
+    _private: (),
+}
+
+/// Starts the DMA transfers from SAI2 block B.
+///
+/// `audio::init_sai_2` and `audio::init_dma` must be called before.
+pub fn init(sai: &mut Sai) -> AudioInput {
+    let dma_2 = dma_2();
+
+    // the DMA transfers whole words for all word lengths, the samples are scaled by the shift
+    let word_length = WordLength::from_sai_data_size(sai.bcr1.read().ds())
+        .expect("audio input requires 16, 24 or 32 bit words");
+    SAMPLE_SHIFT.store(word_length.sample_shift() as usize, Ordering::Relaxed);
+
+    // disable block b while the DMA is configured
+    sai.bcr1.update(|r| r.set_saiben(false));
+    while sai.bcr1.read().saiben() {}
+
+    // disable stream 7 and wait until it is really disabled
+    dma_2.s7cr.update(|r| r.set_en(false));
+    while dma_2.s7cr.read().en() {}
+    dma_2.hifcr.write(clear_stream_7_flags());
+
+    // addresses and number of data items
+    dma_2.s7par.update(|r| r.set_pa(&sai.bdr as *const _ as u32)); // peripheral_address
+    dma_2.s7m0ar.update(|r| r.set_m0a(unsafe { DMA_BUFFER.as_ptr() } as u32)); // memory_address
+    dma_2.s7ndtr.update(|r| r.set_ndt(2 * BLOCK_LEN as u16)); // number_of_data_items
+
+    // fifo
+    dma_2.s7fcr
+        .update(|r| {
+                    r.set_dmdis(true); // direct_mode_disable
+                    r.set_fth(0b11); // fifo_threshold full
+                });
+
+    let mut s7cr = dma::S7cr::default();
+    s7cr.set_chsel(0); // channel 0 = SAI2_B
+    s7cr.set_dir(0b00); // direction peripheral_to_memory
+    s7cr.set_circ(true); // circular_mode
+    s7cr.set_pinc(false); // peripheral_increment
+    s7cr.set_minc(true); // memory_increment
+    s7cr.set_psize(0b10); // peripheral_size word
+    s7cr.set_msize(0b10); // memory_size word
+    s7cr.set_pl(0b11); // priority_level very_high
+    s7cr.set_mburst(0b00); // memory_burst single
+    s7cr.set_pburst(0b00); // peripheral_burst single
+    s7cr.set_htie(true); // half_transfer_interrupt_enable
+    s7cr.set_tcie(true); // transfer_complete_interrupt_enable
+    s7cr.set_teie(true); // transfer_error_interrupt_enable
+    s7cr.set_dmeie(true); // direct_mode_error_interrupt_enable
+    dma_2.s7cr.write(s7cr);
+
+    RING.clear();
+    OVERRUNS.store(0, Ordering::Relaxed);
+    DMA_ERRORS.store(0, Ordering::Relaxed);
+
+    dma_2.s7cr.update(|r| r.set_en(true)); // stream_enable
+    interrupts::enable(interrupts::DMA2_STREAM7);
+
+    // let block b request DMA transfers and enable it again
+    sai.bcr1
+        .update(|r| {
+                    r.set_dmaen(true); // dma_enable
+                    r.set_saiben(true); // audio_block_enable
+                });
+
+    AudioInput { _private: () }
+}
+
+impl AudioInput {
+    /// Copies the oldest captured samples (left and right interleaved) into `buffer` and
+    /// returns the number of copied samples. Only whole stereo frames are copied.
+    ///
+    /// The samples are scaled to the full `i32` range for all word lengths.
+    pub fn read(&mut self, buffer: &mut [i32]) -> usize {
+        let len = buffer.len() & !1;
+        RING.pop(&mut buffer[..len])
+    }
+
+    /// Number of samples that can be read without waiting.
+    pub fn available(&self) -> usize {
+        RING.len()
+    }
+
+    /// Number of blocks that were dropped because `read` was not called often enough.
+    pub fn overruns(&self) -> usize {
+        OVERRUNS.load(Ordering::Relaxed)
+    }
+
+    /// Number of DMA transfer and direct mode errors.
+    pub fn dma_errors(&self) -> usize {
+        DMA_ERRORS.load(Ordering::Relaxed)
+    }
+}
+
+fn clear_stream_7_flags() -> dma::Hifcr {
+    let mut clear = dma::Hifcr::default();
+    clear.set_ctcif7(true); // transfer complete clear flag
+    clear.set_chtif7(true); // half transfer clear flag
+    clear.set_cteif7(true); // transfer error clear flag
+    clear.set_cdmeif7(true); // direct mode error clear flag
+    clear.set_cfeif7(true); // fifo error clear flag
+    clear
+}
+
+/// Copies a finished half of the DMA buffer into the ring buffer.
+fn push_block(words: &[u32]) {
+    if RING.free() < words.len() {
+        OVERRUNS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let shift = SAMPLE_SHIFT.load(Ordering::Relaxed);
+    let mut block = [0; BLOCK_LEN];
+    for (sample, &word) in block.iter_mut().zip(words) {
+        // the received word is right aligned, its sign bit ends up in bit 31
+        *sample = (word << shift) as i32;
+    }
+    RING.push(&block[..words.len()]);
+}
+
+/// Interrupt handler for DMA2 stream 7.
+pub unsafe extern "C" fn dma2_stream7() {
+    let dma_2 = dma_2();
+
+    let hisr = dma_2.hisr.read();
+    dma_2.hifcr.write(clear_stream_7_flags());
+
+    if hisr.teif7() || hisr.dmeif7() {
+        DMA_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the first half is complete
+    if hisr.htif7() {
+        push_block(&DMA_BUFFER[..BLOCK_LEN]);
+    }
+    // the second half is complete
+    if hisr.tcif7() {
+        push_block(&DMA_BUFFER[BLOCK_LEN..]);
+    }
+}
@@ -0,0 +1,229 @@
+use board::dma::Dma;
+use board::rcc::Rcc;
+use board::sai::{self, Sai};
+use embedded::interfaces::gpio::Gpio;
+
+pub use self::analysis::{Analyzer, AnalyzerConfig};
+pub use self::config::{AudioConfig, ConfigError, SampleRate, WordLength, SlotConfig,
+                       InputDevice, OutputDevice};
+pub use self::direction::{DirectionEstimator, DirectionConfig};
+pub use self::input::AudioInput;
+pub use self::output::AudioOutput;
+pub use self::spectrum::{SpectrumAnalyzer, SpectrumMode};
+pub use self::wm8994::Wm8994;
+
+pub mod analysis;
+pub mod config;
+pub mod direction;
+pub mod dsp;
+pub mod input;
+pub mod output;
+pub mod spectrum;
+pub mod wav;
+pub mod wm8994;
+mod ring;
+
+static mut DMA_2: Option<&'static mut Dma> = None;
+
+/// Takes DMA2, which transfers the samples of `AudioInput` and `AudioOutput`.
+pub fn init_dma(dma_2: &'static mut Dma, rcc: &mut Rcc) {
+    // enable DMA2 clock
+    rcc.ahb1enr.update(|r| r.set_dma2en(true));
+
+    unsafe { DMA_2 = Some(dma_2) };
+}
+
+fn dma_2() -> &'static mut Dma {
+    match unsafe { DMA_2.as_mut() } {
+        Some(dma_2) => dma_2,
+        None => panic!("audio::init_dma was not called"),
+    }
+}
+
+/// Configures SAI2 block A as master transmitter and block B as synchronous receiver.
+///
+/// Fails without touching the hardware if the configuration is not supported.
+pub fn init_sai_2(sai: &mut Sai, rcc: &mut Rcc, config: &AudioConfig) -> Result<(), ConfigError> {
+    config.validate()?;
+    let pll_i2s = config.sample_rate.pll_i2s_config();
+    // MCLK_x = SAI_CK_x / (MCKDIV[3:0] * 2) with MCLK_x = 256 * FS
+    let sai_clock = pll_i2s.sai_clock(rcc.pllcfgr.read().pllm());
+    let mckdiv = config::master_clock_divider(sai_clock, config.sample_rate.hz())?;
+
+    // disable block a and block b
+    sai.acr1.update(|r| r.set_saiaen(false)); // audio_block_enable
+    sai.bcr1.update(|r| r.set_saiben(false)); // audio_block_enable
+    while sai.acr1.read().saiaen() {}
+    while sai.bcr1.read().saiben() {}
+
+    // enable sai2 clock
+    rcc.apb2enr.update(|r| r.set_sai2en(true));
+
+    // Disabled All interrupt and clear all the flag
+    sai.bim.write(Default::default());
+    let mut clear_all_flags = sai::Bclrfr::default();
+    clear_all_flags.set_lfsdet(true); // Clear late frame synchronization detection flag
+    clear_all_flags.set_cafsdet(true); // Clear anticipated frame synchronization detection flag
+    clear_all_flags.set_cnrdy(true); // Clear codec not ready flag
+    clear_all_flags.set_wckcfg(true); // Clear wrong clock configuration flag
+    clear_all_flags.set_mutedet(true); // Clear mute detection flag
+    clear_all_flags.set_ovrudr(true); // Clear overrun / underrun
+    sai.bclrfr.write(clear_all_flags);
+
+    // Flush the fifo
+    sai.bcr2.update(|r| r.set_fflus(true)); // fifo_flush
+
+
+    // PLL clock is set depending on the AudioFreq (44.1khz vs 48khz groups)
+
+    // I2S clock config
+    // 44.1 kHz family: PLLI2S_VCO = 429 MHz, SAI_CLK = 429/2/19 = 11.289 MHz
+    // other rates: PLLI2S_VCO = 344 MHz, SAI_CLK = 344/7/1 = 49.142 MHz
+
+    // Configure SAI2 Clock source
+    rcc.dkcfgr1.update(|r| r.set_sai2sel(0b01)); // sai2_clock_source plli2s
+
+    // Disable the PLLI2S
+    rcc.cr.update(|r| r.set_plli2son(false));
+    while rcc.cr.read().plli2srdy() {}
+
+    // Configure the PLLI2S division factors
+    // PLLI2S_VCO Input  = PLL_SOURCE/PLLM
+    // PLLI2S_VCO Output = PLLI2S_VCO Input * PLLI2SN
+    // SAI_CLK(first level) = PLLI2S_VCO Output/PLLI2SQ
+    rcc.plli2scfgr
+        .update(|r| {
+                    r.set_plli2sn(pll_i2s.n);
+                    r.set_plli2sq(pll_i2s.q);
+                });
+
+    // SAI_CLK_x = SAI_CLK(first level)/PLLI2SDIVQ
+    rcc.dkcfgr1.update(|r| r.set_plli2sdiv(pll_i2s.div_q - 1));
+
+    // Enable the PLLI2S
+    rcc.cr.update(|r| r.set_plli2son(true));
+    while !rcc.cr.read().plli2srdy() {}
+
+
+    // configure sai registers
+
+    // disable synchronization outputs
+    sai.gcr.update(|r| r.set_syncout(0)); // NoSyncOutput
+
+    // Initialize SAI2 block A in MASTER TX
+
+    // configure cr1
+    let data_size = config.word_length.sai_data_size();
+    let slot_size = config.word_length.sai_slot_size();
+    let frame_length = config.slots.frame_length(config.word_length);
+    let number_of_slots = config.slots.number_of_slots;
+    let input_slots = config.input_slots();
+    let output_slots = config.slots.output_slots;
+
+    let mut acr1 = sai::Acr1::default();
+    acr1.set_mode(0b00); // MasterTransmitter
+    acr1.set_prtcfg(0b00); // protocol free
+    acr1.set_ds(data_size); // data_size
+    acr1.set_lsbfirst(false);
+    acr1.set_ckstr(true); // clock_strobing_edge
+    acr1.set_syncen(0b00); // synchronization asynchronous
+    acr1.set_mono(false);
+    acr1.set_out_dri(true); // output_drive
+    acr1.set_nodiv(false); // no_divider
+    acr1.set_mcjdiv(mckdiv); // master_clock_divider8
+    sai.acr1.write(acr1);
+
+    // configure cr2
+    let mut acr2 = sai::Acr2::default();
+    acr2.set_fth(0b001); // fifo_threshold QuarterFifo
+    acr2.set_tris(false); // tristate_management
+    acr2.set_comp(0b00); // companding_mode None
+    sai.acr2.write(acr2);
+
+    // configure frame
+    let mut afrcr = sai::Afrcr::default();
+    afrcr.set_frl((frame_length - 1) as u8); // frame_length
+    afrcr.set_fsall((frame_length / 2 - 1) as u8); // sync_active_level_length
+    afrcr.set_fsdef(true); // frame_sync_definition
+    afrcr.set_fspol(false); // frame_sync_polarity
+    afrcr.set_fsoff(true); // frame_sync_offset
+    sai.afrcr.write(afrcr);
+
+    // configure slot
+    let mut aslotr = sai::Aslotr::default();
+    aslotr.set_fboff(0); // first_bit_offset
+    aslotr.set_slotsz(slot_size); // slot_size
+    aslotr.set_nbslot(number_of_slots - 1); // number_of_slots
+    aslotr.set_sloten(output_slots); // enable_slots
+    sai.aslotr.write(aslotr);
+
+    // Initialize SAI2 block B in SLAVE RX synchronous from SAI2 block A
+
+    // configure cr1
+    let mut bcr1 = sai::Bcr1::default();
+    bcr1.set_mode(0b11); // SlaveReceiver
+    bcr1.set_prtcfg(0b00); // protocol free
+    bcr1.set_ds(data_size); // data_size
+    bcr1.set_lsbfirst(false);
+    bcr1.set_ckstr(true); // clock_strobing_edge
+    bcr1.set_syncen(0b01); // synchronization SynchronousWithOtherSubBlock
+    bcr1.set_mono(false);
+    bcr1.set_out_dri(true); // output_drive
+    bcr1.set_nodiv(false); // no_divider
+    bcr1.set_mcjdiv(mckdiv); // master_clock_divider8
+    sai.bcr1.write(bcr1);
+
+    // configure cr2
+    let mut bcr2 = sai::Bcr2::default();
+    bcr2.set_fth(0b001); // fifo_threshold QuarterFifo
+    bcr2.set_tris(false); // tristate_management
+    bcr2.set_comp(0b00); // companding_mode None
+    sai.bcr2.write(bcr2);
+
+    // configure frame
+    let mut bfrcr = sai::Bfrcr::default();
+    bfrcr.set_frl((frame_length - 1) as u8); // frame_length
+    bfrcr.set_fsall((frame_length / 2 - 1) as u8); // sync_active_level_length
+    bfrcr.set_fsdef(true); // frame_sync_definition
+    bfrcr.set_fspol(false); // frame_sync_polarity
+    bfrcr.set_fsoff(true); // frame_sync_offset
+    sai.bfrcr.write(bfrcr);
+
+    // configure slot
+    let mut bslotr = sai::Bslotr::default();
+    bslotr.set_fboff(0); // first_bit_offset
+    bslotr.set_slotsz(slot_size); // slot_size
+    bslotr.set_nbslot(number_of_slots - 1); // number_of_slots
+    bslotr.set_sloten(input_slots); // enable_slots
+    sai.bslotr.write(bslotr);
+
+    // Enable SAI peripheral block a to generate MCLK
+    sai.acr1.update(|r| r.set_saiaen(true)); // audio_block_enable
+
+    // Enable SAI peripheral block b
+    sai.bcr1.update(|r| r.set_saiben(true)); // audio_block_enable
+
+    Ok(())
+}
+
+pub fn init_sai_2_pins(gpio: &mut Gpio) {
+    use embedded::interfaces::gpio::{OutputType, OutputSpeed, AlternateFunction, Resistor};
+    use embedded::interfaces::gpio::Port::*;
+    use embedded::interfaces::gpio::Pin::*;
+
+    // block A (master)
+    let sai2_fs_a = (PortI, Pin7);
+    let sai2_sck_a = (PortI, Pin5);
+    let sai2_sd_a = (PortI, Pin6);
+    let sai2_mclk_a = (PortI, Pin4);
+    // block B (synchronous slave)
+    let sai2_sd_b = (PortG, Pin10);
+
+    let pins = [sai2_fs_a, sai2_sck_a, sai2_sd_a, sai2_mclk_a, sai2_sd_b];
+    gpio.to_alternate_function_all(&pins,
+                                   AlternateFunction::AF10,
+                                   OutputType::PushPull,
+                                   OutputSpeed::High,
+                                   Resistor::NoPull)
+        .unwrap();
+}
@@ -0,0 +1,177 @@
+//! DMA driven playback through SAI2 block A.
+//!
+//! `AudioOutput::write` fills a ring buffer. The half transfer and transfer complete
+//! interrupts of DMA2 stream 4 refill the half of the circular DMA buffer that was just sent.
+
+use board::dma;
+use board::sai::Sai;
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use interrupts;
+use super::config::WordLength;
+use super::dma_2;
+use super::ring::SampleRing;
+
+/// Number of samples (left and right interleaved) in each half of the DMA buffer.
+const BLOCK_LEN: usize = 256;
+
+static mut DMA_BUFFER: [u32; 2 * BLOCK_LEN] = [0; 2 * BLOCK_LEN];
+static RING: SampleRing = SampleRing::new();
+// `WordLength::sample_shift` of the configured word length
+static SAMPLE_SHIFT: AtomicUsize = AtomicUsize::new(16);
+
+// number of blocks that were (partly) filled with silence because the ring buffer was empty
+static UNDERRUNS: AtomicUsize = AtomicUsize::new(0);
+// number of DMA transfer errors
+static DMA_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Stereo sample sink for the headphone and speaker outputs.
+pub struct AudioOutput {
+    _private: (),
+}
+
+/// Starts the DMA transfers to SAI2 block A.
+///
+/// `audio::init_sai_2` and `audio::init_dma` must be called before.
+pub fn init(sai: &mut Sai) -> AudioOutput {
+    let dma_2 = dma_2();
+
+    // the DMA transfers whole words for all word lengths, the samples are scaled by the shift
+    let word_length = WordLength::from_sai_data_size(sai.acr1.read().ds())
+        .expect("audio output requires 16, 24 or 32 bit words");
+    SAMPLE_SHIFT.store(word_length.sample_shift() as usize, Ordering::Relaxed);
+
+    // disable stream 4 and wait until it is really disabled
+    dma_2.s4cr.update(|r| r.set_en(false));
+    while dma_2.s4cr.read().en() {}
+    dma_2.hifcr.write(clear_stream_4_flags());
+
+    // start with silence
+    unsafe {
+        for sample in DMA_BUFFER.iter_mut() {
+            *sample = 0;
+        }
+    }
+
+    // addresses and number of data items
+    dma_2.s4par.update(|r| r.set_pa(&sai.adr as *const _ as u32)); // peripheral_address
+    dma_2.s4m0ar.update(|r| r.set_m0a(unsafe { DMA_BUFFER.as_ptr() } as u32)); // memory_address
+    dma_2.s4ndtr.update(|r| r.set_ndt(2 * BLOCK_LEN as u16)); // number_of_data_items
+
+    // fifo
+    dma_2.s4fcr
+        .update(|r| {
+                    r.set_dmdis(true); // direct_mode_disable
+                    r.set_fth(0b11); // fifo_threshold full
+                });
+
+    let mut s4cr = dma::S4cr::default();
+    s4cr.set_chsel(3); // channel 3 = SAI2_A
+    s4cr.set_dir(0b01); // direction memory_to_peripheral
+    s4cr.set_circ(true); // circular_mode
+    s4cr.set_pinc(false); // peripheral_increment
+    s4cr.set_minc(true); // memory_increment
+    s4cr.set_psize(0b10); // peripheral_size word
+    s4cr.set_msize(0b10); // memory_size word
+    s4cr.set_pl(0b11); // priority_level very_high
+    s4cr.set_mburst(0b00); // memory_burst single
+    s4cr.set_pburst(0b00); // peripheral_burst single
+    s4cr.set_htie(true); // half_transfer_interrupt_enable
+    s4cr.set_tcie(true); // transfer_complete_interrupt_enable
+    s4cr.set_teie(true); // transfer_error_interrupt_enable
+    s4cr.set_dmeie(true); // direct_mode_error_interrupt_enable
+    dma_2.s4cr.write(s4cr);
+
+    RING.clear();
+    UNDERRUNS.store(0, Ordering::Relaxed);
+    DMA_ERRORS.store(0, Ordering::Relaxed);
+
+    dma_2.s4cr.update(|r| r.set_en(true)); // stream_enable
+    interrupts::enable(interrupts::DMA2_STREAM4);
+
+    // block a generates the clocks for block b, so it is not disabled while the DMA starts
+    sai.acr1.update(|r| r.set_dmaen(true)); // dma_enable
+
+    AudioOutput { _private: () }
+}
+
+impl AudioOutput {
+    /// Queues as many samples (left and right interleaved) as fit into the buffer and returns
+    /// their number. Only whole stereo frames are queued.
+    ///
+    /// The samples use the full `i32` range for all word lengths, the low bits that don't fit
+    /// into the configured word length are dropped.
+    pub fn write(&mut self, samples: &[i32]) -> usize {
+        let len = cmp::min(samples.len(), RING.free()) & !1;
+        RING.push(&samples[..len])
+    }
+
+    /// Queues all samples, waiting for free space if necessary.
+    pub fn write_all(&mut self, mut samples: &[i32]) {
+        while samples.len() >= 2 {
+            let written = self.write(samples);
+            samples = &samples[written..];
+        }
+    }
+
+    /// Number of samples that can be written without waiting.
+    pub fn free(&self) -> usize {
+        RING.free()
+    }
+
+    /// Number of blocks that were padded with silence because `write` was not called often
+    /// enough.
+    pub fn underruns(&self) -> usize {
+        UNDERRUNS.load(Ordering::Relaxed)
+    }
+
+    /// Number of DMA transfer and direct mode errors.
+    pub fn dma_errors(&self) -> usize {
+        DMA_ERRORS.load(Ordering::Relaxed)
+    }
+}
+
+fn clear_stream_4_flags() -> dma::Hifcr {
+    let mut clear = dma::Hifcr::default();
+    clear.set_ctcif4(true); // transfer complete clear flag
+    clear.set_chtif4(true); // half transfer clear flag
+    clear.set_cteif4(true); // transfer error clear flag
+    clear.set_cdmeif4(true); // direct mode error clear flag
+    clear.set_cfeif4(true); // fifo error clear flag
+    clear
+}
+
+/// Refills a sent half of the DMA buffer from the ring buffer.
+fn fill_block(words: &mut [u32]) {
+    let mut block = [0; BLOCK_LEN];
+    let len = RING.pop(&mut block[..words.len()]);
+    if len < words.len() {
+        UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+    }
+    // the SAI sends the low bits of the word register
+    let shift = SAMPLE_SHIFT.load(Ordering::Relaxed);
+    for (word, &sample) in words.iter_mut().zip(block.iter()) {
+        *word = (sample >> shift) as u32;
+    }
+}
+
+/// Interrupt handler for DMA2 stream 4.
+pub unsafe extern "C" fn dma2_stream4() {
+    let dma_2 = dma_2();
+
+    let hisr = dma_2.hisr.read();
+    dma_2.hifcr.write(clear_stream_4_flags());
+
+    if hisr.teif4() || hisr.dmeif4() {
+        DMA_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the first half was sent
+    if hisr.htif4() {
+        fill_block(&mut DMA_BUFFER[..BLOCK_LEN]);
+    }
+    // the second half was sent
+    if hisr.tcif4() {
+        fill_block(&mut DMA_BUFFER[BLOCK_LEN..]);
+    }
+}
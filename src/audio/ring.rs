@@ -0,0 +1,75 @@
+//! Sample ring buffer shared between an interrupt handler and the main loop.
+
+use core::cell::UnsafeCell;
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of samples in a ring buffer.
+pub const RING_LEN: usize = 4096;
+
+/// Single producer, single consumer ring buffer of samples.
+///
+/// One side may only call `push`, the other side only `pop`.
+pub struct SampleRing {
+    buffer: UnsafeCell<[i32; RING_LEN]>,
+    // total number of samples written/read, the ring positions are these modulo `RING_LEN`
+    written: AtomicUsize,
+    read: AtomicUsize,
+}
+
+unsafe impl Sync for SampleRing {}
+
+impl SampleRing {
+    pub const fn new() -> SampleRing {
+        SampleRing {
+            buffer: UnsafeCell::new([0; RING_LEN]),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of samples that can be popped.
+    pub fn len(&self) -> usize {
+        let written = self.written.load(Ordering::Acquire);
+        written.wrapping_sub(self.read.load(Ordering::Acquire))
+    }
+
+    /// Number of samples that can be pushed.
+    pub fn free(&self) -> usize {
+        RING_LEN - self.len()
+    }
+
+    /// Appends as many samples as fit and returns their number.
+    pub fn push(&self, samples: &[i32]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let len = cmp::min(self.free(), samples.len());
+
+        let buffer = unsafe { &mut *self.buffer.get() };
+        for (i, &sample) in samples[..len].iter().enumerate() {
+            buffer[written.wrapping_add(i) % RING_LEN] = sample;
+        }
+
+        self.written.store(written.wrapping_add(len), Ordering::Release);
+        len
+    }
+
+    /// Removes the oldest samples and returns their number.
+    pub fn pop(&self, samples: &mut [i32]) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let len = cmp::min(self.len(), samples.len());
+
+        let buffer = unsafe { &*self.buffer.get() };
+        for (i, sample) in samples[..len].iter_mut().enumerate() {
+            *sample = buffer[read.wrapping_add(i) % RING_LEN];
+        }
+
+        self.read.store(read.wrapping_add(len), Ordering::Release);
+        len
+    }
+
+    /// Discards all samples. Must not be called while the other side is active.
+    pub fn clear(&self) {
+        self.written.store(0, Ordering::Relaxed);
+        self.read.store(0, Ordering::Relaxed);
+    }
+}
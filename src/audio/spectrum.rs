@@ -0,0 +1,198 @@
+//! Spectrum analyzer that renders the microphone input as bar graph or scrolling spectrogram.
+
+use collections::Vec;
+use lcd::{self, Color, Layer, Lcd};
+use super::dsp::{self, Complex};
+
+/// Number of samples per FFT.
+pub const FFT_LEN: usize = 512;
+
+/// Lowest displayed level in dB (relative to a full scale sine).
+const FLOOR_DB: f32 = -90.0;
+
+/// Width of a bar in pixels, including a one pixel gap.
+const BAR_WIDTH: u16 = 4;
+/// Maximum number of bars, for the unrotated screen.
+const MAX_BAR_COUNT: usize = (lcd::MAX_WIDTH / BAR_WIDTH) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectrumMode {
+    /// One vertical bar per frequency band.
+    Bars,
+    /// A scrolling time/frequency plot with one column per FFT.
+    Spectrogram,
+}
+
+pub struct SpectrumAnalyzer {
+    mode: SpectrumMode,
+    layer: Layer,
+    samples: Vec<f32>,
+    spectrum: Vec<Complex>,
+    levels: Vec<f32>,
+    bar_heights: Vec<u16>,
+    next_col: u16,
+    pending: bool,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(mode: SpectrumMode, layer: Layer) -> SpectrumAnalyzer {
+        SpectrumAnalyzer {
+            mode: mode,
+            layer: layer,
+            samples: Vec::with_capacity(FFT_LEN),
+            spectrum: vec![Complex::default(); FFT_LEN / 2 + 1],
+            levels: vec![FLOOR_DB; FFT_LEN / 2 + 1],
+            bar_heights: vec![0; MAX_BAR_COUNT],
+            next_col: 0,
+            pending: false,
+        }
+    }
+
+    pub fn mode(&self) -> SpectrumMode {
+        self.mode
+    }
+
+    /// Switches the display mode. The layer should be cleared afterwards.
+    pub fn set_mode(&mut self, mode: SpectrumMode) {
+        self.mode = mode;
+        self.next_col = 0;
+        for height in self.bar_heights.iter_mut() {
+            *height = 0;
+        }
+    }
+
+    /// Adds stereo samples (left and right interleaved). Both channels are mixed to mono.
+    ///
+    /// A new spectrum is computed whenever `FFT_LEN` samples were collected.
+    pub fn push_samples(&mut self, samples: &[i16]) {
+        for frame in samples.chunks(2) {
+            let mono = frame.iter().map(|&s| dsp::sample_to_f32(s)).sum::<f32>() /
+                       frame.len() as f32;
+            self.samples.push(mono);
+
+            if self.samples.len() == FFT_LEN {
+                self.analyze();
+                self.samples.clear();
+            }
+        }
+    }
+
+    /// Level of each FFT bin in dB, from 0 Hz to the Nyquist frequency.
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    fn analyze(&mut self) {
+        dsp::hann_window(&mut self.samples);
+        dsp::real_fft(&self.samples, &mut self.spectrum);
+        dsp::magnitudes_db(&self.spectrum, FFT_LEN, &mut self.levels, FLOOR_DB);
+        self.pending = true;
+    }
+
+    /// Draws the latest spectrum if a new one was computed since the last call.
+    pub fn draw(&mut self, lcd: &mut Lcd) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+
+        match self.mode {
+            SpectrumMode::Bars => self.draw_bars(lcd),
+            SpectrumMode::Spectrogram => self.draw_spectrogram_column(lcd),
+        }
+    }
+
+    fn draw_bars(&mut self, lcd: &mut Lcd) {
+        let (width, screen_height) = (lcd.width(), lcd.height());
+        let bar_count = usize::from(width / BAR_WIDTH);
+        // skip the dc bin
+        let bins_per_bar = (self.levels.len() - 1) / bar_count;
+        let bins_per_bar = if bins_per_bar == 0 { 1 } else { bins_per_bar };
+
+        for bar in 0..bar_count {
+            let start = 1 + bar * bins_per_bar;
+            if start >= self.levels.len() {
+                break;
+            }
+            let end = if start + bins_per_bar > self.levels.len() {
+                self.levels.len()
+            } else {
+                start + bins_per_bar
+            };
+            let level = self.levels[start..end]
+                .iter()
+                .fold(FLOOR_DB, |max, &l| if l > max { l } else { max });
+            let height = (level_fraction(level) * f32::from(screen_height)) as u16;
+
+            // only redraw the part of the bar that changed
+            let old_height = self.bar_heights[bar];
+            let x = bar as u16 * BAR_WIDTH;
+            if height > old_height {
+                for y in (screen_height - height)..(screen_height - old_height) {
+                    let color = bar_color(y, screen_height).to_argb1555();
+                    for dx in 0..BAR_WIDTH - 1 {
+                        lcd.set_pixel(self.layer, x + dx, y, color);
+                    }
+                }
+            } else {
+                for y in (screen_height - old_height)..(screen_height - height) {
+                    for dx in 0..BAR_WIDTH - 1 {
+                        lcd.set_pixel(self.layer, x + dx, y, 0);
+                    }
+                }
+            }
+            self.bar_heights[bar] = height;
+        }
+    }
+
+    fn draw_spectrogram_column(&mut self, lcd: &mut Lcd) {
+        let (width, height) = (lcd.width(), lcd.height());
+        let x = self.next_col % width;
+        let bins = self.levels.len();
+        for y in 0..height {
+            // low frequencies at the bottom
+            let bin = (usize::from(height - 1 - y) * bins) / usize::from(height);
+            let color = heat_color(level_fraction(self.levels[bin]));
+            lcd.set_pixel(self.layer, x, y, color.to_argb1555());
+        }
+
+        // mark the current position
+        let next = (x + 1) % width;
+        for y in 0..height {
+            lcd.set_pixel(self.layer, next, y, 0xffff);
+        }
+        self.next_col = next;
+    }
+}
+
+/// Maps a level in dB to [0, 1].
+fn level_fraction(level: f32) -> f32 {
+    let fraction = (level - FLOOR_DB) / -FLOOR_DB;
+    if fraction < 0.0 {
+        0.0
+    } else if fraction > 1.0 {
+        1.0
+    } else {
+        fraction
+    }
+}
+
+/// Green at the bottom of the screen, red at the top.
+fn bar_color(y: u16, height: u16) -> Color {
+    let red = 255 - (u32::from(y) * 255 / u32::from(height)) as u8;
+    Color::rgb(red, 255 - red, 0)
+}
+
+/// Black - blue - red - yellow - white color map.
+fn heat_color(fraction: f32) -> Color {
+    let scaled = fraction * 4.0;
+    let step = scaled as u8;
+    let rest = ((scaled - f32::from(step)) * 255.0) as u8;
+    match step {
+        0 => Color::rgb(0, 0, rest),
+        1 => Color::rgb(rest, 0, 255 - rest),
+        2 => Color::rgb(255, rest, 0),
+        3 => Color::rgb(255, 255, rest),
+        _ => Color::rgb(255, 255, 255),
+    }
+}
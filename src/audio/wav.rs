@@ -0,0 +1,469 @@
+//! RIFF/WAV images of PCM audio, recorded from `AudioInput` or played through `AudioOutput`.
+//!
+//! The images are plain byte buffers, e.g. the SDRAM region returned by `sdram_buffer`, so
+//! they can be sent to a host with `EthernetDevice::send_udp_stream`.
+
+use byteorder::{ByteOrder, LittleEndian};
+use core::{cmp, slice};
+use sdram;
+use super::{AudioConfig, AudioInput, AudioOutput};
+
+/// Length of the header written by `WavWriter`.
+pub const HEADER_LEN: usize = 44;
+
+const FORMAT_PCM: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer can't hold the header or the requested samples.
+    BufferTooSmall,
+    /// The image ends inside a chunk.
+    Truncated,
+    NotRiff,
+    NotWave,
+    MissingFormatChunk,
+    MissingDataChunk,
+    /// Only uncompressed PCM is supported.
+    UnsupportedFormat(u16),
+    UnsupportedBitsPerSample(u16),
+    /// The channel count, sample rate or block alignment is zero or inconsistent.
+    InvalidFormat,
+    /// The image does not match the configured sample rate or channel count.
+    FormatMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+impl WavFormat {
+    /// The format of the images written by `record`. The samples are reduced to 16 bits for
+    /// all word lengths.
+    pub fn from_config(config: &AudioConfig) -> WavFormat {
+        WavFormat {
+            sample_rate: config.sample_rate.hz(),
+            channels: 2,
+            bits_per_sample: 16,
+        }
+    }
+
+    /// Number of bytes of a sample of all channels.
+    pub fn block_align(&self) -> u16 {
+        self.channels * ((self.bits_per_sample + 7) / 8)
+    }
+
+    pub fn byte_rate(&self) -> u32 {
+        self.sample_rate * u32::from(self.block_align())
+    }
+
+    /// Number of data bytes for the given duration, rounded down to whole blocks.
+    pub fn data_len(&self, duration_ms: u32) -> usize {
+        let frames = u64::from(self.sample_rate) * u64::from(duration_ms) / 1000;
+        frames as usize * usize::from(self.block_align())
+    }
+}
+
+/// Writes a canonical 44 byte header for `data_len` bytes of samples.
+pub fn write_header(format: &WavFormat, data_len: u32, buffer: &mut [u8]) -> Result<(), Error> {
+    if buffer.len() < HEADER_LEN {
+        return Err(Error::BufferTooSmall);
+    }
+
+    buffer[0..4].copy_from_slice(b"RIFF");
+    LittleEndian::write_u32(&mut buffer[4..8], 36 + data_len);
+    buffer[8..12].copy_from_slice(b"WAVE");
+
+    buffer[12..16].copy_from_slice(b"fmt ");
+    LittleEndian::write_u32(&mut buffer[16..20], 16); // chunk size
+    LittleEndian::write_u16(&mut buffer[20..22], FORMAT_PCM);
+    LittleEndian::write_u16(&mut buffer[22..24], format.channels);
+    LittleEndian::write_u32(&mut buffer[24..28], format.sample_rate);
+    LittleEndian::write_u32(&mut buffer[28..32], format.byte_rate());
+    LittleEndian::write_u16(&mut buffer[32..34], format.block_align());
+    LittleEndian::write_u16(&mut buffer[34..36], format.bits_per_sample);
+
+    buffer[36..40].copy_from_slice(b"data");
+    LittleEndian::write_u32(&mut buffer[40..44], data_len);
+
+    Ok(())
+}
+
+/// Appends 16 bit samples to a WAV image.
+pub struct WavWriter<'a> {
+    buffer: &'a mut [u8],
+    format: WavFormat,
+    data_len: usize,
+}
+
+impl<'a> WavWriter<'a> {
+    pub fn new(buffer: &'a mut [u8], format: WavFormat) -> Result<WavWriter<'a>, Error> {
+        if format.bits_per_sample != 16 {
+            return Err(Error::UnsupportedBitsPerSample(format.bits_per_sample));
+        }
+        if format.channels == 0 || format.sample_rate == 0 {
+            return Err(Error::InvalidFormat);
+        }
+        write_header(&format, 0, buffer)?;
+        Ok(WavWriter {
+               buffer: buffer,
+               format: format,
+               data_len: 0,
+           })
+    }
+
+    /// Appends as many whole frames of `samples` as fit and returns the number of written
+    /// samples.
+    pub fn write_samples(&mut self, samples: &[i16]) -> usize {
+        let channels = usize::from(self.format.channels);
+        let len = cmp::min(samples.len(), self.remaining_samples());
+        let len = len - len % channels;
+
+        let start = HEADER_LEN + self.data_len;
+        for (&sample, bytes) in samples[..len]
+                .iter()
+                .zip(self.buffer[start..].chunks_mut(2)) {
+            LittleEndian::write_i16(bytes, sample);
+        }
+        self.data_len += len * 2;
+        len
+    }
+
+    /// Number of samples that still fit into the buffer.
+    pub fn remaining_samples(&self) -> usize {
+        (self.buffer.len() - HEADER_LEN - self.data_len) / 2
+    }
+
+    /// Number of sample bytes written so far.
+    pub fn data_len(&self) -> usize {
+        self.data_len
+    }
+
+    /// Updates the chunk sizes in the header and returns the complete image.
+    pub fn finish(self) -> &'a [u8] {
+        let total_len = HEADER_LEN + self.data_len;
+        write_header(&self.format, self.data_len as u32, self.buffer)
+            .expect("buffer holds at least the header");
+        &self.buffer[..total_len]
+    }
+}
+
+/// A parsed WAV image.
+#[derive(Debug, Clone, Copy)]
+pub struct Wav<'a> {
+    pub format: WavFormat,
+    /// The content of the data chunk.
+    pub data: &'a [u8],
+}
+
+/// Parses a RIFF/WAV image with uncompressed PCM samples. Unknown chunks are skipped.
+pub fn parse(image: &[u8]) -> Result<Wav, Error> {
+    if image.len() < 12 {
+        return Err(Error::Truncated);
+    }
+    if &image[0..4] != b"RIFF" {
+        return Err(Error::NotRiff);
+    }
+    if &image[8..12] != b"WAVE" {
+        return Err(Error::NotWave);
+    }
+
+    let mut format = None;
+    let mut chunks = &image[12..];
+    while chunks.len() >= 8 {
+        let id = &chunks[0..4];
+        let len = LittleEndian::read_u32(&chunks[4..8]) as usize;
+        let body = &chunks[8..];
+        if body.len() < len {
+            return Err(Error::Truncated);
+        }
+        let body = &body[..len];
+
+        if id == b"fmt " {
+            format = Some(parse_format(body)?);
+        } else if id == b"data" {
+            return match format {
+                       Some(format) => {
+                           Ok(Wav {
+                                  format: format,
+                                  data: body,
+                              })
+                       }
+                       None => Err(Error::MissingFormatChunk),
+                   };
+        }
+
+        // chunks are padded to an even length
+        let next = 8 + len + (len & 1);
+        chunks = if next < chunks.len() { &chunks[next..] } else { &[] };
+    }
+
+    match format {
+        Some(_) => Err(Error::MissingDataChunk),
+        None => Err(Error::MissingFormatChunk),
+    }
+}
+
+fn parse_format(body: &[u8]) -> Result<WavFormat, Error> {
+    if body.len() < 16 {
+        return Err(Error::Truncated);
+    }
+    let format_tag = LittleEndian::read_u16(&body[0..2]);
+    if format_tag != FORMAT_PCM {
+        return Err(Error::UnsupportedFormat(format_tag));
+    }
+    let bits_per_sample = LittleEndian::read_u16(&body[14..16]);
+    if bits_per_sample != 16 {
+        return Err(Error::UnsupportedBitsPerSample(bits_per_sample));
+    }
+    let format = WavFormat {
+        channels: LittleEndian::read_u16(&body[2..4]),
+        sample_rate: LittleEndian::read_u32(&body[4..8]),
+        bits_per_sample: bits_per_sample,
+    };
+    // `Wav::frames` and `Wav::duration_ms` divide by these
+    let block_align = LittleEndian::read_u16(&body[12..14]);
+    if format.channels == 0 || format.sample_rate == 0 || block_align != format.block_align() {
+        return Err(Error::InvalidFormat);
+    }
+    Ok(format)
+}
+
+impl<'a> Wav<'a> {
+    /// Iterates over the samples, all channels interleaved.
+    pub fn samples(&self) -> Samples<'a> {
+        Samples { chunks: self.data.chunks(2) }
+    }
+
+    /// Number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.data.len() / usize::from(self.format.block_align())
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        (self.frames() as u64 * 1000 / u64::from(self.format.sample_rate)) as u32
+    }
+}
+
+pub struct Samples<'a> {
+    chunks: slice::Chunks<'a, u8>,
+}
+
+impl<'a> Iterator for Samples<'a> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        loop {
+            match self.chunks.next() {
+                Some(bytes) if bytes.len() == 2 => return Some(LittleEndian::read_i16(bytes)),
+                Some(_) => {} // incomplete trailing byte
+                None => return None,
+            }
+        }
+    }
+}
+
+/// The free SDRAM from `sdram::FREE_START` to the end (about 4 MB).
+///
+/// This is unsafe because every call returns a mutable reference to the same memory.
+pub unsafe fn sdram_buffer() -> &'static mut [u8] {
+    slice::from_raw_parts_mut(sdram::FREE_START as *mut u8, sdram::FREE_LEN as usize)
+}
+
+/// Records `duration_ms` of microphone input into `buffer` and returns the WAV image.
+///
+/// Samples that were captured before the call are discarded.
+pub fn record<'a>(input: &mut AudioInput,
+                  config: &AudioConfig,
+                  duration_ms: u32,
+                  buffer: &'a mut [u8])
+                  -> Result<&'a [u8], Error> {
+    let format = WavFormat::from_config(config);
+    if buffer.len() < HEADER_LEN + format.data_len(duration_ms) {
+        return Err(Error::BufferTooSmall);
+    }
+    let image_len = HEADER_LEN + format.data_len(duration_ms);
+    let mut writer = WavWriter::new(&mut buffer[..image_len], format)?;
+
+    let mut words = [0; 256];
+    let mut samples = [0; 256];
+    while input.available() > 0 {
+        input.read(&mut words);
+    }
+    while writer.remaining_samples() > 0 {
+        let len = input.read(&mut words);
+        for (sample, &word) in samples.iter_mut().zip(&words[..len]) {
+            *sample = (word >> 16) as i16;
+        }
+        writer.write_samples(&samples[..len]);
+    }
+
+    Ok(writer.finish())
+}
+
+/// Plays a WAV image through the codec. Blocks until all samples are queued.
+///
+/// The image must have the configured sample rate and two channels.
+pub fn play(output: &mut AudioOutput, config: &AudioConfig, image: &[u8]) -> Result<(), Error> {
+    let wav = parse(image)?;
+    let format = WavFormat::from_config(config);
+    if wav.format.sample_rate != format.sample_rate || wav.format.channels != format.channels {
+        return Err(Error::FormatMismatch);
+    }
+
+    let mut samples = wav.samples();
+    let mut buffer = [0; 256];
+    loop {
+        let mut len = 0;
+        for (slot, sample) in buffer.iter_mut().zip(&mut samples) {
+            *slot = i32::from(sample) << 16;
+            len += 1;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        output.write_all(&buffer[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    const STEREO_16K: WavFormat = WavFormat {
+        sample_rate: 16000,
+        channels: 2,
+        bits_per_sample: 16,
+    };
+
+    fn encode(format: WavFormat, samples: &[i16]) -> Vec<u8> {
+        let mut buffer = vec![0; HEADER_LEN + samples.len() * 2];
+        let len = {
+            let mut writer = WavWriter::new(&mut buffer, format).unwrap();
+            assert_eq!(writer.write_samples(samples), samples.len());
+            writer.finish().len()
+        };
+        buffer.truncate(len);
+        buffer
+    }
+
+    /// A format chunk with the given fields.
+    fn format_chunk(channels: u16, sample_rate: u32, block_align: u16) -> Vec<u8> {
+        let mut chunk = vec![0; 24];
+        chunk[0..4].copy_from_slice(b"fmt ");
+        LittleEndian::write_u32(&mut chunk[4..8], 16);
+        LittleEndian::write_u16(&mut chunk[8..10], FORMAT_PCM);
+        LittleEndian::write_u16(&mut chunk[10..12], channels);
+        LittleEndian::write_u32(&mut chunk[12..16], sample_rate);
+        LittleEndian::write_u16(&mut chunk[20..22], block_align);
+        LittleEndian::write_u16(&mut chunk[22..24], 16);
+        chunk
+    }
+
+    fn riff(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut image = Vec::new();
+        image.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        for chunk in chunks {
+            image.extend_from_slice(chunk);
+        }
+        let len = image.len() as u32 - 8;
+        LittleEndian::write_u32(&mut image[4..8], len);
+        image
+    }
+
+    #[test]
+    fn header_layout() {
+        let image = encode(STEREO_16K, &[1, -1, 0x1234, -0x1234]);
+        assert_eq!(image.len(), HEADER_LEN + 8);
+        assert_eq!(&image[0..4], b"RIFF");
+        assert_eq!(LittleEndian::read_u32(&image[4..8]), 36 + 8);
+        assert_eq!(&image[8..16], b"WAVEfmt ");
+        assert_eq!(LittleEndian::read_u16(&image[22..24]), 2);
+        assert_eq!(LittleEndian::read_u32(&image[24..28]), 16000);
+        assert_eq!(LittleEndian::read_u32(&image[28..32]), 64000);
+        assert_eq!(LittleEndian::read_u16(&image[32..34]), 4);
+        assert_eq!(&image[36..40], b"data");
+        assert_eq!(LittleEndian::read_u32(&image[40..44]), 8);
+        assert_eq!(&image[44..48], &[1, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let samples: Vec<i16> = (0..200).map(|i| (i * 331 - 30000) as i16).collect();
+        let image = encode(STEREO_16K, &samples);
+        let wav = parse(&image).unwrap();
+        assert_eq!(wav.format, STEREO_16K);
+        assert_eq!(wav.frames(), 100);
+        assert_eq!(wav.duration_ms(), 6);
+        assert_eq!(wav.samples().collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn writer_stops_at_whole_frames() {
+        let mut buffer = [0; HEADER_LEN + 10];
+        let mut writer = WavWriter::new(&mut buffer, STEREO_16K).unwrap();
+        assert_eq!(writer.write_samples(&[1, 2, 3]), 2);
+        assert_eq!(writer.remaining_samples(), 3);
+        assert_eq!(writer.write_samples(&[4, 5, 6, 7]), 2);
+        assert_eq!(writer.write_samples(&[8, 9]), 0);
+        assert_eq!(writer.data_len(), 8);
+        assert_eq!(writer.finish().len(), HEADER_LEN + 8);
+    }
+
+    #[test]
+    fn writer_rejects_invalid_formats() {
+        let mut buffer = [0; 64];
+        let mono_zero = WavFormat { channels: 0, ..STEREO_16K };
+        assert_eq!(WavWriter::new(&mut buffer, mono_zero).err(), Some(Error::InvalidFormat));
+        let bits_24 = WavFormat { bits_per_sample: 24, ..STEREO_16K };
+        assert_eq!(WavWriter::new(&mut buffer, bits_24).err(),
+                   Some(Error::UnsupportedBitsPerSample(24)));
+        assert_eq!(WavWriter::new(&mut buffer[..40], STEREO_16K).err(),
+                   Some(Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn skips_unknown_and_padded_chunks() {
+        let format = format_chunk(1, 8000, 2);
+        let image = riff(&[&format, b"LIST\x03\0\0\0abc\0", b"data\x04\0\0\0\x01\0\x02\0"]);
+        let wav = parse(&image).unwrap();
+        assert_eq!(wav.format.channels, 1);
+        assert_eq!(wav.samples().collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn rejects_zero_fields() {
+        let data = b"data\x04\0\0\0\x01\0\x02\0";
+        for &(channels, sample_rate, block_align) in
+            [(0, 8000, 0), (1, 0, 2), (1, 8000, 0), (2, 8000, 2)].iter() {
+            let format = format_chunk(channels, sample_rate, block_align);
+            let image = riff(&[&format, data]);
+            assert_eq!(parse(&image).err(), Some(Error::InvalidFormat));
+        }
+    }
+
+    #[test]
+    fn rejects_broken_images() {
+        let image = encode(STEREO_16K, &[1, 2]);
+        assert_eq!(parse(&image[..10]).err(), Some(Error::Truncated));
+        assert_eq!(parse(&image[..HEADER_LEN + 2]).err(), Some(Error::Truncated));
+
+        let mut not_riff = image.clone();
+        not_riff[0] = b'X';
+        assert_eq!(parse(&not_riff).err(), Some(Error::NotRiff));
+        let mut not_wave = image.clone();
+        not_wave[8] = b'X';
+        assert_eq!(parse(&not_wave).err(), Some(Error::NotWave));
+        let mut float = image.clone();
+        float[20] = 3;
+        assert_eq!(parse(&float).err(), Some(Error::UnsupportedFormat(3)));
+
+        assert_eq!(parse(&riff(&[b"data\0\0\0\0"])).err(),
+                   Some(Error::MissingFormatChunk));
+        assert_eq!(parse(&riff(&[&format_chunk(1, 8000, 2)])).err(),
+                   Some(Error::MissingDataChunk));
+    }
+}
@@ -0,0 +1,643 @@
+//! Driver for the WM8994 audio codec.
+//!
+//! All register accesses go through the `RegisterBus` trait, which is implemented for
+//! `i2c::I2C`.
+
+use i2c;
+use system_clock;
+use super::config::{AudioConfig, InputDevice, OutputDevice};
+
+const WM8994_ADDRESS: i2c::Address = i2c::Address::bits_7(0b0011010);
+const WM8994_ID: u16 = 0x8994;
+
+// registers
+const SOFTWARE_RESET: u16 = 0x000;
+const POWER_MANAGEMENT_1: u16 = 0x001;
+const POWER_MANAGEMENT_2: u16 = 0x002;
+const POWER_MANAGEMENT_3: u16 = 0x003;
+const POWER_MANAGEMENT_4: u16 = 0x004;
+const POWER_MANAGEMENT_5: u16 = 0x005;
+const LEFT_OUTPUT_VOLUME: u16 = 0x01C;
+const RIGHT_OUTPUT_VOLUME: u16 = 0x01D;
+const SPEAKER_VOLUME_LEFT: u16 = 0x026;
+const SPEAKER_VOLUME_RIGHT: u16 = 0x027;
+const SPKMIXL_ATTENUATION: u16 = 0x022;
+const SPKMIXR_ATTENUATION: u16 = 0x023;
+const INPUT_MIXER_2: u16 = 0x028;
+const INPUT_MIXER_3: u16 = 0x029;
+const INPUT_MIXER_4: u16 = 0x02A;
+const OUTPUT_MIXER_1: u16 = 0x02D;
+const OUTPUT_MIXER_2: u16 = 0x02E;
+const SPEAKER_MIXER: u16 = 0x036;
+const ANTIPOP_2: u16 = 0x039;
+const CHARGE_PUMP_1: u16 = 0x04C;
+const CLASS_W_1: u16 = 0x051;
+const DC_SERVO_1: u16 = 0x054;
+const ANALOGUE_HP_1: u16 = 0x060;
+const CONTROL_INTERFACE: u16 = 0x102;
+const AIF1_CLOCKING_1: u16 = 0x200;
+const CLOCKING_1: u16 = 0x208;
+const AIF1_RATE: u16 = 0x210;
+const AIF1_CONTROL_1: u16 = 0x300;
+const AIF1_MASTER_SLAVE: u16 = 0x302;
+const AIF1_ADC1_LEFT_VOLUME: u16 = 0x400;
+const AIF1_ADC1_RIGHT_VOLUME: u16 = 0x401;
+const AIF1_ADC2_LEFT_VOLUME: u16 = 0x404;
+const AIF1_ADC2_RIGHT_VOLUME: u16 = 0x405;
+const AIF1_ADC1_FILTERS: u16 = 0x410;
+const AIF1_ADC2_FILTERS: u16 = 0x411;
+const AIF1_DAC1_FILTERS_1: u16 = 0x420;
+const AIF1_DRC1_1: u16 = 0x440;
+const AIF1_DRC2_1: u16 = 0x450;
+const DAC1_LEFT_MIXER_ROUTING: u16 = 0x601;
+const DAC1_RIGHT_MIXER_ROUTING: u16 = 0x602;
+const DAC2_LEFT_MIXER_ROUTING: u16 = 0x604;
+const DAC2_RIGHT_MIXER_ROUTING: u16 = 0x605;
+const AIF1_ADC1_LEFT_MIXER_ROUTING: u16 = 0x606;
+const AIF1_ADC1_RIGHT_MIXER_ROUTING: u16 = 0x607;
+const AIF1_ADC2_LEFT_MIXER_ROUTING: u16 = 0x608;
+const AIF1_ADC2_RIGHT_MIXER_ROUTING: u16 = 0x609;
+const DAC1_LEFT_VOLUME: u16 = 0x610;
+const DAC1_RIGHT_VOLUME: u16 = 0x611;
+const DAC2_LEFT_VOLUME: u16 = 0x612;
+const DAC2_RIGHT_VOLUME: u16 = 0x613;
+const OVERSAMPLING: u16 = 0x620;
+const GPIO_1: u16 = 0x700;
+const WRITE_SEQUENCER_CTRL_3: u16 = 0x817;
+
+// register values
+const VOLUME_UPDATE: u16 = 0x100;
+const DAC_MUTE: u16 = 0x200;
+const DIGITAL_VOLUME_0DB: u16 = 0xC0;
+const ADC_DIGITAL_VOLUME_MAX: u16 = 0xEF; // +17.625dB
+const DRC_DISABLED: u16 = 0x0098; // register default
+const DRC_ENABLED: u16 = 0x00DB; // signal detect, DRC on left and right ADC
+
+#[derive(Debug)]
+pub enum Error {
+    I2c(i2c::Error),
+    /// The device ID register did not contain 0x8994.
+    UnknownDevice(u16),
+}
+
+impl From<i2c::Error> for Error {
+    fn from(err: i2c::Error) -> Error {
+        Error::I2c(err)
+    }
+}
+
+/// Access to the 16 bit registers of the codec.
+pub trait RegisterBus {
+    fn read_register(&mut self, register: u16) -> Result<u16, i2c::Error>;
+    fn write_register(&mut self, register: u16, value: u16) -> Result<(), i2c::Error>;
+
+    /// Waits for the codec, e.g. until the charge pump is up.
+    fn delay(&mut self, ms: usize) {
+        system_clock::wait(ms);
+    }
+}
+
+impl RegisterBus for i2c::I2C {
+    fn read_register(&mut self, register: u16) -> Result<u16, i2c::Error> {
+        let mut value = 0;
+        self.connect::<u16, _>(WM8994_ADDRESS, |mut conn| {
+                value = conn.read(register)?;
+                Ok(())
+            })?;
+        Ok(value)
+    }
+
+    fn write_register(&mut self, register: u16, value: u16) -> Result<(), i2c::Error> {
+        self.connect::<u16, _>(WM8994_ADDRESS, |mut conn| conn.write(register, value))
+    }
+}
+
+/// Cut-off of the ADC high pass filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighPassFilter {
+    Disabled,
+    /// fc = 3.7 Hz at fs = 44.1 kHz
+    HiFi,
+    /// fc = 127 Hz at fs = 8 kHz
+    Voice1,
+    /// fc = 130 Hz at fs = 8 kHz
+    Voice2,
+    /// fc = 267 Hz at fs = 8 kHz
+    Voice3,
+}
+
+impl HighPassFilter {
+    fn register_value(&self) -> u16 {
+        const LEFT_AND_RIGHT_ENABLE: u16 = 0x1800;
+        match *self {
+            HighPassFilter::Disabled => 0x0000,
+            HighPassFilter::HiFi => LEFT_AND_RIGHT_ENABLE | 0b00 << 13,
+            HighPassFilter::Voice1 => LEFT_AND_RIGHT_ENABLE | 0b01 << 13,
+            HighPassFilter::Voice2 => LEFT_AND_RIGHT_ENABLE | 0b10 << 13,
+            HighPassFilter::Voice3 => LEFT_AND_RIGHT_ENABLE | 0b11 << 13,
+        }
+    }
+}
+
+/// Converts a digital volume in dB (-71.625 to +17.625 in 0.375 dB steps) to a register value.
+///
+/// Values below -71.625 dB mute the path.
+pub fn digital_volume(db: f32, max: u16) -> u16 {
+    let steps = (db + 72.0) / 0.375 + 0.5;
+    if steps < 1.0 {
+        0
+    } else if steps >= f32::from(max) {
+        max
+    } else {
+        steps as u16
+    }
+}
+
+/// Converts a volume in percent to the headphone/speaker PGA value (0 to 63 in 1 dB steps).
+pub fn output_volume(percent: u8) -> u16 {
+    let volume = u16::from(percent) * 63 / 100;
+    if volume > 0x3F { 0x3F } else { volume }
+}
+
+pub struct Wm8994 {
+    input_device: InputDevice,
+    output_device: OutputDevice,
+    adc_volume: u16,
+}
+
+impl Wm8994 {
+    /// Resets the codec and configures the input and output paths of `config`.
+    pub fn init<B: RegisterBus>(bus: &mut B, config: &AudioConfig) -> Result<Wm8994, Error> {
+        // read and check device family ID
+        let id = bus.read_register(SOFTWARE_RESET)?;
+        if id != WM8994_ID {
+            return Err(Error::UnknownDevice(id));
+        }
+        // reset device
+        bus.write_register(SOFTWARE_RESET, 0)?;
+
+        let mut codec = Wm8994 {
+            input_device: config.input_device,
+            output_device: config.output_device,
+            adc_volume: digital_volume(17.625, ADC_DIGITAL_VOLUME_MAX),
+        };
+        let input = config.input_device;
+        let output = config.output_device;
+
+        // wm8994 Errata Work-Arounds
+        bus.write_register(CONTROL_INTERFACE, 0x0003)?;
+        bus.write_register(WRITE_SEQUENCER_CTRL_3, 0x0000)?;
+        bus.write_register(CONTROL_INTERFACE, 0x0000)?;
+
+        // Enable VMID soft start (fast), Start-up Bias Current Enabled
+        bus.write_register(ANTIPOP_2, 0x006C)?;
+
+        // Enable bias generator, Enable VMID
+        bus.write_register(POWER_MANAGEMENT_1, 0x0003)?;
+
+        bus.delay(50);
+
+        // output paths
+
+        // Enable AIF1DAC1 (Left), Enable AIF1DAC1 (Right)
+        // Enable DAC1 (Left), Enable DAC1 (Right) for the headphone
+        // Enable DAC2 (Left), Enable DAC2 (Right) for the speaker
+        let mut dac_enable = 0;
+        if output != OutputDevice::None {
+            dac_enable |= 0x0300;
+        }
+        if output.headphone() {
+            dac_enable |= 0x0003;
+        }
+        if output.speaker() {
+            dac_enable |= 0x000C;
+        }
+        bus.write_register(POWER_MANAGEMENT_5, dac_enable)?;
+
+        // AIF1 Timeslot 0 (Left/Right) to DAC 1 (Left/Right) mixer path
+        let dac1_mixer = if output.headphone() { 0x0001 } else { 0x0000 };
+        bus.write_register(DAC1_LEFT_MIXER_ROUTING, dac1_mixer)?;
+        bus.write_register(DAC1_RIGHT_MIXER_ROUTING, dac1_mixer)?;
+
+        // AIF1 Timeslot 0 (Left/Right) to DAC 2 (Left/Right) mixer path
+        let dac2_mixer = if output.speaker() { 0x0001 } else { 0x0000 };
+        bus.write_register(DAC2_LEFT_MIXER_ROUTING, dac2_mixer)?;
+        bus.write_register(DAC2_RIGHT_MIXER_ROUTING, dac2_mixer)?;
+
+        // input paths
+
+        match input {
+            InputDevice::None => {}
+            InputDevice::DigitalMic1 => {
+                // Enable AIF1ADC1 (Left), Enable AIF1ADC1 (Right)
+                // Enable DMICDAT1 (Left), Enable DMICDAT1 (Right)
+                // Enable Left ADC, Enable Right ADC
+                bus.write_register(POWER_MANAGEMENT_4, 0x030C)?;
+
+                // Disable IN1L, IN1R, IN2L, IN2R, Enable Thermal sensor & shutdown
+                bus.write_register(POWER_MANAGEMENT_2, 0x6000)?;
+
+                // Enable the DMIC1 (Left/Right) to AIF1 Timeslot 0 (Left/Right) mixer path
+                bus.write_register(AIF1_ADC1_LEFT_MIXER_ROUTING, 0x0002)?;
+                bus.write_register(AIF1_ADC1_RIGHT_MIXER_ROUTING, 0x0002)?;
+
+                // GPIO1 pin configuration GP1_DIR = output, GP1_FN = AIF1 DRC1 signal detect
+                bus.write_register(GPIO_1, 0x000D)?;
+            }
+            InputDevice::DigitalMic2 => {
+                // Enable AIF1ADC2 (Left), Enable AIF1ADC2 (Right)
+                // Enable DMICDAT2 (Left), Enable DMICDAT2 (Right)
+                // Enable Left ADC, Enable Right ADC
+                bus.write_register(POWER_MANAGEMENT_4, 0x0C30)?;
+
+                // Disable IN1L, IN1R, IN2L, IN2R, Enable Thermal sensor & shutdown
+                bus.write_register(POWER_MANAGEMENT_2, 0x6000)?;
+
+                // Enable the DMIC2 (Left/Right) to AIF1 Timeslot 1 (Left/Right) mixer path
+                bus.write_register(AIF1_ADC2_LEFT_MIXER_ROUTING, 0x0002)?;
+                bus.write_register(AIF1_ADC2_RIGHT_MIXER_ROUTING, 0x0002)?;
+
+                // GPIO1 pin configuration GP1_DIR = output, GP1_FN = AIF1 DRC2 signal detect
+                bus.write_register(GPIO_1, 0x000E)?;
+            }
+            InputDevice::LineIn => {
+                // IN1LN to IN1L, IN1RN to IN1R
+                bus.write_register(INPUT_MIXER_2, 0x0011)?;
+
+                // IN1L (Right: IN1R) to MIXINL (MIXINR), IN1L PGA output 0dB
+                bus.write_register(INPUT_MIXER_3, 0x0035)?;
+                bus.write_register(INPUT_MIXER_4, 0x0035)?;
+
+                // Enable AIF1ADC1 (Left), Enable AIF1ADC1 (Right)
+                // Enable Left ADC, Enable Right ADC
+                bus.write_register(POWER_MANAGEMENT_4, 0x0303)?;
+
+                // Enable IN1L, IN1R, MIXINL, MIXINR, Enable Thermal sensor & shutdown
+                bus.write_register(POWER_MANAGEMENT_2, 0x6350)?;
+
+                // Enable the ADC (Left/Right) to AIF1 Timeslot 0 (Left/Right) mixer path
+                bus.write_register(AIF1_ADC1_LEFT_MIXER_ROUTING, 0x0002)?;
+                bus.write_register(AIF1_ADC1_RIGHT_MIXER_ROUTING, 0x0002)?;
+
+                // GPIO1 pin configuration GP1_DIR = output, GP1_FN = AIF1 DRC1 signal detect
+                bus.write_register(GPIO_1, 0x000D)?;
+            }
+        }
+        codec.set_drc(bus, true)?;
+
+        // clock configuration
+
+        // AIF1 Sample Rate, ratio=256
+        bus.write_register(AIF1_RATE, config.sample_rate.wm8994_aif1_rate())?;
+
+        // AIF1 Word Length, AIF1 Format = I2S
+        bus.write_register(AIF1_CONTROL_1, config.word_length.wm8994_aif1_control())?;
+
+        // slave mode
+        bus.write_register(AIF1_MASTER_SLAVE, 0x0000)?;
+
+        // Enable the DSP processing clock for AIF1, Enable the core clock
+        bus.write_register(CLOCKING_1, 0x000A)?;
+
+        // Enable AIF1 Clock, AIF1 Clock Source = MCLK1 pin
+        bus.write_register(AIF1_CLOCKING_1, 0x0001)?;
+
+        // power up
+
+        // Enable bias generator, Enable VMID
+        // Enable Microphone bias 1 generator for the digital microphones
+        // Enable HPOUT1 (Left) and HPOUT1 (Right) input stages
+        // Enable SPKOUTL, Enable SPKOUTR
+        let mut power_management_1 = 0x0003;
+        match input {
+            InputDevice::DigitalMic1 | InputDevice::DigitalMic2 => power_management_1 |= 0x0010,
+            InputDevice::LineIn | InputDevice::None => {}
+        }
+        if output.headphone() {
+            power_management_1 |= 0x0300;
+        }
+        if output.speaker() {
+            power_management_1 |= 0x3000;
+        }
+
+        if output.speaker() {
+            // Enable SPKRVOL PGA, Enable SPKLVOL PGA
+            bus.write_register(POWER_MANAGEMENT_3, 0x0300)?;
+
+            // Left/Right Speaker Mixer Volume = 0dB, Speaker output mode = Class D
+            bus.write_register(SPKMIXL_ATTENUATION, 0x0000)?;
+            bus.write_register(SPKMIXR_ATTENUATION, 0x0000)?;
+
+            // Unmute DAC2 (Left/Right) to Left/Right Speaker Mixer (SPKMIXL/R) path
+            bus.write_register(SPEAKER_MIXER, 0x0300)?;
+        }
+
+        bus.write_register(POWER_MANAGEMENT_1, power_management_1)?;
+
+        if output.headphone() {
+            // Enable Class W, Class W Envelope Tracking = AIF1 Timeslot 0
+            bus.write_register(CLASS_W_1, 0x0005)?;
+
+            // Enable HPOUT1 (Left) and HPOUT1 (Right) intermediate stages
+            bus.write_register(ANALOGUE_HP_1, 0x0022)?;
+
+            // Enable Charge Pump
+            bus.write_register(CHARGE_PUMP_1, 0x9F25)?;
+
+            bus.delay(15);
+
+            // Select DAC1 (Left/Right) to Left/Right Headphone Output PGA (HPOUT1L/RVOL) path
+            bus.write_register(OUTPUT_MIXER_1, 0x0001)?;
+            bus.write_register(OUTPUT_MIXER_2, 0x0001)?;
+
+            // Enable Left Output Mixer (MIXOUTL), Enable Right Output Mixer (MIXOUTR)
+            // (and SPKRVOL PGA, SPKLVOL PGA for the speaker)
+            let output_mixers = if output.speaker() { 0x0330 } else { 0x0030 };
+            bus.write_register(POWER_MANAGEMENT_3, output_mixers)?;
+
+            // Enable DC Servo and trigger start-up mode on left and right channels
+            bus.write_register(DC_SERVO_1, 0x0033)?;
+
+            bus.delay(257);
+
+            // Enable HPOUT1 (Left) and HPOUT1 (Right) intermediate and output stages.
+            // Remove clamps
+            bus.write_register(ANALOGUE_HP_1, 0x00EE)?;
+        }
+
+        if output != OutputDevice::None {
+            codec.set_dac_volume(bus, 0.0)?;
+            codec.set_output_mute(bus, false)?;
+        }
+
+        if input != InputDevice::None {
+            // ADC oversample enable
+            bus.write_register(OVERSAMPLING, 0x0002)?;
+
+            codec.set_high_pass_filter(bus, HighPassFilter::Voice1)?;
+            let adc_volume = codec.adc_volume;
+            codec.write_adc_volume(bus, adc_volume)?;
+        }
+
+        codec.set_output_volume(bus, config.output_volume)?;
+
+        Ok(codec)
+    }
+
+    /// Sets the digital volume of the ADC path (-71.625 dB to +17.625 dB) and unmutes it.
+    pub fn set_adc_volume<B: RegisterBus>(&mut self, bus: &mut B, db: f32) -> Result<(), Error> {
+        self.adc_volume = digital_volume(db, ADC_DIGITAL_VOLUME_MAX);
+        let adc_volume = self.adc_volume;
+        self.write_adc_volume(bus, adc_volume)
+    }
+
+    /// Mutes the ADC path by setting its digital volume to zero.
+    pub fn set_input_mute<B: RegisterBus>(&mut self, bus: &mut B, mute: bool) -> Result<(), Error> {
+        let volume = if mute { 0 } else { self.adc_volume };
+        self.write_adc_volume(bus, volume)
+    }
+
+    fn write_adc_volume<B: RegisterBus>(&mut self, bus: &mut B, volume: u16) -> Result<(), Error> {
+        let (left, right) = match self.input_device {
+            InputDevice::None => return Ok(()),
+            InputDevice::DigitalMic1 |
+            InputDevice::LineIn => (AIF1_ADC1_LEFT_VOLUME, AIF1_ADC1_RIGHT_VOLUME),
+            InputDevice::DigitalMic2 => (AIF1_ADC2_LEFT_VOLUME, AIF1_ADC2_RIGHT_VOLUME),
+        };
+        bus.write_register(left, volume | VOLUME_UPDATE)?;
+        bus.write_register(right, volume | VOLUME_UPDATE)?;
+        Ok(())
+    }
+
+    /// Sets the digital volume of the DACs (-71.625 dB to 0 dB).
+    pub fn set_dac_volume<B: RegisterBus>(&mut self, bus: &mut B, db: f32) -> Result<(), Error> {
+        let volume = digital_volume(db, DIGITAL_VOLUME_0DB) | VOLUME_UPDATE;
+        if self.output_device.headphone() {
+            bus.write_register(DAC1_LEFT_VOLUME, volume)?;
+            bus.write_register(DAC1_RIGHT_VOLUME, volume)?;
+        }
+        if self.output_device.speaker() {
+            bus.write_register(DAC2_LEFT_VOLUME, volume)?;
+            bus.write_register(DAC2_RIGHT_VOLUME, volume)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the headphone and/or speaker volume in percent.
+    pub fn set_output_volume<B: RegisterBus>(&mut self,
+                                             bus: &mut B,
+                                             percent: u8)
+                                             -> Result<(), Error> {
+        // 0x40 = unmute, 0x100 = volume update
+        let volume = output_volume(percent) | 0x40 | VOLUME_UPDATE;
+        if self.output_device.headphone() {
+            bus.write_register(LEFT_OUTPUT_VOLUME, volume)?;
+            bus.write_register(RIGHT_OUTPUT_VOLUME, volume)?;
+        }
+        if self.output_device.speaker() {
+            bus.write_register(SPEAKER_VOLUME_LEFT, volume)?;
+            bus.write_register(SPEAKER_VOLUME_RIGHT, volume)?;
+        }
+        Ok(())
+    }
+
+    /// Soft mutes or unmutes the AIF1 Timeslot 0 DAC path.
+    pub fn set_output_mute<B: RegisterBus>(&mut self,
+                                           bus: &mut B,
+                                           mute: bool)
+                                           -> Result<(), Error> {
+        bus.write_register(AIF1_DAC1_FILTERS_1, if mute { DAC_MUTE } else { 0x0000 })?;
+        Ok(())
+    }
+
+    pub fn set_high_pass_filter<B: RegisterBus>(&mut self,
+                                                bus: &mut B,
+                                                filter: HighPassFilter)
+                                                -> Result<(), Error> {
+        let register = match self.input_device {
+            InputDevice::None => return Ok(()),
+            InputDevice::DigitalMic1 | InputDevice::LineIn => AIF1_ADC1_FILTERS,
+            InputDevice::DigitalMic2 => AIF1_ADC2_FILTERS,
+        };
+        bus.write_register(register, filter.register_value())?;
+        Ok(())
+    }
+
+    /// Enables or disables the dynamic range controller of the ADC path.
+    pub fn set_drc<B: RegisterBus>(&mut self, bus: &mut B, enabled: bool) -> Result<(), Error> {
+        let register = match self.input_device {
+            InputDevice::None => return Ok(()),
+            InputDevice::DigitalMic1 | InputDevice::LineIn => AIF1_DRC1_1,
+            InputDevice::DigitalMic2 => AIF1_DRC2_1,
+        };
+        bus.write_register(register, if enabled { DRC_ENABLED } else { DRC_DISABLED })?;
+        Ok(())
+    }
+
+    /// Mutes the outputs, disables all paths and resets the codec.
+    pub fn power_down<B: RegisterBus>(self, bus: &mut B) -> Result<(), Error> {
+        // Mute the AIF1 Timeslot 0 DAC path
+        bus.write_register(AIF1_DAC1_FILTERS_1, DAC_MUTE)?;
+
+        // Disable DAC1 (Left/Right) to Headphone Output PGA path
+        bus.write_register(OUTPUT_MIXER_1, 0x0000)?;
+        bus.write_register(OUTPUT_MIXER_2, 0x0000)?;
+
+        // Disable DAC1 and DAC2, Disable AIF1DAC1
+        bus.write_register(POWER_MANAGEMENT_5, 0x0000)?;
+
+        // reset device
+        bus.write_register(SOFTWARE_RESET, 0x0000)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::{BTreeMap, Vec};
+    use audio::config::{AudioConfig, InputDevice, OutputDevice};
+
+    /// Records the register writes instead of sending them to a codec.
+    struct FakeBus {
+        registers: BTreeMap<u16, u16>,
+        writes: Vec<(u16, u16)>,
+    }
+
+    impl FakeBus {
+        fn new(id: u16) -> FakeBus {
+            let mut registers = BTreeMap::new();
+            registers.insert(SOFTWARE_RESET, id);
+            FakeBus {
+                registers: registers,
+                writes: Vec::new(),
+            }
+        }
+
+        fn last_write(&self, register: u16) -> Option<u16> {
+            self.writes.iter().rev().find(|w| w.0 == register).map(|w| w.1)
+        }
+
+        fn written(&self, register: u16) -> bool {
+            self.last_write(register).is_some()
+        }
+    }
+
+    impl RegisterBus for FakeBus {
+        fn read_register(&mut self, register: u16) -> Result<u16, i2c::Error> {
+            Ok(self.registers.get(&register).cloned().unwrap_or(0))
+        }
+
+        fn write_register(&mut self, register: u16, value: u16) -> Result<(), i2c::Error> {
+            self.writes.push((register, value));
+            Ok(())
+        }
+
+        fn delay(&mut self, _ms: usize) {}
+    }
+
+    fn init(input: InputDevice, output: OutputDevice) -> (Wm8994, FakeBus) {
+        let mut bus = FakeBus::new(WM8994_ID);
+        let config = AudioConfig {
+            input_device: input,
+            output_device: output,
+            ..AudioConfig::default()
+        };
+        let codec = Wm8994::init(&mut bus, &config).unwrap();
+        (codec, bus)
+    }
+
+    #[test]
+    fn unknown_device() {
+        let mut bus = FakeBus::new(0x1234);
+        match Wm8994::init(&mut bus, &AudioConfig::default()) {
+            Err(Error::UnknownDevice(0x1234)) => {}
+            _ => panic!("expected UnknownDevice"),
+        }
+        assert!(bus.writes.is_empty());
+    }
+
+    #[test]
+    fn reset_first() {
+        let (_, bus) = init(InputDevice::DigitalMic2, OutputDevice::Headphone);
+        assert_eq!(bus.writes[0], (SOFTWARE_RESET, 0));
+    }
+
+    #[test]
+    fn input_timeslot_matches_sai_slots() {
+        // AIF1 ADC timeslot 0 is sent in SAI slots 0 and 2, timeslot 1 in slots 1 and 3
+        let devices = [(InputDevice::DigitalMic1, AIF1_ADC1_LEFT_MIXER_ROUTING, 0b0101),
+                       (InputDevice::LineIn, AIF1_ADC1_LEFT_MIXER_ROUTING, 0b0101),
+                       (InputDevice::DigitalMic2, AIF1_ADC2_LEFT_MIXER_ROUTING, 0b1010)];
+        for &(device, routing, slots) in devices.iter() {
+            let (_, bus) = init(device, OutputDevice::None);
+            assert_eq!(bus.last_write(routing), Some(0x0002), "{:?}", device);
+            let config = AudioConfig {
+                input_device: device,
+                ..AudioConfig::default()
+            };
+            assert_eq!(config.input_slots(), slots, "{:?}", device);
+        }
+    }
+
+    #[test]
+    fn no_input() {
+        let (mut codec, mut bus) = init(InputDevice::None, OutputDevice::Headphone);
+        assert!(!bus.written(POWER_MANAGEMENT_4));
+        assert!(!bus.written(OVERSAMPLING));
+        bus.writes.clear();
+        codec.set_adc_volume(&mut bus, 0.0).unwrap();
+        codec.set_drc(&mut bus, false).unwrap();
+        assert!(bus.writes.is_empty());
+    }
+
+    #[test]
+    fn output_volume_per_device() {
+        let (mut codec, mut bus) = init(InputDevice::DigitalMic2, OutputDevice::Headphone);
+        bus.writes.clear();
+        codec.set_output_volume(&mut bus, 100).unwrap();
+        assert_eq!(bus.last_write(LEFT_OUTPUT_VOLUME), Some(0x3F | 0x40 | VOLUME_UPDATE));
+        assert_eq!(bus.last_write(RIGHT_OUTPUT_VOLUME), Some(0x3F | 0x40 | VOLUME_UPDATE));
+        assert!(!bus.written(SPEAKER_VOLUME_LEFT));
+
+        let (mut codec, mut bus) = init(InputDevice::DigitalMic2, OutputDevice::Speaker);
+        bus.writes.clear();
+        codec.set_output_volume(&mut bus, 0).unwrap();
+        assert_eq!(bus.last_write(SPEAKER_VOLUME_LEFT), Some(0x40 | VOLUME_UPDATE));
+        assert!(!bus.written(LEFT_OUTPUT_VOLUME));
+    }
+
+    #[test]
+    fn input_mute_restores_volume() {
+        let (mut codec, mut bus) = init(InputDevice::DigitalMic1, OutputDevice::None);
+        codec.set_adc_volume(&mut bus, 0.0).unwrap();
+        codec.set_input_mute(&mut bus, true).unwrap();
+        assert_eq!(bus.last_write(AIF1_ADC1_LEFT_VOLUME), Some(VOLUME_UPDATE));
+        codec.set_input_mute(&mut bus, false).unwrap();
+        assert_eq!(bus.last_write(AIF1_ADC1_LEFT_VOLUME),
+                   Some(DIGITAL_VOLUME_0DB | VOLUME_UPDATE));
+        assert!(!bus.written(AIF1_ADC2_LEFT_VOLUME));
+    }
+
+    #[test]
+    fn high_pass_filter_and_drc_registers() {
+        let (mut codec, mut bus) = init(InputDevice::DigitalMic2, OutputDevice::None);
+        codec.set_high_pass_filter(&mut bus, HighPassFilter::Disabled).unwrap();
+        assert_eq!(bus.last_write(AIF1_ADC2_FILTERS), Some(0));
+        codec.set_drc(&mut bus, false).unwrap();
+        assert_eq!(bus.last_write(AIF1_DRC2_1), Some(DRC_DISABLED));
+        assert!(!bus.written(AIF1_DRC1_1));
+    }
+
+    #[test]
+    fn volume_conversions() {
+        assert_eq!(digital_volume(0.0, DIGITAL_VOLUME_0DB), DIGITAL_VOLUME_0DB);
+        assert_eq!(digital_volume(17.625, ADC_DIGITAL_VOLUME_MAX), ADC_DIGITAL_VOLUME_MAX);
+        assert_eq!(digital_volume(30.0, ADC_DIGITAL_VOLUME_MAX), ADC_DIGITAL_VOLUME_MAX);
+        assert_eq!(digital_volume(-71.625, DIGITAL_VOLUME_0DB), 1);
+        assert_eq!(digital_volume(-100.0, DIGITAL_VOLUME_0DB), 0);
+        assert_eq!(output_volume(0), 0);
+        assert_eq!(output_volume(100), 63);
+        assert_eq!(output_volume(255), 63);
+    }
+}
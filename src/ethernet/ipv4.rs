@@ -0,0 +1,75 @@
+//! Ethernet and IPv4 headers and the internet checksum for the frames that are encoded here
+//! instead of in the `net` crate (ICMP echo requests and UDP datagrams).
+
+use collections::Vec;
+use byteorder::{ByteOrder, BigEndian};
+use net::ethernet::EthernetAddress;
+use net::ipv4::Ipv4Address;
+use super::ETH_ADDR;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const IP_PROTOCOL_ICMP: u8 = 1;
+pub const IP_PROTOCOL_UDP: u8 = 17;
+
+pub const ETHERNET_HEADER_LEN: usize = 14;
+pub const IPV4_HEADER_LEN: usize = 20;
+
+/// The header fields of an outgoing IPv4 packet and its ethernet frame.
+pub struct Ipv4Header {
+    pub dst_mac: EthernetAddress,
+    pub src_ip: Ipv4Address,
+    pub dst_ip: Ipv4Address,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub identification: u16,
+    pub dont_fragment: bool,
+}
+
+impl Ipv4Header {
+    /// Allocates a frame with room for `payload_len` bytes after the IP header and writes the
+    /// ethernet and IP headers.
+    pub fn to_frame(&self, payload_len: usize) -> Vec<u8> {
+        let ip_len = IPV4_HEADER_LEN + payload_len;
+        let mut frame = vec![0; ETHERNET_HEADER_LEN + ip_len];
+
+        {
+            let (ethernet, ip) = frame.split_at_mut(ETHERNET_HEADER_LEN);
+            ethernet[0..6].copy_from_slice(self.dst_mac.as_bytes());
+            ethernet[6..12].copy_from_slice(ETH_ADDR.as_bytes());
+            BigEndian::write_u16(&mut ethernet[12..14], ETHERTYPE_IPV4);
+
+            let ip_header = &mut ip[..IPV4_HEADER_LEN];
+            ip_header[0] = 0x45; // version 4, header length 5 words
+            BigEndian::write_u16(&mut ip_header[2..4], ip_len as u16);
+            BigEndian::write_u16(&mut ip_header[4..6], self.identification);
+            if self.dont_fragment {
+                ip_header[6] = 0x40;
+            }
+            ip_header[8] = self.ttl;
+            ip_header[9] = self.protocol;
+            ip_header[12..16].copy_from_slice(self.src_ip.as_bytes());
+            ip_header[16..20].copy_from_slice(self.dst_ip.as_bytes());
+            let ip_checksum = checksum(ip_header);
+            BigEndian::write_u16(&mut ip_header[10..12], ip_checksum);
+        }
+
+        frame
+    }
+}
+
+/// Internet checksum (RFC 1071).
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            BigEndian::read_u16(chunk)
+        } else {
+            u16::from(chunk[0]) << 8
+        };
+        sum += u32::from(word);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -10,10 +10,16 @@ use net::ipv4::Ipv4Address;
 use net::ethernet::{EthernetAddress, EthernetPacket};
 use net::{self, TxPacket};
 
+pub use self::ping::{PingConfig, PingStatistics, PingReport, PingResult, Hop, IcmpError};
+pub use self::udp::MAX_UDP_PAYLOAD;
+
 mod init;
 mod phy;
 mod rx;
 mod tx;
+mod ipv4;
+mod ping;
+mod udp;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
@@ -21,6 +27,8 @@ pub enum Error {
     Checksum,
     Truncated,
     NoIp,
+    Timeout,
+    PayloadTooLarge,
     Unknown,
     Parsing(net::ParseError),
     Initialization(init::Error),
@@ -55,6 +63,8 @@ pub struct EthernetDevice {
     requested_ipv4_addr: Option<Ipv4Address>,
     last_discover_at: usize,
     arp_cache: BTreeMap<Ipv4Address, EthernetAddress>,
+    ping_state: ping::PingState,
+    udp_identification: u16,
 }
 
 impl EthernetDevice {
@@ -98,6 +108,8 @@ impl EthernetDevice {
             requested_ipv4_addr: None,
             last_discover_at: 0,
             arp_cache: BTreeMap::new(),
+            ping_state: ping::PingState::new(),
+            udp_identification: 0,
         };
 
         device.send_dhcp_discover()?;
@@ -169,6 +181,7 @@ impl EthernetDevice {
                      ref mut ipv4_addr,
                      ref mut requested_ipv4_addr,
                      ref mut arp_cache,
+                     ref mut ping_state,
                      ..
                  } = self;
 
@@ -181,6 +194,12 @@ impl EthernetDevice {
                 use net::dhcp::{self, DhcpPacket, DhcpType};
                 use net::icmp::IcmpType;
 
+                // destination unreachable or time exceeded for one of our echo requests
+                if let Some(message) = ping::parse_icmp_error(data) {
+                    ping_state.record_error(&message);
+                    return Ok(None);
+                }
+
                 let EthernetPacket { header: _, payload } = net::parse(data)?;
 
                 match payload {
@@ -260,9 +279,13 @@ impl EthernetDevice {
                                 id,
                                 sequence_number,
                             } => {
-                                println!("icmp echo reply {{id: {}, sequence_number: {}}}",
-                                         id,
-                                         sequence_number);
+                                if !ping_state.record_reply(ip_header.src_addr,
+                                                            id,
+                                                            sequence_number) {
+                                    println!("icmp echo reply {{id: {}, sequence_number: {}}}",
+                                             id,
+                                             sequence_number);
+                                }
                             }
                         }
                     }
@@ -0,0 +1,503 @@
+//! ICMP echo client and traceroute-style diagnostics.
+//!
+//! The `net` crate only knows echo requests and replies, so the echo requests and the
+//! destination unreachable / time exceeded messages are encoded and decoded here.
+
+use collections::Vec;
+use byteorder::{ByteOrder, BigEndian};
+use net::arp;
+use net::ethernet::EthernetAddress;
+use net::ipv4::Ipv4Address;
+use net::TxPacket;
+use system_clock;
+use super::{EthernetDevice, Error, ETH_ADDR};
+use super::ipv4::{checksum, Ipv4Header, ETHERTYPE_IPV4, ETHERNET_HEADER_LEN, IPV4_HEADER_LEN,
+                  IP_PROTOCOL_ICMP};
+
+const ICMP_DESTINATION_UNREACHABLE: u8 = 3;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+
+const ICMP_HEADER_LEN: usize = 8;
+
+pub struct PingConfig {
+    /// Number of echo requests to send.
+    pub count: u16,
+    /// Time to wait for a reply to each request in milliseconds.
+    pub timeout: usize,
+    /// Time between two requests in milliseconds.
+    pub interval: usize,
+    /// Time to live of the sent requests.
+    pub ttl: u8,
+    /// Number of payload bytes after the ICMP header.
+    pub payload_size: usize,
+    /// Next hop (e.g. the gateway) whose MAC address is used instead of the destination's.
+    pub via: Option<Ipv4Address>,
+}
+
+impl Default for PingConfig {
+    fn default() -> PingConfig {
+        PingConfig {
+            count: 4,
+            timeout: 1000,
+            interval: 1000,
+            ttl: 64,
+            payload_size: 32,
+            via: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpError {
+    DestinationUnreachable { code: u8 },
+    TimeExceeded { code: u8 },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PingStatistics {
+    pub transmitted: u16,
+    pub received: u16,
+    /// Number of requests that were answered with an ICMP error message.
+    pub errors: u16,
+    /// Round trip times in milliseconds (only valid if `received > 0`).
+    pub min_rtt: usize,
+    pub max_rtt: usize,
+    total_rtt: usize,
+}
+
+impl PingStatistics {
+    pub fn avg_rtt(&self) -> Option<usize> {
+        if self.received == 0 {
+            None
+        } else {
+            Some(self.total_rtt / usize::from(self.received))
+        }
+    }
+
+    pub fn loss_percent(&self) -> u8 {
+        if self.transmitted == 0 {
+            return 0;
+        }
+        let lost = u32::from(self.transmitted - self.received);
+        (lost * 100 / u32::from(self.transmitted)) as u8
+    }
+
+    fn record_rtt(&mut self, rtt: usize) {
+        if self.received == 0 || rtt < self.min_rtt {
+            self.min_rtt = rtt;
+        }
+        if rtt > self.max_rtt {
+            self.max_rtt = rtt;
+        }
+        self.total_rtt += rtt;
+        self.received += 1;
+    }
+}
+
+/// The outcome of a single echo request.
+#[derive(Debug, Clone, Copy)]
+pub enum PingResult {
+    Reply { from: Ipv4Address, rtt: usize },
+    Error { from: Ipv4Address, error: IcmpError },
+    Timeout,
+}
+
+/// The result of `EthernetDevice::ping`.
+#[derive(Debug)]
+pub struct PingReport {
+    pub statistics: PingStatistics,
+    /// The outcome of each request, indexed by sequence number.
+    pub results: Vec<PingResult>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hop {
+    pub ttl: u8,
+    /// Address of the router (or the destination) that answered, `None` on timeout.
+    pub address: Option<Ipv4Address>,
+    pub rtt: Option<usize>,
+    pub error: Option<IcmpError>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    Reply {
+        from: Ipv4Address,
+        sequence_number: u16,
+        received_at: usize,
+    },
+    Error {
+        from: Ipv4Address,
+        error: IcmpError,
+        sequence_number: u16,
+        received_at: usize,
+    },
+}
+
+impl Event {
+    fn sequence_number(&self) -> u16 {
+        match *self {
+            Event::Reply { sequence_number, .. } |
+            Event::Error { sequence_number, .. } => sequence_number,
+        }
+    }
+}
+
+/// Matches incoming ICMP messages against the request of the currently running ping that
+/// is waiting for an answer. Late answers to earlier requests are dropped.
+pub struct PingState {
+    id: Option<u16>,
+    next_id: u16,
+    destination: Option<Ipv4Address>,
+    sequence_number: Option<u16>,
+    events: Vec<Event>,
+}
+
+impl PingState {
+    pub fn new() -> PingState {
+        PingState {
+            id: None,
+            next_id: 0x5354, // "ST"
+            destination: None,
+            sequence_number: None,
+            events: Vec::new(),
+        }
+    }
+
+    fn start(&mut self, destination: Ipv4Address) -> u16 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.id = Some(id);
+        self.destination = Some(destination);
+        self.sequence_number = None;
+        self.events.clear();
+        id
+    }
+
+    /// Accepts only answers to the request with `sequence_number` from now on.
+    fn expect(&mut self, sequence_number: u16) {
+        self.sequence_number = Some(sequence_number);
+        self.events.clear();
+    }
+
+    fn stop(&mut self) {
+        self.id = None;
+        self.destination = None;
+        self.sequence_number = None;
+        self.events.clear();
+    }
+
+    pub fn record_reply(&mut self, from: Ipv4Address, id: u16, sequence_number: u16) -> bool {
+        if self.id != Some(id) {
+            return false;
+        }
+        if self.sequence_number != Some(sequence_number) {
+            // a late reply to an earlier request, which was already reported as timed out
+            return true;
+        }
+        self.events.push(Event::Reply {
+                             from: from,
+                             sequence_number: sequence_number,
+                             received_at: system_clock::ticks(),
+                         });
+        true
+    }
+
+    pub fn record_error(&mut self, message: &IcmpErrorMessage) -> bool {
+        if self.id != Some(message.id) || self.destination != Some(message.destination) {
+            return false;
+        }
+        if self.sequence_number != Some(message.sequence_number) {
+            return true;
+        }
+        self.events.push(Event::Error {
+                             from: message.from,
+                             error: message.error,
+                             sequence_number: message.sequence_number,
+                             received_at: system_clock::ticks(),
+                         });
+        true
+    }
+
+    fn take_event(&mut self, sequence_number: u16) -> Option<Event> {
+        self.events
+            .iter()
+            .position(|e| e.sequence_number() == sequence_number)
+            .map(|i| self.events.remove(i))
+    }
+}
+
+/// An ICMP error message that quotes one of our echo requests.
+pub struct IcmpErrorMessage {
+    pub from: Ipv4Address,
+    /// The destination of the quoted request.
+    pub destination: Ipv4Address,
+    pub error: IcmpError,
+    pub id: u16,
+    pub sequence_number: u16,
+}
+
+/// Parses a destination unreachable or time exceeded message from a raw ethernet frame.
+pub fn parse_icmp_error(data: &[u8]) -> Option<IcmpErrorMessage> {
+    if data.len() < ETHERNET_HEADER_LEN || BigEndian::read_u16(&data[12..14]) != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let ip = &data[ETHERNET_HEADER_LEN..];
+    let icmp = match icmp_payload(ip) {
+        Some(icmp) => icmp,
+        None => return None,
+    };
+
+    let error = match icmp[0] {
+        ICMP_DESTINATION_UNREACHABLE => IcmpError::DestinationUnreachable { code: icmp[1] },
+        ICMP_TIME_EXCEEDED => IcmpError::TimeExceeded { code: icmp[1] },
+        _ => return None,
+    };
+
+    // the message contains the original ip header and the first 8 bytes of its payload
+    let original_ip = &icmp[ICMP_HEADER_LEN..];
+    let original = match icmp_payload(original_ip) {
+        Some(original) if original[0] == ICMP_ECHO_REQUEST => original,
+        _ => return None,
+    };
+
+    Some(IcmpErrorMessage {
+             from: Ipv4Address::new([ip[12], ip[13], ip[14], ip[15]]),
+             destination: Ipv4Address::new([original_ip[16],
+                                            original_ip[17],
+                                            original_ip[18],
+                                            original_ip[19]]),
+             error: error,
+             id: BigEndian::read_u16(&original[4..6]),
+             sequence_number: BigEndian::read_u16(&original[6..8]),
+         })
+}
+
+/// Returns the ICMP part of an IPv4 packet if it contains at least a full ICMP header.
+fn icmp_payload(ip: &[u8]) -> Option<&[u8]> {
+    if ip.len() < IPV4_HEADER_LEN || ip[0] >> 4 != 4 || ip[9] != IP_PROTOCOL_ICMP {
+        return None;
+    }
+    let header_len = usize::from(ip[0] & 0xf) * 4;
+    if ip.len() < header_len + ICMP_HEADER_LEN {
+        return None;
+    }
+    Some(&ip[header_len..])
+}
+
+struct EchoRequest {
+    dst_mac: EthernetAddress,
+    src_ip: Ipv4Address,
+    dst_ip: Ipv4Address,
+    id: u16,
+    sequence_number: u16,
+    ttl: u8,
+    payload_size: usize,
+}
+
+impl EchoRequest {
+    fn to_frame(&self) -> Vec<u8> {
+        let header = Ipv4Header {
+            dst_mac: self.dst_mac,
+            src_ip: self.src_ip,
+            dst_ip: self.dst_ip,
+            protocol: IP_PROTOCOL_ICMP,
+            ttl: self.ttl,
+            identification: self.sequence_number,
+            dont_fragment: false,
+        };
+        let mut frame = header.to_frame(ICMP_HEADER_LEN + self.payload_size);
+
+        {
+            let icmp = &mut frame[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN..];
+            icmp[0] = ICMP_ECHO_REQUEST;
+            BigEndian::write_u16(&mut icmp[4..6], self.id);
+            BigEndian::write_u16(&mut icmp[6..8], self.sequence_number);
+            for (i, b) in icmp[ICMP_HEADER_LEN..].iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            let icmp_checksum = checksum(icmp);
+            BigEndian::write_u16(&mut icmp[2..4], icmp_checksum);
+        }
+
+        frame
+    }
+}
+
+impl EthernetDevice {
+    /// Sends `config.count` echo requests to `dst` and waits for the replies.
+    ///
+    /// Other packets are handled as usual while waiting.
+    pub fn ping(&mut self, dst: Ipv4Address, config: &PingConfig) -> Result<PingReport, Error> {
+        let dst_mac = self.resolve(config.via.unwrap_or(dst), config.timeout)?;
+        let id = self.ping_state.start(dst);
+
+        let mut statistics = PingStatistics::default();
+        let mut results = Vec::with_capacity(usize::from(config.count));
+        for sequence_number in 0..config.count {
+            let sent_at = self.send_echo_request(dst_mac, dst, id, sequence_number, config)?;
+            statistics.transmitted += 1;
+
+            let result = match self.wait_for_event(sequence_number, sent_at, config.timeout) {
+                Some(Event::Reply { from, received_at, .. }) => {
+                    statistics.record_rtt(received_at - sent_at);
+                    PingResult::Reply {
+                        from: from,
+                        rtt: received_at - sent_at,
+                    }
+                }
+                Some(Event::Error { from, error, .. }) => {
+                    statistics.errors += 1;
+                    PingResult::Error {
+                        from: from,
+                        error: error,
+                    }
+                }
+                None => PingResult::Timeout,
+            };
+            results.push(result);
+
+            if sequence_number + 1 < config.count {
+                while system_clock::ticks() - sent_at < config.interval {
+                    self.poll_packets();
+                }
+            }
+        }
+
+        self.ping_state.stop();
+        Ok(PingReport {
+               statistics: statistics,
+               results: results,
+           })
+    }
+
+    /// Sends echo requests with increasing TTL to `dst` and records which router answered.
+    ///
+    /// Stops at the first hop that is not a time exceeded message (i.e. the destination or
+    /// an unreachable error) or after `max_hops` hops.
+    pub fn traceroute(&mut self,
+                      dst: Ipv4Address,
+                      max_hops: u8,
+                      config: &PingConfig)
+                      -> Result<Vec<Hop>, Error> {
+        let dst_mac = self.resolve(config.via.unwrap_or(dst), config.timeout)?;
+        let id = self.ping_state.start(dst);
+
+        let mut hops = Vec::new();
+        for ttl in 1..(u16::from(max_hops) + 1) {
+            let ttl = ttl as u8;
+            let config = PingConfig {
+                ttl: ttl,
+                ..*config
+            };
+            let sent_at = self.send_echo_request(dst_mac, dst, id, u16::from(ttl), &config)?;
+
+            let (hop, done) = match self.wait_for_event(u16::from(ttl), sent_at, config.timeout) {
+                Some(Event::Reply { from, received_at, .. }) => {
+                    (Hop {
+                         ttl: ttl,
+                         address: Some(from),
+                         rtt: Some(received_at - sent_at),
+                         error: None,
+                     },
+                     true)
+                }
+                Some(Event::Error { from, error, received_at, .. }) => {
+                    let done = match error {
+                        IcmpError::TimeExceeded { .. } => false,
+                        IcmpError::DestinationUnreachable { .. } => true,
+                    };
+                    (Hop {
+                         ttl: ttl,
+                         address: Some(from),
+                         rtt: Some(received_at - sent_at),
+                         error: Some(error),
+                     },
+                     done)
+                }
+                None => {
+                    (Hop {
+                         ttl: ttl,
+                         address: None,
+                         rtt: None,
+                         error: None,
+                     },
+                     false)
+                }
+            };
+            hops.push(hop);
+            if done {
+                break;
+            }
+        }
+
+        self.ping_state.stop();
+        Ok(hops)
+    }
+
+    fn send_echo_request(&mut self,
+                         dst_mac: EthernetAddress,
+                         dst: Ipv4Address,
+                         id: u16,
+                         sequence_number: u16,
+                         config: &PingConfig)
+                         -> Result<usize, Error> {
+        self.ping_state.expect(sequence_number);
+        let request = EchoRequest {
+            dst_mac: dst_mac,
+            src_ip: self.ipv4_addr.ok_or(Error::NoIp)?,
+            dst_ip: dst,
+            id: id,
+            sequence_number: sequence_number,
+            ttl: config.ttl,
+            payload_size: config.payload_size,
+        };
+
+        self.tx.insert(request.to_frame().into_boxed_slice());
+        self.start_send();
+
+        Ok(system_clock::ticks())
+    }
+
+    fn wait_for_event(&mut self,
+                      sequence_number: u16,
+                      sent_at: usize,
+                      timeout: usize)
+                      -> Option<Event> {
+        while system_clock::ticks() - sent_at < timeout {
+            self.poll_packets();
+            if let Some(event) = self.ping_state.take_event(sequence_number) {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Looks up the MAC address of `ip` and sends an ARP request if it is unknown.
+    fn resolve(&mut self, ip: Ipv4Address, timeout: usize) -> Result<EthernetAddress, Error> {
+        if let Some(&mac) = self.arp_cache.get(&ip) {
+            return Ok(mac);
+        }
+
+        let src_ip = self.ipv4_addr.ok_or(Error::NoIp)?;
+        let arp_request = arp::new_request_packet(ETH_ADDR, src_ip, ip);
+        self.tx.insert(TxPacket::write_out(arp_request)?.into_boxed_slice());
+        self.start_send();
+
+        let start = system_clock::ticks();
+        while system_clock::ticks() - start < timeout {
+            self.poll_packets();
+            if let Some(&mac) = self.arp_cache.get(&ip) {
+                return Ok(mac);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Handles all pending packets. Errors are ignored like in the main loop because they
+    /// belong to unrelated packets.
+    fn poll_packets(&mut self) {
+        while self.handle_next_packet().is_ok() {}
+    }
+}
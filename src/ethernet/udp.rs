@@ -0,0 +1,118 @@
+//! Sending of UDP datagrams, e.g. to stream recorded audio to a host.
+
+use collections::Vec;
+use byteorder::{ByteOrder, BigEndian};
+use net::ethernet::EthernetAddress;
+use net::ipv4::Ipv4Address;
+use super::ipv4::{checksum, Ipv4Header, ETHERNET_HEADER_LEN, IPV4_HEADER_LEN, IP_PROTOCOL_UDP};
+use super::{EthernetDevice, Error};
+
+const UDP_HEADER_LEN: usize = 8;
+const TTL: u8 = 64;
+
+/// Time to wait for the ARP reply of the destination in milliseconds.
+const ARP_TIMEOUT: usize = 1000;
+
+/// Largest payload that fits into a single unfragmented datagram.
+pub const MAX_UDP_PAYLOAD: usize = 1500 - IPV4_HEADER_LEN - UDP_HEADER_LEN;
+
+struct Datagram<'a> {
+    dst_mac: EthernetAddress,
+    src_ip: Ipv4Address,
+    dst_ip: Ipv4Address,
+    src_port: u16,
+    dst_port: u16,
+    identification: u16,
+    payload: &'a [u8],
+}
+
+impl<'a> Datagram<'a> {
+    fn to_frame(&self) -> Vec<u8> {
+        let udp_len = UDP_HEADER_LEN + self.payload.len();
+        let header = Ipv4Header {
+            dst_mac: self.dst_mac,
+            src_ip: self.src_ip,
+            dst_ip: self.dst_ip,
+            protocol: IP_PROTOCOL_UDP,
+            ttl: TTL,
+            identification: self.identification,
+            dont_fragment: true,
+        };
+        let mut frame = header.to_frame(udp_len);
+
+        {
+            let udp = &mut frame[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN..];
+            BigEndian::write_u16(&mut udp[0..2], self.src_port);
+            BigEndian::write_u16(&mut udp[2..4], self.dst_port);
+            BigEndian::write_u16(&mut udp[4..6], udp_len as u16);
+            udp[UDP_HEADER_LEN..].copy_from_slice(self.payload);
+
+            // the checksum covers a pseudo header with the addresses, protocol and length
+            let mut pseudo = Vec::with_capacity(12 + udp_len);
+            pseudo.extend_from_slice(self.src_ip.as_bytes());
+            pseudo.extend_from_slice(self.dst_ip.as_bytes());
+            pseudo.extend_from_slice(&[0, IP_PROTOCOL_UDP]);
+            pseudo.extend_from_slice(&udp[4..6]);
+            pseudo.extend_from_slice(udp);
+            let udp_checksum = match checksum(&pseudo) {
+                0 => 0xffff, // zero means no checksum
+                sum => sum,
+            };
+            BigEndian::write_u16(&mut udp[6..8], udp_checksum);
+        }
+
+        frame
+    }
+}
+
+impl EthernetDevice {
+    /// Sends a single UDP datagram. The payload must not exceed `MAX_UDP_PAYLOAD` bytes.
+    ///
+    /// The destination must be on the local network because its MAC address is resolved via ARP.
+    pub fn send_udp(&mut self,
+                    dst: Ipv4Address,
+                    src_port: u16,
+                    dst_port: u16,
+                    payload: &[u8])
+                    -> Result<(), Error> {
+        if payload.len() > MAX_UDP_PAYLOAD {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        let dst_mac = self.resolve(dst, ARP_TIMEOUT)?;
+        let datagram = Datagram {
+            dst_mac: dst_mac,
+            src_ip: self.ipv4_addr.ok_or(Error::NoIp)?,
+            dst_ip: dst,
+            src_port: src_port,
+            dst_port: dst_port,
+            identification: self.next_udp_identification(),
+            payload: payload,
+        };
+
+        self.tx.insert(datagram.to_frame().into_boxed_slice());
+        self.start_send();
+        Ok(())
+    }
+
+    /// Sends `data` as a sequence of datagrams of at most `MAX_UDP_PAYLOAD` bytes.
+    ///
+    /// UDP gives no delivery guarantees, so the receiver has to check that nothing was lost,
+    /// e.g. by comparing the received length with the length in a WAV header.
+    pub fn send_udp_stream(&mut self,
+                           dst: Ipv4Address,
+                           src_port: u16,
+                           dst_port: u16,
+                           data: &[u8])
+                           -> Result<(), Error> {
+        for chunk in data.chunks(MAX_UDP_PAYLOAD) {
+            self.send_udp(dst, src_port, dst_port, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn next_udp_identification(&mut self) -> u16 {
+        self.udp_identification = self.udp_identification.wrapping_add(1);
+        self.udp_identification
+    }
+}
@@ -0,0 +1,139 @@
+//! Erasing and programming of the internal flash, e.g. to persist settings.
+//!
+//! The STM32F746 has 1 MB of flash in eight sectors: four of 32 KB, one of 128 KB and three of
+//! 256 KB. The program is linked to the start of the flash and the linker script limits it to the
+//! first seven sectors, so the last sector is used for data.
+
+use board::flash::{self, Flash};
+use core::ptr;
+
+const KEY_1: u32 = 0x4567_0123;
+const KEY_2: u32 = 0xCDEF_89AB;
+
+/// The last 256 KB sector, which is reserved for data.
+pub const DATA_SECTOR: u8 = 7;
+pub const DATA_SECTOR_START: usize = 0x080C_0000;
+pub const DATA_SECTOR_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    WriteProtection,
+    ProgrammingAlignment,
+    ProgrammingParallelism,
+    EraseSequence,
+    Operation,
+    /// The address is not word aligned or outside of the data sector.
+    InvalidAddress,
+}
+
+/// Erases the data sector (all bytes become 0xff).
+pub fn erase_data_sector(flash: &mut Flash) -> Result<(), Error> {
+    unlock(flash);
+    let result = erase_sector(flash, DATA_SECTOR);
+    lock(flash);
+    result
+}
+
+/// Programs words into the erased data sector.
+pub fn program_words(flash: &mut Flash, address: usize, words: &[u32]) -> Result<(), Error> {
+    let end = address + words.len() * 4;
+    if address % 4 != 0 || address < DATA_SECTOR_START ||
+       end > DATA_SECTOR_START + DATA_SECTOR_LEN {
+        return Err(Error::InvalidAddress);
+    }
+
+    unlock(flash);
+    let result = program(flash, address, words);
+    lock(flash);
+    result
+}
+
+/// Reads a word from the flash.
+pub fn read_word(address: usize) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn unlock(flash: &mut Flash) {
+    if flash.cr.read().lock() {
+        let mut key = flash::Keyr::default();
+        key.set_key(KEY_1);
+        flash.keyr.write(key);
+        key.set_key(KEY_2);
+        flash.keyr.write(key);
+    }
+}
+
+fn lock(flash: &mut Flash) {
+    flash.cr.update(|r| r.set_lock(true));
+}
+
+fn erase_sector(flash: &mut Flash, sector: u8) -> Result<(), Error> {
+    wait_until_ready(flash)?;
+    flash.cr
+        .update(|r| {
+                    r.set_psize(0b10); // parallelism x32
+                    r.set_ser(true); // sector_erase
+                    r.set_snb(sector); // sector_number
+                });
+    flash.cr.update(|r| r.set_strt(true)); // start
+    let result = wait_until_ready(flash);
+    flash.cr.update(|r| r.set_ser(false));
+    result
+}
+
+fn program(flash: &mut Flash, address: usize, words: &[u32]) -> Result<(), Error> {
+    wait_until_ready(flash)?;
+    flash.cr
+        .update(|r| {
+                    r.set_psize(0b10); // parallelism x32
+                    r.set_pg(true); // programming
+                });
+
+    let mut result = Ok(());
+    for (i, &word) in words.iter().enumerate() {
+        unsafe { ptr::write_volatile((address + i * 4) as *mut u32, word) };
+        // make sure the write reaches the flash interface before checking the status
+        unsafe { asm!("DSB"::::"volatile") };
+        result = wait_until_ready(flash);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    flash.cr.update(|r| r.set_pg(false));
+    result
+}
+
+fn wait_until_ready(flash: &mut Flash) -> Result<(), Error> {
+    while flash.sr.read().bsy() {}
+
+    let sr = flash.sr.read();
+    let error = if sr.wrperr() {
+        Some(Error::WriteProtection)
+    } else if sr.pgaerr() {
+        Some(Error::ProgrammingAlignment)
+    } else if sr.pgperr() {
+        Some(Error::ProgrammingParallelism)
+    } else if sr.erserr() {
+        Some(Error::EraseSequence)
+    } else if sr.operr() {
+        Some(Error::Operation)
+    } else {
+        None
+    };
+
+    // the flags are cleared by writing 1
+    let mut clear = flash::Sr::default();
+    clear.set_eop(true);
+    clear.set_operr(true);
+    clear.set_wrperr(true);
+    clear.set_pgaerr(true);
+    clear.set_pgperr(true);
+    clear.set_erserr(true);
+    flash.sr.write(clear);
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
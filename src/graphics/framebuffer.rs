@@ -0,0 +1,90 @@
+use lcd::Color;
+use super::{Canvas, PixelFormat, Rect};
+
+/// An image in a byte buffer, in the memory layout that the LTDC and the DMA2D use: rows from
+/// top to bottom, pixels in little endian byte order.
+///
+/// The buffer can be any memory, e.g. a `Vec` on the host or the SDRAM returned by
+/// `graphics::offscreen_buffer` for off-screen rendering on the board.
+pub struct FrameBuffer<'a> {
+    data: &'a mut [u8],
+    width: u16,
+    height: u16,
+    format: PixelFormat,
+}
+
+impl<'a> FrameBuffer<'a> {
+    /// Returns `None` if `data` is shorter than `format.buffer_len(width, height)`.
+    pub fn new(data: &'a mut [u8],
+               width: u16,
+               height: u16,
+               format: PixelFormat)
+               -> Option<FrameBuffer<'a>> {
+        if data.len() < format.buffer_len(width, height) {
+            return None;
+        }
+        Some(FrameBuffer {
+                 data: data,
+                 width: width,
+                 height: height,
+                 format: format,
+             })
+    }
+
+    /// The pixel data, e.g. to display the buffer with a layer or to copy it with the DMA2D.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.format.buffer_len(self.width, self.height)]
+    }
+
+    fn offset(&self, x: u16, y: u16) -> usize {
+        (usize::from(y) * usize::from(self.width) + usize::from(x)) * self.format.bytes_per_pixel()
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        let bytes = self.format.bytes_per_pixel();
+        for (i, byte) in self.data[offset..offset + bytes].iter_mut().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+    }
+}
+
+impl<'a> Canvas for FrameBuffer<'a> {
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn set_pixel(&mut self, x: u16, y: u16, color: Color) {
+        if x < self.width && y < self.height {
+            let (offset, value) = (self.offset(x, y), self.format.encode(color));
+            self.write(offset, value);
+        }
+    }
+
+    fn pixel(&self, x: u16, y: u16) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let offset = self.offset(x, y);
+        let bytes = &self.data[offset..offset + self.format.bytes_per_pixel()];
+        let value = bytes.iter()
+            .enumerate()
+            .fold(0, |value, (i, &byte)| value | u32::from(byte) << (8 * i));
+        Some(self.format.decode(value))
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersection(&Rect::new(0, 0, self.width, self.height));
+        // encode the color only once
+        let value = self.format.encode(color);
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let offset = self.offset(x, y);
+                self.write(offset, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use lcd::{Color, Layer, LayerFormat, Lcd};
+use super::{Canvas, PixelFormat};
+
+/// Draws to a layer of the LCD, using the rotation of the `Lcd`.
+///
+/// On L8 layers, colors are mapped to the closest entry of the lookup table of the layer.
+pub struct LayerCanvas<'a> {
+    lcd: &'a mut Lcd,
+    layer: Layer,
+}
+
+impl<'a> LayerCanvas<'a> {
+    pub fn new(lcd: &'a mut Lcd, layer: Layer) -> LayerCanvas<'a> {
+        LayerCanvas {
+            lcd: lcd,
+            layer: layer,
+        }
+    }
+}
+
+impl<'a> Canvas for LayerCanvas<'a> {
+    fn size(&self) -> (u16, u16) {
+        (self.lcd.width(), self.lcd.height())
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Argb1555
+    }
+
+    fn set_pixel(&mut self, x: u16, y: u16, color: Color) {
+        match self.lcd.layer_format(self.layer) {
+            LayerFormat::Argb1555 => self.lcd.set_pixel(self.layer, x, y, color.to_argb1555()),
+            LayerFormat::L8 => {
+                let index = color.to_l8(self.lcd.clut(self.layer));
+                self.lcd.set_pixel_index(self.layer, x, y, index);
+            }
+        }
+    }
+
+    fn pixel(&self, x: u16, y: u16) -> Option<Color> {
+        match self.lcd.layer_format(self.layer) {
+            LayerFormat::Argb1555 => self.lcd.pixel(self.layer, x, y).map(Color::from_argb1555),
+            LayerFormat::L8 => {
+                let clut = self.lcd.clut(self.layer);
+                self.lcd.pixel_index(self.layer, x, y).map(|index| Color::from_l8(index, clut))
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use collections::Vec;
+use image;
+use lcd::Color;
+use super::{Canvas, PixelFormat};
+
+/// A canvas in RAM, e.g. for off-screen rendering or for testing on the host.
+///
+/// The pixels are stored with full precision and alpha, so the content can be compared with a
+/// golden image after saving it with `to_png`.
+pub struct MemoryCanvas {
+    width: u16,
+    height: u16,
+    pixels: Vec<Color>,
+}
+
+impl MemoryCanvas {
+    pub fn new(width: u16, height: u16, color: Color) -> MemoryCanvas {
+        MemoryCanvas {
+            width: width,
+            height: height,
+            pixels: vec![color; usize::from(width) * usize::from(height)],
+        }
+    }
+
+    /// All pixels, row by row.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Encodes the canvas as an RGBA PNG image.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        let width = usize::from(self.width);
+        let pixel = |x: u16, y: u16| self.pixels[usize::from(y) * width + usize::from(x)];
+        let result: Result<(), ()> = image::encode_png(self.width, self.height, pixel, |bytes| {
+            png.extend_from_slice(bytes);
+            Ok(())
+        });
+        result.expect("writing to a Vec can't fail");
+        png
+    }
+}
+
+impl Canvas for MemoryCanvas {
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Argb8888
+    }
+
+    fn set_pixel(&mut self, x: u16, y: u16, color: Color) {
+        if x < self.width && y < self.height {
+            self.pixels[usize::from(y) * usize::from(self.width) + usize::from(x)] = color;
+        }
+    }
+
+    fn pixel(&self, x: u16, y: u16) -> Option<Color> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[usize::from(y) * usize::from(self.width) + usize::from(x)])
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+    use graphics::Rect;
+    use lcd::color::{RED, TRANSPARENT};
+
+    fn shapes() -> MemoryCanvas {
+        let background = Color {
+            red: 0x11,
+            green: 0x22,
+            blue: 0x33,
+            ..TRANSPARENT
+        };
+        let mut canvas = MemoryCanvas::new(7, 5, background);
+        for i in 0..5 {
+            canvas.set_pixel(i, i, Color::rgba(0, 0, 255, 128));
+        }
+        canvas.fill_rect(Rect::new(1, 1, 3, 2), RED);
+        canvas.set_pixel(6, 4, Color::rgba(10, 20, 30, 40));
+        // outside of the canvas
+        canvas.set_pixel(7, 0, RED);
+        canvas
+    }
+
+    #[test]
+    fn golden_png() {
+        // encoded with zlib stored blocks by an independent encoder
+        assert_eq!(&shapes().to_png()[..], &include_bytes!("testdata/shapes.png")[..]);
+    }
+
+    fn decode(png: &[u8]) -> (u16, u16, Vec<Color>) {
+        let mut pixels = Vec::new();
+        let info = image::decode(png, |x, y, color| pixels.push((x, y, color))).unwrap();
+        let colors = pixels.iter().map(|&(_, _, color)| color).collect();
+        (info.width, info.height, colors)
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let canvas = shapes();
+        assert_eq!(decode(&canvas.to_png()), (7, 5, canvas.pixels().to_vec()));
+    }
+
+    #[test]
+    fn png_with_several_deflate_blocks() {
+        // 110 rows of 1 + 160 * 4 bytes need two stored blocks
+        let mut canvas = MemoryCanvas::new(160, 110, TRANSPARENT);
+        for y in 0..110 {
+            for x in 0..160 {
+                let color = Color::rgba(x as u8, y as u8, (x + y) as u8, 255 - x as u8);
+                canvas.set_pixel(x, y, color);
+            }
+        }
+        let png = canvas.to_png();
+        assert_eq!(decode(&png), (160, 110, canvas.pixels().to_vec()));
+        // signature, IHDR, IDAT with the zlib header, two block headers and the checksum, IEND
+        let raw_len = 110 * (1 + 160 * 4);
+        assert_eq!(png.len(), 8 + 25 + 12 + 2 + 2 * 5 + raw_len + 4 + 12);
+    }
+}
@@ -0,0 +1,250 @@
+//! Drawing surfaces that are independent of the LTDC.
+//!
+//! The `gui` widgets, the `image` decoders and the `raster` shapes draw through the
+//! `Canvas` trait. It is implemented by `LayerCanvas` for a layer of the LCD, by
+//! `FrameBuffer` for off-screen buffers in any LTDC pixel format, e.g. in the SDRAM of
+//! `offscreen_buffer`, and by
+//! `MemoryCanvas`, which keeps the pixels in a `Vec` and can be saved as PNG. So the same
+//! drawing code runs on the board and in tests on the host.
+
+pub use self::framebuffer::FrameBuffer;
+pub use self::layer::LayerCanvas;
+pub use self::memory::MemoryCanvas;
+
+use core::{cmp, slice};
+use lcd::{self, Color};
+use sdram;
+
+mod framebuffer;
+mod layer;
+mod memory;
+
+/// Length of `offscreen_buffer`, enough for an ARGB8888 `FrameBuffer` of the largest panel.
+pub const OFFSCREEN_BUFFER_LEN: usize = lcd::MAX_WIDTH as usize * lcd::MAX_HEIGHT as usize * 4;
+
+/// The SDRAM that is reserved for off-screen rendering into a `FrameBuffer`.
+///
+/// This is unsafe because every call returns a mutable reference to the same memory.
+pub unsafe fn offscreen_buffer() -> &'static mut [u8] {
+    slice::from_raw_parts_mut(sdram::OFFSCREEN_BUFFER_START as *mut u8, OFFSCREEN_BUFFER_LEN)
+}
+
+/// A rectangle in screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+
+    /// First column right of the rectangle, at most `u16::max_value()`.
+    pub fn right(&self) -> u16 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// First row below the rectangle, at most `u16::max_value()`.
+    pub fn bottom(&self) -> u16 {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    /// The overlapping area, which is empty if the rectangles do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = cmp::max(self.x, other.x);
+        let y = cmp::max(self.y, other.y);
+        let right = cmp::min(self.right(), other.right());
+        let bottom = cmp::min(self.bottom(), other.bottom());
+        if right <= x || bottom <= y {
+            Rect::default()
+        } else {
+            Rect::new(x, y, right - x, bottom - y)
+        }
+    }
+
+    /// The smallest rectangle that contains both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = cmp::min(self.x, other.x);
+        let y = cmp::min(self.y, other.y);
+        let right = cmp::max(self.right(), other.right());
+        let bottom = cmp::max(self.bottom(), other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Shrinks the rectangle by `amount` pixels on each side.
+    pub fn inset(&self, amount: u16) -> Rect {
+        let width = self.width.saturating_sub(amount.saturating_mul(2));
+        let height = self.height.saturating_sub(amount.saturating_mul(2));
+        Rect::new(self.x.saturating_add(amount), self.y.saturating_add(amount), width, height)
+    }
+}
+
+/// The pixel formats of the LTDC and the DMA2D that store colors directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Argb8888,
+    Rgb888,
+    Rgb565,
+    Argb1555,
+    Argb4444,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Argb1555 | PixelFormat::Argb4444 => 2,
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        match *self {
+            PixelFormat::Rgb888 | PixelFormat::Rgb565 => false,
+            PixelFormat::Argb8888 | PixelFormat::Argb1555 | PixelFormat::Argb4444 => true,
+        }
+    }
+
+    /// Bytes of a `width`×`height` buffer without padding.
+    pub fn buffer_len(&self, width: u16, height: u16) -> usize {
+        usize::from(width) * usize::from(height) * self.bytes_per_pixel()
+    }
+
+    /// Converts a color to a pixel value in the lower `bytes_per_pixel` bytes.
+    pub fn encode(&self, color: Color) -> u32 {
+        match *self {
+            PixelFormat::Argb8888 => color.to_argb8888(),
+            PixelFormat::Rgb888 => color.to_rgb888(),
+            PixelFormat::Rgb565 => u32::from(color.to_rgb565()),
+            PixelFormat::Argb1555 => u32::from(color.to_argb1555()),
+            PixelFormat::Argb4444 => u32::from(color.to_argb4444()),
+        }
+    }
+
+    pub fn decode(&self, value: u32) -> Color {
+        match *self {
+            PixelFormat::Argb8888 => Color::from_argb8888(value),
+            PixelFormat::Rgb888 => Color::from_rgb888(value),
+            PixelFormat::Rgb565 => Color::from_rgb565(value as u16),
+            PixelFormat::Argb1555 => Color::from_argb1555(value as u16),
+            PixelFormat::Argb4444 => Color::from_argb4444(value as u16),
+        }
+    }
+}
+
+/// Something that pixels can be drawn to.
+pub trait Canvas {
+    /// Width and height in pixels.
+    fn size(&self) -> (u16, u16);
+
+    /// The format of the stored pixels. Drawn colors lose the precision that it doesn't have.
+    fn pixel_format(&self) -> PixelFormat;
+
+    /// Sets a pixel. Pixels outside of the canvas are ignored.
+    fn set_pixel(&mut self, x: u16, y: u16, color: Color);
+
+    /// Returns the color of a pixel, or `None` if it is outside of the canvas or the canvas
+    /// can't be read.
+    fn pixel(&self, _x: u16, _y: u16) -> Option<Color> {
+        None
+    }
+
+    /// Draws `color` over a pixel, weighted by its alpha. If the canvas can't be read, the
+    /// color is drawn opaque if its alpha is at least 128.
+    fn blend_pixel(&mut self, x: u16, y: u16, color: Color) {
+        match self.pixel(x, y) {
+            Some(below) => self.set_pixel(x, y, color.over(below)),
+            None if color.alpha >= 128 => self.set_pixel(x, y, Color { alpha: 255, ..color }),
+            None => {}
+        }
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Copies the area `rect` of `source` with its top left corner to (`x`, `y`). The area is
+    /// clipped to both canvases, and the pixels are copied including their alpha, not blended.
+    /// Pixels that `source` can't read are skipped.
+    fn blit<S: Canvas>(&mut self, source: &S, rect: Rect, x: i32, y: i32) {
+        let (source_width, source_height) = source.size();
+        let rect = rect.intersection(&Rect::new(0, 0, source_width, source_height));
+        let (width, height) = self.size();
+        for source_y in rect.y..rect.bottom() {
+            let target_y = y + i32::from(source_y - rect.y);
+            if target_y < 0 || target_y >= i32::from(height) {
+                continue;
+            }
+            for source_x in rect.x..rect.right() {
+                let target_x = x + i32::from(source_x - rect.x);
+                if target_x < 0 || target_x >= i32::from(width) {
+                    continue;
+                }
+                if let Some(color) = source.pixel(source_x, source_y) {
+                    self.set_pixel(target_x as u16, target_y as u16, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_edges_saturate() {
+        let max = u16::max_value();
+        let rect = Rect::new(max - 10, 100, 20, max);
+        assert_eq!((rect.right(), rect.bottom()), (max, max));
+        assert!(rect.contains(max - 1, max - 1));
+        assert!(!rect.contains(max - 11, 100));
+        assert_eq!(rect.intersection(&Rect::new(0, 0, max, 200)),
+                   Rect::new(max - 10, 100, 10, 100));
+        assert_eq!(Rect::new(0, 0, 10, 10).union(&rect), Rect::new(0, 0, max, max));
+        assert_eq!(rect.inset(max), Rect::new(max, max, 0, 0));
+    }
+
+    #[test]
+    fn rect_intersection_and_union() {
+        let a = Rect::new(10, 20, 30, 40);
+        let b = Rect::new(30, 50, 30, 30);
+        assert_eq!(a.intersection(&b), Rect::new(30, 50, 10, 10));
+        assert_eq!(a.union(&b), Rect::new(10, 20, 50, 60));
+        assert!(!a.intersects(&Rect::new(40, 20, 5, 5)));
+        assert_eq!(a.union(&Rect::default()), a);
+        assert_eq!(a.inset(5), Rect::new(15, 25, 20, 30));
+        assert!(a.inset(20).is_empty());
+    }
+}
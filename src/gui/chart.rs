@@ -0,0 +1,701 @@
+use collections::{String, Vec, VecDeque};
+use core::cmp;
+use graphics::{Canvas, Rect};
+use lcd::Color;
+use math;
+use super::{font, Painter, Theme};
+
+/// Blank columns in front of the newest column in sweep mode.
+const SWEEP_GAP: u16 = 8;
+/// Ranges with more ticks, e.g. because of overflows, are drawn without ticks.
+const MAX_TICKS: i32 = 64;
+/// Limit of the tick indices and decimals, so that huge values and NaN can't overflow.
+const MAX_INDEX: i32 = 1_000_000_000;
+
+/// How new columns enter a chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartMode {
+    /// The newest column is always at the right edge and older columns move to the left.
+    Scroll,
+    /// New columns are drawn from left to right over the old ones, like on an oscilloscope.
+    /// Only the new columns have to be redrawn.
+    Sweep,
+}
+
+/// The value range of the Y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YAxis {
+    /// Fits all kept samples, extended to the next tick. Changes of the range redraw the whole
+    /// chart.
+    Auto,
+    /// A fixed range from `min` to `max`. Samples outside are drawn at the edges.
+    Fixed { min: f32, max: f32 },
+}
+
+/// The part of a `Chart` that changed, returned by `Chart::push`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartChange {
+    None,
+    /// The columns from the first to the last index (inclusive) changed.
+    Columns(usize, usize),
+    /// New columns up to the last index were started, the columns from the first index on
+    /// changed.
+    Advanced(usize, usize),
+    /// The Y axis changed.
+    All,
+}
+
+/// The samples of a column, reduced to their extremes.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    min: f32,
+    max: f32,
+    /// The earliest sample, the previous column is connected to it.
+    first: f32,
+    /// The latest sample, the next column is connected to it.
+    last: f32,
+}
+
+impl Bucket {
+    fn new(value: f32) -> Bucket {
+        Bucket {
+            min: value,
+            max: value,
+            first: value,
+            last: value,
+        }
+    }
+
+    fn add(self, value: f32) -> Bucket {
+        Bucket {
+            min: math::min(self.min, value),
+            max: math::max(self.max, value),
+            first: self.first,
+            last: value,
+        }
+    }
+}
+
+/// A line of a `Chart`.
+#[derive(Debug, Clone)]
+pub struct Series {
+    /// Shown in the legend.
+    pub name: String,
+    pub color: Color,
+    /// One bucket per column, the newest at the back. All series have the same length.
+    columns: VecDeque<Option<Bucket>>,
+    /// The column and bucket that was dropped last, so that the line to the next bucket
+    /// doesn't change when old columns are dropped.
+    dropped: Option<(usize, Bucket)>,
+}
+
+impl Series {
+    /// The column and bucket of the latest bucket before `index`. `front` is the column of the
+    /// first kept bucket.
+    fn previous(&self, index: usize, front: usize) -> Option<(usize, Bucket)> {
+        for previous in (0..index).rev() {
+            if let Some(bucket) = self.columns[previous] {
+                return Some((front + previous, bucket));
+            }
+        }
+        self.dropped
+    }
+
+    /// The index and bucket of the earliest bucket at or after `index`.
+    fn next(&self, index: usize) -> Option<(usize, Bucket)> {
+        (index..self.columns.len())
+            .filter_map(|next| self.columns[next].map(|bucket| (next, bucket)))
+            .next()
+    }
+
+    /// The lowest and highest value drawn in a column: its samples and the line from the
+    /// previous sample. Columns without samples show the line between the samples around them.
+    fn span(&self, index: usize, front: usize) -> Option<(f32, f32)> {
+        let (next, next_bucket) = match self.next(index) {
+            Some(next) => next,
+            None => return None,
+        };
+        let (column, next_column) = (front + index, front + next);
+        let line = self.previous(index, front).map(|(previous, bucket)| {
+            let slope = (next_bucket.first - bucket.last) / (next_column - previous) as f32;
+            let at = |column: usize| bucket.last + slope * (column - previous) as f32;
+            (at(column - 1), at(column))
+        });
+        match (line, next == index) {
+            (Some((from, _)), true) => {
+                Some((math::min(from, next_bucket.min), math::max(from, next_bucket.max)))
+            }
+            (Some((from, to)), false) => Some((math::min(from, to), math::max(from, to))),
+            (None, true) => Some((next_bucket.min, next_bucket.max)),
+            (None, false) => None,
+        }
+    }
+
+    /// The indices of the first and last column whose span depends on the bucket at `index`.
+    fn dependent_columns(&self, index: usize) -> (usize, usize) {
+        let first = match (0..index).rev().find(|&previous| self.columns[previous].is_some()) {
+            Some(previous) => previous + 1,
+            None if self.dropped.is_some() => 0,
+            None => index,
+        };
+        let last = (index + 1..self.columns.len())
+            .find(|&next| self.columns[next].is_some())
+            .unwrap_or(index);
+        (first, last)
+    }
+}
+
+/// Tick positions of the Y axis.
+#[derive(Debug, Clone, Copy)]
+struct Ticks {
+    low: f32,
+    high: f32,
+    step: f32,
+    decimals: usize,
+}
+
+impl Ticks {
+    /// Range of tick indices, the tick values are the indices times `step`.
+    fn indices(&self) -> (i32, i32) {
+        let first = math::to_i32(-math::floor(-self.low / self.step + 0.001), MAX_INDEX);
+        let last = math::to_i32(math::floor(self.high / self.step + 0.001), MAX_INDEX);
+        if last - first > MAX_TICKS {
+            (0, -1)
+        } else {
+            (first, last)
+        }
+    }
+}
+
+struct Layout {
+    plot: Rect,
+    y_ticks: Ticks,
+    /// Milliseconds between two ticks of the time axis.
+    time_tick: u64,
+}
+
+/// A live line chart of one or more series of samples over time.
+///
+/// Each pixel column covers `column_duration` milliseconds. Samples that arrive faster are
+/// reduced to the minimum and maximum of their column, so the chart shows peaks of fast
+/// signals like audio levels as well as slow ones like temperatures.
+///
+/// Add samples with `Ui::push_sample`, which only redraws the changed columns, or with `push`
+/// through `Ui::widget_mut`, which redraws the whole chart.
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub mode: ChartMode,
+    pub y_axis: YAxis,
+    /// Milliseconds per pixel column. Call `clear` after changing it.
+    pub column_duration: u32,
+    /// Approximate number of intervals between the ticks of the Y axis.
+    pub y_tick_count: u16,
+    /// Number of kept columns.
+    history: usize,
+    series: Vec<Series>,
+    /// Index of the newest column, which is the time divided by `column_duration`.
+    newest: Option<usize>,
+    /// Minimum and maximum of all kept samples.
+    data_range: Option<(f32, f32)>,
+}
+
+impl Chart {
+    /// Creates a scrolling chart with an automatic Y axis that keeps `history` columns, which
+    /// should be at least the width of the plot, e.g. the width of the screen.
+    pub fn new(history: usize, column_duration: u32) -> Chart {
+        Chart {
+            mode: ChartMode::Scroll,
+            y_axis: YAxis::Auto,
+            column_duration: column_duration,
+            y_tick_count: 4,
+            history: cmp::max(history, 1),
+            series: Vec::new(),
+            newest: None,
+            data_range: None,
+        }
+    }
+
+    /// Adds a series and returns its index for `push`.
+    pub fn add_series(&mut self, name: &str, color: Color) -> usize {
+        let len = self.series.first().map(|series| series.columns.len()).unwrap_or(0);
+        self.series
+            .push(Series {
+                      name: String::from(name),
+                      color: color,
+                      columns: (0..len).map(|_| None).collect(),
+                      dropped: None,
+                  });
+        self.series.len() - 1
+    }
+
+    pub fn series(&self) -> &[Series] {
+        &self.series
+    }
+
+    /// Removes all samples.
+    pub fn clear(&mut self) {
+        for series in &mut self.series {
+            series.columns.clear();
+            series.dropped = None;
+        }
+        self.newest = None;
+        self.data_range = None;
+    }
+
+    /// The current range of the Y axis.
+    pub fn y_range(&self) -> (f32, f32) {
+        let ticks = self.y_ticks();
+        (ticks.low, ticks.high)
+    }
+
+    /// Adds a sample of a series at `time` milliseconds, e.g. `system_clock::ticks()`.
+    ///
+    /// Samples of columns that are no longer kept, of unknown series and values that are not
+    /// finite are ignored.
+    pub fn push(&mut self, series: usize, time: usize, value: f32) -> ChartChange {
+        // true for NaN and infinity
+        if series >= self.series.len() || value - value != 0.0 {
+            return ChartChange::None;
+        }
+        let column = time / cmp::max(self.column_duration, 1) as usize;
+        let old_range = self.y_range();
+
+        let change = match self.newest {
+            Some(newest) if column <= newest => {
+                let columns = &mut self.series[series].columns;
+                let age = newest - column;
+                if age >= columns.len() {
+                    return ChartChange::None;
+                }
+                let index = columns.len() - 1 - age;
+                columns[index] = Some(match columns[index] {
+                                          Some(bucket) => bucket.add(value),
+                                          None => Bucket::new(value),
+                                      });
+                let (low, high) = self.data_range.unwrap_or((value, value));
+                self.data_range = Some((math::min(low, value), math::max(high, value)));
+                let (first, last) = self.series[series].dependent_columns(index);
+                ChartChange::Columns(column - (index - first), column + (last - index))
+            }
+            _ => {
+                let first = match self.newest {
+                    Some(newest) => newest + 1,
+                    None => column,
+                };
+                let new_columns = cmp::min(column - first + 1, self.history);
+                for series in &mut self.series {
+                    for _ in 0..new_columns {
+                        series.columns.push_back(None);
+                    }
+                    while series.columns.len() > self.history {
+                        let front = column + 1 - series.columns.len();
+                        if let Some(Some(bucket)) = series.columns.pop_front() {
+                            series.dropped = Some((front, bucket));
+                        }
+                    }
+                }
+                let index = self.series[series].columns.len() - 1;
+                self.series[series].columns[index] = Some(Bucket::new(value));
+                self.newest = Some(column);
+                // old columns were dropped, so the range may shrink
+                self.data_range = self.compute_data_range();
+                let first_changed = self.series[series].dependent_columns(index).0;
+                let first = cmp::min(column - (index - first_changed), first);
+                ChartChange::Advanced(first, column)
+            }
+        };
+
+        if self.y_range() != old_range {
+            ChartChange::All
+        } else {
+            change
+        }
+    }
+
+    /// The area of a chart in `rect` that has to be redrawn after a change.
+    pub fn changed_area(&self, rect: Rect, theme: &Theme, change: ChartChange) -> Rect {
+        let plot = self.layout(rect, theme).plot;
+        match change {
+            ChartChange::None => Rect::default(),
+            ChartChange::Columns(first, last) => self.columns_area(first, last, plot),
+            ChartChange::Advanced(first, last) => {
+                match self.mode {
+                    ChartMode::Scroll => plot,
+                    // the gap in front of the new columns moves as well
+                    ChartMode::Sweep => {
+                        self.columns_area(first, last + usize::from(SWEEP_GAP), plot)
+                    }
+                }
+            }
+            ChartChange::All => rect,
+        }
+    }
+
+    /// Draws the axes, the plot and the legend into `rect`.
+    pub fn draw<C: Canvas>(&self, painter: &mut Painter<C>, rect: Rect, theme: &Theme) {
+        let layout = self.layout(rect, theme);
+        let plot = layout.plot;
+        let clip = painter.clip().intersection(&rect);
+
+        if clip.intersection(&plot) != clip {
+            let (top, bottom) = (plot.y - rect.y, rect.bottom() - plot.bottom());
+            painter.fill_rect(Rect::new(rect.x, rect.y, rect.width, top), theme.background);
+            painter.fill_rect(Rect::new(rect.x, plot.bottom(), rect.width, bottom),
+                              theme.background);
+            painter.fill_rect(Rect::new(rect.x, plot.y, plot.x - rect.x, plot.height),
+                              theme.background);
+            self.draw_labels(painter, rect, &layout, theme);
+        }
+        if plot.is_empty() {
+            return;
+        }
+
+        let ticks = layout.y_ticks;
+        let (first_tick, last_tick) = ticks.indices();
+        let tick_ys: Vec<u16> = (first_tick..last_tick + 1)
+            .map(|index| value_y(plot, &ticks, index as f32 * ticks.step))
+            .collect();
+        let grid = theme.surface.lerp(theme.foreground, 0.25);
+        let columns = clip.intersection(&plot);
+        for x in columns.x..columns.right() {
+            let column_rect = Rect::new(x, plot.y, 1, plot.height);
+            if self.is_time_tick(x, &layout) {
+                painter.fill_rect(column_rect, grid);
+            } else {
+                painter.fill_rect(column_rect, theme.surface);
+                for &y in &tick_ys {
+                    painter.set_pixel(x, y, grid);
+                }
+            }
+
+            let age = match self.column_age(x, plot) {
+                Some(age) => age,
+                None => continue,
+            };
+            for series in &self.series {
+                let len = series.columns.len();
+                let front = self.newest.unwrap_or(0) + 1 - len;
+                let (low, high) = match series.span(len - 1 - age, front) {
+                    Some(span) => span,
+                    None => continue,
+                };
+                let (top, bottom) = (value_y(plot, &ticks, high), value_y(plot, &ticks, low));
+                painter.fill_rect(Rect::new(x, top, 1, bottom - top + 1), series.color);
+            }
+        }
+
+        let scale = theme.text_scale;
+        let mut legend_x = plot.x + 2 * scale;
+        for series in &self.series {
+            painter.text(legend_x, plot.y + 2 * scale, &series.name, scale, series.color);
+            legend_x = legend_x
+                .saturating_add(font::text_width(&series.name, scale))
+                .saturating_add(font::ADVANCE * scale * 2);
+        }
+    }
+
+    fn draw_labels<C: Canvas>(&self,
+                              painter: &mut Painter<C>,
+                              rect: Rect,
+                              layout: &Layout,
+                              theme: &Theme) {
+        let (plot, ticks) = (layout.plot, layout.y_ticks);
+        let scale = theme.text_scale;
+        let text_height = font::text_height(scale);
+        if plot.height > 0 {
+            let (first_tick, last_tick) = ticks.indices();
+            for index in first_tick..last_tick + 1 {
+                let value = index as f32 * ticks.step;
+                let text = format_value(value, ticks.decimals);
+                let width = font::text_width(&text, scale);
+                let x = cmp::max((plot.x - scale).saturating_sub(width), rect.x);
+                // the labels stay above the time axis
+                let y = value_y(plot, &ticks, value).saturating_sub(text_height / 2);
+                let y = cmp::max(cmp::min(y, plot.bottom().saturating_sub(text_height)), rect.y);
+                painter.text(x, y, &text, scale, theme.foreground);
+            }
+        }
+
+        let duration = u64::from(cmp::max(self.column_duration, 1));
+        let seconds = layout.time_tick as f32 / 1000.0;
+        let decimals = decimals_for_step(seconds);
+        for index in 0u64.. {
+            let offset = tick_offset(index, layout.time_tick, duration);
+            if offset >= u64::from(plot.width) {
+                break;
+            }
+            let (x, text) = match self.mode {
+                ChartMode::Scroll => {
+                    let text = format_value(-(index as f32) * seconds, decimals);
+                    (plot.right() - 1 - offset as u16, text)
+                }
+                ChartMode::Sweep => {
+                    (plot.x + offset as u16, format_value(index as f32 * seconds, decimals))
+                }
+            };
+            let text = text + "s";
+            let width = font::text_width(&text, scale);
+            let x = cmp::max(cmp::min(x.saturating_sub(width / 2),
+                                      rect.right().saturating_sub(width)),
+                             rect.x);
+            painter.text(x, plot.bottom() + scale, &text, scale, theme.foreground);
+        }
+    }
+
+    fn layout(&self, rect: Rect, theme: &Theme) -> Layout {
+        let scale = theme.text_scale;
+        let text_height = font::text_height(scale);
+        let ticks = self.y_ticks();
+        let (first_tick, last_tick) = ticks.indices();
+        let label_width = (first_tick..last_tick + 1)
+            .map(|index| {
+                     font::text_width(&format_value(index as f32 * ticks.step, ticks.decimals),
+                                      scale)
+                 })
+            .max()
+            .unwrap_or(0);
+
+        // the top label is centered at the top of the plot
+        let left = cmp::min(label_width + 2 * scale, rect.width);
+        let top = cmp::min(text_height / 2, rect.height);
+        let bottom = text_height + 2 * scale;
+        let plot = Rect::new(rect.x + left,
+                             rect.y + top,
+                             rect.width - left,
+                             rect.height.saturating_sub(top + bottom));
+
+        // the labels of the time axis must not overlap
+        let spacing = u64::from(font::text_width("-00.0s", scale) + 4 * scale);
+        let min_interval = spacing.saturating_mul(u64::from(cmp::max(self.column_duration, 1)));
+        let (mut magnitude, mut factor, mut time_tick) = (1u64, 0, 1u64);
+        while time_tick < min_interval {
+            // 1, 2, 5, 10, 20, 50, ..., the saturation ends the loop in any case
+            factor = (factor + 1) % 3;
+            if factor == 0 {
+                magnitude = magnitude.saturating_mul(10);
+            }
+            time_tick = magnitude.saturating_mul([1, 2, 5][factor]);
+        }
+
+        Layout {
+            plot: plot,
+            y_ticks: ticks,
+            time_tick: time_tick,
+        }
+    }
+
+    fn y_ticks(&self) -> Ticks {
+        let count = f32::from(cmp::max(self.y_tick_count, 1));
+        let (low, high, fit) = match self.y_axis {
+            YAxis::Fixed { min: low, max: high } => {
+                (math::min(low, high), math::max(low, high), false)
+            }
+            YAxis::Auto => {
+                let (low, high) = self.data_range.unwrap_or((0.0, 1.0));
+                (low, high, true)
+            }
+        };
+        let largest = math::max(math::max(math::abs(low), math::abs(high)), 1.0);
+        let (low, high) = if high - low > 1e-6 * largest {
+            (low, high)
+        } else {
+            // a constant value is shown in the middle
+            let margin = if low == 0.0 { 1.0 } else { math::abs(low) * 0.1 };
+            (low - margin, high + margin)
+        };
+
+        let rough_step = (high - low) / count;
+        let magnitude = math::powf(10.0, math::floor(math::log10(rough_step)));
+        let normalized = rough_step / magnitude;
+        let factor = if normalized <= 1.001 {
+            1.0
+        } else if normalized <= 2.001 {
+            2.0
+        } else if normalized <= 5.001 {
+            5.0
+        } else {
+            10.0
+        };
+        let step = factor * magnitude;
+        let (low, high) = if fit {
+            (math::floor(low / step + 0.001) * step, -math::floor(-high / step + 0.001) * step)
+        } else {
+            (low, high)
+        };
+        Ticks {
+            low: low,
+            high: high,
+            step: step,
+            decimals: decimals_for_step(step),
+        }
+    }
+
+    fn compute_data_range(&self) -> Option<(f32, f32)> {
+        self.series
+            .iter()
+            .flat_map(|series| series.columns.iter())
+            .fold(None, |range, bucket| match (range, *bucket) {
+                (None, Some(bucket)) => Some((bucket.min, bucket.max)),
+                (Some((low, high)), Some(bucket)) => {
+                    Some((math::min(low, bucket.min), math::max(high, bucket.max)))
+                }
+                (range, None) => range,
+            })
+    }
+
+    /// Number of columns between the newest column and the one shown at `x`, if any.
+    fn column_age(&self, x: u16, plot: Rect) -> Option<usize> {
+        let (newest, len) = match (self.newest, self.series.first()) {
+            (Some(newest), Some(series)) => (newest, series.columns.len()),
+            _ => return None,
+        };
+        let width = usize::from(plot.width);
+        let age = match self.mode {
+            ChartMode::Scroll => usize::from(plot.right() - 1 - x),
+            ChartMode::Sweep => {
+                let age = (newest % width + width - usize::from(x - plot.x)) % width;
+                if age + usize::from(SWEEP_GAP) >= width {
+                    return None;
+                }
+                age
+            }
+        };
+        if age < len && age <= newest {
+            Some(age)
+        } else {
+            None
+        }
+    }
+
+    /// The part of the plot that shows the columns from `first` to `last` (inclusive).
+    fn columns_area(&self, first: usize, last: usize, plot: Rect) -> Rect {
+        let newest = match self.newest {
+            Some(newest) if !plot.is_empty() => newest,
+            _ => return Rect::default(),
+        };
+        let visible = match self.mode {
+            ChartMode::Scroll => plot.width,
+            ChartMode::Sweep => plot.width.saturating_sub(SWEEP_GAP),
+        };
+        let first = cmp::max(first, (newest + 1).saturating_sub(usize::from(visible)));
+        if first > last {
+            return Rect::default();
+        }
+        let x = match self.mode {
+            ChartMode::Scroll => usize::from(plot.right() - 1) - (newest - first),
+            ChartMode::Sweep => usize::from(plot.x) + first % usize::from(plot.width),
+        };
+        let width = last - first + 1;
+        if x + width > usize::from(plot.right()) {
+            // wraps around in sweep mode
+            plot
+        } else {
+            Rect::new(x as u16, plot.y, width as u16, plot.height)
+        }
+    }
+
+    fn is_time_tick(&self, x: u16, layout: &Layout) -> bool {
+        let plot = layout.plot;
+        let offset = match self.mode {
+            ChartMode::Scroll => u64::from(plot.right() - 1 - x),
+            ChartMode::Sweep => u64::from(x - plot.x),
+        };
+        let duration = u64::from(cmp::max(self.column_duration, 1));
+        let index = offset
+            .saturating_mul(duration)
+            .saturating_add(layout.time_tick / 2) / layout.time_tick;
+        tick_offset(index, layout.time_tick, duration) == offset
+    }
+}
+
+/// Columns between the start of the time axis and the tick with the given index. Saturates
+/// far beyond the widest plot.
+fn tick_offset(index: u64, interval: u64, duration: u64) -> u64 {
+    index.saturating_mul(interval).saturating_add(duration / 2) / duration
+}
+
+/// The row of a value in the plot, limited to the plot.
+fn value_y(plot: Rect, ticks: &Ticks, value: f32) -> u16 {
+    let fraction = (value - ticks.low) / (ticks.high - ticks.low);
+    let fraction = if fraction > 0.0 { math::min(fraction, 1.0) } else { 0.0 };
+    plot.bottom() - 1 - math::round(fraction * f32::from(plot.height - 1)) as u16
+}
+
+/// Number of decimals that show multiples of `step` exactly.
+fn decimals_for_step(step: f32) -> usize {
+    cmp::max(math::to_i32(-math::floor(math::log10(step) + 0.001), MAX_INDEX), 0) as usize
+}
+
+/// Formats a value without a sign for zero.
+fn format_value(value: f32, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    let is_zero = text.bytes().all(|byte| byte == b'-' || byte == b'0' || byte == b'.');
+    if text.starts_with('-') && is_zero {
+        String::from(&text[1..])
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graphics::MemoryCanvas;
+    use lcd::color::{RED, TRANSPARENT};
+
+    fn time_tick(column_duration: u32) -> u64 {
+        let chart = Chart::new(100, column_duration);
+        chart.layout(Rect::new(0, 0, 400, 200), &Theme::default()).time_tick
+    }
+
+    #[test]
+    fn time_ticks_leave_room_for_the_labels() {
+        // labels are 78 pixels apart at the default text scale
+        assert_eq!(time_tick(0), 100);
+        assert_eq!(time_tick(1), 100);
+        assert_eq!(time_tick(10), 1000);
+        assert_eq!(time_tick(20), 2000);
+        assert_eq!(time_tick(100), 10_000);
+        assert_eq!(time_tick(u32::max_value()), 500_000_000_000);
+    }
+
+    #[test]
+    fn tick_offsets_saturate() {
+        assert_eq!(tick_offset(3, 1000, 10), 300);
+        assert_eq!(tick_offset(1, 15, 10), 2);
+        assert_eq!(tick_offset(2, u64::max_value(), 1), u64::max_value());
+    }
+
+    #[test]
+    fn long_columns_can_be_drawn() {
+        for &mode in [ChartMode::Scroll, ChartMode::Sweep].iter() {
+            let mut chart = Chart::new(200, u32::max_value());
+            chart.mode = mode;
+            let series = chart.add_series("a", RED);
+            chart.push(series, 0, 1.0);
+            chart.push(series, usize::max_value(), 2.0);
+            let mut canvas = MemoryCanvas::new(200, 100, TRANSPARENT);
+            {
+                let mut painter = Painter::new(&mut canvas, Rect::new(0, 0, 200, 100));
+                chart.draw(&mut painter, Rect::new(0, 0, 200, 100), &Theme::default());
+            }
+            assert!(canvas.pixels().iter().any(|&color| color == RED));
+        }
+    }
+
+    #[test]
+    fn y_ticks_and_labels() {
+        let mut chart = Chart::new(10, 10);
+        chart.y_axis = YAxis::Fixed { min: 10.0, max: -10.0 };
+        let ticks = chart.y_ticks();
+        assert_eq!((ticks.low, ticks.high), (-10.0, 10.0));
+        assert_eq!(ticks.indices(), (-2, 2));
+        assert_eq!(ticks.decimals, 0);
+
+        assert_eq!(decimals_for_step(0.25), 1);
+        assert_eq!(decimals_for_step(0.01), 2);
+        assert_eq!(decimals_for_step(::core::f32::NAN), 0);
+        assert_eq!(format_value(-0.01, 1), "0.0");
+        assert_eq!(format_value(-2.5, 1), "-2.5");
+    }
+}
@@ -0,0 +1,24 @@
+//! A small retained-mode widget toolkit for the LCD and the touch screen.
+//!
+//! Widgets are added to a tree that is owned by `Ui`. The containers arrange their children in
+//! rows or columns, and `Ui::render` only redraws the areas that changed since the last call.
+//! Touch events of the `touch::TouchTracker` are dispatched with `Ui::handle_touch`, and the
+//! resulting clicks and value changes are returned by `Ui::next_event`. Live data, e.g. audio
+//! levels or temperatures, can be plotted with a `Chart`.
+//!
+//! Drawing goes through the `graphics::Canvas` trait, so rendering and event handling also
+//! work on the host with a `graphics::MemoryCanvas`.
+
+pub use self::chart::{Chart, ChartChange, ChartMode, Series, YAxis};
+pub use self::painter::Painter;
+pub use self::theme::Theme;
+pub use self::ui::{Ui, WidgetId, Event};
+pub use self::widget::{Widget, Direction, Align, Container, Label, Button, Toggle, Slider,
+                       ProgressBar, List, Keypad};
+
+mod chart;
+pub mod font;
+mod painter;
+mod theme;
+mod ui;
+mod widget;
@@ -0,0 +1,82 @@
+use graphics::{Canvas, Rect};
+use lcd::Color;
+use super::font;
+
+/// Draws shapes and text to a canvas, clipped to a rectangle.
+pub struct Painter<'a, C: Canvas + 'a> {
+    canvas: &'a mut C,
+    clip: Rect,
+}
+
+impl<'a, C: Canvas> Painter<'a, C> {
+    /// Only pixels inside `clip` and inside the canvas are drawn.
+    pub fn new(canvas: &'a mut C, clip: Rect) -> Painter<'a, C> {
+        let (width, height) = canvas.size();
+        let clip = clip.intersection(&Rect::new(0, 0, width, height));
+        Painter {
+            canvas: canvas,
+            clip: clip,
+        }
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    pub fn set_pixel(&mut self, x: u16, y: u16, color: Color) {
+        if self.clip.contains(x, y) {
+            self.canvas.set_pixel(x, y, color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersection(&self.clip);
+        if !rect.is_empty() {
+            self.canvas.fill_rect(rect, color);
+        }
+    }
+
+    /// Draws the outline of a rectangle with the given line width.
+    pub fn stroke_rect(&mut self, rect: Rect, width: u16, color: Color) {
+        if 2 * width >= rect.width || 2 * width >= rect.height {
+            self.fill_rect(rect, color);
+            return;
+        }
+        let (inner_height, bottom) = (rect.height - 2 * width, rect.bottom() - width);
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, width), color);
+        self.fill_rect(Rect::new(rect.x, bottom, rect.width, width), color);
+        self.fill_rect(Rect::new(rect.x, rect.y + width, width, inner_height), color);
+        self.fill_rect(Rect::new(rect.right() - width, rect.y + width, width, inner_height),
+                       color);
+    }
+
+    /// Draws text with the top left corner at (`x`, `y`). Each font pixel becomes a
+    /// `scale`×`scale` square.
+    pub fn text(&mut self, x: u16, y: u16, text: &str, scale: u16, color: Color) {
+        let mut glyph_x = x;
+        for c in text.chars() {
+            let glyph = font::glyph(c);
+            for column in 0..font::GLYPH_WIDTH {
+                for row in 0..font::GLYPH_HEIGHT {
+                    if font::is_set(glyph, column, row) {
+                        let pixel = Rect::new(glyph_x + column * scale,
+                                              y + row * scale,
+                                              scale,
+                                              scale);
+                        self.fill_rect(pixel, color);
+                    }
+                }
+            }
+            glyph_x = glyph_x.saturating_add(font::ADVANCE * scale);
+        }
+    }
+
+    /// Draws a single line of text centered in `rect`.
+    pub fn text_centered(&mut self, rect: Rect, text: &str, scale: u16, color: Color) {
+        let width = font::text_width(text, scale);
+        let height = font::text_height(scale);
+        let x = rect.x + rect.width.saturating_sub(width) / 2;
+        let y = rect.y + rect.height.saturating_sub(height) / 2;
+        self.text(x, y, text, scale, color);
+    }
+}
@@ -0,0 +1,37 @@
+use lcd::Color;
+
+/// Colors and sizes used to draw the widgets.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Background of the `Ui` area and of containers.
+    pub background: Color,
+    /// Background of buttons, slider tracks, list rows and keys.
+    pub surface: Color,
+    /// Text and outlines.
+    pub foreground: Color,
+    /// Pressed buttons, enabled toggles, filled slider and progress bar parts, selections.
+    pub accent: Color,
+    /// Text of disabled widgets.
+    pub disabled: Color,
+    /// Outline of the focused widget.
+    pub focus: Color,
+    /// Scale of the 5×7 pixel font.
+    pub text_scale: u16,
+    /// Width of widget outlines in pixels.
+    pub border_width: u16,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            background: Color::rgb(0x20, 0x20, 0x20),
+            surface: Color::rgb(0x40, 0x40, 0x48),
+            foreground: Color::rgb(0xf0, 0xf0, 0xf0),
+            accent: Color::rgb(0x20, 0x90, 0xe0),
+            disabled: Color::rgb(0x80, 0x80, 0x80),
+            focus: Color::rgb(0xf0, 0xc0, 0x20),
+            text_scale: 2,
+            border_width: 1,
+        }
+    }
+}
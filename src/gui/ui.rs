@@ -0,0 +1,956 @@
+use arrayvec::ArrayVec;
+use collections::{Vec, VecDeque};
+use core::{cmp, mem};
+use graphics::{Canvas, Rect};
+use touch;
+use super::{Container, Direction, List, Painter, Theme, Widget};
+
+/// Maximum number of queued events. The oldest events are dropped first.
+const MAX_EVENTS: usize = 32;
+/// Maximum number of separately redrawn areas. Further areas are merged.
+const MAX_DIRTY_RECTS: usize = 8;
+
+/// Identifies a widget of a `Ui`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WidgetId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A button was released while the finger was on it, or it was activated by focus.
+    Clicked(WidgetId),
+    Toggled(WidgetId, bool),
+    /// The value of a slider changed.
+    ValueChanged(WidgetId, i32),
+    /// A list item was selected.
+    Selected(WidgetId, usize),
+    /// A digit was added to or removed from the text of a keypad.
+    KeypadChanged(WidgetId),
+    /// The OK key of a keypad was pressed.
+    KeypadEntered(WidgetId),
+}
+
+/// What the layout of a widget depends on, to detect changes through `Ui::widget_mut`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Geometry {
+    size: (u16, u16),
+    /// Direction, spacing and padding of a container.
+    container: Option<(Direction, u16, u16)>,
+}
+
+struct Node {
+    widget: Widget,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    /// Overrides the preferred size of the widget.
+    size: Option<(u16, u16)>,
+    rect: Rect,
+    enabled: bool,
+}
+
+/// The widget and finger of the current touch.
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    widget: WidgetId,
+    touch_id: u8,
+    start_y: u16,
+    start_scroll: usize,
+    scrolled: bool,
+}
+
+/// A tree of widgets in a rectangular area of the screen.
+pub struct Ui {
+    bounds: Rect,
+    theme: Theme,
+    nodes: Vec<Node>,
+    focus: Option<WidgetId>,
+    press: Option<Press>,
+    needs_layout: bool,
+    /// Widgets changed through `widget_mut` since the last layout, with their geometry before
+    /// the first change.
+    changed: Vec<(WidgetId, Geometry)>,
+    dirty: ArrayVec<[Rect; MAX_DIRTY_RECTS]>,
+    events: VecDeque<Event>,
+}
+
+impl Ui {
+    /// Creates a `Ui` with a column container as root that fills `bounds`.
+    pub fn new(bounds: Rect, theme: Theme) -> Ui {
+        let root = Node {
+            widget: Widget::Container(Container::column()),
+            parent: None,
+            children: Vec::new(),
+            size: None,
+            rect: bounds,
+            enabled: true,
+        };
+        let mut ui = Ui {
+            bounds: bounds,
+            theme: theme,
+            nodes: vec![root],
+            focus: None,
+            press: None,
+            needs_layout: true,
+            changed: Vec::new(),
+            dirty: ArrayVec::new(),
+            events: VecDeque::with_capacity(MAX_EVENTS),
+        };
+        ui.invalidate_all();
+        ui
+    }
+
+    pub fn root(&self) -> WidgetId {
+        WidgetId(0)
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.needs_layout = true;
+        self.invalidate_all();
+    }
+
+    /// Adds a widget as last child of a container.
+    ///
+    /// Panics if `parent` is not a container.
+    pub fn add(&mut self, parent: WidgetId, widget: Widget) -> WidgetId {
+        match self.nodes[parent.0].widget {
+            Widget::Container(_) => {}
+            _ => panic!("widgets can only be added to containers"),
+        }
+        let id = WidgetId(self.nodes.len());
+        self.nodes
+            .push(Node {
+                      widget: widget,
+                      parent: Some(parent),
+                      children: Vec::new(),
+                      size: None,
+                      rect: Rect::default(),
+                      enabled: true,
+                  });
+        self.nodes[parent.0].children.push(id);
+        self.needs_layout = true;
+        id
+    }
+
+    /// Overrides the preferred size of a widget. Zero means that the widget fills the available
+    /// space in that direction.
+    pub fn set_size(&mut self, id: WidgetId, width: u16, height: u16) {
+        self.nodes[id.0].size = Some((width, height));
+        self.needs_layout = true;
+    }
+
+    pub fn parent(&self, id: WidgetId) -> Option<WidgetId> {
+        self.nodes[id.0].parent
+    }
+
+    /// The position of a widget after the last layout.
+    pub fn rect(&self, id: WidgetId) -> Rect {
+        self.nodes[id.0].rect
+    }
+
+    pub fn widget(&self, id: WidgetId) -> &Widget {
+        &self.nodes[id.0].widget
+    }
+
+    /// Gives mutable access to a widget, which is redrawn by the next `render`. The widgets are
+    /// only arranged again if the change affects the layout, e.g. a longer text or a different
+    /// container spacing.
+    pub fn widget_mut(&mut self, id: WidgetId) -> &mut Widget {
+        let rect = self.nodes[id.0].rect;
+        self.mark_dirty(rect);
+        if !self.needs_layout && !self.changed.iter().any(|&(changed, _)| changed == id) {
+            let geometry = self.geometry(id);
+            self.changed.push((id, geometry));
+        }
+        &mut self.nodes[id.0].widget
+    }
+
+    /// Sets the text of a label, button or toggle.
+    pub fn set_text(&mut self, id: WidgetId, text: &str) {
+        match *self.widget_mut(id) {
+            Widget::Label(ref mut label) => label.text = text.into(),
+            Widget::Button(ref mut button) => button.text = text.into(),
+            Widget::Toggle(ref mut toggle) => toggle.text = text.into(),
+            _ => {}
+        }
+    }
+
+    /// Sets the value of a slider or progress bar. No event is generated.
+    pub fn set_value(&mut self, id: WidgetId, value: i32) {
+        match *self.widget_mut(id) {
+            Widget::Slider(ref mut slider) => {
+                slider.set_value(value);
+            }
+            Widget::ProgressBar(ref mut bar) => bar.value = cmp::max(value, 0) as u32,
+            _ => {}
+        }
+    }
+
+    /// Adds a sample to a series of a chart, see `Chart::push`. Unlike changes through
+    /// `widget_mut`, only the changed part of the chart is redrawn by the next `render`.
+    pub fn push_sample(&mut self, id: WidgetId, series: usize, time: usize, value: f32) {
+        let rect = self.nodes[id.0].rect;
+        let theme = self.theme;
+        let area = match self.nodes[id.0].widget {
+            Widget::Chart(ref mut chart) => {
+                let change = chart.push(series, time, value);
+                chart.changed_area(rect, &theme, change)
+            }
+            _ => return,
+        };
+        self.mark_dirty(area);
+    }
+
+    pub fn is_enabled(&self, id: WidgetId) -> bool {
+        self.nodes[id.0].enabled
+    }
+
+    /// Disabled widgets are drawn grayed out and ignore touches and focus.
+    pub fn set_enabled(&mut self, id: WidgetId, enabled: bool) {
+        if self.nodes[id.0].enabled != enabled {
+            self.nodes[id.0].enabled = enabled;
+            let rect = self.nodes[id.0].rect;
+            self.mark_dirty(rect);
+            if !enabled && self.focus == Some(id) {
+                self.set_focus(None);
+            }
+        }
+    }
+
+    /// Returns the oldest unhandled event.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    pub fn focused(&self) -> Option<WidgetId> {
+        self.focus
+    }
+
+    /// Moves the focus. Widgets that are not interactive or disabled can't be focused.
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        let id = id.and_then(|id| if self.is_focusable(id) { Some(id) } else { None });
+        if id == self.focus {
+            return;
+        }
+        if let Some(old) = self.focus {
+            let rect = self.nodes[old.0].rect;
+            self.mark_dirty(rect);
+        }
+        if let Some(new) = id {
+            let rect = self.nodes[new.0].rect;
+            self.mark_dirty(rect);
+        }
+        self.focus = id;
+    }
+
+    /// Focuses the next focusable widget in tree order, wrapping around at the end.
+    pub fn focus_next(&mut self) {
+        let len = self.nodes.len();
+        let start = self.focus.map(|id| id.0 + 1).unwrap_or(0);
+        let next = (0..len)
+            .map(|offset| WidgetId((start + offset) % len))
+            .find(|&id| self.is_focusable(id));
+        self.set_focus(next);
+    }
+
+    /// Focuses the previous focusable widget in tree order, wrapping around at the start.
+    pub fn focus_previous(&mut self) {
+        let len = self.nodes.len();
+        let start = self.focus.map(|id| id.0).unwrap_or(0);
+        let previous = (1..len + 1)
+            .map(|offset| WidgetId((start + len - offset) % len))
+            .find(|&id| self.is_focusable(id));
+        self.set_focus(previous);
+    }
+
+    /// Activates the focused widget as if it was tapped: clicks a button, flips a toggle,
+    /// confirms a keypad entry or reports the selected list item again.
+    pub fn activate_focused(&mut self) {
+        let id = match self.focus {
+            Some(id) => id,
+            None => return,
+        };
+        let event = match *self.widget_mut(id) {
+            Widget::Button(_) => Some(Event::Clicked(id)),
+            Widget::Toggle(ref mut toggle) => {
+                toggle.on = !toggle.on;
+                Some(Event::Toggled(id, toggle.on))
+            }
+            Widget::List(ref list) => list.selected.map(|index| Event::Selected(id, index)),
+            Widget::Keypad(_) => Some(Event::KeypadEntered(id)),
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.push_event(event);
+        }
+    }
+
+    /// Changes the focused slider by `steps` steps, or moves the selection of the focused list
+    /// by `steps` items.
+    pub fn adjust_focused(&mut self, steps: i32) {
+        let id = match self.focus {
+            Some(id) => id,
+            None => return,
+        };
+        let rect = self.nodes[id.0].rect;
+        let theme = self.theme;
+        let event = match *self.widget_mut(id) {
+            Widget::Slider(ref mut slider) => {
+                let value = slider.value.saturating_add(steps.saturating_mul(slider.step));
+                if slider.set_value(value) {
+                    Some(Event::ValueChanged(id, slider.value))
+                } else {
+                    None
+                }
+            }
+            Widget::List(ref mut list) => {
+                let last = list.items.len() as i32 - 1;
+                let current = list.selected.map(|index| index as i32).unwrap_or(-1);
+                let index = cmp::max(0, cmp::min(last, current + steps)) as usize;
+                if last < 0 || list.selected == Some(index) {
+                    None
+                } else {
+                    list.selected = Some(index);
+                    list.scroll_to_selected(rect, &theme);
+                    Some(Event::Selected(id, index))
+                }
+            }
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.push_event(event);
+        }
+    }
+
+    /// Handles an event of the `touch::TouchTracker`.
+    ///
+    /// A touch belongs to the widget where the finger touched down, until the finger is lifted.
+    /// Only one finger is tracked at a time.
+    pub fn handle_touch(&mut self, event: &touch::Event) {
+        match *event {
+            touch::Event::Down { id, x, y } => self.touch_down(id, x, y),
+            touch::Event::Move { id, x, y } => self.touch_move(id, x, y),
+            touch::Event::Up { id, x, y } => self.touch_up(id, x, y),
+            touch::Event::Gesture(_) => {}
+        }
+    }
+
+    /// The innermost enabled interactive widget at a position.
+    pub fn hit_test(&mut self, x: u16, y: u16) -> Option<WidgetId> {
+        self.layout_if_needed();
+        // children are always added after their parents, so the last match is the innermost
+        (0..self.nodes.len())
+            .rev()
+            .map(WidgetId)
+            .find(|&id| self.is_focusable(id) && self.nodes[id.0].rect.contains(x, y))
+    }
+
+    /// Redraws everything on the next `render`.
+    pub fn invalidate_all(&mut self) {
+        self.dirty.clear();
+        let bounds = self.bounds;
+        self.mark_dirty(bounds);
+    }
+
+    /// Draws all areas that changed since the last call. Returns whether anything was drawn.
+    pub fn render<C: Canvas>(&mut self, canvas: &mut C) -> bool {
+        self.layout_if_needed();
+        if self.dirty.is_empty() {
+            return false;
+        }
+
+        let dirty = mem::replace(&mut self.dirty, ArrayVec::new());
+        for area in dirty {
+            let mut painter = Painter::new(canvas, area);
+            painter.fill_rect(area, self.theme.background);
+            // parents are drawn before their children because they were added earlier
+            for (index, node) in self.nodes.iter().enumerate() {
+                if node.rect.intersects(&area) {
+                    let focused = self.focus == Some(WidgetId(index));
+                    let enabled = self.is_enabled_in_tree(WidgetId(index));
+                    node.widget.draw(&mut painter, node.rect, &self.theme, focused, enabled);
+                }
+            }
+        }
+        true
+    }
+
+    /// Computes the positions of all widgets and marks moved widgets for redrawing.
+    pub fn layout(&mut self) {
+        self.needs_layout = false;
+        self.changed.clear();
+        let (root, bounds) = (self.root(), self.bounds);
+        self.set_rect(root, bounds);
+        self.layout_children(root);
+    }
+
+    fn layout_if_needed(&mut self) {
+        if self.changed.iter().any(|&(id, geometry)| self.geometry(id) != geometry) {
+            self.needs_layout = true;
+        }
+        self.changed.clear();
+        if self.needs_layout {
+            self.layout();
+        }
+    }
+
+    fn layout_children(&mut self, id: WidgetId) {
+        let container = match self.nodes[id.0].widget {
+            Widget::Container(container) => container,
+            _ => return,
+        };
+        let children = self.nodes[id.0].children.clone();
+        if children.is_empty() {
+            return;
+        }
+
+        let inner = self.nodes[id.0].rect.inset(container.padding);
+        let (start, available) = match container.direction {
+            Direction::Row => (inner.x, inner.width),
+            Direction::Column => (inner.y, inner.height),
+        };
+        let sizes: Vec<u16> = children
+            .iter()
+            .map(|&child| {
+                     let (width, height) = self.preferred_size(child);
+                     match container.direction {
+                         Direction::Row => width,
+                         Direction::Column => height,
+                     }
+                 })
+            .collect();
+        let spacing = container.spacing * (children.len() as u16 - 1);
+        let fixed = sizes.iter().fold(0u16, |sum, &size| sum.saturating_add(size));
+        let fill_count = sizes.iter().filter(|&&size| size == 0).count() as u16;
+        let fill_size = if fill_count > 0 {
+            available.saturating_sub(fixed.saturating_add(spacing)) / fill_count
+        } else {
+            0
+        };
+
+        let end = start + available;
+        let mut position = start;
+        for (&child, &size) in children.iter().zip(sizes.iter()) {
+            let size = if size == 0 { fill_size } else { size };
+            let size = cmp::min(size, end.saturating_sub(position));
+            let rect = match container.direction {
+                Direction::Row => Rect::new(position, inner.y, size, inner.height),
+                Direction::Column => Rect::new(inner.x, position, inner.width, size),
+            };
+            self.set_rect(child, rect);
+            self.layout_children(child);
+            position = cmp::min(position.saturating_add(size + container.spacing), end);
+        }
+    }
+
+    fn preferred_size(&self, id: WidgetId) -> (u16, u16) {
+        let node = &self.nodes[id.0];
+        node.size.unwrap_or_else(|| node.widget.preferred_size(&self.theme))
+    }
+
+    fn geometry(&self, id: WidgetId) -> Geometry {
+        let container = match self.nodes[id.0].widget {
+            Widget::Container(ref c) => Some((c.direction, c.spacing, c.padding)),
+            _ => None,
+        };
+        Geometry {
+            size: self.preferred_size(id),
+            container: container,
+        }
+    }
+
+    fn set_rect(&mut self, id: WidgetId, rect: Rect) {
+        let old = self.nodes[id.0].rect;
+        if old != rect {
+            self.mark_dirty(old);
+            self.mark_dirty(rect);
+            self.nodes[id.0].rect = rect;
+        }
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        let rect = rect.intersection(&self.bounds);
+        if rect.is_empty() {
+            return;
+        }
+        if let Some(existing) = self.dirty.iter_mut().find(|r| r.intersects(&rect)) {
+            *existing = existing.union(&rect);
+            return;
+        }
+        if let Some(rect) = self.dirty.push(rect) {
+            let last = self.dirty.len() - 1;
+            self.dirty[last] = self.dirty[last].union(&rect);
+        }
+    }
+
+    fn is_focusable(&self, id: WidgetId) -> bool {
+        id.0 < self.nodes.len() && self.nodes[id.0].widget.is_interactive() &&
+        self.is_enabled_in_tree(id)
+    }
+
+    /// A widget is disabled if it or one of its parents is disabled.
+    fn is_enabled_in_tree(&self, id: WidgetId) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if !self.nodes[id.0].enabled {
+                return false;
+            }
+            current = self.nodes[id.0].parent;
+        }
+        true
+    }
+
+    fn touch_down(&mut self, touch_id: u8, x: u16, y: u16) {
+        if self.press.is_some() {
+            return;
+        }
+        let id = match self.hit_test(x, y) {
+            Some(id) => id,
+            None => return,
+        };
+        self.set_focus(Some(id));
+
+        let rect = self.nodes[id.0].rect;
+        let mut start_scroll = 0;
+        let event = match *self.widget_mut(id) {
+            Widget::Button(ref mut button) => {
+                button.pressed = true;
+                None
+            }
+            Widget::Slider(ref mut slider) => {
+                let value = slider.value_at(rect, x);
+                if slider.set_value(value) {
+                    Some(Event::ValueChanged(id, value))
+                } else {
+                    None
+                }
+            }
+            Widget::List(ref list) => {
+                start_scroll = list.scroll;
+                None
+            }
+            Widget::Keypad(ref mut keypad) => {
+                keypad.pressed = keypad.key_at(rect, x, y);
+                None
+            }
+            _ => None,
+        };
+        if let Some(event) = event {
+            self.push_event(event);
+        }
+
+        self.press = Some(Press {
+                              widget: id,
+                              touch_id: touch_id,
+                              start_y: y,
+                              start_scroll: start_scroll,
+                              scrolled: false,
+                          });
+    }
+
+    fn touch_move(&mut self, touch_id: u8, x: u16, y: u16) {
+        let mut press = match self.press {
+            Some(press) if press.touch_id == touch_id => press,
+            _ => return,
+        };
+        let id = press.widget;
+        let rect = self.nodes[id.0].rect;
+        let theme = self.theme;
+        let event = match *self.widget_mut(id) {
+            Widget::Button(ref mut button) => {
+                button.pressed = rect.contains(x, y);
+                None
+            }
+            Widget::Slider(ref mut slider) => {
+                let value = slider.value_at(rect, x);
+                if slider.set_value(value) {
+                    Some(Event::ValueChanged(id, value))
+                } else {
+                    None
+                }
+            }
+            Widget::List(ref mut list) => {
+                let row_height = i32::from(List::row_height(&theme));
+                let rows = (i32::from(press.start_y) - i32::from(y)) / row_height;
+                if rows != 0 {
+                    press.scrolled = true;
+                }
+                let scroll = cmp::max(press.start_scroll as i32 + rows, 0) as usize;
+                list.scroll_to(scroll, rect, &theme);
+                None
+            }
+            Widget::Keypad(ref mut keypad) => {
+                if keypad.pressed != keypad.key_at(rect, x, y) {
+                    keypad.pressed = None;
+                }
+                None
+            }
+            _ => None,
+        };
+        self.press = Some(press);
+        if let Some(event) = event {
+            self.push_event(event);
+        }
+    }
+
+    fn touch_up(&mut self, touch_id: u8, x: u16, y: u16) {
+        let press = match self.press {
+            Some(press) if press.touch_id == touch_id => press,
+            _ => return,
+        };
+        self.press = None;
+        let id = press.widget;
+        let rect = self.nodes[id.0].rect;
+        let theme = self.theme;
+        let inside = rect.contains(x, y);
+
+        let mut events = ArrayVec::<[Event; 2]>::new();
+        match *self.widget_mut(id) {
+            Widget::Button(ref mut button) => {
+                if button.pressed && inside {
+                    events.push(Event::Clicked(id));
+                }
+                button.pressed = false;
+            }
+            Widget::Toggle(ref mut toggle) if inside => {
+                toggle.on = !toggle.on;
+                events.push(Event::Toggled(id, toggle.on));
+            }
+            Widget::List(ref mut list) if inside && !press.scrolled => {
+                if let Some(index) = list.item_at(rect, &theme, y) {
+                    list.selected = Some(index);
+                    events.push(Event::Selected(id, index));
+                }
+            }
+            Widget::Keypad(ref mut keypad) => {
+                if let Some(key) = keypad.pressed.take() {
+                    match keypad.press_key(key) {
+                        Some(true) => {
+                            events.push(Event::KeypadEntered(id));
+                        }
+                        Some(false) => {
+                            events.push(Event::KeypadChanged(id));
+                        }
+                        None => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        for event in events {
+            self.push_event(event);
+        }
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.events.push_back(event);
+        while self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+    use graphics::MemoryCanvas;
+    use gui::{Button, Keypad, Label, Slider, Toggle};
+    use lcd::color::TRANSPARENT;
+
+    fn events(ui: &mut Ui) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = ui.next_event() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn tap(ui: &mut Ui, x: u16, y: u16) {
+        ui.handle_touch(&touch::Event::Down { id: 0, x: x, y: y });
+        ui.handle_touch(&touch::Event::Up { id: 0, x: x, y: y });
+    }
+
+    fn center(rect: Rect) -> (u16, u16) {
+        (rect.x + rect.width / 2, rect.y + rect.height / 2)
+    }
+
+    /// Whether any of the pending redraws overlaps `rect`.
+    fn is_dirty(ui: &Ui, rect: Rect) -> bool {
+        ui.dirty.iter().any(|dirty| dirty.intersects(&rect))
+    }
+
+    #[test]
+    fn column_layout() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), Theme::default());
+        let root = ui.root();
+        let label = ui.add(root, Widget::Label(Label::new("Hi")));
+        let button = ui.add(root, Widget::Button(Button::new("OK")));
+        let fill = ui.add(root, Widget::Container(Container::row()));
+        ui.layout();
+        // text height 14 plus 2 * 4 margin, padding and spacing 4
+        assert_eq!(ui.rect(label), Rect::new(4, 4, 192, 22));
+        assert_eq!(ui.rect(button), Rect::new(4, 30, 192, 22));
+        assert_eq!(ui.rect(fill), Rect::new(4, 56, 192, 40));
+        assert_eq!(ui.parent(button), Some(root));
+    }
+
+    #[test]
+    fn row_layout_shares_the_space_between_filling_children() {
+        let mut ui = Ui::new(Rect::new(10, 20, 300, 50), Theme::default());
+        let root = ui.root();
+        let row = ui.add(root, Widget::Container(Container::row()));
+        let left = ui.add(row, Widget::Slider(Slider::new(0, 10, 0)));
+        let fixed = ui.add(row, Widget::Button(Button::new("OK")));
+        let right = ui.add(row, Widget::Container(Container::column()));
+        ui.set_size(fixed, 100, 0);
+        ui.layout();
+        assert_eq!(ui.rect(row), Rect::new(14, 24, 292, 42));
+        // 284 pixels inside the padding, minus two gaps and the fixed button
+        assert_eq!(ui.rect(left), Rect::new(18, 28, 88, 34));
+        assert_eq!(ui.rect(fixed), Rect::new(110, 28, 100, 34));
+        assert_eq!(ui.rect(right), Rect::new(214, 28, 88, 34));
+    }
+
+    #[test]
+    fn children_are_clipped_to_their_container() {
+        let mut ui = Ui::new(Rect::new(0, 0, 100, 40), Theme::default());
+        let root = ui.root();
+        let first = ui.add(root, Widget::Label(Label::new("one")));
+        let second = ui.add(root, Widget::Label(Label::new("two")));
+        let third = ui.add(root, Widget::Label(Label::new("three")));
+        ui.layout();
+        assert_eq!(ui.rect(first), Rect::new(4, 4, 92, 22));
+        assert_eq!(ui.rect(second), Rect::new(4, 30, 92, 6));
+        assert!(ui.rect(third).is_empty());
+    }
+
+    #[test]
+    fn state_changes_do_not_rearrange_the_widgets() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), Theme::default());
+        let root = ui.root();
+        let slider = ui.add(root, Widget::Slider(Slider::new(0, 100, 0)));
+        let button = ui.add(root, Widget::Button(Button::new("OK")));
+        let mut canvas = MemoryCanvas::new(200, 100, TRANSPARENT);
+        ui.render(&mut canvas);
+
+        let (x, y) = center(ui.rect(slider));
+        ui.handle_touch(&touch::Event::Down { id: 0, x: x, y: y });
+        ui.handle_touch(&touch::Event::Move { id: 0, x: x + 20, y: y });
+        ui.set_value(slider, 30);
+        assert!(!ui.needs_layout);
+        assert!(ui.changed.iter().all(|&(id, geometry)| ui.geometry(id) == geometry));
+        assert!(is_dirty(&ui, ui.rect(slider)));
+        assert!(!is_dirty(&ui, ui.rect(button)));
+        assert!(ui.render(&mut canvas));
+        assert!(ui.changed.is_empty());
+        assert!(!ui.render(&mut canvas));
+    }
+
+    #[test]
+    fn geometry_changes_rearrange_the_widgets() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), Theme::default());
+        let root = ui.root();
+        let row = ui.add(root, Widget::Container(Container::row()));
+        let label = ui.add(row, Widget::Label(Label::new("a")));
+        let button = ui.add(row, Widget::Button(Button::new("OK")));
+        let mut canvas = MemoryCanvas::new(200, 100, TRANSPARENT);
+        ui.render(&mut canvas);
+        assert_eq!(ui.rect(button).x, 8 + 10 + 4);
+
+        ui.set_text(label, "abc");
+        ui.render(&mut canvas);
+        assert_eq!(ui.rect(label).width, 34);
+        assert_eq!(ui.rect(button).x, 8 + 34 + 4);
+
+        if let Widget::Container(ref mut container) = *ui.widget_mut(row) {
+            container.spacing = 10;
+        }
+        ui.render(&mut canvas);
+        assert_eq!(ui.rect(button).x, 8 + 34 + 10);
+    }
+
+    #[test]
+    fn render_only_redraws_changed_areas() {
+        let theme = Theme::default();
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), theme);
+        let root = ui.root();
+        let top = ui.add(root, Widget::Button(Button::new("top")));
+        let bottom = ui.add(root, Widget::Button(Button::new("bottom")));
+        let mut canvas = MemoryCanvas::new(200, 100, TRANSPARENT);
+        assert!(ui.render(&mut canvas));
+        assert_eq!(canvas.pixel(0, 0), Some(theme.background));
+        assert!(!ui.render(&mut canvas));
+
+        let (top_rect, bottom_rect) = (ui.rect(top), ui.rect(bottom));
+        canvas.fill_rect(Rect::new(0, 0, 200, 100), TRANSPARENT);
+        ui.set_text(bottom, "BOTTOM");
+        assert!(ui.render(&mut canvas));
+        assert_eq!(canvas.pixel(top_rect.x, top_rect.y), Some(TRANSPARENT));
+        assert_eq!(canvas.pixel(bottom_rect.x, bottom_rect.y), Some(theme.foreground));
+    }
+
+    #[test]
+    fn button_clicks_only_when_released_on_it() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), Theme::default());
+        let root = ui.root();
+        let button = ui.add(root, Widget::Button(Button::new("OK")));
+        ui.layout();
+        let (x, y) = center(ui.rect(button));
+
+        tap(&mut ui, x, y);
+        assert_eq!(events(&mut ui), vec![Event::Clicked(button)]);
+        assert_eq!(ui.focused(), Some(button));
+
+        ui.handle_touch(&touch::Event::Down { id: 1, x: x, y: y });
+        ui.handle_touch(&touch::Event::Move { id: 1, x: x, y: 99 });
+        ui.handle_touch(&touch::Event::Up { id: 1, x: x, y: 99 });
+        assert_eq!(events(&mut ui), vec![]);
+
+        // a second finger is ignored
+        ui.handle_touch(&touch::Event::Down { id: 1, x: x, y: y });
+        ui.handle_touch(&touch::Event::Up { id: 2, x: x, y: y });
+        assert_eq!(events(&mut ui), vec![]);
+        ui.handle_touch(&touch::Event::Up { id: 1, x: x, y: y });
+        assert_eq!(events(&mut ui), vec![Event::Clicked(button)]);
+    }
+
+    #[test]
+    fn toggle_slider_and_keypad_events() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 300), Theme::default());
+        let root = ui.root();
+        let toggle = ui.add(root, Widget::Toggle(Toggle::new("On", false)));
+        let slider = ui.add(root, Widget::Slider(Slider::new(0, 10, 5)));
+        let keypad = ui.add(root, Widget::Keypad(Keypad::new(2)));
+        ui.layout();
+
+        let (x, y) = center(ui.rect(toggle));
+        tap(&mut ui, x, y);
+        tap(&mut ui, x, y);
+        assert_eq!(events(&mut ui),
+                   vec![Event::Toggled(toggle, true), Event::Toggled(toggle, false)]);
+
+        let rect = ui.rect(slider);
+        let (_, y) = center(rect);
+        ui.handle_touch(&touch::Event::Down { id: 0, x: rect.x, y: y });
+        ui.handle_touch(&touch::Event::Move { id: 0, x: rect.right() - 1, y: y });
+        ui.handle_touch(&touch::Event::Up { id: 0, x: rect.right() - 1, y: y });
+        assert_eq!(events(&mut ui),
+                   vec![Event::ValueChanged(slider, 0), Event::ValueChanged(slider, 10)]);
+
+        // keys "1", "2", "3" and "OK" in rows of 240 / 5 pixels
+        let rect = ui.rect(keypad);
+        let (key_width, key_height) = (rect.width / 3, rect.height / 5);
+        let key = |column: u16, row: u16| {
+            (rect.x + column * key_width + key_width / 2,
+             rect.y + row * key_height + key_height / 2)
+        };
+        for &(column, row) in [(0, 1), (1, 1), (2, 1), (2, 4)].iter() {
+            let (x, y) = key(column, row);
+            tap(&mut ui, x, y);
+        }
+        assert_eq!(events(&mut ui),
+                   vec![Event::KeypadChanged(keypad),
+                        Event::KeypadChanged(keypad),
+                        Event::KeypadEntered(keypad)]);
+        match *ui.widget(keypad) {
+            Widget::Keypad(ref keypad) => assert_eq!(keypad.value(), Some(12)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn list_selection_and_scrolling() {
+        let theme = Theme::default();
+        let row_height = List::row_height(&theme);
+        let mut ui = Ui::new(Rect::new(0, 0, 100, 8 + 3 * row_height), theme);
+        let root = ui.root();
+        let list = ui.add(root, Widget::List(List::new(&["a", "b", "c", "d", "e"])));
+        ui.layout();
+        let rect = ui.rect(list);
+        let x = rect.x + 10;
+        let row_y = |row: u16| rect.y + row * row_height + row_height / 2;
+
+        tap(&mut ui, x, row_y(1));
+        assert_eq!(events(&mut ui), vec![Event::Selected(list, 1)]);
+
+        // dragging up by two rows scrolls and doesn't select
+        ui.handle_touch(&touch::Event::Down { id: 0, x: x, y: row_y(2) });
+        ui.handle_touch(&touch::Event::Move { id: 0, x: x, y: row_y(0) });
+        ui.handle_touch(&touch::Event::Up { id: 0, x: x, y: row_y(0) });
+        assert_eq!(events(&mut ui), vec![]);
+        match *ui.widget(list) {
+            Widget::List(ref list) => assert_eq!(list.scroll, 2),
+            _ => unreachable!(),
+        }
+
+        tap(&mut ui, x, row_y(0));
+        assert_eq!(events(&mut ui), vec![Event::Selected(list, 2)]);
+        ui.adjust_focused(5);
+        ui.adjust_focused(1);
+        ui.adjust_focused(-1);
+        assert_eq!(events(&mut ui),
+                   vec![Event::Selected(list, 4), Event::Selected(list, 3)]);
+    }
+
+    #[test]
+    fn focus_skips_disabled_and_passive_widgets() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 200), Theme::default());
+        let root = ui.root();
+        let first = ui.add(root, Widget::Button(Button::new("1")));
+        ui.add(root, Widget::Label(Label::new("label")));
+        let row = ui.add(root, Widget::Container(Container::row()));
+        let disabled = ui.add(row, Widget::Button(Button::new("2")));
+        let last = ui.add(root, Widget::Toggle(Toggle::new("3", false)));
+        ui.set_enabled(row, false);
+
+        ui.focus_next();
+        assert_eq!(ui.focused(), Some(first));
+        ui.focus_next();
+        assert_eq!(ui.focused(), Some(last));
+        ui.focus_next();
+        assert_eq!(ui.focused(), Some(first));
+        ui.focus_previous();
+        assert_eq!(ui.focused(), Some(last));
+
+        ui.activate_focused();
+        assert_eq!(events(&mut ui), vec![Event::Toggled(last, true)]);
+
+        ui.layout();
+        let (x, y) = center(ui.rect(disabled));
+        assert_eq!(ui.hit_test(x, y), None);
+        ui.set_enabled(row, true);
+        assert_eq!(ui.hit_test(x, y), Some(disabled));
+
+        ui.set_focus(Some(last));
+        ui.set_enabled(last, false);
+        assert_eq!(ui.focused(), None);
+    }
+
+    #[test]
+    fn event_queue_keeps_the_newest_events() {
+        let mut ui = Ui::new(Rect::new(0, 0, 200, 100), Theme::default());
+        let root = ui.root();
+        let button = ui.add(root, Widget::Button(Button::new("OK")));
+        let slider = ui.add(root, Widget::Slider(Slider::new(0, 100, 0)));
+        ui.set_focus(Some(button));
+        for _ in 0..10 {
+            ui.activate_focused();
+        }
+        ui.set_focus(Some(slider));
+        for _ in 0..40 {
+            ui.adjust_focused(1);
+        }
+        let events = events(&mut ui);
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0], Event::ValueChanged(slider, 9));
+        assert_eq!(events[MAX_EVENTS - 1], Event::ValueChanged(slider, 40));
+    }
+}
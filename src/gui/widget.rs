@@ -0,0 +1,580 @@
+use collections::{String, Vec};
+use core::cmp;
+use graphics::{Canvas, Rect};
+use super::{font, Chart, Painter, Theme};
+
+/// Keys of the numeric keypad, row by row.
+const KEYPAD_KEYS: [&'static str; 12] = ["1", "2", "3", "4", "5", "6", "7", "8", "9", "<", "0",
+                                         "OK"];
+const KEYPAD_COLUMNS: u16 = 3;
+/// The entered text and four rows of keys.
+const KEYPAD_ROWS: u16 = 5;
+
+/// Direction in which a container arranges its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Arranges its children in a row or column.
+///
+/// Children with a preferred size keep it along the direction, the remaining space is shared
+/// by the children that should fill. In the other direction all children are stretched.
+#[derive(Debug, Clone, Copy)]
+pub struct Container {
+    pub direction: Direction,
+    /// Space between two children in pixels.
+    pub spacing: u16,
+    /// Space around the children in pixels.
+    pub padding: u16,
+}
+
+impl Container {
+    pub fn row() -> Container {
+        Container {
+            direction: Direction::Row,
+            spacing: 4,
+            padding: 4,
+        }
+    }
+
+    pub fn column() -> Container {
+        Container {
+            direction: Direction::Column,
+            spacing: 4,
+            padding: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub text: String,
+    pub align: Align,
+}
+
+impl Label {
+    pub fn new(text: &str) -> Label {
+        Label {
+            text: String::from(text),
+            align: Align::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Button {
+    pub text: String,
+    /// Whether the button is currently held down.
+    pub pressed: bool,
+}
+
+impl Button {
+    pub fn new(text: &str) -> Button {
+        Button {
+            text: String::from(text),
+            pressed: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Toggle {
+    pub text: String,
+    pub on: bool,
+}
+
+impl Toggle {
+    pub fn new(text: &str, on: bool) -> Toggle {
+        Toggle {
+            text: String::from(text),
+            on: on,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Slider {
+    pub min: i32,
+    pub max: i32,
+    pub value: i32,
+    /// Change of the value per `Ui::adjust_focused` step.
+    pub step: i32,
+}
+
+impl Slider {
+    pub fn new(min: i32, max: i32, value: i32) -> Slider {
+        assert!(min < max);
+        Slider {
+            min: min,
+            max: max,
+            value: cmp::max(min, cmp::min(max, value)),
+            step: 1,
+        }
+    }
+
+    /// Sets the value, clamped to `min..=max`. Returns whether the value changed.
+    pub fn set_value(&mut self, value: i32) -> bool {
+        let value = cmp::max(self.min, cmp::min(self.max, value));
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+
+    /// The value that corresponds to the horizontal position `x` on the track.
+    pub fn value_at(&self, rect: Rect, x: u16) -> i32 {
+        let track = slider_track(rect);
+        if track.width <= 1 || x <= track.x {
+            return self.min;
+        }
+        let offset = i64::from(x - track.x);
+        let range = i64::from(self.max) - i64::from(self.min);
+        let width = i64::from(track.width - 1);
+        let value = i64::from(self.min) + (offset * range + width / 2) / width;
+        cmp::min(value, i64::from(self.max)) as i32
+    }
+
+    fn fraction(&self) -> f32 {
+        (self.value - self.min) as f32 / (self.max - self.min) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressBar {
+    pub value: u32,
+    pub max: u32,
+}
+
+impl ProgressBar {
+    pub fn new(max: u32) -> ProgressBar {
+        ProgressBar { value: 0, max: max }
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.max == 0 {
+            0.0
+        } else {
+            cmp::min(self.value, self.max) as f32 / self.max as f32
+        }
+    }
+}
+
+/// A vertical list of text items with an optional selection. Dragging scrolls the list.
+#[derive(Debug, Clone)]
+pub struct List {
+    pub items: Vec<String>,
+    pub selected: Option<usize>,
+    /// Index of the first visible item.
+    pub scroll: usize,
+}
+
+impl List {
+    pub fn new(items: &[&str]) -> List {
+        List {
+            items: items.iter().map(|&item| String::from(item)).collect(),
+            selected: None,
+            scroll: 0,
+        }
+    }
+
+    /// Height of a row in pixels.
+    pub fn row_height(theme: &Theme) -> u16 {
+        font::text_height(theme.text_scale) + 4 * theme.text_scale
+    }
+
+    /// Number of completely visible rows.
+    pub fn visible_rows(&self, rect: Rect, theme: &Theme) -> usize {
+        usize::from(rect.height / List::row_height(theme))
+    }
+
+    /// Scrolls so that `scroll` is the first visible item, limited so that the end of the
+    /// list stays at the bottom. Returns whether the scroll position changed.
+    pub fn scroll_to(&mut self, scroll: usize, rect: Rect, theme: &Theme) -> bool {
+        let max_scroll = self.items.len().saturating_sub(self.visible_rows(rect, theme));
+        let scroll = cmp::min(scroll, max_scroll);
+        let changed = scroll != self.scroll;
+        self.scroll = scroll;
+        changed
+    }
+
+    /// Scrolls just enough to make the selected item visible.
+    pub fn scroll_to_selected(&mut self, rect: Rect, theme: &Theme) {
+        if let Some(selected) = self.selected {
+            let rows = cmp::max(self.visible_rows(rect, theme), 1);
+            if selected < self.scroll {
+                self.scroll = selected;
+            } else if selected >= self.scroll + rows {
+                self.scroll = selected + 1 - rows;
+            }
+        }
+    }
+
+    /// The item at the vertical position `y`.
+    pub fn item_at(&self, rect: Rect, theme: &Theme, y: u16) -> Option<usize> {
+        if y < rect.y {
+            return None;
+        }
+        let index = self.scroll + usize::from((y - rect.y) / List::row_height(theme));
+        if index < self.items.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// Digits 0 to 9, backspace and OK, with a line that shows the entered text.
+#[derive(Debug, Clone)]
+pub struct Keypad {
+    pub text: String,
+    /// Maximum number of digits.
+    pub max_len: usize,
+    /// Index of the key that is held down.
+    pub pressed: Option<usize>,
+}
+
+impl Keypad {
+    pub fn new(max_len: usize) -> Keypad {
+        Keypad {
+            text: String::new(),
+            max_len: max_len,
+            pressed: None,
+        }
+    }
+
+    /// The index of the key at a position, `None` for the text line.
+    pub fn key_at(&self, rect: Rect, x: u16, y: u16) -> Option<usize> {
+        if !rect.contains(x, y) {
+            return None;
+        }
+        let column = (x - rect.x) / cmp::max(rect.width / KEYPAD_COLUMNS, 1);
+        let row = (y - rect.y) / cmp::max(rect.height / KEYPAD_ROWS, 1);
+        if row == 0 || row >= KEYPAD_ROWS || column >= KEYPAD_COLUMNS {
+            None
+        } else {
+            Some(usize::from((row - 1) * KEYPAD_COLUMNS + column))
+        }
+    }
+
+    /// Applies a key press. Returns `Some(true)` for OK, `Some(false)` if the text changed and
+    /// `None` if nothing happened.
+    pub fn press_key(&mut self, key: usize) -> Option<bool> {
+        match KEYPAD_KEYS.get(key) {
+            Some(&"OK") => Some(true),
+            Some(&"<") => self.text.pop().map(|_| false),
+            Some(digit) if self.text.len() < self.max_len => {
+                self.text.push_str(digit);
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+
+    /// The value of the entered digits.
+    pub fn value(&self) -> Option<u32> {
+        self.text.parse().ok()
+    }
+
+    fn key_rect(rect: Rect, key: usize) -> Rect {
+        let (key_width, key_height) = (rect.width / KEYPAD_COLUMNS, rect.height / KEYPAD_ROWS);
+        let (column, row) = (key as u16 % KEYPAD_COLUMNS, key as u16 / KEYPAD_COLUMNS + 1);
+        Rect::new(rect.x + column * key_width,
+                  rect.y + row * key_height,
+                  key_width,
+                  key_height)
+            .inset(2)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Widget {
+    Container(Container),
+    Label(Label),
+    Button(Button),
+    Toggle(Toggle),
+    Slider(Slider),
+    ProgressBar(ProgressBar),
+    List(List),
+    Keypad(Keypad),
+    Chart(Chart),
+}
+
+impl Widget {
+    /// Whether the widget reacts to touches and can be focused.
+    pub fn is_interactive(&self) -> bool {
+        match *self {
+            Widget::Container(_) |
+            Widget::Label(_) |
+            Widget::ProgressBar(_) |
+            Widget::Chart(_) => false,
+            _ => true,
+        }
+    }
+
+    /// The preferred width and height. Zero means that the widget fills the available space.
+    pub fn preferred_size(&self, theme: &Theme) -> (u16, u16) {
+        let scale = theme.text_scale;
+        let line_height = font::text_height(scale) + 4 * scale;
+        match *self {
+            Widget::Container(_) => (0, 0),
+            Widget::Label(ref label) => (font::text_width(&label.text, scale), line_height),
+            Widget::Button(ref button) => {
+                (font::text_width(&button.text, scale) + 8 * scale, line_height)
+            }
+            Widget::Toggle(ref toggle) => {
+                let switch_width = toggle_switch_width(theme);
+                (font::text_width(&toggle.text, scale) + switch_width + 4 * scale, line_height)
+            }
+            Widget::Slider(_) => (0, line_height),
+            Widget::ProgressBar(_) => (0, font::text_height(scale)),
+            Widget::List(_) => (0, 0),
+            Widget::Keypad(_) => (0, 0),
+            Widget::Chart(_) => (0, 0),
+        }
+    }
+
+    /// Draws the widget into `rect`. Containers only draw their background.
+    pub fn draw<C: Canvas>(&self,
+                           painter: &mut Painter<C>,
+                           rect: Rect,
+                           theme: &Theme,
+                           focused: bool,
+                           enabled: bool) {
+        let text_color = if enabled {
+            theme.foreground
+        } else {
+            theme.disabled
+        };
+        let scale = theme.text_scale;
+
+        match *self {
+            Widget::Container(_) => painter.fill_rect(rect, theme.background),
+            Widget::Label(ref label) => {
+                painter.fill_rect(rect, theme.background);
+                let width = font::text_width(&label.text, scale);
+                let x = match label.align {
+                    Align::Left => rect.x,
+                    Align::Center => rect.x + rect.width.saturating_sub(width) / 2,
+                    Align::Right => rect.x + rect.width.saturating_sub(width),
+                };
+                let y = rect.y + rect.height.saturating_sub(font::text_height(scale)) / 2;
+                painter.text(x, y, &label.text, scale, text_color);
+            }
+            Widget::Button(ref button) => {
+                let fill = if button.pressed {
+                    theme.accent
+                } else {
+                    theme.surface
+                };
+                painter.fill_rect(rect, fill);
+                painter.stroke_rect(rect, theme.border_width, theme.foreground);
+                painter.text_centered(rect, &button.text, scale, text_color);
+            }
+            Widget::Toggle(ref toggle) => {
+                painter.fill_rect(rect, theme.background);
+                let y = rect.y + rect.height.saturating_sub(font::text_height(scale)) / 2;
+                painter.text(rect.x, y, &toggle.text, scale, text_color);
+
+                let switch_width = toggle_switch_width(theme);
+                let switch = Rect::new(rect.right().saturating_sub(switch_width),
+                                       rect.y,
+                                       cmp::min(switch_width, rect.width),
+                                       rect.height)
+                        .inset(scale);
+                let fill = if toggle.on { theme.accent } else { theme.surface };
+                painter.fill_rect(switch, fill);
+                painter.stroke_rect(switch, theme.border_width, theme.foreground);
+                let knob_width = switch.width / 2;
+                let knob_x = if toggle.on {
+                    switch.right() - knob_width
+                } else {
+                    switch.x
+                };
+                let knob = Rect::new(knob_x, switch.y, knob_width, switch.height).inset(scale);
+                painter.fill_rect(knob, text_color);
+            }
+            Widget::Slider(ref slider) => {
+                painter.fill_rect(rect, theme.background);
+                let track = slider_track(rect);
+                let filled = (slider.fraction() * f32::from(track.width)) as u16;
+                let thickness = cmp::max(track.height / 4, 1);
+                let track_y = track.y + (track.height - thickness) / 2;
+                painter.fill_rect(Rect::new(track.x, track_y, filled, thickness), theme.accent);
+                painter.fill_rect(Rect::new(track.x + filled,
+                                            track_y,
+                                            track.width - filled,
+                                            thickness),
+                                  theme.surface);
+                let knob_width = cmp::max(rect.height / 2, 1);
+                let knob_x = (track.x + filled).saturating_sub(knob_width / 2);
+                painter.fill_rect(Rect::new(knob_x, rect.y, knob_width, rect.height),
+                                  text_color);
+            }
+            Widget::ProgressBar(ref bar) => {
+                painter.fill_rect(rect, theme.surface);
+                let filled = (bar.fraction() * f32::from(rect.width)) as u16;
+                painter.fill_rect(Rect::new(rect.x, rect.y, filled, rect.height), theme.accent);
+                painter.stroke_rect(rect, theme.border_width, theme.foreground);
+            }
+            Widget::List(ref list) => {
+                painter.fill_rect(rect, theme.background);
+                let row_height = List::row_height(theme);
+                let mut y = rect.y;
+                for (index, item) in list.items.iter().enumerate().skip(list.scroll) {
+                    if y >= rect.bottom() {
+                        break;
+                    }
+                    let row = Rect::new(rect.x, y, rect.width, row_height)
+                        .intersection(&rect);
+                    let fill = if list.selected == Some(index) {
+                        theme.accent
+                    } else {
+                        theme.surface
+                    };
+                    painter.fill_rect(Rect::new(row.x, row.y, row.width, row.height - 1), fill);
+                    let text_y = y + (row_height - font::text_height(scale)) / 2;
+                    painter.text(rect.x + 2 * scale, text_y, item, scale, text_color);
+                    y += row_height;
+                }
+            }
+            Widget::Keypad(ref keypad) => {
+                painter.fill_rect(rect, theme.background);
+                let line = Rect::new(rect.x, rect.y, rect.width, rect.height / KEYPAD_ROWS)
+                    .inset(2);
+                painter.stroke_rect(line, theme.border_width, theme.foreground);
+                let text_x = line.x + 2 * scale;
+                let text_y = line.y + line.height.saturating_sub(font::text_height(scale)) / 2;
+                painter.text(text_x, text_y, &keypad.text, scale, text_color);
+
+                for (index, key) in KEYPAD_KEYS.iter().enumerate() {
+                    let key_rect = Keypad::key_rect(rect, index);
+                    let fill = if keypad.pressed == Some(index) {
+                        theme.accent
+                    } else {
+                        theme.surface
+                    };
+                    painter.fill_rect(key_rect, fill);
+                    painter.text_centered(key_rect, key, scale, text_color);
+                }
+            }
+            Widget::Chart(ref chart) => chart.draw(painter, rect, theme),
+        }
+
+        if focused {
+            painter.stroke_rect(rect, theme.border_width + 1, theme.focus);
+        }
+    }
+}
+
+fn toggle_switch_width(theme: &Theme) -> u16 {
+    font::text_height(theme.text_scale) * 3
+}
+
+/// The part of the slider that the knob center can move along.
+fn slider_track(rect: Rect) -> Rect {
+    let margin = cmp::min(rect.height / 4, rect.width / 2);
+    Rect::new(rect.x + margin, rect.y, rect.width - 2 * margin, rect.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slider_values() {
+        let mut slider = Slider::new(-10, 10, 20);
+        assert_eq!(slider.value, 10);
+        assert!(!slider.set_value(11));
+        assert!(slider.set_value(-100));
+        assert_eq!(slider.value, -10);
+
+        // the track is inset by a quarter of the height
+        let rect = Rect::new(100, 0, 88, 16);
+        assert_eq!(slider.value_at(rect, 0), -10);
+        assert_eq!(slider.value_at(rect, 104), -10);
+        assert_eq!(slider.value_at(rect, 144), 0);
+        assert_eq!(slider.value_at(rect, 183), 10);
+        assert_eq!(slider.value_at(rect, 500), 10);
+        assert_eq!(slider.value_at(Rect::new(0, 0, 1, 16), 1), -10);
+
+        let wide = Slider::new(i32::min_value(), i32::max_value(), 0);
+        assert_eq!(wide.value_at(rect, 183), i32::max_value());
+    }
+
+    #[test]
+    fn progress_bar_fraction() {
+        let mut bar = ProgressBar::new(0);
+        assert_eq!(bar.fraction(), 0.0);
+        bar.max = 4;
+        bar.value = 1;
+        assert_eq!(bar.fraction(), 0.25);
+        bar.value = 10;
+        assert_eq!(bar.fraction(), 1.0);
+    }
+
+    #[test]
+    fn list_scrolling() {
+        let theme = Theme::default();
+        let row_height = List::row_height(&theme);
+        let rect = Rect::new(0, 10, 50, 3 * row_height + 1);
+        let mut list = List::new(&["a", "b", "c", "d", "e"]);
+        assert_eq!(list.visible_rows(rect, &theme), 3);
+
+        assert!(list.scroll_to(10, rect, &theme));
+        assert_eq!(list.scroll, 2);
+        assert!(!list.scroll_to(2, rect, &theme));
+        assert_eq!(list.item_at(rect, &theme, 10), Some(2));
+        assert_eq!(list.item_at(rect, &theme, 10 + 3 * row_height - 1), Some(4));
+        assert_eq!(list.item_at(rect, &theme, 10 + 3 * row_height), None);
+        assert_eq!(list.item_at(rect, &theme, 9), None);
+
+        list.selected = Some(0);
+        list.scroll_to_selected(rect, &theme);
+        assert_eq!(list.scroll, 0);
+        list.selected = Some(4);
+        list.scroll_to_selected(rect, &theme);
+        assert_eq!(list.scroll, 2);
+    }
+
+    #[test]
+    fn keypad_keys() {
+        let rect = Rect::new(0, 0, 30, 50);
+        let keypad = Keypad::new(3);
+        assert_eq!(keypad.key_at(rect, 5, 5), None);
+        assert_eq!(keypad.key_at(rect, 5, 15), Some(0));
+        assert_eq!(keypad.key_at(rect, 25, 45), Some(11));
+        assert_eq!(keypad.key_at(rect, 30, 45), None);
+        assert_eq!(Keypad::key_rect(rect, 4), Rect::new(12, 22, 6, 6));
+
+        let mut keypad = Keypad::new(2);
+        assert_eq!(keypad.press_key(9), None);
+        assert_eq!(keypad.press_key(10), Some(false));
+        assert_eq!(keypad.press_key(6), Some(false));
+        assert_eq!(keypad.press_key(0), None);
+        assert_eq!(keypad.value(), Some(7));
+        assert_eq!(keypad.press_key(9), Some(false));
+        assert_eq!(keypad.text, "0");
+        assert_eq!(keypad.press_key(11), Some(true));
+        assert_eq!(keypad.press_key(12), None);
+    }
+
+    #[test]
+    fn preferred_sizes() {
+        let theme = Theme::default();
+        assert_eq!(Widget::Label(Label::new("ab")).preferred_size(&theme), (22, 22));
+        assert_eq!(Widget::Button(Button::new("ab")).preferred_size(&theme), (38, 22));
+        assert_eq!(Widget::Toggle(Toggle::new("ab", true)).preferred_size(&theme),
+                   (22 + 42 + 8, 22));
+        assert_eq!(Widget::Slider(Slider::new(0, 1, 0)).preferred_size(&theme), (0, 22));
+        assert!(!Widget::Label(Label::new("")).is_interactive());
+        assert!(Widget::Keypad(Keypad::new(1)).is_interactive());
+    }
+}
@@ -1,16 +1,81 @@
 #![allow(dead_code)]
 
+//! I2C master driver.
+//!
+//! Transfers of any length are split into chunks of 255 bytes with the NBYTES/RELOAD
+//! mechanism. Large reads can also use DMA: `I2C::read_dma` starts the transfer and
+//! `I2C::poll_dma` reloads NBYTES and reports the completion, so the main loop keeps running
+//! while the bytes arrive.
+//!
+//! All waits for status flags time out after `I2C::timeout` milliseconds. A device that holds
+//! SDA low after a reset in the middle of a transfer can be freed with `I2C::recover`.
+
+use board::dma::{self, Dma};
 use board::rcc::Rcc;
+use board::gpio;
 use board::i2c;
-use embedded::interfaces::gpio::Gpio;
+use embedded::interfaces::gpio::{Gpio, Port, Pin};
+use core::cmp;
 use core::marker::PhantomData;
 use core::iter::TrustedLen;
-
-pub struct I2C(&'static mut i2c::I2c);
+use core::ptr;
+use system_clock;
+
+/// Maximum value of the NBYTES field, larger transfers are reloaded.
+const MAX_NBYTES: usize = 255;
+/// Maximum number of data items of a DMA stream.
+pub const MAX_DMA_LEN: usize = 0xffff;
+/// Default for `I2C::set_timeout`, in milliseconds.
+pub const DEFAULT_TIMEOUT: usize = 25;
+
+/// Number of clock pulses of the bus clear sequence, enough to finish any byte and its ACK.
+const BUS_CLEAR_PULSES: usize = 9;
+/// Half the clock period of the bus clear sequence in microseconds, which gives 50 kHz.
+const BUS_CLEAR_HALF_PERIOD: usize = 10;
+
+pub struct I2C {
+    registers: &'static mut i2c::I2c,
+    dma: Option<&'static mut Dma>,
+    /// The channel of the stream, set by `enable_dma`.
+    dma_channel: u8,
+    transfer: Option<DmaTransfer>,
+    timeout: usize,
+}
 
 #[derive(Debug)]
 pub enum Error {
+    /// The device didn't acknowledge its address or a byte.
     Nack,
+    /// Another master won the arbitration.
+    ArbitrationLost,
+    /// A misplaced start or stop condition was detected.
+    BusError,
+    /// A status flag wasn't set within `I2C::timeout` milliseconds. The peripheral was reset.
+    Timeout,
+    /// SDA is still held low after the bus clear sequence of `I2C::recover`.
+    BusStuck,
+    /// A DMA transfer is in progress, see `I2C::poll_dma`.
+    Busy,
+    /// `I2C::enable_dma` was not called.
+    DmaNotEnabled,
+    /// The DMA stream reported a transfer or direct mode error.
+    Dma,
+}
+
+/// A DMA read in progress.
+struct DmaTransfer {
+    buffer: &'static mut [u8],
+    /// Bytes that were not yet announced in NBYTES.
+    unannounced: usize,
+    /// The remaining number of data items of the stream and the tick when it last changed.
+    remaining: u16,
+    last_progress: usize,
+}
+
+/// The result of a DMA read, which returns the buffer also on errors.
+pub struct CompletedRead {
+    pub buffer: &'static mut [u8],
+    pub result: Result<(), Error>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -22,10 +87,45 @@ impl Address {
     }
 }
 
+/// The SCL and SDA pins of a bus.
+#[derive(Clone, Copy)]
+pub struct BusPins {
+    pub scl: (Port, Pin),
+    pub sda: (Port, Pin),
+}
+
+pub const I2C1_PINS: BusPins = BusPins {
+    scl: (Port::PortB, Pin::Pin8),
+    sda: (Port::PortB, Pin::Pin9),
+};
+pub const I2C2_PINS: BusPins = BusPins {
+    scl: (Port::PortB, Pin::Pin10),
+    sda: (Port::PortB, Pin::Pin11),
+};
+pub const I2C3_PINS: BusPins = BusPins {
+    scl: (Port::PortH, Pin::Pin7),
+    sda: (Port::PortH, Pin::Pin8),
+};
+pub const I2C4_PINS: BusPins = BusPins {
+    scl: (Port::PortH, Pin::Pin11),
+    sda: (Port::PortD, Pin::Pin13),
+};
+
+/// The channel of DMA1 stream 2 that carries the RX request of a bus.
+///
+/// I2C1_RX is only mapped to streams 0 and 5, so there is no constant for I2C1 and its reads
+/// can't use DMA.
+#[derive(Clone, Copy)]
+pub struct DmaRequest {
+    channel: u8,
+}
+
+pub const I2C2_RX_DMA: DmaRequest = DmaRequest { channel: 7 };
+pub const I2C3_RX_DMA: DmaRequest = DmaRequest { channel: 3 };
+pub const I2C4_RX_DMA: DmaRequest = DmaRequest { channel: 2 };
+
 pub fn init_pins_and_clocks(rcc: &mut Rcc, gpio: &mut Gpio) {
     use embedded::interfaces::gpio::{OutputType, OutputSpeed, AlternateFunction, Resistor};
-    use embedded::interfaces::gpio::Port::*;
-    use embedded::interfaces::gpio::Pin::*;
 
     // enable clocks
     rcc.apb1enr
@@ -36,16 +136,14 @@ pub fn init_pins_and_clocks(rcc: &mut Rcc, gpio: &mut Gpio) {
                     r.set_i2c4en(true);
                 });
 
-    let i2c1_scl = (PortB, Pin8);
-    let i2c1_sda = (PortB, Pin9);
-    let i2c2_scl = (PortB, Pin10);
-    let i2c2_sda = (PortB, Pin11);
-    let i2c3_scl = (PortH, Pin7);
-    let i2c3_sda = (PortH, Pin8);
-    let i2c4_scl = (PortH, Pin11);
-    let i2c4_sda = (PortD, Pin13);
-
-    let pins = [i2c1_scl, i2c1_sda, i2c2_scl, i2c2_sda, i2c3_scl, i2c3_sda, i2c4_scl, i2c4_sda];
+    let pins = [I2C1_PINS.scl,
+                I2C1_PINS.sda,
+                I2C2_PINS.scl,
+                I2C2_PINS.sda,
+                I2C3_PINS.scl,
+                I2C3_PINS.sda,
+                I2C4_PINS.scl,
+                I2C4_PINS.sda];
     gpio.to_alternate_function_all(&pins,
                                    AlternateFunction::AF4,
                                    OutputType::OpenDrain,
@@ -55,6 +153,17 @@ pub fn init_pins_and_clocks(rcc: &mut Rcc, gpio: &mut Gpio) {
 }
 
 pub fn init(i2c: &'static mut i2c::I2c) -> I2C {
+    configure(i2c);
+    I2C {
+        registers: i2c,
+        dma: None,
+        dma_channel: 0,
+        transfer: None,
+        timeout: DEFAULT_TIMEOUT,
+    }
+}
+
+fn configure(i2c: &mut i2c::I2c) {
     // disable I2C peripheral
     i2c.cr1.update(|r| r.set_pe(false)); // peripheral_enable
 
@@ -99,7 +208,6 @@ pub fn init(i2c: &'static mut i2c::I2c) -> I2C {
                 });
     // wait that init can finish
     ::system_clock::wait(50);
-    I2C(i2c)
 }
 
 fn icr_clear_all() -> i2c::Icr {
@@ -160,28 +268,20 @@ impl RegisterType for u16 {
 }
 
 impl<'a, T: RegisterType> I2cConnection<'a, T> {
-    fn start(&mut self, read: bool, bytes: u8) {
-        let mut cr2 = i2c::Cr2::default();
-        cr2.set_sadd(self.device_address.0); // slave_address
-        cr2.set_start(true); // start_generation
-        cr2.set_rd_wrn(read); // read_transfer
-        cr2.set_nbytes(bytes); // number_of_bytes
-        cr2.set_autoend(false); // automatic_end_mode
-        self.i2c.0.cr2.write(cr2);
-    }
-
     fn write_bytes<ITER>(&mut self, bytes: ITER) -> Result<(), Error>
         where ITER: Iterator<Item = u8> + TrustedLen
     {
         assert!(bytes.size_hint().1.is_some());
-        assert_eq!(bytes.size_hint().0 as u8 as usize,
-                   bytes.size_hint().0,
-                   "transfers > 255 bytes are not implemented yet");
-        self.start(false, bytes.size_hint().0 as u8);
+        let len = bytes.size_hint().0;
+        self.i2c.start(self.device_address, false, len);
 
-        for b in bytes {
+        for (i, b) in bytes.enumerate() {
+            if i > 0 && i % MAX_NBYTES == 0 {
+                self.i2c.wait_for_transfer_complete_reload()?;
+                self.i2c.reload(len - i);
+            }
             self.i2c.wait_for_txis()?;
-            self.i2c.0.txdr.update(|r| r.set_txdata(b)); // transmit_data
+            self.i2c.registers.txdr.update(|r| r.set_txdata(b)); // transmit_data
         }
 
         self.i2c.wait_for_transfer_complete()?;
@@ -189,7 +289,7 @@ impl<'a, T: RegisterType> I2cConnection<'a, T> {
         self.clear_status_flags();
 
         // reset cr2
-        self.i2c.0.cr2.write(Default::default());
+        self.i2c.registers.cr2.write(Default::default());
 
         Ok(())
     }
@@ -198,15 +298,17 @@ impl<'a, T: RegisterType> I2cConnection<'a, T> {
         where ITER: Iterator<Item = &'b mut u8> + TrustedLen
     {
         assert!(buffer.size_hint().1.is_some());
-        assert_eq!(buffer.size_hint().0 as u8 as usize,
-                   buffer.size_hint().0,
-                   "transfers > 255 bytes are not implemented yet");
-        self.start(true, buffer.size_hint().0 as u8);
+        let len = buffer.size_hint().0;
+        self.i2c.start(self.device_address, true, len);
 
         // read data from receive data register
-        for b in buffer {
+        for (i, b) in buffer.enumerate() {
+            if i > 0 && i % MAX_NBYTES == 0 {
+                self.i2c.wait_for_transfer_complete_reload()?;
+                self.i2c.reload(len - i);
+            }
             self.i2c.wait_for_rxne()?;
-            *b = self.i2c.0.rxdr.read().rxdata(); // receive_data
+            *b = self.i2c.registers.rxdr.read().rxdata(); // receive_data
         }
 
         self.i2c.wait_for_transfer_complete()?;
@@ -214,7 +316,7 @@ impl<'a, T: RegisterType> I2cConnection<'a, T> {
         self.clear_status_flags();
 
         // reset cr2
-        self.i2c.0.cr2.write(Default::default());
+        self.i2c.registers.cr2.write(Default::default());
 
         Ok(())
     }
@@ -222,12 +324,12 @@ impl<'a, T: RegisterType> I2cConnection<'a, T> {
     fn pre(&mut self) {
         self.clear_status_flags();
         // flush transmit data register
-        self.i2c.0.isr.update(|r| r.set_txe(true)); // flush_txdr
+        self.i2c.registers.isr.update(|r| r.set_txe(true)); // flush_txdr
     }
 
     fn clear_status_flags(&mut self) {
         let clear_all = icr_clear_all();
-        self.i2c.0.icr.write(clear_all);
+        self.i2c.registers.icr.write(clear_all);
     }
 
     pub fn read(&mut self, register_address: T) -> Result<T, Error> {
@@ -257,10 +359,14 @@ impl<'a, T: RegisterType> I2cConnection<'a, T> {
 }
 
 impl I2C {
+    /// Fails with `Error::Busy` while a DMA read is in progress.
     pub fn connect<T, F>(&mut self, device_address: Address, f: F) -> Result<(), Error>
         where T: RegisterType,
               F: FnOnce(I2cConnection<T>) -> Result<(), Error>
     {
+        if self.transfer.is_some() {
+            return Err(Error::Busy);
+        }
         {
             let conn = I2cConnection {
                 i2c: self,
@@ -272,16 +378,291 @@ impl I2C {
         self.stop()
     }
 
+    /// Milliseconds to wait for each status flag before a transfer fails with
+    /// `Error::Timeout`.
+    pub fn timeout(&self) -> usize {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, ms: usize) {
+        self.timeout = ms;
+    }
+
+    /// Frees a bus whose SDA line is held low by a device, e.g. because the MCU was reset in
+    /// the middle of a read, and reinitializes the peripheral.
+    ///
+    /// The pins are switched to open drain outputs and SCL is pulsed until the device releases
+    /// SDA, at most 9 times. Then a stop condition is generated and the pins are switched
+    /// back to the I2C alternate function. `pins` must be the pins of this bus. The clock runs
+    /// at 50 kHz, timed with the SysTick counter of `system_clock`.
+    ///
+    /// The `Gpio` owns the port registers and hands out each pin only once, so it lends the
+    /// two pins for the duration of the call: while it is borrowed, no other code can
+    /// reconfigure a port. The output type (open drain) and the alternate function number
+    /// stay as configured by `init_pins_and_clocks`.
+    ///
+    /// Fails with `Error::Busy` while a DMA read is in progress and with `Error::BusStuck` if
+    /// SDA stays low.
+    pub fn recover(&mut self, gpio: &mut Gpio, pins: &BusPins) -> Result<(), Error> {
+        if self.transfer.is_some() {
+            return Err(Error::Busy);
+        }
+        // disable I2C peripheral, which releases the pins
+        self.registers.cr1.update(|r| r.set_pe(false)); // peripheral_enable
+
+        let (scl, sda) = (RawPin::lend(gpio, pins.scl), RawPin::lend(gpio, pins.sda));
+        scl.set(true);
+        sda.set(true);
+        scl.set_mode(MODE_OUTPUT);
+        sda.set_mode(MODE_OUTPUT);
+        bus_clear_delay();
+
+        // clock out the byte the device is sending
+        let mut pulses = 0;
+        while !sda.get() && pulses < BUS_CLEAR_PULSES {
+            scl.set(false);
+            bus_clear_delay();
+            scl.set(true);
+            bus_clear_delay();
+            pulses += 1;
+        }
+
+        // stop condition: SDA rises while SCL is high
+        sda.set(false);
+        bus_clear_delay();
+        sda.set(true);
+        bus_clear_delay();
+        let released = scl.get() && sda.get();
+
+        scl.set_mode(MODE_ALTERNATE_FUNCTION);
+        sda.set_mode(MODE_ALTERNATE_FUNCTION);
+        configure(self.registers);
+
+        if released {
+            Ok(())
+        } else {
+            Err(Error::BusStuck)
+        }
+    }
+
+    /// Resets the state machine and the status flags, which aborts the current transfer.
+    fn software_reset(&mut self) {
+        self.registers.cr1.update(|r| r.set_pe(false)); // peripheral_enable
+        // PE must stay low for at least three APB clock cycles
+        while self.registers.cr1.read().pe() {}
+        self.registers.cr2.write(Default::default());
+        self.registers.cr1.update(|r| r.set_pe(true));
+    }
+
 
     pub fn stop(&mut self) -> Result<(), Error> {
-        self.0.cr2.update(|r| r.set_stop(true));
+        self.registers.cr2.update(|r| r.set_stop(true));
 
         // reset cr2
-        self.0.cr2.write(Default::default());
+        self.registers.cr2.write(Default::default());
 
         self.wait_for_stop()
     }
 
+    /// Starts a transfer of `len` bytes. Transfers of more than 255 bytes are reloaded with
+    /// `reload` after every 255 bytes.
+    fn start(&mut self, device_address: Address, read: bool, len: usize) {
+        let mut cr2 = i2c::Cr2::default();
+        cr2.set_sadd(device_address.0); // slave_address
+        cr2.set_start(true); // start_generation
+        cr2.set_rd_wrn(read); // read_transfer
+        cr2.set_nbytes(cmp::min(len, MAX_NBYTES) as u8); // number_of_bytes
+        cr2.set_reload(len > MAX_NBYTES); // nbytes_reload_mode
+        cr2.set_autoend(false); // automatic_end_mode
+        self.registers.cr2.write(cr2);
+    }
+
+    /// Continues a transfer after the "transfer complete reload" flag with the next chunk of
+    /// the `remaining` bytes.
+    fn reload(&mut self, remaining: usize) {
+        self.registers
+            .cr2
+            .update(|r| {
+                        r.set_nbytes(cmp::min(remaining, MAX_NBYTES) as u8); // number_of_bytes
+                        r.set_reload(remaining > MAX_NBYTES); // nbytes_reload_mode
+                    });
+    }
+
+    /// Lets `read_dma` use stream 2 of DMA1 with the channel of `request`, which has to be the
+    /// RX request of this bus, e.g. `I2C3_RX_DMA` for I2C3.
+    ///
+    /// Only one bus can use the stream at a time.
+    pub fn enable_dma(&mut self, dma_1: &'static mut Dma, request: DmaRequest, rcc: &mut Rcc) {
+        // enable DMA1 clock
+        rcc.ahb1enr.update(|r| r.set_dma1en(true));
+        self.dma = Some(dma_1);
+        self.dma_channel = request.channel;
+    }
+
+    /// Whether a DMA read is in progress.
+    pub fn is_busy(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Reads `buffer.len()` bytes from a register with DMA, in the background.
+    ///
+    /// The register address is written before this returns. `poll_dma` has to be called
+    /// regularly until it reports the completion, because the I2C stretches the clock after
+    /// each 255 bytes until `poll_dma` reloads the byte count. Other transfers fail with
+    /// `Error::Busy` in the meantime.
+    ///
+    /// Panics if the buffer is empty or longer than `MAX_DMA_LEN`.
+    pub fn read_dma<T>(&mut self,
+                       device_address: Address,
+                       register_address: T,
+                       buffer: &'static mut [u8])
+                       -> Result<(), Error>
+        where T: RegisterType
+    {
+        assert!(!buffer.is_empty() && buffer.len() <= MAX_DMA_LEN,
+                "invalid DMA buffer length");
+        if self.transfer.is_some() {
+            return Err(Error::Busy);
+        }
+        if self.dma.is_none() {
+            return Err(Error::DmaNotEnabled);
+        }
+
+        {
+            let mut conn = I2cConnection {
+                i2c: self,
+                device_address: device_address,
+                register_type: PhantomData,
+            };
+            conn.pre();
+            register_address.write(|addr_bytes| conn.write_bytes(addr_bytes.iter().cloned()))?;
+        }
+
+        let len = buffer.len();
+        if let Some(ref mut dma) = self.dma {
+            // disable stream 2 and wait until it is really disabled
+            dma.s2cr.update(|r| r.set_en(false));
+            while dma.s2cr.read().en() {}
+            dma.lifcr.write(clear_stream_2_flags());
+
+            // addresses and number of data items
+            let rxdr = &self.registers.rxdr as *const _ as u32;
+            dma.s2par.update(|r| r.set_pa(rxdr)); // peripheral_address
+            dma.s2m0ar.update(|r| r.set_m0a(buffer.as_mut_ptr() as u32)); // memory_address
+            dma.s2ndtr.update(|r| r.set_ndt(len as u16)); // number_of_data_items
+
+            dma.s2fcr.update(|r| r.set_dmdis(false)); // direct mode
+
+            let mut s2cr = dma::S2cr::default();
+            s2cr.set_chsel(self.dma_channel); // channel_selection
+            s2cr.set_dir(0b00); // direction peripheral_to_memory
+            s2cr.set_circ(false); // circular_mode
+            s2cr.set_pinc(false); // peripheral_increment
+            s2cr.set_minc(true); // memory_increment
+            s2cr.set_psize(0b00); // peripheral_size byte
+            s2cr.set_msize(0b00); // memory_size byte
+            s2cr.set_pl(0b10); // priority_level high
+            dma.s2cr.write(s2cr);
+            dma.s2cr.update(|r| r.set_en(true)); // stream_enable
+        }
+
+        self.registers.cr1.update(|r| r.set_rxdmaen(true)); // dma_reception_requests_enable
+        self.start(device_address, true, len);
+        self.transfer = Some(DmaTransfer {
+                                 buffer: buffer,
+                                 unannounced: len - cmp::min(len, MAX_NBYTES),
+                                 remaining: len as u16,
+                                 last_progress: system_clock::ticks(),
+                             });
+        Ok(())
+    }
+
+    /// Advances the DMA read and returns the buffer when it is complete or failed. Returns
+    /// `None` while the transfer is in progress or if there is none.
+    ///
+    /// The read fails with `Error::Timeout` if no byte arrives within `I2C::timeout`
+    /// milliseconds.
+    pub fn poll_dma(&mut self) -> Option<CompletedRead> {
+        let unannounced = match self.transfer {
+            Some(ref transfer) => transfer.unannounced,
+            None => return None,
+        };
+        let isr = self.registers.isr.read();
+        let (dma_error, dma_complete, remaining) = match self.dma {
+            Some(ref dma) => {
+                let lisr = dma.lisr.read();
+                (lisr.teif2() || lisr.dmeif2(), lisr.tcif2(), dma.s2ndtr.read().ndt())
+            }
+            None => (false, false, 0),
+        };
+        let now = system_clock::ticks();
+        let mut stalled = false;
+        if let Some(ref mut transfer) = self.transfer {
+            if remaining != transfer.remaining {
+                transfer.remaining = remaining;
+                transfer.last_progress = now;
+            }
+            stalled = now.wrapping_sub(transfer.last_progress) > self.timeout;
+        }
+
+        if let Err(error) = check_errors(&isr) {
+            Some(self.finish_dma(Err(error)))
+        } else if dma_error {
+            Some(self.finish_dma(Err(Error::Dma)))
+        } else if isr.tcr() {
+            // transfer_complete_reload
+            self.reload(unannounced);
+            if let Some(ref mut transfer) = self.transfer {
+                transfer.unannounced -= cmp::min(unannounced, MAX_NBYTES);
+                transfer.last_progress = now;
+            }
+            None
+        } else if isr.tc() && dma_complete {
+            let result = self.stop();
+            Some(self.finish_dma(result))
+        } else if stalled {
+            Some(self.finish_dma(Err(Error::Timeout)))
+        } else {
+            None
+        }
+    }
+
+    /// Busy waits until the DMA read is complete.
+    pub fn wait_dma(&mut self) -> Option<CompletedRead> {
+        while self.transfer.is_some() {
+            if let Some(completed) = self.poll_dma() {
+                return Some(completed);
+            }
+        }
+        None
+    }
+
+    fn finish_dma(&mut self, result: Result<(), Error>) -> CompletedRead {
+        if let Some(ref mut dma) = self.dma {
+            dma.s2cr.update(|r| r.set_en(false));
+            while dma.s2cr.read().en() {}
+            dma.lifcr.write(clear_stream_2_flags());
+        }
+        self.registers.cr1.update(|r| r.set_rxdmaen(false));
+        match result {
+            Ok(()) => {}
+            Err(Error::Timeout) |
+            Err(Error::BusError) => self.software_reset(),
+            Err(_) => {
+                // release the bus, the error is already reported
+                let _ = self.stop();
+            }
+        }
+        self.registers.icr.write(icr_clear_all());
+        self.registers.cr2.write(Default::default());
+
+        let transfer = self.transfer.take().expect("no DMA transfer in progress");
+        CompletedRead {
+            buffer: transfer.buffer,
+            result: result,
+        }
+    }
+
     pub fn update<F>(&mut self,
                      device_address: Address,
                      register_address: u16,
@@ -297,66 +678,63 @@ impl I2C {
     }
 
     /// Wait for “transmit interrupt status” flag
-    fn wait_for_txis(&self) -> Result<(), Error> {
-        loop {
-            let isr = self.0.isr.read();
-            if isr.nackf() {
-                // nack_received
-                return Err(Error::Nack);
-            }
-            if isr.txis() {
-                return Ok(());
-            }
-        }
+    fn wait_for_txis(&mut self) -> Result<(), Error> {
+        self.wait_for(|isr| isr.txis())
     }
 
     /// Wait for "receive data register not empty" flag
-    fn wait_for_rxne(&self) -> Result<(), Error> {
-        loop {
-            let isr = self.0.isr.read();
-            if isr.nackf() {
-                // nack_received
-                return Err(Error::Nack);
-            }
-            if isr.rxne() {
-                return Ok(());
-            }
-        }
+    fn wait_for_rxne(&mut self) -> Result<(), Error> {
+        self.wait_for(|isr| isr.rxne())
+    }
+
+    /// Wait for “transfer complete reload” flag, after which NBYTES has to be reloaded
+    fn wait_for_transfer_complete_reload(&mut self) -> Result<(), Error> {
+        self.wait_for(|isr| isr.tcr())
     }
 
     /// Wait for “transfer complete” flag
-    fn wait_for_transfer_complete(&self) -> Result<(), Error> {
-        loop {
-            let isr = self.0.isr.read();
-            if isr.nackf() {
-                // nack_received
-                return Err(Error::Nack);
-            }
-            if isr.tc() {
-                // transfer_complete
-                return Ok(());
-            }
-        }
+    fn wait_for_transfer_complete(&mut self) -> Result<(), Error> {
+        self.wait_for(|isr| isr.tc())
     }
 
     /// Wait for automatically generated stop flag
-    fn wait_for_stop(&self) -> Result<(), Error> {
+    fn wait_for_stop(&mut self) -> Result<(), Error> {
+        self.wait_for(|isr| isr.stopf())
+    }
+
+    /// Waits until `flag` returns true, an error flag is set or the timeout expires. The
+    /// peripheral is reset after timeouts and bus errors, so that the next transfer can start.
+    fn wait_for<F>(&mut self, flag: F) -> Result<(), Error>
+        where F: Fn(&i2c::Isr) -> bool
+    {
+        let result = self.poll_until(flag);
+        match result {
+            Err(Error::Timeout) |
+            Err(Error::BusError) => self.software_reset(),
+            _ => {}
+        }
+        result
+    }
+
+    fn poll_until<F>(&self, flag: F) -> Result<(), Error>
+        where F: Fn(&i2c::Isr) -> bool
+    {
+        let start = system_clock::ticks();
         loop {
-            let isr = self.0.isr.read();
-            if isr.nackf() {
-                // nack_received
-                return Err(Error::Nack);
-            }
-            if isr.stopf() {
-                // stop_detected
+            let isr = self.registers.isr.read();
+            check_errors(&isr)?;
+            if flag(&isr) {
                 return Ok(());
             }
+            if system_clock::ticks().wrapping_sub(start) > self.timeout {
+                return Err(Error::Timeout);
+            }
         }
     }
 
     // provokes a NACK
     pub fn test_1(&mut self) {
-        let mut i2c = &mut self.0;
+        let mut i2c = &mut self.registers;
 
         i2c.cr2
             .update(|r| {
@@ -382,7 +760,7 @@ impl I2C {
     // try all addresses
     #[allow(dead_code)]
     pub fn test_2(&mut self) {
-        let mut i2c = &mut self.0;
+        let mut i2c = &mut self.registers;
 
         let mut addr = 0;
         loop {
@@ -419,6 +797,98 @@ impl I2C {
     }
 }
 
+/// Maps the error flags of the interrupt and status register to an error.
+fn check_errors(isr: &i2c::Isr) -> Result<(), Error> {
+    if isr.nackf() {
+        // nack_received
+        Err(Error::Nack)
+    } else if isr.arlo() {
+        // arbitration_lost
+        Err(Error::ArbitrationLost)
+    } else if isr.berr() {
+        // bus_error
+        Err(Error::BusError)
+    } else {
+        Ok(())
+    }
+}
+
+const MODE_OUTPUT: u32 = 0b01;
+const MODE_ALTERNATE_FUNCTION: u32 = 0b10;
+
+/// Base addresses of the GPIO ports, see the memory map in the reference manual.
+const GPIO_PORTS: [usize; 11] = [0x4002_0000, 0x4002_0400, 0x4002_0800, 0x4002_0C00,
+                                 0x4002_1000, 0x4002_1400, 0x4002_1800, 0x4002_1C00,
+                                 0x4002_2000, 0x4002_2400, 0x4002_2800];
+
+/// Direct access to the registers of a GPIO pin during `I2C::recover`.
+struct RawPin {
+    port: *const gpio::Gpio,
+    pin: usize,
+}
+
+impl RawPin {
+    /// A pin of a port that the mutably borrowed `Gpio` owns. The pin must not outlive the
+    /// borrow.
+    fn lend(_gpio: &mut Gpio, (port, pin): (Port, Pin)) -> RawPin {
+        let port = match port {
+            Port::PortA => GPIO_PORTS[0],
+            Port::PortB => GPIO_PORTS[1],
+            Port::PortC => GPIO_PORTS[2],
+            Port::PortD => GPIO_PORTS[3],
+            Port::PortE => GPIO_PORTS[4],
+            Port::PortF => GPIO_PORTS[5],
+            Port::PortG => GPIO_PORTS[6],
+            Port::PortH => GPIO_PORTS[7],
+            Port::PortI => GPIO_PORTS[8],
+            Port::PortJ => GPIO_PORTS[9],
+            Port::PortK => GPIO_PORTS[10],
+        };
+        RawPin {
+            port: port as *const gpio::Gpio,
+            pin: pin as usize,
+        }
+    }
+
+    fn set_mode(&self, mode: u32) {
+        unsafe {
+            let moder = &(*self.port).moder as *const _ as *mut u32; // mode register
+            let value = ptr::read_volatile(moder) & !(0b11 << (2 * self.pin));
+            ptr::write_volatile(moder, value | mode << (2 * self.pin));
+        }
+    }
+
+    fn set(&self, high: bool) {
+        let bit = if high { self.pin } else { self.pin + 16 };
+        unsafe {
+            let bsrr = &(*self.port).bsrr as *const _ as *mut u32; // bit set/reset register
+            ptr::write_volatile(bsrr, 1 << bit);
+        }
+    }
+
+    fn get(&self) -> bool {
+        unsafe {
+            let idr = &(*self.port).idr as *const _ as *const u32; // input data register
+            ptr::read_volatile(idr) & (1 << self.pin) != 0
+        }
+    }
+}
+
+/// Waits for half a clock period of the bus clear sequence.
+fn bus_clear_delay() {
+    system_clock::wait_us(BUS_CLEAR_HALF_PERIOD);
+}
+
+fn clear_stream_2_flags() -> dma::Lifcr {
+    let mut clear = dma::Lifcr::default();
+    clear.set_ctcif2(true); // transfer complete clear flag
+    clear.set_chtif2(true); // half transfer clear flag
+    clear.set_cteif2(true); // transfer error clear flag
+    clear.set_cdmeif2(true); // direct mode error clear flag
+    clear.set_cfeif2(true); // fifo error clear flag
+    clear
+}
+
 fn panic() {
     panic!();
 }
@@ -0,0 +1,432 @@
+//! Uncompressed Windows bitmaps with 1, 4, 8, 16, 24 or 32 bits per pixel.
+
+use byteorder::{ByteOrder, LittleEndian};
+use lcd::Color;
+use super::{scale_to_u8, Error, Format, ImageInfo};
+
+const FILE_HEADER_LEN: usize = 14;
+/// `BITMAPCOREHEADER`, with 16 bit width and height and 3 byte palette entries.
+const CORE_HEADER_LEN: usize = 12;
+/// `BITMAPINFOHEADER`, the V4 and V5 headers extend it.
+const INFO_HEADER_LEN: usize = 40;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+struct Header {
+    width: u16,
+    height: u16,
+    /// Rows are stored top to bottom instead of bottom to top.
+    top_down: bool,
+    bits_per_pixel: u16,
+    pixel_offset: usize,
+    palette_offset: usize,
+    palette_entry_len: usize,
+    palette_len: usize,
+    masks: [u32; 4],
+}
+
+pub fn info(data: &[u8]) -> Result<ImageInfo, Error> {
+    parse_header(data).map(|header| image_info(&header))
+}
+
+pub fn decode<F>(data: &[u8], mut pixel: F) -> Result<ImageInfo, Error>
+    where F: FnMut(u16, u16, Color)
+{
+    let header = parse_header(data)?;
+    let bits = usize::from(header.bits_per_pixel);
+    let stride = (bits * usize::from(header.width) + 31) / 32 * 4;
+
+    for row in 0..header.height {
+        let start = u64::from(row) * stride as u64 + header.pixel_offset as u64;
+        if start + stride as u64 > data.len() as u64 {
+            return Err(Error::Truncated);
+        }
+        let start = start as usize;
+        let row_data = &data[start..start + stride];
+        let y = if header.top_down {
+            row
+        } else {
+            header.height - 1 - row
+        };
+
+        for x in 0..header.width {
+            let color = match header.bits_per_pixel {
+                1 | 4 | 8 => {
+                    let bit = usize::from(x) * bits;
+                    let byte = row_data[bit / 8];
+                    let index = (byte >> (8 - bits - bit % 8)) & ((1 << bits) - 1) as u8;
+                    palette_color(data, &header, usize::from(index))
+                }
+                16 => {
+                    let offset = usize::from(x) * 2;
+                    let value = LittleEndian::read_u16(&row_data[offset..offset + 2]);
+                    masked_color(u32::from(value), &header.masks)
+                }
+                24 => {
+                    let offset = usize::from(x) * 3;
+                    Color::rgb(row_data[offset + 2], row_data[offset + 1], row_data[offset])
+                }
+                _ => {
+                    let offset = usize::from(x) * 4;
+                    let value = LittleEndian::read_u32(&row_data[offset..offset + 4]);
+                    masked_color(value, &header.masks)
+                }
+            };
+            pixel(x, y, color);
+        }
+    }
+    Ok(image_info(&header))
+}
+
+fn image_info(header: &Header) -> ImageInfo {
+    ImageInfo {
+        format: Format::Bmp,
+        width: header.width,
+        height: header.height,
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, Error> {
+    if !data.starts_with(b"BM") {
+        return Err(Error::UnknownFormat);
+    }
+    if data.len() < FILE_HEADER_LEN + 4 {
+        return Err(Error::Truncated);
+    }
+    let pixel_offset = LittleEndian::read_u32(&data[10..14]) as usize;
+    let header_len = LittleEndian::read_u32(&data[14..18]) as usize;
+    if header_len != CORE_HEADER_LEN && header_len < INFO_HEADER_LEN {
+        return Err(Error::InvalidHeader);
+    }
+    if header_len > data.len() - FILE_HEADER_LEN {
+        return Err(Error::Truncated);
+    }
+    let header = &data[FILE_HEADER_LEN..FILE_HEADER_LEN + header_len];
+
+    let core_header = header_len == CORE_HEADER_LEN;
+    let (width, height, bits_per_pixel, compression, colors_used) = if core_header {
+        let width = i32::from(LittleEndian::read_u16(&header[4..6]));
+        let height = i32::from(LittleEndian::read_u16(&header[6..8]));
+        (width, height, LittleEndian::read_u16(&header[10..12]), BI_RGB, 0)
+    } else {
+        (LittleEndian::read_i32(&header[4..8]),
+         LittleEndian::read_i32(&header[8..12]),
+         LittleEndian::read_u16(&header[14..16]),
+         LittleEndian::read_u32(&header[16..20]),
+         LittleEndian::read_u32(&header[32..36]) as usize)
+    };
+
+    let top_down = height < 0;
+    let height = if top_down { -(height as i64) } else { height as i64 };
+    if width <= 0 || height == 0 {
+        return Err(Error::InvalidHeader);
+    }
+    if width > i32::from(u16::max_value()) || height > i64::from(u16::max_value()) {
+        return Err(Error::Unsupported);
+    }
+
+    let masks = match (compression, bits_per_pixel) {
+        (BI_RGB, 16) => [0x7C00, 0x03E0, 0x001F, 0],
+        (BI_RGB, 32) => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0],
+        (BI_RGB, 1) | (BI_RGB, 4) | (BI_RGB, 8) | (BI_RGB, 24) => [0; 4],
+        (BI_BITFIELDS, 16) |
+        (BI_BITFIELDS, 32) |
+        (BI_ALPHABITFIELDS, 16) |
+        (BI_ALPHABITFIELDS, 32) => {
+            // the masks follow a BITMAPINFOHEADER or are part of the larger headers
+            let start = FILE_HEADER_LEN + INFO_HEADER_LEN;
+            let with_alpha = compression == BI_ALPHABITFIELDS || header_len >= 56;
+            let len = if with_alpha { 16 } else { 12 };
+            let masks = match data.get(start..start + len) {
+                Some(masks) => masks,
+                None => return Err(Error::Truncated),
+            };
+            let mut result = [0; 4];
+            for (i, mask) in masks.chunks(4).enumerate() {
+                result[i] = LittleEndian::read_u32(mask);
+            }
+            result
+        }
+        (BI_RGB, _) | (BI_BITFIELDS, _) | (BI_ALPHABITFIELDS, _) => {
+            return Err(Error::InvalidHeader)
+        }
+        // run length encoding, embedded jpeg or png
+        _ => return Err(Error::Unsupported),
+    };
+
+    let palette_entry_len = if core_header { 3 } else { 4 };
+    let palette_len = if bits_per_pixel <= 8 {
+        let max = 1 << bits_per_pixel;
+        if colors_used == 0 || colors_used > max {
+            max
+        } else {
+            colors_used
+        }
+    } else {
+        0
+    };
+
+    Ok(Header {
+           width: width as u16,
+           height: height as u16,
+           top_down: top_down,
+           bits_per_pixel: bits_per_pixel,
+           pixel_offset: pixel_offset,
+           palette_offset: FILE_HEADER_LEN + header_len,
+           palette_entry_len: palette_entry_len,
+           palette_len: palette_len,
+           masks: masks,
+       })
+}
+
+/// Returns black for indices outside of the palette.
+fn palette_color(data: &[u8], header: &Header, index: usize) -> Color {
+    if index >= header.palette_len {
+        return Color::rgb(0, 0, 0);
+    }
+    let offset = header.palette_offset + index * header.palette_entry_len;
+    match data.get(offset..offset + 3) {
+        // stored as blue, green, red
+        Some(entry) => Color::rgb(entry[2], entry[1], entry[0]),
+        None => Color::rgb(0, 0, 0),
+    }
+}
+
+fn masked_color(value: u32, masks: &[u32; 4]) -> Color {
+    let alpha = if masks[3] == 0 {
+        255
+    } else {
+        masked_value(value, masks[3])
+    };
+    Color::rgba(masked_value(value, masks[0]),
+                masked_value(value, masks[1]),
+                masked_value(value, masks[2]),
+                alpha)
+}
+
+fn masked_value(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shifted = (value & mask) >> mask.trailing_zeros();
+    // the span of the mask, which is larger than its bit count if the mask has holes
+    let bits = 32 - (mask >> mask.trailing_zeros()).leading_zeros();
+    scale_to_u8(shifted, bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cmp;
+
+    /// The sample of the fixtures at a position, generated with the same formula as the PNG
+    /// fixtures.
+    fn value(x: u16, y: u16, channel: u16, bits: u32) -> u32 {
+        (u32::from(x) * 37 + u32::from(y) * 71 + u32::from(channel) * 113) & ((1 << bits) - 1)
+    }
+
+    /// The palette entry of the fixtures for an index.
+    fn palette_entry(index: u32) -> Color {
+        let index = index as u8;
+        Color::rgb(index.wrapping_mul(16), 255 - index.wrapping_mul(16), index)
+    }
+
+    /// Checks that every pixel is reported exactly once, BMP rows are reported bottom-up.
+    fn check_fixture<F>(data: &[u8], width: u16, height: u16, expected: F)
+        where F: Fn(u16, u16) -> Color
+    {
+        let mut pixels = vec![None; usize::from(width) * usize::from(height)];
+        let info = decode(data, |x, y, color| {
+                let pixel = &mut pixels[usize::from(y) * usize::from(width) + usize::from(x)];
+                assert_eq!(*pixel, None, "pixel ({}, {}) reported twice", x, y);
+                *pixel = Some(color);
+            })
+            .unwrap();
+        assert_eq!(info,
+                   ImageInfo {
+                       format: Format::Bmp,
+                       width: width,
+                       height: height,
+                   });
+        assert_eq!(info, super::info(data).unwrap());
+        for (i, &color) in pixels.iter().enumerate() {
+            let (x, y) = ((i % usize::from(width)) as u16, (i / usize::from(width)) as u16);
+            assert_eq!(color, Some(expected(x, y)), "pixel ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn palette_1() {
+        check_fixture(include_bytes!("testdata/palette_1.bmp"), 13, 3, |x, y| {
+            palette_entry(value(x, y, 0, 1))
+        });
+    }
+
+    #[test]
+    fn palette_4_with_12_colors() {
+        check_fixture(include_bytes!("testdata/palette_4.bmp"), 7, 3, |x, y| {
+            let index = value(x, y, 0, 4);
+            if index < 12 {
+                palette_entry(index)
+            } else {
+                Color::rgb(0, 0, 0)
+            }
+        });
+    }
+
+    #[test]
+    fn palette_8() {
+        check_fixture(include_bytes!("testdata/palette_8.bmp"), 5, 4, |x, y| {
+            palette_entry(value(x, y, 0, 8))
+        });
+    }
+
+    #[test]
+    fn core_header_palette_4() {
+        // 3 byte palette entries
+        check_fixture(include_bytes!("testdata/core_4.bmp"), 5, 3, |x, y| {
+            palette_entry(value(x, y, 0, 4))
+        });
+    }
+
+    #[test]
+    fn rgb_16() {
+        check_fixture(include_bytes!("testdata/rgb_16.bmp"), 6, 3, |x, y| {
+            let channel = |c| scale_to_u8(value(x, y, c, 5), 5);
+            Color::rgb(channel(0), channel(1), channel(2))
+        });
+    }
+
+    #[test]
+    fn bitfields_16() {
+        check_fixture(include_bytes!("testdata/bitfields_16.bmp"), 5, 2, |x, y| {
+            Color::rgb(scale_to_u8(value(x, y, 0, 5), 5),
+                       scale_to_u8(value(x, y, 1, 6), 6),
+                       scale_to_u8(value(x, y, 2, 5), 5))
+        });
+    }
+
+    #[test]
+    fn rgb_24() {
+        let expected = |x, y| {
+            Color::rgb(value(x, y, 0, 8) as u8,
+                       value(x, y, 1, 8) as u8,
+                       value(x, y, 2, 8) as u8)
+        };
+        check_fixture(include_bytes!("testdata/rgb_24.bmp"), 5, 3, &expected);
+        check_fixture(include_bytes!("testdata/rgb_24_top_down.bmp"), 5, 3, &expected);
+    }
+
+    #[test]
+    fn rgb_32_ignores_the_unused_byte() {
+        check_fixture(include_bytes!("testdata/rgb_32.bmp"), 4, 3, |x, y| {
+            Color::rgb(value(x, y, 0, 8) as u8,
+                       value(x, y, 1, 8) as u8,
+                       value(x, y, 2, 8) as u8)
+        });
+    }
+
+    #[test]
+    fn bitfields_32_v4_header() {
+        // top-down, red in the lowest byte and alpha in the highest
+        check_fixture(include_bytes!("testdata/bitfields_32_v4.bmp"), 4, 3, |x, y| {
+            let channel = |c| value(x, y, c, 8) as u8;
+            Color::rgba(channel(0), channel(1), channel(2), channel(3))
+        });
+    }
+
+    #[test]
+    fn rejects_compressed_images() {
+        let mut file = include_bytes!("testdata/rgb_24.bmp").to_vec();
+        // BI_RLE8
+        file[FILE_HEADER_LEN + 16] = 1;
+        assert_eq!(info(&file), Err(Error::Unsupported));
+        // BI_RGB with 2 bits per pixel
+        file[FILE_HEADER_LEN + 16] = 0;
+        file[FILE_HEADER_LEN + 14] = 2;
+        assert_eq!(info(&file), Err(Error::InvalidHeader));
+    }
+
+    const FIXTURES: [&'static [u8]; 10] = [include_bytes!("testdata/palette_1.bmp"),
+                                           include_bytes!("testdata/palette_4.bmp"),
+                                           include_bytes!("testdata/palette_8.bmp"),
+                                           include_bytes!("testdata/core_4.bmp"),
+                                           include_bytes!("testdata/rgb_16.bmp"),
+                                           include_bytes!("testdata/bitfields_16.bmp"),
+                                           include_bytes!("testdata/rgb_24.bmp"),
+                                           include_bytes!("testdata/rgb_24_top_down.bmp"),
+                                           include_bytes!("testdata/rgb_32.bmp"),
+                                           include_bytes!("testdata/bitfields_32_v4.bmp")];
+
+    #[test]
+    fn truncated_fixtures() {
+        for fixture in FIXTURES.iter() {
+            for len in 0..fixture.len() {
+                assert!(decode(&fixture[..len], |_, _, _| {}).is_err());
+            }
+        }
+    }
+
+    /// Xorshift, for reproducible fuzzing.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    /// Decodes the file and checks that the reported pixels are inside the image.
+    fn decode_checked(file: &[u8]) {
+        let mut pixels = 0;
+        let result = decode(file, |_, _, _| pixels += 1);
+        let mut inside = true;
+        if let Ok(info) = result {
+            decode(file, |x, y, _| inside &= x < info.width && y < info.height).unwrap();
+            assert!(inside);
+            assert_eq!(pixels, usize::from(info.width) * usize::from(info.height));
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_fixtures() {
+        let mut random = Random(0x1234_5678);
+        for _ in 0..20_000 {
+            let fixture = FIXTURES[random.next() as usize % FIXTURES.len()];
+            let mut file = fixture.to_vec();
+            for _ in 0..1 + random.next() % 4 {
+                // keep the signature intact, mostly mutate the headers
+                let len = if random.next() % 2 == 0 { 70 } else { file.len() - 2 };
+                let index = 2 + random.next() as usize % cmp::min(len, file.len() - 2);
+                file[index] = random.next() as u8;
+            }
+            decode_checked(&file);
+        }
+    }
+
+    #[test]
+    fn fuzz_random_headers() {
+        let mut random = Random(0x9E37_79B9);
+        for _ in 0..20_000 {
+            let mut file = include_bytes!("testdata/bitfields_32_v4.bmp").to_vec();
+            let header_len = [12, 40, 52, 56, 108, 124][random.next() as usize % 6];
+            LittleEndian::write_u32(&mut file[14..18], header_len);
+            LittleEndian::write_u32(&mut file[10..14], random.next() % 200);
+            LittleEndian::write_i32(&mut file[18..22], (random.next() % 40) as i32 - 4);
+            LittleEndian::write_i32(&mut file[22..26], (random.next() % 80) as i32 - 40);
+            LittleEndian::write_u16(&mut file[28..30],
+                                    [1, 4, 8, 16, 24, 32][random.next() as usize % 6]);
+            LittleEndian::write_u32(&mut file[30..34], [0, 3, 6][random.next() as usize % 3]);
+            LittleEndian::write_u32(&mut file[46..50], random.next() % 300);
+            for byte in file[54..70].iter_mut() {
+                *byte = random.next() as u8;
+            }
+            decode_checked(&file);
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Decompression of zlib streams (RFC 1950 and 1951), as used by PNG.
+//!
+//! The input may be split into several slices, like the IDAT chunks of a PNG, and the output
+//! is passed to a callback byte by byte. Only the 32 KB window is kept in memory.
+
+use collections::Vec;
+use super::Error;
+
+const WINDOW_LEN: usize = 32 * 1024;
+const MAX_BITS: usize = 15;
+const LITERAL_LENGTH_CODES: usize = 288;
+const DISTANCE_CODES: usize = 32;
+const END_OF_BLOCK: u16 = 256;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43,
+                                51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4,
+                                4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257,
+                                  385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193,
+                                  12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9,
+                                  9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// Order in which the code lengths of the code length alphabet are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2,
+                                        14, 1, 15];
+
+/// Reads bits, least significant first, from a sequence of slices.
+pub struct BitReader<'a> {
+    slices: &'a [&'a [u8]],
+    slice: usize,
+    position: usize,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(slices: &'a [&'a [u8]]) -> BitReader<'a> {
+        BitReader {
+            slices: slices,
+            slice: 0,
+            position: 0,
+            bits: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        while self.slice < self.slices.len() {
+            if let Some(&byte) = self.slices[self.slice].get(self.position) {
+                self.position += 1;
+                return Ok(byte);
+            }
+            self.slice += 1;
+            self.position = 0;
+        }
+        Err(Error::Truncated)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, Error> {
+        while self.bit_count < count {
+            self.bits |= u32::from(self.next_byte()?) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bits & ((1u64 << count) - 1) as u32;
+        self.bits = if count == 32 { 0 } else { self.bits >> count };
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Skips the remaining bits of the current byte.
+    fn align_to_byte(&mut self) {
+        let skip = self.bit_count % 8;
+        self.bits >>= skip;
+        self.bit_count -= skip;
+    }
+}
+
+/// A canonical Huffman code, decoded bit by bit.
+struct Huffman {
+    /// Number of codes of each length.
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code.
+    symbols: [u16; LITERAL_LENGTH_CODES],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, Error> {
+        let mut huffman = Huffman {
+            counts: [0; MAX_BITS + 1],
+            symbols: [0; LITERAL_LENGTH_CODES],
+        };
+        for &length in lengths {
+            huffman.counts[usize::from(length)] += 1;
+        }
+        huffman.counts[0] = 0;
+
+        // reject over-subscribed codes, incomplete codes are allowed
+        let mut left: i32 = 1;
+        for length in 1..MAX_BITS + 1 {
+            left = (left << 1) - i32::from(huffman.counts[length]);
+            if left < 0 {
+                return Err(Error::InvalidData);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + huffman.counts[length];
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                huffman.symbols[usize::from(offsets[usize::from(length)])] = symbol as u16;
+                offsets[usize::from(length)] += 1;
+            }
+        }
+        Ok(huffman)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Error> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..MAX_BITS + 1 {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::InvalidData)
+    }
+}
+
+struct Output<F> {
+    window: Vec<u8>,
+    position: usize,
+    total: usize,
+    adler_a: u32,
+    adler_b: u32,
+    sink: F,
+}
+
+impl<F> Output<F>
+    where F: FnMut(u8) -> Result<(), Error>
+{
+    fn push(&mut self, byte: u8) -> Result<(), Error> {
+        self.window[self.position] = byte;
+        self.position = (self.position + 1) % WINDOW_LEN;
+        self.total += 1;
+        self.adler_a = (self.adler_a + u32::from(byte)) % 65521;
+        self.adler_b = (self.adler_b + self.adler_a) % 65521;
+        (self.sink)(byte)
+    }
+
+    fn copy(&mut self, distance: usize, length: usize) -> Result<(), Error> {
+        if distance > self.total || distance > WINDOW_LEN {
+            return Err(Error::InvalidData);
+        }
+        for _ in 0..length {
+            let byte = self.window[(self.position + WINDOW_LEN - distance) % WINDOW_LEN];
+            self.push(byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses a zlib stream and checks its Adler-32 checksum.
+///
+/// `sink` receives the decompressed bytes. Errors returned by `sink` abort the decompression.
+pub fn inflate_zlib<F>(reader: &mut BitReader, sink: F) -> Result<(), Error>
+    where F: FnMut(u8) -> Result<(), Error>
+{
+    let cmf = reader.bits(8)?;
+    let flags = reader.bits(8)?;
+    let method = cmf & 0x0F;
+    let window_bits = (cmf >> 4) + 8;
+    let preset_dictionary = flags & 0x20 != 0;
+    if method != 8 || window_bits > 15 || (cmf << 8 | flags) % 31 != 0 || preset_dictionary {
+        return Err(Error::InvalidData);
+    }
+
+    let mut output = Output {
+        window: vec![0; WINDOW_LEN],
+        position: 0,
+        total: 0,
+        adler_a: 1,
+        adler_b: 0,
+        sink: sink,
+    };
+
+    loop {
+        let last_block = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                compressed_block(reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                compressed_block(reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(Error::InvalidData),
+        }
+        if last_block {
+            break;
+        }
+    }
+
+    reader.align_to_byte();
+    let mut checksum = 0;
+    for _ in 0..4 {
+        checksum = checksum << 8 | reader.bits(8)?;
+    }
+    if checksum != output.adler_b << 16 | output.adler_a {
+        return Err(Error::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+fn stored_block<F>(reader: &mut BitReader, output: &mut Output<F>) -> Result<(), Error>
+    where F: FnMut(u8) -> Result<(), Error>
+{
+    reader.align_to_byte();
+    let len = reader.bits(16)?;
+    let inverted_len = reader.bits(16)?;
+    if len != !inverted_len & 0xFFFF {
+        return Err(Error::InvalidData);
+    }
+    for _ in 0..len {
+        let byte = reader.bits(8)? as u8;
+        output.push(byte)?;
+    }
+    Ok(())
+}
+
+fn compressed_block<F>(reader: &mut BitReader,
+                       output: &mut Output<F>,
+                       literals: &Huffman,
+                       distances: &Huffman)
+                       -> Result<(), Error>
+    where F: FnMut(u8) -> Result<(), Error>
+{
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8)?;
+        } else if symbol == END_OF_BLOCK {
+            return Ok(());
+        } else {
+            let index = usize::from(symbol - END_OF_BLOCK - 1);
+            if index >= LENGTH_BASE.len() {
+                return Err(Error::InvalidData);
+            }
+            let length = u32::from(LENGTH_BASE[index]) +
+                         reader.bits(u32::from(LENGTH_EXTRA[index]))?;
+
+            let index = usize::from(distances.decode(reader)?);
+            if index >= DISTANCE_BASE.len() {
+                return Err(Error::InvalidData);
+            }
+            let distance = u32::from(DISTANCE_BASE[index]) +
+                           reader.bits(u32::from(DISTANCE_EXTRA[index]))?;
+            output.copy(distance as usize, length as usize)?;
+        }
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths = [0; LITERAL_LENGTH_CODES];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0...143 => 8,
+            144...255 => 9,
+            256...279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; DISTANCE_CODES])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(Error::InvalidData);
+    }
+
+    let mut code_lengths = [0; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // the literal/length and distance code lengths form one sequence
+    let mut lengths = [0u8; 286 + 30];
+    let count = literal_count + distance_count;
+    let mut index = 0;
+    while index < count {
+        let symbol = code_length_code.decode(reader)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+        let (value, repeat) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(Error::InvalidData);
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > count {
+            return Err(Error::InvalidData);
+        }
+        for length in &mut lengths[index..index + repeat] {
+            *length = value;
+        }
+        index += repeat;
+    }
+
+    if lengths[usize::from(END_OF_BLOCK)] == 0 {
+        return Err(Error::InvalidData);
+    }
+    Ok((Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..count])?))
+}
@@ -0,0 +1,138 @@
+//! Decoders for BMP, QOI and PNG images, e.g. for logos and icons that are embedded with
+//! `include_bytes!`, and a PNG encoder.
+//!
+//! The decoders don't allocate an image buffer. They pass each pixel to a callback, so images
+//! are drawn directly to an LCD layer or any other `graphics::Canvas`. PNG decoding needs
+//! about 32 KB of heap for the inflate window and up to 32 KB for two rows, PNGs wider than
+//! 2048 pixels are rejected as `Error::Unsupported`.
+
+use graphics::{Canvas, LayerCanvas};
+use lcd::{Color, Layer, Lcd};
+
+mod bmp;
+mod inflate;
+mod png;
+mod qoi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The data ends before the image is complete.
+    Truncated,
+    /// The data doesn't start with the signature of a supported format.
+    UnknownFormat,
+    /// A header field has an invalid value.
+    InvalidHeader,
+    /// A valid image that the decoder does not support, e.g. a compressed BMP, an interlaced
+    /// PNG or a PNG that is wider than 2048 pixels.
+    Unsupported,
+    /// The compressed data, the QOI end marker or a PNG filter type is invalid.
+    InvalidData,
+    /// The CRC of a PNG chunk or the Adler-32 checksum of the zlib stream does not match.
+    ChecksumMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bmp,
+    Qoi,
+    Png,
+}
+
+impl Format {
+    /// Detects the format from the signature at the start of the data.
+    pub fn detect(data: &[u8]) -> Option<Format> {
+        if data.starts_with(b"BM") {
+            Some(Format::Bmp)
+        } else if data.starts_with(b"qoif") {
+            Some(Format::Qoi)
+        } else if data.starts_with(&png::SIGNATURE) {
+            Some(Format::Png)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub format: Format,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Reads the format and size from the header without decoding the pixels.
+pub fn info(data: &[u8]) -> Result<ImageInfo, Error> {
+    match Format::detect(data) {
+        Some(Format::Bmp) => bmp::info(data),
+        Some(Format::Qoi) => qoi::info(data),
+        Some(Format::Png) => png::info(data),
+        None => Err(Error::UnknownFormat),
+    }
+}
+
+/// Decodes an image and calls `pixel(x, y, color)` for every pixel.
+///
+/// The pixels are not necessarily reported row by row from the top, e.g. most BMPs are stored
+/// bottom-up. If an error occurs, the pixels decoded up to that point were already reported.
+pub fn decode<F>(data: &[u8], pixel: F) -> Result<ImageInfo, Error>
+    where F: FnMut(u16, u16, Color)
+{
+    match Format::detect(data) {
+        Some(Format::Bmp) => bmp::decode(data, pixel),
+        Some(Format::Qoi) => qoi::decode(data, pixel),
+        Some(Format::Png) => png::decode(data, pixel),
+        None => Err(Error::UnknownFormat),
+    }
+}
+
+/// Draws an image with the top left corner at (`x`, `y`). The image is clipped to the canvas,
+/// so the position may be negative.
+///
+/// The pixels are copied including their alpha value, they are not blended with the content
+/// of the canvas.
+pub fn draw<C: Canvas>(canvas: &mut C,
+                       x: i32,
+                       y: i32,
+                       data: &[u8])
+                       -> Result<ImageInfo, Error> {
+    let (width, height) = canvas.size();
+    let (width, height) = (i32::from(width), i32::from(height));
+    decode(data, |image_x, image_y, color| {
+        let (target_x, target_y) = (x + i32::from(image_x), y + i32::from(image_y));
+        if target_x >= 0 && target_x < width && target_y >= 0 && target_y < height {
+            canvas.set_pixel(target_x as u16, target_y as u16, color);
+        }
+    })
+}
+
+/// Draws an image to a layer of the LCD. See `draw`.
+pub fn blit(lcd: &mut Lcd, layer: Layer, x: i32, y: i32, data: &[u8]) -> Result<ImageInfo, Error> {
+    draw(&mut LayerCanvas::new(lcd, layer), x, y, data)
+}
+
+/// Encodes an 8 bit RGBA PNG image with `pixel(x, y)` as the source, e.g. to save a canvas as
+/// a golden image in host tests.
+///
+/// The image data is not compressed, so the file is a bit larger than the raw pixels. The file
+/// is passed to `write` in pieces, at most one row at a time, and the first error returned by
+/// `write` is returned. PNG doesn't allow a width or height of 0, so decoders reject such
+/// images.
+pub fn encode_png<P, W, E>(width: u16, height: u16, pixel: P, write: W) -> Result<(), E>
+    where P: FnMut(u16, u16) -> Color,
+          W: FnMut(&[u8]) -> Result<(), E>
+{
+    png::encode(width, height, pixel, write)
+}
+
+/// Scales a sample with `bits` bits to 8 bits.
+fn scale_to_u8(value: u32, bits: u32) -> u8 {
+    match bits {
+        0 => 0,
+        8 => value as u8,
+        bits if bits > 8 => (value >> (bits - 8)) as u8,
+        bits => {
+            let max = (1 << bits) - 1;
+            ((value * 255 + max / 2) / max) as u8
+        }
+    }
+}
@@ -0,0 +1,682 @@
+//! Non-interlaced PNG images of all color types and bit depths, and an encoder for
+//! uncompressed RGBA images.
+
+use byteorder::{BigEndian, ByteOrder};
+use collections::Vec;
+use core::cmp;
+use lcd::Color;
+use super::inflate::{self, BitReader};
+use super::{scale_to_u8, Error, Format, ImageInfo};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+const COLOR_GRAY_ALPHA: u8 = 4;
+const COLOR_RGBA: u8 = 6;
+
+/// Widest image that `decode` accepts. The decoder keeps two rows, so the buffers stay below
+/// 32 KB even for 16 bit RGBA. The height doesn't matter because the rows are streamed.
+pub const MAX_WIDTH: u16 = 2048;
+
+/// Maximum length of an uncompressed deflate block.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    width: u16,
+    height: u16,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_GRAY | COLOR_PALETTE => 1,
+            COLOR_GRAY_ALPHA => 2,
+            COLOR_RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    /// Bytes per row, without the filter type byte.
+    fn stride(&self) -> usize {
+        (self.bits_per_pixel() * usize::from(self.width) + 7) / 8
+    }
+
+    /// Distance of the bytes that the filters combine, at least one byte.
+    fn filter_distance(&self) -> usize {
+        (self.bits_per_pixel() + 7) / 8
+    }
+}
+
+/// Palette and transparency information of the PLTE and tRNS chunks.
+struct Palette<'a> {
+    colors: &'a [u8],
+    alphas: &'a [u8],
+    /// Samples of this gray level or rgb color are transparent.
+    transparent: Option<[u16; 3]>,
+}
+
+struct Chunk<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Iterates over the chunks after the signature and checks their CRC.
+struct Chunks<'a> {
+    data: &'a [u8],
+    position: usize,
+    crc_table: [u32; 256],
+}
+
+impl<'a> Chunks<'a> {
+    fn new(data: &'a [u8]) -> Chunks<'a> {
+        Chunks {
+            data: data,
+            position: SIGNATURE.len(),
+            crc_table: crc_table(),
+        }
+    }
+
+    fn next_chunk(&mut self) -> Result<Chunk<'a>, Error> {
+        let data: &'a [u8] = self.data;
+        let rest = &data[self.position..];
+        if rest.len() < 12 {
+            return Err(Error::Truncated);
+        }
+        let len = BigEndian::read_u32(&rest[0..4]) as usize;
+        if len > rest.len() - 12 {
+            return Err(Error::Truncated);
+        }
+        let crc = BigEndian::read_u32(&rest[8 + len..12 + len]);
+        if crc != crc32(&self.crc_table, &rest[4..8 + len]) {
+            return Err(Error::ChecksumMismatch);
+        }
+        self.position += 12 + len;
+
+        let mut kind = [0; 4];
+        kind.copy_from_slice(&rest[4..8]);
+        Ok(Chunk {
+               kind: kind,
+               data: &rest[8..8 + len],
+           })
+    }
+}
+
+pub fn info(data: &[u8]) -> Result<ImageInfo, Error> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(Error::UnknownFormat);
+    }
+    let header = parse_header(&Chunks::new(data).next_chunk()?)?;
+    Ok(image_info(&header))
+}
+
+pub fn decode<F>(data: &[u8], mut pixel: F) -> Result<ImageInfo, Error>
+    where F: FnMut(u16, u16, Color)
+{
+    if !data.starts_with(&SIGNATURE) {
+        return Err(Error::UnknownFormat);
+    }
+    let mut chunks = Chunks::new(data);
+    let header = parse_header(&chunks.next_chunk()?)?;
+
+    let mut palette = Palette {
+        colors: &[],
+        alphas: &[],
+        transparent: None,
+    };
+    let mut image_data = Vec::new();
+    loop {
+        let chunk = chunks.next_chunk()?;
+        match &chunk.kind {
+            b"PLTE" => {
+                if chunk.data.len() % 3 != 0 || chunk.data.len() > 256 * 3 {
+                    return Err(Error::InvalidHeader);
+                }
+                palette.colors = chunk.data;
+            }
+            b"tRNS" => {
+                match header.color_type {
+                    COLOR_PALETTE => palette.alphas = chunk.data,
+                    COLOR_GRAY if chunk.data.len() >= 2 => {
+                        let gray = BigEndian::read_u16(&chunk.data[0..2]);
+                        palette.transparent = Some([gray, gray, gray]);
+                    }
+                    COLOR_RGB if chunk.data.len() >= 6 => {
+                        palette.transparent = Some([BigEndian::read_u16(&chunk.data[0..2]),
+                                                    BigEndian::read_u16(&chunk.data[2..4]),
+                                                    BigEndian::read_u16(&chunk.data[4..6])]);
+                    }
+                    _ => return Err(Error::InvalidHeader),
+                }
+            }
+            b"IDAT" => image_data.push(chunk.data),
+            b"IEND" => break,
+            // ancillary chunks have a lowercase first letter and can be ignored
+            kind if kind[0] & 0x20 != 0 => {}
+            _ => return Err(Error::Unsupported),
+        }
+    }
+    if header.color_type == COLOR_PALETTE && palette.colors.is_empty() {
+        return Err(Error::InvalidHeader);
+    }
+
+    let stride = header.stride();
+    let distance = header.filter_distance();
+    // the filter type byte and the row
+    let mut row = vec![0; 1 + stride];
+    let mut previous = vec![0; stride];
+    let (mut filled, mut y) = (0, 0);
+
+    let mut reader = BitReader::new(&image_data);
+    inflate::inflate_zlib(&mut reader, |byte| {
+            if y == header.height {
+                // ignore data after the last row
+                return Ok(());
+            }
+            row[filled] = byte;
+            filled += 1;
+            if filled == row.len() {
+                unfilter(row[0], &mut row[1..], &previous, distance)?;
+                emit_row(&header, &palette, &row[1..], y, &mut pixel);
+                previous.copy_from_slice(&row[1..]);
+                filled = 0;
+                y += 1;
+            }
+            Ok(())
+        })?;
+
+    if y < header.height {
+        return Err(Error::Truncated);
+    }
+    Ok(image_info(&header))
+}
+
+/// Writes the pieces of a chunk and keeps track of its CRC.
+struct ChunkWriter<'a, W: 'a> {
+    write: &'a mut W,
+    crc_table: &'a [u32; 256],
+    crc: u32,
+}
+
+impl<'a, W, E> ChunkWriter<'a, W>
+    where W: FnMut(&[u8]) -> Result<(), E>
+{
+    fn start(write: &'a mut W,
+             crc_table: &'a [u32; 256],
+             kind: &[u8; 4],
+             len: u32)
+             -> Result<ChunkWriter<'a, W>, E> {
+        let mut len_bytes = [0; 4];
+        BigEndian::write_u32(&mut len_bytes, len);
+        write(&len_bytes)?;
+        let mut writer = ChunkWriter {
+            write: write,
+            crc_table: crc_table,
+            crc: 0xFFFF_FFFF,
+        };
+        writer.data(kind)?;
+        Ok(writer)
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), E> {
+        self.crc = crc_update(self.crc_table, self.crc, data);
+        (self.write)(data)
+    }
+
+    fn finish(self) -> Result<(), E> {
+        let mut crc = [0; 4];
+        BigEndian::write_u32(&mut crc, !self.crc);
+        (self.write)(&crc)
+    }
+}
+
+/// Encodes an 8 bit RGBA image with `pixel(x, y)` as the source and passes the file to `write`
+/// in pieces. See `image::encode_png`.
+pub fn encode<P, W, E>(width: u16, height: u16, mut pixel: P, mut write: W) -> Result<(), E>
+    where P: FnMut(u16, u16) -> Color,
+          W: FnMut(&[u8]) -> Result<(), E>
+{
+    let crc_table = crc_table();
+    write(&SIGNATURE)?;
+
+    let mut header = [0; 13];
+    BigEndian::write_u32(&mut header[0..4], u32::from(width));
+    BigEndian::write_u32(&mut header[4..8], u32::from(height));
+    header[8] = 8; // bit depth
+    header[9] = COLOR_RGBA;
+    let mut chunk = ChunkWriter::start(&mut write, &crc_table, b"IHDR", header.len() as u32)?;
+    chunk.data(&header)?;
+    chunk.finish()?;
+
+    // a zlib stream of stored blocks, the rows start with filter type 0
+    let stride = 1 + usize::from(width) * 4;
+    let raw_len = stride * usize::from(height);
+    let block_count = cmp::max((raw_len + MAX_STORED_BLOCK - 1) / MAX_STORED_BLOCK, 1);
+    let zlib_len = 2 + block_count * 5 + raw_len + 4;
+    let mut chunk = ChunkWriter::start(&mut write, &crc_table, b"IDAT", zlib_len as u32)?;
+    chunk.data(&[0x78, 0x01])?;
+
+    let mut row = vec![0; stride];
+    let (mut remaining, mut block_left) = (raw_len, 0);
+    let (mut adler_a, mut adler_b) = (1u32, 0u32);
+    if raw_len == 0 {
+        chunk.data(&[1, 0, 0, 0xFF, 0xFF])?;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let color = pixel(x, y);
+            let offset = 1 + usize::from(x) * 4;
+            row[offset..offset + 4].copy_from_slice(&[color.red,
+                                                      color.green,
+                                                      color.blue,
+                                                      color.alpha]);
+        }
+        for &byte in &row {
+            adler_a = (adler_a + u32::from(byte)) % 65521;
+            adler_b = (adler_b + adler_a) % 65521;
+        }
+
+        let mut rest = &row[..];
+        while !rest.is_empty() {
+            if block_left == 0 {
+                block_left = cmp::min(remaining, MAX_STORED_BLOCK);
+                let last = if block_left == remaining { 1 } else { 0 };
+                let len = block_left as u16;
+                chunk.data(&[last, len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8])?;
+            }
+            let len = cmp::min(block_left, rest.len());
+            chunk.data(&rest[..len])?;
+            rest = &rest[len..];
+            block_left -= len;
+            remaining -= len;
+        }
+    }
+    let mut adler = [0; 4];
+    BigEndian::write_u32(&mut adler, adler_b << 16 | adler_a);
+    chunk.data(&adler)?;
+    chunk.finish()?;
+
+    ChunkWriter::start(&mut write, &crc_table, b"IEND", 0)?.finish()
+}
+
+fn image_info(header: &Header) -> ImageInfo {
+    ImageInfo {
+        format: Format::Png,
+        width: header.width,
+        height: header.height,
+    }
+}
+
+fn parse_header(chunk: &Chunk) -> Result<Header, Error> {
+    if &chunk.kind != b"IHDR" || chunk.data.len() != 13 {
+        return Err(Error::InvalidHeader);
+    }
+    let data = chunk.data;
+    let width = BigEndian::read_u32(&data[0..4]);
+    let height = BigEndian::read_u32(&data[4..8]);
+    let (bit_depth, color_type) = (data[8], data[9]);
+    let (compression, filter, interlace) = (data[10], data[11], data[12]);
+
+    let valid_depth = match color_type {
+        COLOR_GRAY => [1, 2, 4, 8, 16].contains(&bit_depth),
+        COLOR_PALETTE => [1, 2, 4, 8].contains(&bit_depth),
+        COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => bit_depth == 8 || bit_depth == 16,
+        _ => false,
+    };
+    if width == 0 || height == 0 || !valid_depth || compression != 0 || filter != 0 ||
+       interlace > 1 {
+        return Err(Error::InvalidHeader);
+    }
+    if interlace != 0 || width > u32::from(MAX_WIDTH) || height > u32::from(u16::max_value()) {
+        return Err(Error::Unsupported);
+    }
+    Ok(Header {
+           width: width as u16,
+           height: height as u16,
+           bit_depth: bit_depth,
+           color_type: color_type,
+       })
+}
+
+/// Reverses the filter of a row in place.
+fn unfilter(filter: u8,
+            row: &mut [u8],
+            previous: &[u8],
+            distance: usize)
+            -> Result<(), Error> {
+    for i in 0..row.len() {
+        let left = if i >= distance { row[i - distance] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= distance { previous[i - distance] } else { 0 };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(Error::InvalidData),
+        };
+        row[i] = row[i].wrapping_add(predictor);
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let (a, b, c) = (i16::from(left), i16::from(up), i16::from(up_left));
+    let p = a + b - c;
+    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn emit_row<F>(header: &Header, palette: &Palette, row: &[u8], y: u16, pixel: &mut F)
+    where F: FnMut(u16, u16, Color)
+{
+    let depth = usize::from(header.bit_depth);
+    let channels = header.channels();
+    let sample = |x: usize, channel: usize| -> u16 {
+        let index = x * channels + channel;
+        match depth {
+            16 => BigEndian::read_u16(&row[index * 2..index * 2 + 2]),
+            8 => u16::from(row[index]),
+            _ => {
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                u16::from((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8)
+            }
+        }
+    };
+    let to_u8 = |value: u16| scale_to_u8(u32::from(value), depth as u32);
+
+    for x in 0..usize::from(header.width) {
+        let color = match header.color_type {
+            COLOR_GRAY => {
+                let gray = sample(x, 0);
+                let alpha = if palette.transparent == Some([gray, gray, gray]) {
+                    0
+                } else {
+                    255
+                };
+                let gray = to_u8(gray);
+                Color::rgba(gray, gray, gray, alpha)
+            }
+            COLOR_RGB => {
+                let rgb = [sample(x, 0), sample(x, 1), sample(x, 2)];
+                let alpha = if palette.transparent == Some(rgb) {
+                    0
+                } else {
+                    255
+                };
+                Color::rgba(to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]), alpha)
+            }
+            COLOR_PALETTE => {
+                let index = usize::from(sample(x, 0));
+                let alpha = palette.alphas.get(index).cloned().unwrap_or(255);
+                match palette.colors.get(index * 3..index * 3 + 3) {
+                    Some(rgb) => Color::rgba(rgb[0], rgb[1], rgb[2], alpha),
+                    // invalid index
+                    None => Color::rgb(0, 0, 0),
+                }
+            }
+            COLOR_GRAY_ALPHA => {
+                let gray = to_u8(sample(x, 0));
+                Color::rgba(gray, gray, gray, to_u8(sample(x, 1)))
+            }
+            _ => {
+                Color::rgba(to_u8(sample(x, 0)),
+                            to_u8(sample(x, 1)),
+                            to_u8(sample(x, 2)),
+                            to_u8(sample(x, 3)))
+            }
+        };
+        pixel(x as u16, y, color);
+    }
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+    table
+}
+
+fn crc32(table: &[u32; 256], data: &[u8]) -> u32 {
+    !crc_update(table, 0xFFFF_FFFF, data)
+}
+
+/// Continues a CRC computation without the final inversion.
+fn crc_update(table: &[u32; 256], crc: u32, data: &[u8]) -> u32 {
+    data.iter()
+        .fold(crc,
+              |crc, &byte| table[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    /// The sample of the fixtures at a position, generated with the same formula.
+    fn value(x: u16, y: u16, channel: u16, bits: u32) -> u16 {
+        ((u32::from(x) * 37 + u32::from(y) * 71 + u32::from(channel) * 113) &
+         ((1 << bits) - 1)) as u16
+    }
+
+    fn check_fixture<F>(data: &[u8], width: u16, height: u16, expected: F)
+        where F: Fn(u16, u16) -> Color
+    {
+        let mut pixels = Vec::new();
+        let info = decode(data, |x, y, color| pixels.push((x, y, color))).unwrap();
+        assert_eq!((info.width, info.height), (width, height));
+        assert_eq!(info, super::info(data).unwrap());
+        assert_eq!(pixels.len(), usize::from(width) * usize::from(height));
+        for (i, &(x, y, color)) in pixels.iter().enumerate() {
+            assert_eq!((x, y), ((i % usize::from(width)) as u16, (i / usize::from(width)) as u16));
+            assert_eq!(color, expected(x, y), "pixel ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn gray_1() {
+        check_fixture(include_bytes!("testdata/gray_1.png"), 13, 3, |x, y| {
+            Color::gray(value(x, y, 0, 1) as u8 * 255)
+        });
+    }
+
+    #[test]
+    fn gray_16_transparent() {
+        let transparent = value(1, 0, 0, 16);
+        check_fixture(include_bytes!("testdata/gray_16_trns.png"), 4, 3, |x, y| {
+            let gray = value(x, y, 0, 16);
+            let alpha = if gray == transparent { 0 } else { 255 };
+            Color::rgba((gray >> 8) as u8, (gray >> 8) as u8, (gray >> 8) as u8, alpha)
+        });
+    }
+
+    #[test]
+    fn palette_4_transparent() {
+        check_fixture(include_bytes!("testdata/palette_4_trns.png"), 7, 3, |x, y| {
+            let index = value(x, y, 0, 4) as u8;
+            let alpha = match index {
+                0 => 0,
+                1 => 128,
+                _ => 255,
+            };
+            Color::rgba(index * 16, 255 - index * 16, index, alpha)
+        });
+    }
+
+    #[test]
+    fn rgb_8_all_filters() {
+        check_fixture(include_bytes!("testdata/rgb_8_filters.png"), 9, 10, |x, y| {
+            Color::rgb(value(x, y, 0, 8) as u8,
+                       value(x, y, 1, 8) as u8,
+                       value(x, y, 2, 8) as u8)
+        });
+    }
+
+    #[test]
+    fn gray_alpha_8() {
+        check_fixture(include_bytes!("testdata/gray_alpha_8.png"), 5, 4, |x, y| {
+            let gray = value(x, y, 0, 8) as u8;
+            Color::rgba(gray, gray, gray, value(x, y, 1, 8) as u8)
+        });
+    }
+
+    #[test]
+    fn rgba_16() {
+        check_fixture(include_bytes!("testdata/rgba_16.png"), 6, 5, |x, y| {
+            let channel = |c| (value(x, y, c, 16) >> 8) as u8;
+            Color::rgba(channel(0), channel(1), channel(2), channel(3))
+        });
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let color = |x: u16, y: u16| Color::rgba(x as u8 * 40, y as u8 * 50, 7, 255 - x as u8);
+        let mut file = Vec::new();
+        encode(5, 4, color, |bytes| -> Result<(), ()> {
+                file.extend_from_slice(bytes);
+                Ok(())
+            })
+            .unwrap();
+        check_fixture(&file, 5, 4, color);
+    }
+
+    /// An image with only a header.
+    fn header_only(width: u32, height: u32) -> Vec<u8> {
+        let crc_table = crc_table();
+        let mut file = SIGNATURE.to_vec();
+        let mut chunk = vec![0, 0, 0, 13];
+        chunk.extend_from_slice(b"IHDR");
+        let mut header = [0; 13];
+        BigEndian::write_u32(&mut header[0..4], width);
+        BigEndian::write_u32(&mut header[4..8], height);
+        header[8] = 8;
+        header[9] = COLOR_RGB;
+        chunk.extend_from_slice(&header);
+        let mut crc = [0; 4];
+        BigEndian::write_u32(&mut crc, crc32(&crc_table, &chunk[4..]));
+        chunk.extend_from_slice(&crc);
+        file.extend_from_slice(&chunk);
+        file
+    }
+
+    #[test]
+    fn rejects_wide_images() {
+        assert_eq!(info(&header_only(u32::from(MAX_WIDTH), 1)).unwrap().width, MAX_WIDTH);
+        assert_eq!(info(&header_only(u32::from(MAX_WIDTH) + 1, 1)), Err(Error::Unsupported));
+        assert_eq!(decode(&header_only(0xFFFF, 1), |_, _, _| {}), Err(Error::Unsupported));
+        assert_eq!(info(&header_only(1, 0x1_0000)), Err(Error::Unsupported));
+        assert_eq!(info(&header_only(0, 1)), Err(Error::InvalidHeader));
+    }
+
+    const FIXTURES: [&'static [u8]; 6] = [include_bytes!("testdata/gray_1.png"),
+                                          include_bytes!("testdata/gray_16_trns.png"),
+                                          include_bytes!("testdata/palette_4_trns.png"),
+                                          include_bytes!("testdata/rgb_8_filters.png"),
+                                          include_bytes!("testdata/gray_alpha_8.png"),
+                                          include_bytes!("testdata/rgba_16.png")];
+
+    #[test]
+    fn truncated_fixtures() {
+        for fixture in FIXTURES.iter() {
+            for len in 0..fixture.len() {
+                assert!(decode(&fixture[..len], |_, _, _| {}).is_err());
+            }
+        }
+    }
+
+    /// Xorshift, for reproducible fuzzing.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    /// Recomputes the CRC of every chunk, so that the decoder gets past the chunk parser.
+    fn fix_crcs(file: &mut [u8]) {
+        let crc_table = crc_table();
+        let mut position = SIGNATURE.len();
+        while position + 12 <= file.len() {
+            let len = BigEndian::read_u32(&file[position..position + 4]) as usize;
+            if len > file.len() - position - 12 {
+                break;
+            }
+            let crc = crc32(&crc_table, &file[position + 4..position + 8 + len]);
+            BigEndian::write_u32(&mut file[position + 8 + len..position + 12 + len], crc);
+            position += 12 + len;
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_fixtures() {
+        let mut random = Random(0x1234_5678);
+        for _ in 0..20_000 {
+            let fixture = FIXTURES[random.next() as usize % FIXTURES.len()];
+            let mut file = fixture.to_vec();
+            for _ in 0..1 + random.next() % 4 {
+                // keep the signature intact
+                let index = SIGNATURE.len() + random.next() as usize % (file.len() - 8);
+                file[index] = random.next() as u8;
+            }
+            fix_crcs(&mut file);
+            let mut pixels = 0;
+            if let Ok(info) = decode(&file, |x, y, _| {
+                pixels += 1;
+                assert!(x < MAX_WIDTH && y < 0xFFFF);
+            }) {
+                assert_eq!(pixels, usize::from(info.width) * usize::from(info.height));
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_random_chunks() {
+        let mut random = Random(0x9E37_79B9);
+        for _ in 0..20_000 {
+            let mut file = header_only(1 + random.next() % 40, 1 + random.next() % 40);
+            file[8 + 8 + 8] = [1, 2, 4, 8, 16][random.next() as usize % 5];
+            file[8 + 8 + 9] = [0, 2, 3, 4, 6][random.next() as usize % 5];
+            let len = random.next() as usize % 200;
+            file.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8,
+                                     len as u8]);
+            file.extend_from_slice(if random.next() % 4 == 0 { b"PLTE" } else { b"IDAT" });
+            file.push(0x78);
+            file.push(0x9C);
+            for _ in 2..len + 4 {
+                file.push(random.next() as u8);
+            }
+            file.extend_from_slice(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0, 0, 0, 0]);
+            fix_crcs(&mut file);
+            let _ = decode(&file, |_, _, _| {});
+        }
+    }
+}
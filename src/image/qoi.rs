@@ -0,0 +1,285 @@
+//! The "Quite OK Image" format, a simple lossless compression.
+
+use byteorder::{BigEndian, ByteOrder};
+use lcd::Color;
+use super::{Error, Format, ImageInfo};
+
+const HEADER_LEN: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+// the other operations are identified by the upper two bits
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+
+pub fn info(data: &[u8]) -> Result<ImageInfo, Error> {
+    if !data.starts_with(b"qoif") {
+        return Err(Error::UnknownFormat);
+    }
+    if data.len() < HEADER_LEN {
+        return Err(Error::Truncated);
+    }
+    let width = BigEndian::read_u32(&data[4..8]);
+    let height = BigEndian::read_u32(&data[8..12]);
+    let (channels, colorspace) = (data[12], data[13]);
+    if width == 0 || height == 0 || (channels != 3 && channels != 4) || colorspace > 1 {
+        return Err(Error::InvalidHeader);
+    }
+    if width > u32::from(u16::max_value()) || height > u32::from(u16::max_value()) {
+        return Err(Error::Unsupported);
+    }
+    Ok(ImageInfo {
+           format: Format::Qoi,
+           width: width as u16,
+           height: height as u16,
+       })
+}
+
+pub fn decode<F>(data: &[u8], mut pixel: F) -> Result<ImageInfo, Error>
+    where F: FnMut(u16, u16, Color)
+{
+    let info = info(data)?;
+    let mut index = [[0u8; 4]; 64];
+    // red, green, blue, alpha
+    let mut current = [0, 0, 0, 255];
+    let mut run = 0;
+    let mut position = HEADER_LEN;
+
+    for y in 0..info.height {
+        for x in 0..info.width {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let op = match data.get(position) {
+                    Some(&op) => op,
+                    None => return Err(Error::Truncated),
+                };
+                position += 1;
+                let len = match op {
+                    OP_RGB => 3,
+                    OP_RGBA => 4,
+                    op if op & 0xC0 == OP_LUMA => 1,
+                    _ => 0,
+                };
+                let args = match data.get(position..position + len) {
+                    Some(args) => args,
+                    None => return Err(Error::Truncated),
+                };
+                position += len;
+
+                match op {
+                    OP_RGB => current[..3].copy_from_slice(args),
+                    OP_RGBA => current.copy_from_slice(args),
+                    op if op & 0xC0 == OP_INDEX => current = index[usize::from(op & 0x3F)],
+                    op if op & 0xC0 == OP_DIFF => {
+                        current[0] = current[0].wrapping_add((op >> 4) & 0x03).wrapping_sub(2);
+                        current[1] = current[1].wrapping_add((op >> 2) & 0x03).wrapping_sub(2);
+                        current[2] = current[2].wrapping_add(op & 0x03).wrapping_sub(2);
+                    }
+                    op if op & 0xC0 == OP_LUMA => {
+                        let green_diff = (op & 0x3F).wrapping_sub(32);
+                        let red_diff = green_diff.wrapping_add(args[0] >> 4).wrapping_sub(8);
+                        let blue_diff = green_diff.wrapping_add(args[0] & 0x0F).wrapping_sub(8);
+                        current[0] = current[0].wrapping_add(red_diff);
+                        current[1] = current[1].wrapping_add(green_diff);
+                        current[2] = current[2].wrapping_add(blue_diff);
+                    }
+                    op => {
+                        // the current pixel is the first of the run
+                        debug_assert_eq!(op & 0xC0, OP_RUN);
+                        run = op & 0x3F;
+                    }
+                }
+                index[hash(&current)] = current;
+            }
+            pixel(x, y, Color::rgba(current[0], current[1], current[2], current[3]));
+        }
+    }
+
+    match data.get(position..position + END_MARKER.len()) {
+        Some(marker) if marker == &END_MARKER[..] => Ok(info),
+        Some(_) => Err(Error::InvalidData),
+        None => Err(Error::Truncated),
+    }
+}
+
+fn hash(color: &[u8; 4]) -> usize {
+    let (red, green, blue, alpha) = (usize::from(color[0]),
+                                     usize::from(color[1]),
+                                     usize::from(color[2]),
+                                     usize::from(color[3]));
+    (red * 3 + green * 5 + blue * 7 + alpha * 11) % 64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    /// The pixels of the fixtures. Each row exercises other operations: a run, small
+    /// differences, luma differences, colors from the index, full RGB values and, in the RGBA
+    /// fixture, alpha changes.
+    fn pixel(x: u16, y: u16, alpha: bool) -> Color {
+        let x = x as u8;
+        match y {
+            0 => Color::rgb(100, 100, 100),
+            1 => Color::rgb(100 + x, 100 + x, 100 + x),
+            2 => Color::rgb(20 + 10 * x, 20 + 9 * x, 20 + 5 * x),
+            3 if x % 2 == 0 => Color::rgb(100, 100, 100),
+            3 => Color::rgb(20, 20, 20),
+            4 => {
+                Color::rgb(x.wrapping_mul(37).wrapping_add(28),
+                           200u8.wrapping_sub(x.wrapping_mul(53)),
+                           x.wrapping_mul(x).wrapping_mul(13))
+            }
+            _ => {
+                let a = if alpha { 255 - 30 * x } else { 255 };
+                Color::rgba(x.wrapping_mul(37), 50, 60, a)
+            }
+        }
+    }
+
+    fn check_fixture(data: &[u8], alpha: bool) {
+        let mut pixels = Vec::new();
+        let info = decode(data, |x, y, color| pixels.push((x, y, color))).unwrap();
+        assert_eq!(info,
+                   ImageInfo {
+                       format: Format::Qoi,
+                       width: 8,
+                       height: 6,
+                   });
+        assert_eq!(info, super::info(data).unwrap());
+        assert_eq!(pixels.len(), 8 * 6);
+        for (i, &(x, y, color)) in pixels.iter().enumerate() {
+            assert_eq!((x, y), ((i % 8) as u16, (i / 8) as u16));
+            assert_eq!(color, pixel(x, y, alpha), "pixel ({}, {})", x, y);
+        }
+    }
+
+    /// Whether the image data (between the header and the end marker) contains an operation.
+    fn has_op(data: &[u8], op: u8) -> bool {
+        let mut position = HEADER_LEN;
+        while position < data.len() - END_MARKER.len() {
+            let byte = data[position];
+            let (current, len) = match byte {
+                OP_RGB => (OP_RGB, 4),
+                OP_RGBA => (OP_RGBA, 5),
+                byte if byte & 0xC0 == OP_LUMA => (OP_LUMA, 2),
+                byte => (byte & 0xC0, 1),
+            };
+            if current == op {
+                return true;
+            }
+            position += len;
+        }
+        false
+    }
+
+    const RGB: &'static [u8] = include_bytes!("testdata/rgb.qoi");
+    const RGBA: &'static [u8] = include_bytes!("testdata/rgba.qoi");
+
+    #[test]
+    fn rgb() {
+        check_fixture(RGB, false);
+    }
+
+    #[test]
+    fn rgba() {
+        check_fixture(RGBA, true);
+    }
+
+    #[test]
+    fn fixtures_use_every_op() {
+        for &op in &[OP_INDEX, OP_DIFF, OP_LUMA, OP_RUN, OP_RGB] {
+            assert!(has_op(RGB, op) && has_op(RGBA, op), "op {:#x}", op);
+        }
+        assert!(!has_op(RGB, OP_RGBA));
+        assert!(has_op(RGBA, OP_RGBA));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut file = RGB.to_vec();
+        file[12] = 2;
+        assert_eq!(info(&file), Err(Error::InvalidHeader));
+        file[12] = 3;
+        file[13] = 2;
+        assert_eq!(info(&file), Err(Error::InvalidHeader));
+        file[13] = 0;
+        file[4..8].copy_from_slice(&[0, 1, 0, 0]);
+        assert_eq!(info(&file), Err(Error::Unsupported));
+        let last = file.len() - 1;
+        file[last] = 2;
+        file[4..8].copy_from_slice(&[0, 0, 0, 8]);
+        assert_eq!(decode(&file, |_, _, _| {}), Err(Error::InvalidData));
+    }
+
+    const FIXTURES: [&'static [u8]; 2] = [RGB, RGBA];
+
+    #[test]
+    fn truncated_fixtures() {
+        for fixture in FIXTURES.iter() {
+            for len in 0..fixture.len() {
+                assert!(decode(&fixture[..len], |_, _, _| {}).is_err());
+            }
+        }
+    }
+
+    /// Xorshift, for reproducible fuzzing.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_fixtures() {
+        let mut random = Random(0x1234_5678);
+        for _ in 0..20_000 {
+            let fixture = FIXTURES[random.next() as usize % FIXTURES.len()];
+            let mut file = fixture.to_vec();
+            for _ in 0..1 + random.next() % 4 {
+                // keep the magic intact
+                let index = 4 + random.next() as usize % (file.len() - 4);
+                file[index] = random.next() as u8;
+            }
+            // at most 255 × 255 pixels, to keep the test fast
+            file[4..7].copy_from_slice(&[0, 0, 0]);
+            file[8..11].copy_from_slice(&[0, 0, 0]);
+            let mut pixels = 0;
+            if let Ok(info) = decode(&file, |x, y, _| {
+                pixels += 1;
+                assert!(x < 256 && y < 256);
+            }) {
+                assert_eq!(pixels, usize::from(info.width) * usize::from(info.height));
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_random_ops() {
+        let mut random = Random(0x9E37_79B9);
+        for _ in 0..20_000 {
+            let (width, height) = (1 + random.next() % 20, 1 + random.next() % 20);
+            let mut file = b"qoif".to_vec();
+            file.extend_from_slice(&[0, 0, 0, width as u8, 0, 0, 0, height as u8]);
+            file.push(3 + (random.next() % 2) as u8);
+            file.push(0);
+            for _ in 0..random.next() % 400 {
+                file.push(random.next() as u8);
+            }
+            if random.next() % 2 == 0 {
+                file.extend_from_slice(&END_MARKER);
+            }
+            let _ = decode(&file, |x, y, _| assert!(u32::from(x) < width && u32::from(y) < height));
+        }
+    }
+}
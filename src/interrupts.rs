@@ -1,4 +1,123 @@
 //! Interrupts
 
+use cortex_m::peripheral;
+
+pub const EXTI15_10: u8 = 40;
+pub const TIM7: u8 = 55;
+pub const DMA2_STREAM4: u8 = 60;
+pub const DMA2_STREAM7: u8 = 70;
+pub const LTDC: u8 = 88;
+pub const LTDC_ER: u8 = 89;
+
 #[no_mangle]
-pub static INTERRUPTS: [Option<unsafe extern "C" fn()>; 97] = [None; 97];
+pub static INTERRUPTS: [Option<unsafe extern "C" fn()>; 97] = [
+    None, // 0: WWDG
+    None, // 1: PVD
+    None, // 2: TAMP_STAMP
+    None, // 3: RTC_WKUP
+    None, // 4: FLASH
+    None, // 5: RCC
+    None, // 6: EXTI0
+    None, // 7: EXTI1
+    None, // 8: EXTI2
+    None, // 9: EXTI3
+    None, // 10: EXTI4
+    None, // 11: DMA1_Stream0
+    None, // 12: DMA1_Stream1
+    None, // 13: DMA1_Stream2
+    None, // 14: DMA1_Stream3
+    None, // 15: DMA1_Stream4
+    None, // 16: DMA1_Stream5
+    None, // 17: DMA1_Stream6
+    None, // 18: ADC
+    None, // 19: CAN1_TX
+    None, // 20: CAN1_RX0
+    None, // 21: CAN1_RX1
+    None, // 22: CAN1_SCE
+    None, // 23: EXTI9_5
+    None, // 24: TIM1_BRK_TIM9
+    None, // 25: TIM1_UP_TIM10
+    None, // 26: TIM1_TRG_COM_TIM11
+    None, // 27: TIM1_CC
+    None, // 28: TIM2
+    None, // 29: TIM3
+    None, // 30: TIM4
+    None, // 31: I2C1_EV
+    None, // 32: I2C1_ER
+    None, // 33: I2C2_EV
+    None, // 34: I2C2_ER
+    None, // 35: SPI1
+    None, // 36: SPI2
+    None, // 37: USART1
+    None, // 38: USART2
+    None, // 39: USART3
+    Some(::touch::ft5336::exti15_10), // 40: EXTI15_10
+    None, // 41: RTC_Alarm
+    None, // 42: OTG_FS_WKUP
+    None, // 43: TIM8_BRK_TIM12
+    None, // 44: TIM8_UP_TIM13
+    None, // 45: TIM8_TRG_COM_TIM14
+    None, // 46: TIM8_CC
+    None, // 47: DMA1_Stream7
+    None, // 48: FMC
+    None, // 49: SDMMC1
+    None, // 50: TIM5
+    None, // 51: SPI3
+    None, // 52: UART4
+    None, // 53: UART5
+    None, // 54: TIM6_DAC
+    Some(::lcd::backlight::tim7), // 55: TIM7
+    None, // 56: DMA2_Stream0
+    None, // 57: DMA2_Stream1
+    None, // 58: DMA2_Stream2
+    None, // 59: DMA2_Stream3
+    Some(::audio::output::dma2_stream4), // 60: DMA2_Stream4
+    None, // 61: ETH
+    None, // 62: ETH_WKUP
+    None, // 63: CAN2_TX
+    None, // 64: CAN2_RX0
+    None, // 65: CAN2_RX1
+    None, // 66: CAN2_SCE
+    None, // 67: OTG_FS
+    None, // 68: DMA2_Stream5
+    None, // 69: DMA2_Stream6
+    Some(::audio::input::dma2_stream7), // 70: DMA2_Stream7
+    None, // 71: USART6
+    None, // 72: I2C3_EV
+    None, // 73: I2C3_ER
+    None, // 74: OTG_HS_EP1_OUT
+    None, // 75: OTG_HS_EP1_IN
+    None, // 76: OTG_HS_WKUP
+    None, // 77: OTG_HS
+    None, // 78: DCMI
+    None, // 79: CRYP
+    None, // 80: HASH_RNG
+    None, // 81: FPU
+    None, // 82: UART7
+    None, // 83: UART8
+    None, // 84: SPI4
+    None, // 85: SPI5
+    None, // 86: SPI6
+    None, // 87: SAI1
+    Some(::lcd::interrupt::ltdc), // 88: LTDC
+    Some(::lcd::interrupt::ltdc_er), // 89: LTDC_ER
+    None, // 90: DMA2D
+    None, // 91: SAI2
+    None, // 92: QUADSPI
+    None, // 93: LPTIM1
+    None, // 94: CEC
+    None, // 95: I2C4_EV
+    None, // 96: I2C4_ER
+];
+
+/// Enables the given interrupt in the NVIC.
+pub fn enable(irq: u8) {
+    let nvic = unsafe { peripheral::nvic_mut() };
+    nvic.iser[usize::from(irq / 32)].write(1 << (irq % 32));
+}
+
+/// Disables the given interrupt in the NVIC.
+pub fn disable(irq: u8) {
+    let nvic = unsafe { peripheral::nvic_mut() };
+    nvic.icer[usize::from(irq / 32)].write(1 << (irq % 32));
+}
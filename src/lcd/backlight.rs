@@ -0,0 +1,228 @@
+//! Backlight brightness and display power management.
+//!
+//! The backlight control pin PK3 is not connected to a timer channel, so the PWM is generated
+//! in software: after `Backlight::enable_pwm`, each update interrupt of TIM7 ends the current
+//! high or low phase of the pin and loads the length of the next phase into the auto-reload
+//! register. Without PWM, the backlight is only switched on or off.
+//!
+//! `IdleDimmer` dims the backlight and turns the display off after periods without touch
+//! activity. `IdleDimmer::update` only depends on the activity and a timestamp, so the policy
+//! also runs on the host.
+
+use board::rcc::Rcc;
+use board::tim7::{self, Tim7};
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use embedded::interfaces::gpio::OutputPin;
+use interrupts;
+use system_clock;
+use super::Lcd;
+
+/// The brightness level of a fully lit backlight.
+pub const MAX_BRIGHTNESS: u8 = 255;
+
+/// Length of a PWM period in timer ticks of 1 µs, so the PWM runs at 1 kHz.
+const PWM_PERIOD: usize = 1000;
+/// Minimum length of a phase. Shorter phases could end before the interrupt handler loads
+/// them into the auto-reload register.
+const MIN_PHASE: usize = 20;
+
+// length of the high phase in timer ticks, read by the interrupt handler
+static HIGH_TIME: AtomicUsize = AtomicUsize::new(PWM_PERIOD);
+
+/// The pin and timer that the interrupt handler uses after `Backlight::enable_pwm`.
+struct Pwm {
+    timer: &'static mut Tim7,
+    pin: OutputPin,
+}
+
+static mut PWM: Option<Pwm> = None;
+
+/// The backlight enable pin, either switched directly or by the TIM7 interrupt.
+pub struct Backlight {
+    // `None` after the pin was moved to the interrupt handler
+    pin: Option<OutputPin>,
+}
+
+impl Backlight {
+    pub fn new(pin: OutputPin) -> Backlight {
+        Backlight { pin: Some(pin) }
+    }
+
+    /// Sets the brightness, from 0 (off) to `MAX_BRIGHTNESS`. Without PWM, all levels except 0
+    /// switch the backlight fully on.
+    pub fn set_level(&mut self, level: u8) {
+        match self.pin {
+            Some(ref mut pin) => pin.set(level > 0),
+            None => HIGH_TIME.store(high_time(level), Ordering::Release),
+        }
+    }
+
+    /// Lets TIM7 dim the backlight with a 1 kHz PWM, starting with the given level.
+    pub fn enable_pwm(&mut self, tim_7: &'static mut Tim7, rcc: &mut Rcc, level: u8) {
+        let pin = match self.pin.take() {
+            Some(pin) => pin,
+            // already enabled
+            None => return,
+        };
+        HIGH_TIME.store(high_time(level), Ordering::Release);
+
+        // enable the timer clock
+        rcc.apb1enr.update(|r| r.set_tim7en(true));
+
+        tim_7.cr1.update(|r| r.set_cen(false)); // counter_enable
+        // the timers of apb1 run at twice the apb1 frequency of 54 MHz
+        let ticks_per_us = system_clock::get_frequency() / 2 / 1_000_000;
+        tim_7.psc.update(|r| r.set_psc(ticks_per_us as u16 - 1)); // prescaler
+        tim_7.arr.update(|r| r.set_arr(PWM_PERIOD as u16)); // auto_reload
+        tim_7.cr1
+            .update(|r| {
+                        r.set_arpe(false); // a new auto-reload value applies immediately
+                        r.set_urs(true); // only overflows generate interrupts
+                    });
+        // load the prescaler
+        let mut egr = tim7::Egr::default();
+        egr.set_ug(true); // update_generation
+        tim_7.egr.write(egr);
+        tim_7.sr.update(|r| r.set_uif(false)); // update_interrupt_flag
+        tim_7.dier.update(|r| r.set_uie(true)); // update_interrupt_enable
+        tim_7.cr1.update(|r| r.set_cen(true));
+
+        // the handler starts to run when the interrupt is enabled
+        unsafe {
+            PWM = Some(Pwm {
+                           timer: tim_7,
+                           pin: pin,
+                       })
+        };
+        interrupts::enable(interrupts::TIM7);
+    }
+}
+
+/// The length of the high phase of a PWM period for a brightness level.
+fn high_time(level: u8) -> usize {
+    match level {
+        0 => 0,
+        MAX_BRIGHTNESS => PWM_PERIOD,
+        level => {
+            let time = PWM_PERIOD * usize::from(level) / usize::from(MAX_BRIGHTNESS);
+            cmp::max(cmp::min(time, PWM_PERIOD - MIN_PHASE), MIN_PHASE)
+        }
+    }
+}
+
+/// Interrupt handler for TIM7.
+pub unsafe extern "C" fn tim7() {
+    if let Some(pwm) = PWM.as_mut() {
+        pwm.timer.sr.update(|r| r.set_uif(false));
+
+        let high_time = HIGH_TIME.load(Ordering::Acquire);
+        // the phase that starts now and its length
+        let (high, time) = if high_time == 0 {
+            (false, PWM_PERIOD)
+        } else if high_time >= PWM_PERIOD {
+            (true, PWM_PERIOD)
+        } else if pwm.pin.get() {
+            (false, PWM_PERIOD - high_time)
+        } else {
+            (true, high_time)
+        };
+        pwm.pin.set(high);
+        pwm.timer.arr.update(|r| r.set_arr(time as u16));
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IdleConfig {
+    /// Backlight level while the display is used.
+    pub active_level: u8,
+    /// Backlight level after `dim_timeout`.
+    pub dim_level: u8,
+    /// Time without activity until the backlight is dimmed in milliseconds.
+    pub dim_timeout: usize,
+    /// Time without activity until the display is turned off in milliseconds, or `None` to
+    /// keep it dimmed.
+    pub off_timeout: Option<usize>,
+}
+
+impl Default for IdleConfig {
+    fn default() -> IdleConfig {
+        IdleConfig {
+            active_level: MAX_BRIGHTNESS,
+            dim_level: 40,
+            dim_timeout: 30_000,
+            off_timeout: Some(120_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    Active,
+    Dimmed,
+    Off,
+}
+
+/// Dims the backlight and turns the display off when it wasn't touched for a while.
+pub struct IdleDimmer {
+    config: IdleConfig,
+    state: PowerState,
+    last_activity: usize,
+}
+
+impl IdleDimmer {
+    pub fn new(config: IdleConfig, now: usize) -> IdleDimmer {
+        IdleDimmer {
+            config: config,
+            state: PowerState::Active,
+            last_activity: now,
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Updates the state for the time `now` in milliseconds. `active` tells whether the
+    /// display was touched since the last call, which restores the active state.
+    ///
+    /// Returns the new state if it changed.
+    pub fn update(&mut self, active: bool, now: usize) -> Option<PowerState> {
+        if active {
+            self.last_activity = now;
+        }
+        let idle = now.wrapping_sub(self.last_activity);
+        let state = match self.config.off_timeout {
+            Some(timeout) if idle >= timeout => PowerState::Off,
+            _ if idle >= self.config.dim_timeout => PowerState::Dimmed,
+            _ => PowerState::Active,
+        };
+        if state == self.state {
+            None
+        } else {
+            self.state = state;
+            Some(state)
+        }
+    }
+
+    /// Updates the state with the system clock and applies changes to the display.
+    ///
+    /// The touch that wakes the display up should usually be ignored by the application, so
+    /// check whether this returns `Some(PowerState::Active)`.
+    pub fn poll(&mut self, lcd: &mut Lcd, rcc: &mut Rcc, active: bool) -> Option<PowerState> {
+        let change = self.update(active, system_clock::ticks());
+        match change {
+            Some(PowerState::Active) => {
+                lcd.display_on(rcc);
+                lcd.set_backlight(self.config.active_level);
+            }
+            Some(PowerState::Dimmed) => {
+                lcd.display_on(rcc);
+                lcd.set_backlight(self.config.dim_level);
+            }
+            Some(PowerState::Off) => lcd.display_off(rcc),
+            None => {}
+        }
+        change
+    }
+}
@@ -0,0 +1,194 @@
+//! Color lookup tables (CLUTs) for the L8 pixel format of the layers.
+//!
+//! In the L8 format, each pixel is an index into a lookup table of 256 opaque colors, so a
+//! layer needs half the memory bandwidth of the ARGB1555 format. Changing the table recolors
+//! the whole layer without redrawing it, e.g. for color cycling with `rotate`.
+//!
+//! `quantize` and `to_indices` convert images to the indexed form. They work on plain slices,
+//! so they also run on the host.
+
+use collections::Vec;
+use core::cmp;
+use super::Color;
+
+/// Number of entries of a lookup table.
+pub const CLUT_LEN: usize = 256;
+
+/// Bits per channel of the histogram that `quantize` works on.
+const HISTOGRAM_BITS: u32 = 4;
+const HISTOGRAM_LEN: usize = 1 << (3 * HISTOGRAM_BITS);
+
+/// The pixel format of a layer framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerFormat {
+    /// 16 bits per pixel with one bit alpha, the format of `lcd::init`.
+    Argb1555,
+    /// 8 bit indices into the lookup table of the layer.
+    L8,
+}
+
+impl LayerFormat {
+    /// Decodes the `pf` field of the `LxPFCR` register. Other formats are reported as
+    /// `Argb1555`.
+    pub fn from_bits(bits: u8) -> LayerFormat {
+        match bits {
+            0b101 => LayerFormat::L8,
+            _ => LayerFormat::Argb1555,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        match *self {
+            LayerFormat::Argb1555 => 0b011,
+            LayerFormat::L8 => 0b101,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            LayerFormat::Argb1555 => 2,
+            LayerFormat::L8 => 1,
+        }
+    }
+}
+
+/// The pixels of a histogram cell, which contains the colors with the same upper bits.
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    count: u32,
+    sums: [u32; 3],
+}
+
+impl Cell {
+    fn average(&self, channel: usize) -> u32 {
+        self.sums[channel] / self.count
+    }
+}
+
+/// Chooses up to `max_colors` colors (at most `CLUT_LEN`) that represent the image well, with
+/// the median cut algorithm. The colors are opaque, the alpha of the pixels is ignored.
+///
+/// The colors are sorted into a histogram of 4096 cells first, so at most 4096 colors are
+/// distinguished. Images with fewer colors than `max_colors` get a shorter table.
+pub fn quantize(pixels: &[Color], max_colors: usize) -> Vec<Color> {
+    let max_colors = cmp::min(max_colors, CLUT_LEN);
+    let mut histogram = vec![Cell { count: 0, sums: [0; 3] }; HISTOGRAM_LEN];
+    for pixel in pixels {
+        let cell = &mut histogram[histogram_index(pixel)];
+        cell.count += 1;
+        cell.sums[0] += u32::from(pixel.red);
+        cell.sums[1] += u32::from(pixel.green);
+        cell.sums[2] += u32::from(pixel.blue);
+    }
+    let mut cells: Vec<Cell> = histogram.into_iter().filter(|cell| cell.count > 0).collect();
+    if max_colors == 0 || cells.is_empty() {
+        return Vec::new();
+    }
+
+    // boxes of cells, split at the median of their widest channel
+    let mut boxes = vec![(0, cells.len())];
+    while boxes.len() < max_colors {
+        let widest = boxes.iter()
+            .enumerate()
+            .map(|(index, &(start, end))| (index, widest_channel(&cells[start..end])))
+            .max_by_key(|&(_, (_, range))| range);
+        let (index, channel) = match widest {
+            Some((index, (channel, range))) if range > 0 => (index, channel),
+            // all boxes contain a single color
+            _ => break,
+        };
+        let (start, end) = boxes[index];
+        let cells = &mut cells[start..end];
+        cells.sort_by_key(|cell| cell.average(channel));
+
+        let total = cells.iter().fold(0, |sum, cell| sum + cell.count);
+        let mut count = 0;
+        let mut split = 1;
+        for (i, cell) in cells.iter().enumerate() {
+            count += cell.count;
+            if 2 * count >= total {
+                split = i + 1;
+                break;
+            }
+        }
+        // both halves keep at least one cell
+        let split = cmp::max(cmp::min(split, cells.len() - 1), 1);
+        boxes[index] = (start, start + split);
+        boxes.push((start + split, end));
+    }
+
+    boxes.iter()
+        .map(|&(start, end)| {
+            let (count, sums) = cells[start..end]
+                .iter()
+                .fold((0, [0; 3]), |(count, sums), cell| {
+                    (count + cell.count,
+                     [sums[0] + cell.sums[0], sums[1] + cell.sums[1], sums[2] + cell.sums[2]])
+                });
+            let channel = |sum: u32| ((sum + count / 2) / count) as u8;
+            Color::rgb(channel(sums[0]), channel(sums[1]), channel(sums[2]))
+        })
+        .collect()
+}
+
+/// Replaces each pixel by the index of the closest color of `palette`, ignoring alpha.
+///
+/// Returns the number of converted pixels, which is limited by the length of `indices`.
+pub fn to_indices(pixels: &[Color], palette: &[Color], indices: &mut [u8]) -> usize {
+    // images often contain runs of the same color, which are only searched once
+    let mut last: Option<(Color, u8)> = None;
+    for (pixel, index) in pixels.iter().zip(indices.iter_mut()) {
+        let pixel = Color { alpha: 255, ..*pixel };
+        *index = match last {
+            Some((color, index)) if color == pixel => index,
+            _ => {
+                let index = pixel.to_l8(palette);
+                last = Some((pixel, index));
+                index
+            }
+        };
+    }
+    cmp::min(pixels.len(), indices.len())
+}
+
+/// Fills the entries with a gradient from `from` to `to`, including both colors.
+pub fn gradient(entries: &mut [Color], from: Color, to: Color) {
+    let steps = cmp::max(entries.len(), 2) - 1;
+    for (i, entry) in entries.iter_mut().enumerate() {
+        *entry = from.lerp(to, i as f32 / steps as f32);
+    }
+}
+
+/// Moves each entry `steps` places towards the end, the last entries move to the start.
+///
+/// Rotating a part of the lookup table and uploading it again with `Lcd::set_clut` lets the
+/// colors of the pixels that use these entries cycle.
+pub fn rotate(entries: &mut [Color], steps: usize) {
+    if entries.is_empty() {
+        return;
+    }
+    let split = entries.len() - steps % entries.len();
+    entries[..split].reverse();
+    entries[split..].reverse();
+    entries.reverse();
+}
+
+fn histogram_index(color: &Color) -> usize {
+    let shift = 8 - HISTOGRAM_BITS;
+    usize::from(color.red >> shift) << (2 * HISTOGRAM_BITS) |
+    usize::from(color.green >> shift) << HISTOGRAM_BITS | usize::from(color.blue >> shift)
+}
+
+/// The channel with the largest range of average cell colors, and the range.
+fn widest_channel(cells: &[Cell]) -> (usize, u32) {
+    (0..3)
+        .map(|channel| {
+            let (low, high) = cells.iter().fold((255u32, 0), |(low, high), cell| {
+                let value = cell.average(channel);
+                (cmp::min(low, value), cmp::max(high, value))
+            });
+            (channel, high.saturating_sub(low))
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
@@ -1,4 +1,100 @@
-#[derive(Debug, Clone, Copy)]
+//! Colors with 8 bit channels and straight (not premultiplied) alpha, and their conversion to
+//! the pixel formats of the LTDC and the DMA2D.
+//!
+//! Conversions to formats with fewer bits truncate the channels. Conversions from them repeat
+//! the upper bits in the lower bits like the LTDC does, so converting back gives the original
+//! value. The luminance formats (L8, AL44, AL88) store an index into a color lookup table,
+//! which is passed as a slice of colors.
+
+use core::cmp;
+use math;
+
+pub const TRANSPARENT: Color = Color {
+    red: 0,
+    green: 0,
+    blue: 0,
+    alpha: 0,
+};
+pub const BLACK: Color = Color {
+    red: 0,
+    green: 0,
+    blue: 0,
+    alpha: 255,
+};
+pub const WHITE: Color = Color {
+    red: 255,
+    green: 255,
+    blue: 255,
+    alpha: 255,
+};
+pub const GRAY: Color = Color {
+    red: 128,
+    green: 128,
+    blue: 128,
+    alpha: 255,
+};
+pub const LIGHT_GRAY: Color = Color {
+    red: 192,
+    green: 192,
+    blue: 192,
+    alpha: 255,
+};
+pub const DARK_GRAY: Color = Color {
+    red: 64,
+    green: 64,
+    blue: 64,
+    alpha: 255,
+};
+pub const RED: Color = Color {
+    red: 255,
+    green: 0,
+    blue: 0,
+    alpha: 255,
+};
+pub const GREEN: Color = Color {
+    red: 0,
+    green: 255,
+    blue: 0,
+    alpha: 255,
+};
+pub const BLUE: Color = Color {
+    red: 0,
+    green: 0,
+    blue: 255,
+    alpha: 255,
+};
+pub const YELLOW: Color = Color {
+    red: 255,
+    green: 255,
+    blue: 0,
+    alpha: 255,
+};
+pub const CYAN: Color = Color {
+    red: 0,
+    green: 255,
+    blue: 255,
+    alpha: 255,
+};
+pub const MAGENTA: Color = Color {
+    red: 255,
+    green: 0,
+    blue: 255,
+    alpha: 255,
+};
+pub const ORANGE: Color = Color {
+    red: 255,
+    green: 165,
+    blue: 0,
+    alpha: 255,
+};
+pub const PURPLE: Color = Color {
+    red: 128,
+    green: 0,
+    blue: 128,
+    alpha: 255,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -24,8 +120,14 @@ impl Color {
         }
     }
 
+    /// An opaque gray with all channels set to `level`.
+    pub fn gray(level: u8) -> Color {
+        Self::rgb(level, level, level)
+    }
+
+    /// An opaque color from `0xRRGGBB`. The upper 8 bits are ignored, see `from_argb8888` for
+    /// colors with alpha.
     pub fn from_hex(color: u32) -> Color {
-        assert_eq!(color >> (8 * 3), 0);
         Color {
             red: (color >> 16) as u8,
             green: (color >> 8) as u8,
@@ -42,6 +144,15 @@ impl Color {
         Color::from_hex(color)
     }
 
+    /// The three bytes of a packed RGB888 pixel in memory order: blue, green, red.
+    pub fn to_rgb888_bytes(&self) -> [u8; 3] {
+        [self.blue, self.green, self.red]
+    }
+
+    pub fn from_rgb888_bytes(bytes: [u8; 3]) -> Color {
+        Color::rgb(bytes[2], bytes[1], bytes[0])
+    }
+
     pub fn to_argb8888(&self) -> u32 {
         (u32::from(self.alpha) << 24) | self.to_rgb888()
     }
@@ -55,19 +166,465 @@ impl Color {
         }
     }
 
+    pub fn to_rgb565(&self) -> u16 {
+        (u16::from(self.red) & 0xf8) << 8 | (u16::from(self.green) & 0xfc) << 3 |
+        u16::from(self.blue) >> 3
+    }
+
+    pub fn from_rgb565(color: u16) -> Color {
+        Color::rgb(expand_5(color >> 11), expand_6(color >> 5), expand_5(color))
+    }
+
     pub fn to_argb1555(&self) -> u16 {
-        (u16::from(self.alpha) & 0x80) << 8
-            | (u16::from(self.red) & 0xf8) << 7
-            | (u16::from(self.green) & 0xf8) << 2
-            | (u16::from(self.blue) & 0xf8) >> 3
+        (u16::from(self.alpha) & 0x80) << 8 | (u16::from(self.red) & 0xf8) << 7 |
+        (u16::from(self.green) & 0xf8) << 2 | u16::from(self.blue) >> 3
     }
 
+    /// The alpha bit becomes 0 or 255 and the channels repeat their upper bits, so white is
+    /// 255 again.
+    ///
+    /// Earlier versions returned an alpha of 0x80 and left the lower three bits of the
+    /// channels 0, e.g. 248 for white. Code that compares the result with such values has to
+    /// compare `to_argb1555` instead.
     pub fn from_argb1555(color: u16) -> Color {
-        Color {
-            alpha: ((color >> 8) & 0x80) as u8,
-            red: ((color >> 7) & 0xf8) as u8,
-            green: ((color >> 2) & 0xf8) as u8,
-            blue: ((color << 3) & 0xf8) as u8
+        Color::rgba(expand_5(color >> 10),
+                    expand_5(color >> 5),
+                    expand_5(color),
+                    if color & 0x8000 != 0 { 255 } else { 0 })
+    }
+
+    pub fn to_argb4444(&self) -> u16 {
+        (u16::from(self.alpha) & 0xf0) << 8 | (u16::from(self.red) & 0xf0) << 4 |
+        (u16::from(self.green) & 0xf0) | u16::from(self.blue) >> 4
+    }
+
+    pub fn from_argb4444(color: u16) -> Color {
+        Color::rgba(expand_4(color >> 8),
+                    expand_4(color >> 4),
+                    expand_4(color),
+                    expand_4(color >> 12))
+    }
+
+    /// The color of the lookup table entry `index`, black if the table is too short.
+    pub fn from_l8(index: u8, palette: &[Color]) -> Color {
+        palette.get(usize::from(index)).cloned().unwrap_or(BLACK)
+    }
+
+    /// The index of the closest color in the lookup table, including alpha.
+    pub fn to_l8(&self, palette: &[Color]) -> u8 {
+        self.closest(palette, true)
+    }
+
+    /// Four bits alpha and a four bit lookup table index. The alpha replaces the alpha of the
+    /// table entry.
+    pub fn from_al44(color: u8, palette: &[Color]) -> Color {
+        let entry = Color::from_l8(color & 0x0f, palette);
+        Color { alpha: expand_4(u16::from(color >> 4)), ..entry }
+    }
+
+    /// Only the first 16 entries of the lookup table are used.
+    pub fn to_al44(&self, palette: &[Color]) -> u8 {
+        let palette = &palette[..cmp::min(palette.len(), 16)];
+        self.alpha & 0xf0 | self.closest(palette, false)
+    }
+
+    /// Eight bits alpha and an eight bit lookup table index. The alpha replaces the alpha of
+    /// the table entry.
+    pub fn from_al88(color: u16, palette: &[Color]) -> Color {
+        let entry = Color::from_l8(color as u8, palette);
+        Color { alpha: (color >> 8) as u8, ..entry }
+    }
+
+    pub fn to_al88(&self, palette: &[Color]) -> u16 {
+        u16::from(self.alpha) << 8 | u16::from(self.closest(palette, false))
+    }
+
+    /// Perceived brightness with the ITU-R BT.601 weights.
+    pub fn luminance(&self) -> u8 {
+        ((77 * u32::from(self.red) + 150 * u32::from(self.green) + 29 * u32::from(self.blue) +
+          128) >> 8) as u8
+    }
+
+    /// Porter-Duff "source over": this color drawn on top of `below`.
+    pub fn over(&self, below: Color) -> Color {
+        let (alpha, below_alpha) = (u32::from(self.alpha), u32::from(below.alpha));
+        // the weight of `below`, scaled by 255 * 255
+        let below_weight = below_alpha * (255 - alpha);
+        let result_alpha = alpha * 255 + below_weight;
+        if result_alpha == 0 {
+            return TRANSPARENT;
+        }
+        let channel = |top: u8, bottom: u8| {
+            let sum = u32::from(top) * alpha * 255 + u32::from(bottom) * below_weight;
+            ((sum + result_alpha / 2) / result_alpha) as u8
+        };
+        Color::rgba(channel(self.red, below.red),
+                    channel(self.green, below.green),
+                    channel(self.blue, below.blue),
+                    ((result_alpha + 127) / 255) as u8)
+    }
+
+    /// Linear interpolation of all channels, `t = 0` is this color and `t = 1` is `other`.
+    /// `t` is clamped to this range.
+    pub fn lerp(&self, other: Color, t: f32) -> Color {
+        let t = clamp_unit(t);
+        let channel = |from: u8, to: u8| {
+            to_u8(f32::from(from) + (f32::from(to) - f32::from(from)) * t)
+        };
+        Color::rgba(channel(self.red, other.red),
+                    channel(self.green, other.green),
+                    channel(self.blue, other.blue),
+                    channel(self.alpha, other.alpha))
+    }
+
+    /// Like `lerp`, but the colors are mixed in linear light instead of in sRGB, which avoids
+    /// the dark band between saturated colors. The alpha is interpolated linearly.
+    pub fn mix_linear(&self, other: Color, t: f32) -> Color {
+        let t = clamp_unit(t);
+        let channel = |from: u8, to: u8| {
+            let (from, to) = (srgb_to_linear(from), srgb_to_linear(to));
+            linear_to_srgb(from + (to - from) * t)
+        };
+        Color::rgba(channel(self.red, other.red),
+                    channel(self.green, other.green),
+                    channel(self.blue, other.blue),
+                    self.lerp(other, t).alpha)
+    }
+
+    /// An opaque color from hue (in degrees), saturation and value (both 0 to 1).
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Color {
+        let (saturation, value) = (clamp_unit(saturation), clamp_unit(value));
+        let chroma = value * saturation;
+        from_hue_chroma(hue, chroma, value - chroma)
+    }
+
+    /// Hue in degrees (0 to 360), saturation and value (both 0 to 1). The alpha is ignored.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (hue, chroma, max, _) = self.hue_chroma();
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+        (hue, saturation, max)
+    }
+
+    /// An opaque color from hue (in degrees), saturation and lightness (both 0 to 1).
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Color {
+        let (saturation, lightness) = (clamp_unit(saturation), clamp_unit(lightness));
+        let chroma = (1.0 - math::abs(2.0 * lightness - 1.0)) * saturation;
+        from_hue_chroma(hue, chroma, lightness - chroma / 2.0)
+    }
+
+    /// Hue in degrees (0 to 360), saturation and lightness (both 0 to 1). The alpha is ignored.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (hue, chroma, max, min) = self.hue_chroma();
+        let lightness = (max + min) / 2.0;
+        let saturation = if chroma == 0.0 {
+            0.0
+        } else {
+            chroma / (1.0 - math::abs(2.0 * lightness - 1.0))
+        };
+        (hue, saturation, lightness)
+    }
+
+    /// Hue in degrees, chroma, maximum and minimum of the channels scaled to 0 to 1.
+    fn hue_chroma(&self) -> (f32, f32, f32, f32) {
+        let (red, green, blue) = (f32::from(self.red) / 255.0,
+                                  f32::from(self.green) / 255.0,
+                                  f32::from(self.blue) / 255.0);
+        let max = math::max(math::max(red, green), blue);
+        let min = math::min(math::min(red, green), blue);
+        let chroma = max - min;
+        let sector = if chroma == 0.0 {
+            0.0
+        } else if max == red {
+            (green - blue) / chroma
+        } else if max == green {
+            (blue - red) / chroma + 2.0
+        } else {
+            (red - green) / chroma + 4.0
+        };
+        let hue = sector * 60.0;
+        let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+        (hue, chroma, max, min)
+    }
+
+    /// Index of the closest palette entry by squared distance, 0 for an empty palette.
+    fn closest(&self, palette: &[Color], with_alpha: bool) -> u8 {
+        let distance = |color: &Color| {
+            let difference = |a: u8, b: u8| {
+                let d = i32::from(a) - i32::from(b);
+                (d * d) as u32
+            };
+            let alpha = if with_alpha {
+                difference(self.alpha, color.alpha)
+            } else {
+                0
+            };
+            difference(self.red, color.red) + difference(self.green, color.green) +
+            difference(self.blue, color.blue) + alpha
+        };
+        palette.iter()
+            .take(256)
+            .enumerate()
+            .min_by_key(|&(_, color)| distance(color))
+            .map(|(index, _)| index as u8)
+            .unwrap_or(0)
+    }
+}
+
+fn expand_4(value: u16) -> u8 {
+    (value & 0x0f) as u8 * 0x11
+}
+
+fn expand_5(value: u16) -> u8 {
+    let value = (value & 0x1f) as u8;
+    value << 3 | value >> 2
+}
+
+fn expand_6(value: u16) -> u8 {
+    let value = (value & 0x3f) as u8;
+    value << 2 | value >> 4
+}
+
+fn clamp_unit(value: f32) -> f32 {
+    if value < 0.0 {
+        0.0
+    } else if value > 1.0 {
+        1.0
+    } else {
+        value
+    }
+}
+
+/// Rounds a value in 0 to 255 to a channel.
+fn to_u8(value: f32) -> u8 {
+    math::round(math::min(math::max(value, 0.0), 255.0)) as u8
+}
+
+fn from_hue_chroma(hue: f32, chroma: f32, offset: f32) -> Color {
+    let hue = hue - 360.0 * math::floor(hue / 360.0);
+    // the reduction fails for infinities, NaN and huge values, and `as u32` is undefined for
+    // values out of range
+    let hue = if hue >= 0.0 && hue < 360.0 { hue } else { 0.0 };
+    let sector = hue / 60.0;
+    // the second largest channel
+    let x = chroma * (1.0 - math::abs(sector - 2.0 * math::floor(sector / 2.0) - 1.0));
+    let (red, green, blue) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    Color::rgb(to_u8((red + offset) * 255.0),
+               to_u8((green + offset) * 255.0),
+               to_u8((blue + offset) * 255.0))
+}
+
+/// The sRGB transfer function, returns 0 to 1.
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = f32::from(value) / 255.0;
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        math::powf((value + 0.055) / 1.055, 2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * math::powf(value, 1.0 / 2.4) - 0.055
+    };
+    to_u8(value * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    #[test]
+    fn argb1555_round_trip() {
+        for value in 0..0x1_0000 {
+            let value = value as u16;
+            assert_eq!(Color::from_argb1555(value).to_argb1555(), value);
+        }
+    }
+
+    #[test]
+    fn argb1555_values() {
+        assert_eq!(Color::from_argb1555(0xffff), WHITE);
+        assert_eq!(Color::from_argb1555(0x8000), BLACK);
+        assert_eq!(Color::from_argb1555(0x7fff), Color::rgba(255, 255, 255, 0));
+        // 0b10000 expands to 0b1000_0100
+        assert_eq!(Color::from_argb1555(0x8000 | 0x10 << 10 | 0x01 << 5 | 0x1f),
+                   Color::rgb(0x84, 0x08, 0xff));
+        // alpha 0x80 is the threshold of the alpha bit, the channels are truncated
+        assert_eq!(Color::rgba(0x87, 0x80, 0x7f, 0x80).to_argb1555(),
+                   0x8000 | 0x10 << 10 | 0x10 << 5 | 0x0f);
+        assert_eq!(Color::rgba(255, 255, 255, 0x7f).to_argb1555(), 0x7fff);
+    }
+
+    #[test]
+    fn argb1555_converts_once() {
+        // a converted color is close to the original and doesn't change anymore
+        for level in 0..256 {
+            let color = Color::rgba(level as u8, 0, 0, 255);
+            let converted = Color::from_argb1555(color.to_argb1555());
+            assert!((i16::from(converted.red) - level).abs() < 8);
+            assert_eq!(Color::from_argb1555(converted.to_argb1555()), converted);
+        }
+    }
+
+    #[test]
+    fn other_formats_round_trip() {
+        for value in 0..0x1_0000 {
+            let value = value as u16;
+            assert_eq!(Color::from_rgb565(value).to_rgb565(), value);
+            assert_eq!(Color::from_argb4444(value).to_argb4444(), value);
+        }
+        let color = Color::rgba(1, 2, 3, 4);
+        assert_eq!(Color::from_argb8888(color.to_argb8888()), color);
+        assert_eq!(Color::from_rgb888_bytes(color.to_rgb888_bytes()),
+                   Color { alpha: 255, ..color });
+    }
+
+    #[test]
+    fn hsv_hsl() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), RED);
+        assert_eq!(Color::from_hsv(120.0, 1.0, 1.0), GREEN);
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), BLUE);
+        assert_eq!(Color::from_hsv(600.0, 1.0, 1.0), BLUE);
+        assert_eq!(Color::from_hsl(60.0, 1.0, 0.5), YELLOW);
+        let (hue, saturation, value) = ORANGE.to_hsv();
+        assert_eq!(Color::from_hsv(hue, saturation, value), ORANGE);
+        let (hue, saturation, lightness) = PURPLE.to_hsl();
+        assert_eq!(Color::from_hsl(hue, saturation, lightness), PURPLE);
+    }
+
+    #[test]
+    fn non_finite_hue() {
+        use core::f32::{INFINITY, NAN, NEG_INFINITY};
+        for &hue in &[NAN, INFINITY, NEG_INFINITY, 1e30, -1e30] {
+            assert_eq!(Color::from_hsv(hue, 1.0, 1.0), RED);
+            assert_eq!(Color::from_hsl(hue, 0.0, 0.5), Color::gray(128));
+        }
+    }
+
+    #[test]
+    fn over_and_lerp() {
+        assert_eq!(RED.over(BLUE), RED);
+        assert_eq!(TRANSPARENT.over(BLUE), BLUE);
+        assert_eq!(Color::rgba(255, 0, 0, 128).over(BLACK), Color::rgb(128, 0, 0));
+        assert_eq!(BLACK.lerp(WHITE, 0.5), Color::gray(128));
+        assert_eq!(BLACK.lerp(WHITE, 2.0), WHITE);
+    }
+
+    /// A palette of `len` different colors, with different alphas for the L8 tests.
+    fn palette(len: usize) -> Vec<Color> {
+        (0..len)
+            .map(|i| {
+                     let i = i as u8;
+                     Color::rgba(i, 255 - i, i.wrapping_mul(7), i.wrapping_mul(13))
+                 })
+            .collect()
+    }
+
+    #[test]
+    fn l8_round_trip() {
+        let palette = palette(256);
+        for index in 0..256 {
+            let index = index as u8;
+            assert_eq!(Color::from_l8(index, &palette), palette[usize::from(index)]);
+            assert_eq!(Color::from_l8(index, &palette).to_l8(&palette), index);
+        }
+        // the alpha counts for L8
+        let opaque = Color { alpha: 255, ..palette[3] };
+        assert_eq!(opaque.to_l8(&[palette[3], opaque]), 1);
+    }
+
+    #[test]
+    fn l8_out_of_range() {
+        let palette = palette(4);
+        assert_eq!(Color::from_l8(4, &palette), BLACK);
+        assert_eq!(Color::from_l8(255, &palette), BLACK);
+        assert_eq!(Color::from_l8(0, &[]), BLACK);
+        assert_eq!(WHITE.to_l8(&[]), 0);
+        // colors that are not in the palette map to the closest entry
+        assert_eq!(Color::rgba(2, 250, 15, 25).to_l8(&palette), 2);
+    }
+
+    #[test]
+    fn al44_round_trip() {
+        let palette = palette(16);
+        for value in 0..256 {
+            let value = value as u8;
+            let color = Color::from_al44(value, &palette);
+            assert_eq!(color.alpha, (value >> 4) * 0x11);
+            assert_eq!(Color { alpha: 0, ..color },
+                       Color { alpha: 0, ..palette[usize::from(value & 0x0f)] });
+            assert_eq!(color.to_al44(&palette), value);
+        }
+    }
+
+    #[test]
+    fn al44_only_uses_16_entries() {
+        let mut palette = palette(20);
+        let beyond = palette[18];
+        // indices above 15 can't be encoded, so the closest of the first 16 entries is used
+        assert_eq!(beyond.to_al44(&palette) & 0x0f, 15);
+        palette[5] = beyond;
+        assert_eq!(beyond.to_al44(&palette), beyond.alpha & 0xf0 | 5);
+        // short palettes are padded with black
+        assert_eq!(Color::from_al44(0xf9, &palette[..4]), BLACK);
+        assert_eq!(Color::from_al44(0x79, &palette[..4]), Color { alpha: 0x77, ..BLACK });
+    }
+
+    #[test]
+    fn al88_round_trip() {
+        let palette = palette(256);
+        for value in 0..0x1_0000 {
+            let value = value as u16;
+            let color = Color::from_al88(value, &palette);
+            assert_eq!(color.alpha, (value >> 8) as u8);
+            assert_eq!(color.to_al88(&palette), value);
+        }
+        // the alpha of the palette is replaced, so it doesn't influence the index
+        assert_eq!(Color::rgba(5, 250, 35, 0).to_al88(&palette), 5);
+    }
+
+    #[test]
+    fn al88_out_of_range() {
+        let palette = palette(4);
+        assert_eq!(Color::from_al88(0x80_04, &palette), Color { alpha: 0x80, ..BLACK });
+        assert_eq!(Color::from_al88(0xff_ff, &palette), BLACK);
+        assert_eq!(Color::from_al88(0x40_00, &[]), Color { alpha: 0x40, ..BLACK });
+        assert_eq!(WHITE.to_al88(&[]), 0xff_00);
+    }
+
+    #[test]
+    fn mix_linear_midpoints() {
+        // linear 0.5 is 0.7354 in sRGB, i.e. 188 instead of the 128 of `lerp`
+        assert_eq!(BLACK.mix_linear(WHITE, 0.5), Color::gray(188));
+        assert_eq!(RED.mix_linear(GREEN, 0.5), Color::rgb(188, 188, 0));
+        assert_eq!(RED.lerp(GREEN, 0.5), Color::rgb(128, 128, 0));
+        // linear 0.25 is 0.5371 in sRGB
+        assert_eq!(BLACK.mix_linear(WHITE, 0.25), Color::gray(137));
+        // the sRGB 128 is 0.2159 in linear light, halfway to white is 0.6079 or sRGB 205
+        assert_eq!(Color::gray(128).mix_linear(WHITE, 0.5), Color::gray(205));
+        // the alpha is interpolated linearly
+        assert_eq!(TRANSPARENT.mix_linear(Color::rgba(0, 0, 0, 255), 0.5).alpha, 128);
+    }
+
+    #[test]
+    fn mix_linear_end_points() {
+        for level in 0..256 {
+            let color = Color::rgba(level as u8, 255 - level as u8, 7, level as u8);
+            assert_eq!(color.mix_linear(ORANGE, 0.0), color);
+            assert_eq!(color.mix_linear(ORANGE, 1.0), ORANGE);
+            assert_eq!(color.mix_linear(color, 0.5), color);
+            assert_eq!(color.mix_linear(ORANGE, -1.0), color);
         }
     }
 }
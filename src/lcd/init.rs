@@ -1,11 +1,33 @@
 use board::rcc::Rcc;
 use board::ltdc::Ltdc;
 use embedded::interfaces::gpio::{Gpio, OutputPin};
-use super::Lcd;
+use super::{color, interrupt, timing, Backlight, Layer, LayerFormat, Lcd, Rotation, CLUT_LEN,
+            MAX_BRIGHTNESS};
+use super::timing::{PanelTiming, TimingError};
 
+/// Frequency of the external oscillator, the input of the PLLs.
+const HSE_FREQUENCY: u32 = 25_000_000;
+
+/// Initializes the LTDC for the RK043FN48H panel of the STM32F746G discovery board.
 pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
+    init_panel(ltdc, rcc, gpio, &timing::RK043FN48H).expect("invalid RK043FN48H timing")
+}
+
+/// Initializes the LTDC for a panel with the given timing.
+///
+/// The pins are those of the STM32F746G discovery board.
+pub fn init_panel(ltdc: &'static mut Ltdc,
+                  rcc: &mut Rcc,
+                  gpio: &mut Gpio,
+                  panel: &PanelTiming)
+                  -> Result<Lcd, TimingError> {
+    let timing = panel.ltdc_timing()?;
+    // the PLLs share the input divider of the main PLL
+    let pll_input = HSE_FREQUENCY / u32::from(rcc.pllcfgr.read().pllm());
+    let pll_sai = panel.pll_sai_config(pll_input)?;
+
     // init gpio pins
-    let (mut display_enable, mut backlight_enable) = init_pins(gpio);
+    let (mut display_enable, backlight_enable) = init_pins(gpio);
 
     // enable LTDC and DMA2D clocks
     rcc.ahb1enr.update(|r| r.set_dma2den(true));
@@ -20,15 +42,12 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
 
     rcc.pllsaicfgr
         .update(|r| {
-                    r.set_pllsain(192);
-                    r.set_pllsair(5);
+                    r.set_pllsain(pll_sai.n);
+                    r.set_pllsair(pll_sai.r);
                 });
 
     // set division factor for LCD_CLK
-    rcc.dkcfgr1
-        .update(|r| {
-                    r.set_pllsaidivr(0b01 /* = 4 */)
-                });
+    rcc.dkcfgr1.update(|r| r.set_pllsaidivr(pll_sai.divr_bits));
 
     // enable PLLSAI clock
     rcc.cr.update(|r| r.set_pllsaion(true));
@@ -37,38 +56,38 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
     // configure the HS, VS, DE and PC polarity
     ltdc.gcr
         .update(|r| {
-                    r.set_pcpol(false);
-                    r.set_depol(false);
-                    r.set_hspol(false);
-                    r.set_vspol(false);
+                    r.set_pcpol(timing.pcpol);
+                    r.set_depol(timing.depol);
+                    r.set_hspol(timing.hspol);
+                    r.set_vspol(timing.vspol);
                 });
 
     // set synchronization size
     ltdc.sscr
         .update(|r| {
-                    r.set_hsw(41 - 1); // horizontal_sync_width
-                    r.set_vsh(10 - 1); // vertical_sync_height
+                    r.set_hsw(timing.hsw); // horizontal_sync_width
+                    r.set_vsh(timing.vsh); // vertical_sync_height
                 });
 
     // set accumulated back porch
     ltdc.bpcr
         .update(|r| {
-                    r.set_ahbp(41 + 13 - 1); // accumulated_horizontal_back_porch
-                    r.set_avbp(10 + 2 - 1); // accumulated_vertical_back_porch
+                    r.set_ahbp(timing.ahbp); // accumulated_horizontal_back_porch
+                    r.set_avbp(timing.avbp); // accumulated_vertical_back_porch
                 });
 
     // set accumulated active width
     ltdc.awcr
         .update(|r| {
-                    r.set_aaw(480 + 41 + 13 - 1); // accumulated_active_width
-                    r.set_aah(272 + 10 + 2 - 1); // accumulated_active_height
+                    r.set_aaw(timing.aaw); // accumulated_active_width
+                    r.set_aah(timing.aah); // accumulated_active_height
                 });
 
     // set total width
     ltdc.twcr
         .update(|r| {
-                    r.set_totalw(480 + 41 + 13 + 32 - 1); // total_width
-                    r.set_totalh(272 + 10 + 2 + 2 - 1); // total_height
+                    r.set_totalw(timing.totalw); // total_width
+                    r.set_totalh(timing.totalh); // total_height
                 });
 
     // set background color
@@ -76,11 +95,7 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
 
 
     // enable the transfer error interrupt and the FIFO underrun interrupt
-    ltdc.ier
-        .update(|r| {
-                    r.set_terrie(true); // TRANSFER_ERROR_INTERRUPT_ENABLE
-                    r.set_fuie(true); // FIFO_UNDERRUN_INTERRUPT_ENABLE
-                });
+    interrupt::init(ltdc);
 
     // enable LTDC
     ltdc.gcr.update(|r| r.set_ltdcen(true));
@@ -88,27 +103,29 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
     // configure layers
 
     // configure horizontal start and stop position
+    let (start, stop) = timing.horizontal_window();
     ltdc.l1whpcr
         .update(|r| {
-                    r.set_whstpos(0 + 41 + 13); // window_horizontal_start_position
-                    r.set_whsppos(480 + 41 + 13 - 1); // window_horizontal_stop_position
+                    r.set_whstpos(start); // window_horizontal_start_position
+                    r.set_whsppos(stop); // window_horizontal_stop_position
                 });
     ltdc.l2whpcr
         .update(|r| {
-                    r.set_whstpos(0 + 41 + 13); // window_horizontal_start_position
-                    r.set_whsppos(480 + 41 + 13 - 1); // window_horizontal_stop_position
+                    r.set_whstpos(start); // window_horizontal_start_position
+                    r.set_whsppos(stop); // window_horizontal_stop_position
                 });
 
     // configure vertical start and stop position
+    let (start, stop) = timing.vertical_window();
     ltdc.l1wvpcr
         .update(|r| {
-                    r.set_wvstpos(0 + 10 + 2); // window_vertical_start_position
-                    r.set_wvsppos(272 + 10 + 2 - 1); // window_vertical_stop_position
+                    r.set_wvstpos(start); // window_vertical_start_position
+                    r.set_wvsppos(stop); // window_vertical_stop_position
                 });
     ltdc.l2wvpcr
         .update(|r| {
-                    r.set_wvstpos(0 + 10 + 2); // window_vertical_start_position
-                    r.set_wvsppos(272 + 10 + 2 - 1); // window_vertical_stop_position
+                    r.set_wvstpos(start); // window_vertical_start_position
+                    r.set_wvsppos(stop); // window_vertical_stop_position
                 });
 
     // specify pixed format
@@ -146,26 +163,25 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
     });
 
     // configure color frame buffer start address
-    const SDRAM_START: u32 = 0xC000_0000;
-    ltdc.l1cfbar.update(|r| r.set_cfbadd(SDRAM_START));
-    ltdc.l2cfbar
-        .update(|r| r.set_cfbadd(SDRAM_START + 480 * 272 * 2));
+    ltdc.l1cfbar.update(|r| r.set_cfbadd(Layer::Layer1.start_address()));
+    ltdc.l2cfbar.update(|r| r.set_cfbadd(Layer::Layer2.start_address()));
 
     // configure color frame buffer line length and pitch
+    let line_length = panel.width * 2;
     ltdc.l1cfblr
         .update(|r| {
-                    r.set_cfbp(480 * 2); // pitch
-                    r.set_cfbll(480 * 2 + 3); // line_length
+                    r.set_cfbp(line_length); // pitch
+                    r.set_cfbll(line_length + 3); // line_length
                 });
     ltdc.l2cfblr
         .update(|r| {
-                    r.set_cfbp(480 * 2); // pitch
-                    r.set_cfbll(480 * 2 + 3); // line_length
+                    r.set_cfbp(line_length); // pitch
+                    r.set_cfbll(line_length + 3); // line_length
                 });
 
     // configure frame buffer line number
-    ltdc.l1cfblnr.update(|r| r.set_cfblnbr(272)); // line_number
-    ltdc.l2cfblnr.update(|r| r.set_cfblnbr(272)); // line_number
+    ltdc.l1cfblnr.update(|r| r.set_cfblnbr(panel.height)); // line_number
+    ltdc.l2cfblnr.update(|r| r.set_cfblnbr(panel.height)); // line_number
 
     // enable layers
     ltdc.l1cr.update(|r| r.set_len(true));
@@ -180,7 +196,8 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
 
     // enable display and backlight
     display_enable.set(true);
-    backlight_enable.set(true);
+    let mut backlight = Backlight::new(backlight_enable);
+    backlight.set_level(MAX_BRIGHTNESS);
 
     // TODO
     //
@@ -204,14 +221,20 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
     //
     //
 
-    Lcd {
-        controller: ltdc,
-        display_enable: display_enable,
-        backlight_enable: backlight_enable,
-        next_pixel: 0,
-        next_col: 0,
-        prev_value: (0, 0),
-    }
+    Ok(Lcd {
+           controller: ltdc,
+           panel: *panel,
+           display_enable: display_enable,
+           backlight: backlight,
+           backlight_level: MAX_BRIGHTNESS,
+           powered: true,
+           next_pixel: 0,
+           next_col: 0,
+           prev_value: (0, 0),
+           rotation: Rotation::Deg0,
+           formats: [LayerFormat::Argb1555; 2],
+           cluts: [[color::BLACK; CLUT_LEN]; 2],
+       })
 }
 
 pub fn init_pins(gpio: &mut Gpio) -> (OutputPin, OutputPin) {
@@ -0,0 +1,147 @@
+//! Interrupt handlers of the LTDC.
+//!
+//! The LTDC reports transfer errors and FIFO underruns, which happen when other SDRAM traffic
+//! starves the LTDC and make the panel glitch, and a line interrupt, which fires once per
+//! frame at a configurable line.
+//!
+//! The `Lcd` owns the LTDC, so the handlers don't touch its registers. They only mask their
+//! interrupt in the NVIC and latch that it is pending. `handle_pending`, which the `Lcd` calls
+//! with its register block, clears the flags, counts the errors, reloads the shadow registers
+//! and calls the line callback, then unmasks the interrupts again. A reload after an error
+//! therefore never interleaves with a layer configuration of the `Lcd`.
+
+use board::ltdc::{self, Ltdc};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use interrupts;
+
+static mut LINE_CALLBACK: Option<fn()> = None;
+
+// set by the handlers, cleared by `handle_pending`
+static ERROR_PENDING: AtomicBool = AtomicBool::new(false);
+static LINE_PENDING: AtomicBool = AtomicBool::new(false);
+
+static FIFO_UNDERRUNS: AtomicUsize = AtomicUsize::new(0);
+static TRANSFER_ERRORS: AtomicUsize = AtomicUsize::new(0);
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+static RELOAD_ON_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Numbers of LTDC errors since `lcd::init` or the last `Lcd::reset_error_counts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ErrorCounts {
+    /// The LTDC requested pixels faster than the SDRAM delivered them.
+    pub fifo_underruns: usize,
+    /// A bus error occurred while reading a framebuffer.
+    pub transfer_errors: usize,
+}
+
+/// Enables the error interrupt.
+pub fn init(ltdc: &mut Ltdc) {
+    ERROR_PENDING.store(false, Ordering::Relaxed);
+    FIFO_UNDERRUNS.store(0, Ordering::Relaxed);
+    TRANSFER_ERRORS.store(0, Ordering::Relaxed);
+
+    ltdc.icr.write(clear_errors());
+    ltdc.ier
+        .update(|r| {
+                    r.set_terrie(true); // transfer_error_interrupt_enable
+                    r.set_fuie(true); // fifo_underrun_interrupt_enable
+                });
+    interrupts::enable(interrupts::LTDC_ER);
+}
+
+pub fn error_counts() -> ErrorCounts {
+    ErrorCounts {
+        fifo_underruns: FIFO_UNDERRUNS.load(Ordering::Relaxed),
+        transfer_errors: TRANSFER_ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn reset_error_counts() {
+    FIFO_UNDERRUNS.store(0, Ordering::Relaxed);
+    TRANSFER_ERRORS.store(0, Ordering::Relaxed);
+}
+
+/// Whether `handle_pending` reloads the shadow registers in the next vertical blanking period
+/// after an error, which restarts the layers with a consistent configuration.
+pub fn set_reload_on_error(reload: bool) {
+    RELOAD_ON_ERROR.store(reload, Ordering::Relaxed);
+}
+
+/// Calls `callback` from `handle_pending` after the LTDC reached `position`, counted in lines
+/// including the synchronization and back porch lines. `None` disables the line interrupt.
+pub fn set_line_callback(ltdc: &mut Ltdc, position: u16, callback: Option<fn()>) {
+    interrupts::disable(interrupts::LTDC);
+    ltdc.ier.update(|r| r.set_lie(false)); // line_interrupt_enable
+    LINE_PENDING.store(false, Ordering::Relaxed);
+    unsafe { LINE_CALLBACK = callback };
+
+    if callback.is_some() {
+        ltdc.lipcr.update(|r| r.set_lipos(position)); // line_interrupt_position
+        ltdc.icr.write(clear_line());
+        ltdc.ier.update(|r| r.set_lie(true));
+        interrupts::enable(interrupts::LTDC);
+    }
+}
+
+/// Number of handled line interrupts since the line interrupt was enabled first. Frames
+/// without a `handle_pending` call are not counted.
+pub fn frame_count() -> usize {
+    FRAMES.load(Ordering::Relaxed)
+}
+
+fn clear_errors() -> ltdc::Icr {
+    let mut clear = ltdc::Icr::default();
+    clear.set_cfuif(true); // clear fifo underrun interrupt flag
+    clear.set_cterrif(true); // clear transfer error interrupt flag
+    clear
+}
+
+fn clear_line() -> ltdc::Icr {
+    let mut clear = ltdc::Icr::default();
+    clear.set_clif(true); // clear line interrupt flag
+    clear
+}
+
+/// Acts on the interrupts that the handlers latched since the last call and unmasks them.
+pub fn handle_pending(ltdc: &mut Ltdc) {
+    if ERROR_PENDING.swap(false, Ordering::Acquire) {
+        let isr = ltdc.isr.read();
+        ltdc.icr.write(clear_errors());
+
+        if isr.fuif() {
+            FIFO_UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+        }
+        if isr.terrif() {
+            TRANSFER_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        if RELOAD_ON_ERROR.load(Ordering::Relaxed) {
+            ltdc.srcr.update(|r| r.set_vbr(true)); // vertical_blanking_reload
+        }
+        interrupts::enable(interrupts::LTDC_ER);
+    }
+
+    if LINE_PENDING.swap(false, Ordering::Acquire) {
+        if ltdc.isr.read().lif() {
+            ltdc.icr.write(clear_line());
+            FRAMES.fetch_add(1, Ordering::Relaxed);
+            if let Some(callback) = unsafe { LINE_CALLBACK } {
+                callback();
+            }
+        }
+        interrupts::enable(interrupts::LTDC);
+    }
+}
+
+/// Interrupt handler for the LTDC line interrupt.
+///
+/// The flag stays set until `handle_pending` clears it, so the interrupt is masked meanwhile.
+pub unsafe extern "C" fn ltdc() {
+    interrupts::disable(interrupts::LTDC);
+    LINE_PENDING.store(true, Ordering::Release);
+}
+
+/// Interrupt handler for LTDC errors, masked like the line interrupt.
+pub unsafe extern "C" fn ltdc_er() {
+    interrupts::disable(interrupts::LTDC_ER);
+    ERROR_PENDING.store(true, Ordering::Release);
+}
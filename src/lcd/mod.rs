@@ -1,39 +1,450 @@
 #![allow(dead_code)]
 
+pub use self::backlight::{Backlight, IdleConfig, IdleDimmer, PowerState, MAX_BRIGHTNESS};
+pub use self::clut::{LayerFormat, CLUT_LEN};
 pub use self::color::Color;
-pub use self::init::init;
+pub use self::init::{init, init_panel};
+pub use self::interrupt::ErrorCounts;
+pub use self::timing::{PanelTiming, Polarity, TimingError, MAX_HEIGHT, MAX_WIDTH};
+pub use self::screenshot::{compose, BlendingFactor, LayerBlending, LayerPixels, LayerSource,
+                           Screenshot};
 
-use board::ltdc::Ltdc;
+use board::ltdc::{self, Ltdc};
+use board::rcc::Rcc;
+use board::tim7::Tim7;
 use embedded::interfaces::gpio::OutputPin;
-use core::ptr;
+use core::{cmp, ptr, slice};
+use sdram;
 
+pub mod backlight;
+pub mod clut;
+pub mod color;
 mod init;
-mod color;
+pub mod interrupt;
+mod screenshot;
+pub mod timing;
+
+/// Size of the RK043FN48H panel that `init` configures. Use `Lcd::panel_size` for the actual
+/// panel.
+pub const WIDTH: u16 = 480;
+pub const HEIGHT: u16 = 272;
+
+/// Each layer has room for an ARGB1555 framebuffer of the largest supported panel.
+const FRAMEBUFFER_LEN: u32 = MAX_WIDTH as u32 * MAX_HEIGHT as u32 * 2;
+/// Length of the framebuffers of both layers in the SDRAM.
+pub const FRAMEBUFFERS_LEN: u32 = 2 * FRAMEBUFFER_LEN;
+const LAYER_1_START: u32 = sdram::FRAMEBUFFERS_START;
+const LAYER_2_START: u32 = LAYER_1_START + FRAMEBUFFER_LEN;
+
+/// Buffer length that `Lcd::screenshot` needs for the RGB888 image of the largest panel.
+pub const SCREENSHOT_LEN: usize = MAX_WIDTH as usize * MAX_HEIGHT as usize *
+                                  screenshot::BYTES_PER_PIXEL;
+
+/// The SDRAM behind the framebuffers that is reserved for `Lcd::screenshot`, `SCREENSHOT_LEN`
+/// bytes.
+///
+/// This is unsafe because every call returns a mutable reference to the same memory.
+pub unsafe fn screenshot_buffer() -> &'static mut [u8] {
+    slice::from_raw_parts_mut(sdram::SCREENSHOT_BUFFER_START as *mut u8, SCREENSHOT_LEN)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Layer1,
+    Layer2,
+}
+
+impl Layer {
+    fn start_address(&self) -> u32 {
+        match *self {
+            Layer::Layer1 => LAYER_1_START,
+            Layer::Layer2 => LAYER_2_START,
+        }
+    }
+
+    /// The first `len` pixels of the framebuffer in the ARGB1555 format.
+    fn pixels(&self, len: usize) -> &'static [u16] {
+        unsafe { slice::from_raw_parts(self.start_address() as *const u16, len) }
+    }
+
+    /// The first `len` pixels of the framebuffer in the L8 format.
+    fn indices(&self, len: usize) -> &'static [u8] {
+        unsafe { slice::from_raw_parts(self.start_address() as *const u8, len) }
+    }
+
+    fn index(&self) -> usize {
+        match *self {
+            Layer::Layer1 => 0,
+            Layer::Layer2 => 1,
+        }
+    }
+}
+
+/// Rotation of the displayed image, clockwise.
+///
+/// The logical coordinates passed to the drawing functions are rotated into the physical
+/// framebuffer, whose size is passed as `panel`. `touch::Calibration` uses the same type, so
+/// touches match the drawing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// Logical width and height of the screen.
+    pub fn size(&self, panel: (u16, u16)) -> (u16, u16) {
+        let (width, height) = panel;
+        match *self {
+            Rotation::Deg0 | Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width),
+        }
+    }
+
+    /// Maps logical coordinates to framebuffer coordinates.
+    pub fn to_physical(&self, panel: (u16, u16), x: u16, y: u16) -> (u16, u16) {
+        let (width, height) = panel;
+        match *self {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (width - 1 - y, x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (y, height - 1 - x),
+        }
+    }
+
+    /// Maps framebuffer coordinates to logical coordinates.
+    pub fn to_logical(&self, panel: (u16, u16), x: u16, y: u16) -> (u16, u16) {
+        let (width, height) = panel;
+        match *self {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (y, width - 1 - x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (height - 1 - y, x),
+        }
+    }
+}
 
 pub struct Lcd {
     controller: &'static mut Ltdc,
+    panel: PanelTiming,
     display_enable: OutputPin,
-    backlight_enable: OutputPin,
+    backlight: Backlight,
+    backlight_level: u8,
+    /// Whether the LTDC and its clocks run, see `display_off`.
+    powered: bool,
     next_pixel: u32,
     next_col: u32,
     prev_value: (u32, u32),
+    rotation: Rotation,
+    /// The pixel formats of the layers, which are also needed while the LTDC is off.
+    formats: [LayerFormat; 2],
+    /// Copies of the lookup tables, which can't be read back from the LTDC.
+    cluts: [[Color; CLUT_LEN]; 2],
 }
 
 impl Lcd {
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Rotates all subsequent drawing. Already drawn content is not changed.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Logical width of the screen, depending on the rotation.
+    pub fn width(&self) -> u16 {
+        self.rotation.size(self.panel_size()).0
+    }
+
+    /// Logical height of the screen, depending on the rotation.
+    pub fn height(&self) -> u16 {
+        self.rotation.size(self.panel_size()).1
+    }
+
+    /// The timing that the LTDC was configured with.
+    pub fn panel(&self) -> &PanelTiming {
+        &self.panel
+    }
+
+    /// Physical width and height of the panel, independent of the rotation.
+    pub fn panel_size(&self) -> (u16, u16) {
+        (self.panel.width, self.panel.height)
+    }
+
+    fn pixel_count(&self) -> usize {
+        usize::from(self.panel.width) * usize::from(self.panel.height)
+    }
+
+    /// The backlight brightness, from 0 (off) to `MAX_BRIGHTNESS`.
+    pub fn backlight(&self) -> u8 {
+        self.backlight_level
+    }
+
+    /// Sets the backlight brightness. Without `enable_backlight_pwm`, all levels except 0
+    /// switch the backlight fully on. While the display is off, the level is applied by
+    /// `display_on`.
+    pub fn set_backlight(&mut self, level: u8) {
+        self.backlight_level = level;
+        if self.powered {
+            self.backlight.set_level(level);
+        }
+    }
+
+    /// Dims the backlight with a PWM generated by the TIM7 interrupt, see `lcd::backlight`.
+    pub fn enable_backlight_pwm(&mut self, tim_7: &'static mut Tim7, rcc: &mut Rcc) {
+        let level = if self.powered { self.backlight_level } else { 0 };
+        self.backlight.enable_pwm(tim_7, rcc, level);
+    }
+
+    pub fn is_display_on(&self) -> bool {
+        self.powered
+    }
+
+    /// Turns the backlight and the panel off and stops the LTDC and the PLLSAI clock.
+    ///
+    /// The framebuffers stay in the SDRAM, so drawing still works. The lookup tables are
+    /// uploaded again by `display_on`, but other layer configuration changes are lost while
+    /// the display is off.
+    pub fn display_off(&mut self, rcc: &mut Rcc) {
+        if !self.powered {
+            return;
+        }
+        self.backlight.set_level(0);
+        self.display_enable.set(false);
+
+        self.controller.gcr.update(|r| r.set_ltdcen(false));
+        rcc.apb2enr.update(|r| r.set_ltdcen(false));
+        rcc.cr.update(|r| r.set_pllsaion(false));
+        while rcc.cr.read().pllsairdy() {}
+        self.powered = false;
+    }
+
+    /// Restarts the clocks and the LTDC and turns the panel and the backlight on again.
+    pub fn display_on(&mut self, rcc: &mut Rcc) {
+        if self.powered {
+            return;
+        }
+        // the configuration of the PLLSAI is kept while it is disabled
+        rcc.cr.update(|r| r.set_pllsaion(true));
+        while !rcc.cr.read().pllsairdy() {}
+        rcc.apb2enr.update(|r| r.set_ltdcen(true));
+
+        // the lookup tables can't be written while the clock is off
+        for &layer in &[Layer::Layer1, Layer::Layer2] {
+            if self.layer_format(layer) == LayerFormat::L8 {
+                let clut = self.cluts[layer.index()];
+                self.set_clut(layer, 0, &clut);
+            }
+        }
+        self.controller.gcr.update(|r| r.set_ltdcen(true));
+        self.powered = true;
+
+        self.display_enable.set(true);
+        self.backlight.set_level(self.backlight_level);
+    }
+
+    /// The numbers of FIFO underruns and transfer errors that `handle_interrupts` counted.
+    pub fn error_counts(&self) -> ErrorCounts {
+        interrupt::error_counts()
+    }
+
+    pub fn reset_error_counts(&mut self) {
+        interrupt::reset_error_counts();
+    }
+
+    /// Whether `handle_interrupts` reloads the shadow registers after an error, see
+    /// `interrupt::set_reload_on_error`.
+    pub fn set_reload_on_error(&mut self, reload: bool) {
+        interrupt::set_reload_on_error(reload);
+    }
+
+    /// Calls `callback` from `handle_interrupts` after the LTDC started to send the physical
+    /// line `line` to the panel. Lines from the panel height on are in the vertical blanking
+    /// period.
+    ///
+    /// The callback runs at most once per frame and only if `handle_interrupts` is called
+    /// often enough.
+    pub fn set_line_callback(&mut self, line: u16, callback: fn()) {
+        // the position counts the synchronization and back porch lines too
+        let first_active_line = self.controller.bpcr.read().avbp() + 1;
+        let total_height = self.controller.twcr.read().totalh();
+        let position = cmp::min(first_active_line.saturating_add(line), total_height);
+        interrupt::set_line_callback(self.controller, position, Some(callback));
+    }
+
+    pub fn disable_line_callback(&mut self) {
+        interrupt::set_line_callback(self.controller, 0, None);
+    }
+
+    /// The number of handled line interrupts since the line callback was set first.
+    pub fn frame_count(&self) -> usize {
+        interrupt::frame_count()
+    }
+
+    /// Handles the LTDC interrupts that occurred since the last call: counts the errors,
+    /// reloads the shadow registers after an error if enabled and calls the line callback.
+    ///
+    /// The interrupt handlers only latch the interrupts, so this should be called from the
+    /// main loop.
+    pub fn handle_interrupts(&mut self) {
+        interrupt::handle_pending(self.controller);
+    }
+
     pub fn set_background_color(&mut self, color: Color) {
         self.controller
             .bccr
             .update(|r| r.set_bc(color.to_rgb()));
     }
 
+    /// Composes both layers and the background color into `buffer`, like the panel shows them.
+    ///
+    /// The blending configuration is read from the LTDC registers, so the display has to be on
+    /// (see `display_off`). The image has the physical size of the panel, independent of the
+    /// rotation. Returns `None` if `buffer` can't hold it, `SCREENSHOT_LEN` is enough for all
+    /// panels, e.g. use `screenshot_buffer`.
+    pub fn screenshot<'a>(&self, buffer: &'a mut [u8]) -> Option<Screenshot<'a>> {
+        let len = self.pixel_count() * screenshot::BYTES_PER_PIXEL;
+        if buffer.len() < len {
+            return None;
+        }
+        let background = Color::from_hex(self.controller.bccr.read().bc());
+        let layer_1 = self.controller.l1bfcr.read();
+        let layer_2 = self.controller.l2bfcr.read();
+        let layers = [LayerSource {
+                          pixels: self.layer_pixels(Layer::Layer1),
+                          blending: LayerBlending {
+                              enabled: self.controller.l1cr.read().len(),
+                              constant_alpha: self.controller.l1cacr.read().consta(),
+                              factor_1: BlendingFactor::from_bits(layer_1.bf1()),
+                              factor_2: BlendingFactor::from_bits(layer_1.bf2()),
+                          },
+                      },
+                      LayerSource {
+                          pixels: self.layer_pixels(Layer::Layer2),
+                          blending: LayerBlending {
+                              enabled: self.controller.l2cr.read().len(),
+                              constant_alpha: self.controller.l2cacr.read().consta(),
+                              factor_1: BlendingFactor::from_bits(layer_2.bf1()),
+                              factor_2: BlendingFactor::from_bits(layer_2.bf2()),
+                          },
+                      }];
+        compose(background, &layers, &mut buffer[..len]);
+        Screenshot::new(self.panel.width, self.panel.height, buffer)
+    }
+
+    fn layer_pixels(&self, layer: Layer) -> LayerPixels {
+        match self.layer_format(layer) {
+            LayerFormat::Argb1555 => LayerPixels::Argb1555(layer.pixels(self.pixel_count())),
+            LayerFormat::L8 => {
+                LayerPixels::L8(layer.indices(self.pixel_count()), self.clut(layer))
+            }
+        }
+    }
+
+    pub fn layer_format(&self, layer: Layer) -> LayerFormat {
+        self.formats[layer.index()]
+    }
+
+    /// Switches the pixel format of a layer at the next vertical blanking period. The
+    /// framebuffer keeps its address, so its content has to be redrawn.
+    ///
+    /// The L8 format uses the lookup table of the layer, see `set_clut`.
+    pub fn set_layer_format(&mut self, layer: Layer, format: LayerFormat) {
+        if format == LayerFormat::L8 {
+            // the LTDC table may not match the copy yet
+            let clut = self.cluts[layer.index()];
+            self.set_clut(layer, 0, &clut);
+        }
+        self.formats[layer.index()] = format;
+        let line_length = self.panel.width * format.bytes_per_pixel() as u16;
+        let clut_enabled = format == LayerFormat::L8;
+        match layer {
+            Layer::Layer1 => {
+                self.controller.l1pfcr.update(|r| r.set_pf(format.bits()));
+                self.controller
+                    .l1cfblr
+                    .update(|r| {
+                                r.set_cfbp(line_length); // pitch
+                                r.set_cfbll(line_length + 3); // line_length
+                            });
+                self.controller.l1cr.update(|r| r.set_cluten(clut_enabled));
+            }
+            Layer::Layer2 => {
+                self.controller.l2pfcr.update(|r| r.set_pf(format.bits()));
+                self.controller
+                    .l2cfblr
+                    .update(|r| {
+                                r.set_cfbp(line_length); // pitch
+                                r.set_cfbll(line_length + 3); // line_length
+                            });
+                self.controller.l2cr.update(|r| r.set_cluten(clut_enabled));
+            }
+        }
+        // reload the shadow registers in the vertical blanking period to avoid tearing
+        self.controller.srcr.update(|r| r.set_vbr(true));
+    }
+
+    /// The lookup table of a layer, as set by `set_clut`.
+    pub fn clut(&self, layer: Layer) -> &[Color] {
+        &self.cluts[layer.index()]
+    }
+
+    /// Sets the lookup table entries from `first` on. Entries after the end of the table are
+    /// ignored, and the alpha of the colors is ignored, because L8 pixels are opaque.
+    ///
+    /// The LTDC only accepts new entries while the layer is disabled or in the vertical
+    /// blanking period, so this waits for the blanking period if the layer is enabled. Changing
+    /// entries once per frame animates the colors without redrawing, see `clut::rotate`.
+    pub fn set_clut(&mut self, layer: Layer, first: u8, colors: &[Color]) {
+        let enabled = match layer {
+            Layer::Layer1 => self.controller.l1cr.read().len(),
+            Layer::Layer2 => self.controller.l2cr.read().len(),
+        };
+        // while the display is off, the LTDC doesn't read the tables
+        if enabled && self.powered {
+            self.wait_for_vertical_blanking();
+        }
+        let first = usize::from(first);
+        for (address, &color) in (first..CLUT_LEN).zip(colors.iter()) {
+            self.cluts[layer.index()][address] = Color { alpha: 255, ..color };
+            match layer {
+                Layer::Layer1 => {
+                    let mut entry = ltdc::L1clutwr::default();
+                    entry.set_clutadd(address as u8);
+                    entry.set_red(color.red);
+                    entry.set_green(color.green);
+                    entry.set_blue(color.blue);
+                    self.controller.l1clutwr.write(entry);
+                }
+                Layer::Layer2 => {
+                    let mut entry = ltdc::L2clutwr::default();
+                    entry.set_clutadd(address as u8);
+                    entry.set_red(color.red);
+                    entry.set_green(color.green);
+                    entry.set_blue(color.blue);
+                    self.controller.l2clutwr.write(entry);
+                }
+            }
+        }
+    }
+
+    /// Busy waits until the LTDC doesn't send visible lines to the panel.
+    pub fn wait_for_vertical_blanking(&self) {
+        while self.controller.cdsr.read().vdes() {}
+    }
+
     pub fn test_pixels(&mut self) {
+        let (width, height) = (u32::from(self.panel.width), u32::from(self.panel.height));
         let colors = [0xffff, 0xcccc, 0x9999, 0x6666, 0x3333, 0x0, 0xff00, 0x00ff];
 
         // layer 1: horizontal stripes
-        let addr: u32 = 0xC000_0000;
-        for i in 0..272 {
-            for j in 0..480 {
-                let pixel = i * 480 + j;
+        let addr = Layer::Layer1.start_address();
+        for i in 0..height {
+            for j in 0..width {
+                let pixel = i * width + j;
                 let pixel_color = (addr + pixel * 2) as *mut u16;
                 unsafe { ptr::write_volatile(pixel_color, colors[(i / 10) as usize & 7]) };
             }
@@ -42,10 +453,10 @@ impl Lcd {
         let colors = [0xcccc, 0x9999, 0x6666, 0x3333, 0x0, 0xff00, 0x00ff, 0xffff];
 
         // layer 2: vertical stripes
-        let addr: u32 = 0xC000_0000 + (480 * 272 * 2);
-        for i in 0..272 {
-            for j in 0..480 {
-                let pixel = i * 480 + j;
+        let addr = Layer::Layer2.start_address();
+        for i in 0..height {
+            for j in 0..width {
+                let pixel = i * width + j;
                 let pixel_color = (addr + pixel * 2) as *mut u16;
                 unsafe { ptr::write_volatile(pixel_color, colors[(j / 10) as usize & 7]) };
             }
@@ -53,21 +464,10 @@ impl Lcd {
     }
 
     pub fn clear_screen(&mut self) {
-        // layer 1
-        let addr: u32 = 0xC000_0000;
-        for i in 0..272 {
-            for j in 0..480 {
-                let pixel = i * 480 + j;
-                let pixel_color = (addr + pixel * 2) as *mut u16;
-                unsafe { ptr::write_volatile(pixel_color, 0) };
-            }
-        }
-
-        // layer 2
-        let addr: u32 = 0xC000_0000 + (480 * 272 * 2);
-        for i in 0..272 {
-            for j in 0..480 {
-                let pixel = i * 480 + j;
+        let pixel_count = self.pixel_count() as u32;
+        for &layer in &[Layer::Layer1, Layer::Layer2] {
+            let addr = layer.start_address();
+            for pixel in 0..pixel_count {
                 let pixel_color = (addr + pixel * 2) as *mut u16;
                 unsafe { ptr::write_volatile(pixel_color, 0) };
             }
@@ -76,25 +476,29 @@ impl Lcd {
 
     pub fn set_next_pixel(&mut self, color: u16) {
         // layer 1
-        let addr: u32 = 0xC000_0000;
+        let addr = Layer::Layer1.start_address();
         let pixel_color = (addr + self.next_pixel * 2) as *mut u16;
         unsafe { ptr::write_volatile(pixel_color, color) };
 
-        self.next_pixel = (self.next_pixel + 1) % (272 * 480);
+        self.next_pixel = (self.next_pixel + 1) % self.pixel_count() as u32;
     }
 
     pub fn set_next_col(&mut self, value0: u32, value1: u32) {
+        let (width, height) = (u32::from(self.panel.width), u32::from(self.panel.height));
+        // map the signed 16 bit samples to 0..height
+        let scale = (1 << 16) / height + 1;
+
         let value0 = value0 + 2u32.pow(15);
         let value0 = value0 as u16 as u32;
-        let value0 = value0 / 241;
+        let value0 = value0 / scale;
 
         let value1 = value1 + 2u32.pow(15);
         let value1 = value1 as u16 as u32;
-        let value1 = value1 / 241;
+        let value1 = value1 / scale;
 
         // layer 1
-        let addr: u32 = 0xC000_0000;
-        for i in 0..272 {
+        let addr = Layer::Layer1.start_address();
+        for i in 0..height {
             let mut color = 0;
 
             if value0 >= self.prev_value.0 {
@@ -106,44 +510,95 @@ impl Lcd {
             }
 
             if value1 >= self.prev_value.1 {
-                if i >= self.prev_value.0 && i <= value1 {
+                if i >= self.prev_value.1 && i <= value1 {
                     color |= 0x00ff;
                 }
-            } else if i <= self.prev_value.0 && i >= value1 {
+            } else if i <= self.prev_value.1 && i >= value1 {
                 color |= 0x00ff;
             }
 
-            let pixel = i * 480 + self.next_col;
+            let pixel = i * width + self.next_col;
             let pixel_color = (addr + pixel * 2) as *mut u16;
             unsafe { ptr::write_volatile(pixel_color, color) };
         }
 
 
-        self.next_col = (self.next_col + 1) % 480;
+        self.next_col = (self.next_col + 1) % width;
         self.prev_value = (value0, value1);
     }
 
+    /// Draws a white point on layer 2. Coordinates outside of the screen are clamped.
     pub fn print_point_at(&mut self, x: u16, y: u16) {
-        assert!(x < 480);
-        assert!(y < 272);
+        self.print_point_color_at(x, y, 0xffff);
+    }
 
-        // layer 2
-        let addr: u32 = 0xC000_0000 + (480 * 272 * 2);
-        let pixel = u32::from(y) * 480 + u32::from(x);
-        let pixel_color = (addr + pixel * 2) as *mut u16;
+    /// Sets the ARGB1555 color of a pixel at logical coordinates. Pixels outside of the screen
+    /// are ignored.
+    ///
+    /// On L8 layers, the closest color of the lookup table is used, which is much slower than
+    /// `set_pixel_index`.
+    pub fn set_pixel(&mut self, layer: Layer, x: u16, y: u16, color: u16) {
+        if self.layer_format(layer) == LayerFormat::L8 {
+            let index = Color::from_argb1555(color).to_l8(self.clut(layer));
+            self.set_pixel_index(layer, x, y, index);
+            return;
+        }
+        let pixel = match self.physical_pixel(x, y) {
+            Some(pixel) => pixel,
+            None => return,
+        };
+        let pixel_color = (layer.start_address() + pixel * 2) as *mut u16;
 
-        unsafe { ptr::write_volatile(pixel_color, 0xffff) };
+        unsafe { ptr::write_volatile(pixel_color, color) };
     }
 
-    pub fn print_point_color_at(&mut self, x: u16, y: u16, color: u16) {
-        assert!(x < 480);
-        assert!(y < 272);
+    /// Returns the ARGB1555 color of a pixel at logical coordinates, or `None` if it is
+    /// outside of the screen. On L8 layers, this is the color of the lookup table entry.
+    pub fn pixel(&self, layer: Layer, x: u16, y: u16) -> Option<u16> {
+        if self.layer_format(layer) == LayerFormat::L8 {
+            return self.pixel_index(layer, x, y)
+                       .map(|index| Color::from_l8(index, self.clut(layer)).to_argb1555());
+        }
+        let pixel = match self.physical_pixel(x, y) {
+            Some(pixel) => pixel,
+            None => return None,
+        };
+        let pixel_color = (layer.start_address() + pixel * 2) as *const u16;
 
-        // layer 2
-        let addr: u32 = 0xC000_0000 + (480 * 272 * 2);
-        let pixel = u32::from(y) * 480 + u32::from(x);
-        let pixel_color = (addr + pixel * 2) as *mut u16;
+        Some(unsafe { ptr::read_volatile(pixel_color) })
+    }
 
-        unsafe { ptr::write_volatile(pixel_color, color) };
+    /// Sets the lookup table index of a pixel of an L8 layer at logical coordinates. Pixels
+    /// outside of the screen are ignored.
+    pub fn set_pixel_index(&mut self, layer: Layer, x: u16, y: u16, index: u8) {
+        if let Some(pixel) = self.physical_pixel(x, y) {
+            let pixel_index = (layer.start_address() + pixel) as *mut u8;
+            unsafe { ptr::write_volatile(pixel_index, index) };
+        }
+    }
+
+    /// Returns the lookup table index of a pixel of an L8 layer at logical coordinates, or
+    /// `None` if it is outside of the screen.
+    pub fn pixel_index(&self, layer: Layer, x: u16, y: u16) -> Option<u8> {
+        self.physical_pixel(x, y).map(|pixel| {
+            let pixel_index = (layer.start_address() + pixel) as *const u8;
+            unsafe { ptr::read_volatile(pixel_index) }
+        })
+    }
+
+    /// The number of a pixel in the framebuffer, counted row by row.
+    fn physical_pixel(&self, x: u16, y: u16) -> Option<u32> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let (x, y) = self.rotation.to_physical(self.panel_size(), x, y);
+        Some(u32::from(y) * u32::from(self.panel.width) + u32::from(x))
+    }
+
+    /// Draws a point on layer 2. Coordinates outside of the screen are clamped.
+    pub fn print_point_color_at(&mut self, x: u16, y: u16, color: u16) {
+        let x = cmp::min(x, self.width() - 1);
+        let y = cmp::min(y, self.height() - 1);
+        self.set_pixel(Layer::Layer2, x, y, color);
     }
 }
@@ -0,0 +1,413 @@
+//! Composition of the two layers and the background color, like the LTDC does it for the
+//! panel.
+//!
+//! `compose` works on plain slices, so it also runs on the host. `Lcd::screenshot` reads the
+//! framebuffers and the blending registers and passes them to it.
+
+use byteorder::{ByteOrder, LittleEndian};
+use super::Color;
+
+/// Bytes per pixel of the composed RGB888 image.
+pub const BYTES_PER_PIXEL: usize = 3;
+
+const BMP_HEADER_LEN: usize = 14 + 40;
+
+/// A blending factor of the `LxBFCR` register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendingFactor {
+    /// `bf1 = 0b100`, `bf2 = 0b101`
+    ConstantAlpha,
+    /// `bf1 = 0b110`, `bf2 = 0b111`
+    PixelAlphaTimesConstantAlpha,
+}
+
+impl BlendingFactor {
+    /// Decodes `bf1` or `bf2`. Only the second bit differs between the two factors.
+    pub fn from_bits(bits: u8) -> BlendingFactor {
+        if bits & 0b010 != 0 {
+            BlendingFactor::PixelAlphaTimesConstantAlpha
+        } else {
+            BlendingFactor::ConstantAlpha
+        }
+    }
+
+    fn alpha(&self, pixel_alpha: u8, constant_alpha: u8) -> u32 {
+        match *self {
+            BlendingFactor::ConstantAlpha => u32::from(constant_alpha),
+            BlendingFactor::PixelAlphaTimesConstantAlpha => {
+                div_255(u32::from(pixel_alpha) * u32::from(constant_alpha))
+            }
+        }
+    }
+}
+
+/// The blending configuration of a layer.
+///
+/// The default is the configuration of `lcd::init`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerBlending {
+    pub enabled: bool,
+    pub constant_alpha: u8,
+    /// Weight of the layer color.
+    pub factor_1: BlendingFactor,
+    /// One minus this weight is the weight of the color below the layer.
+    pub factor_2: BlendingFactor,
+}
+
+impl Default for LayerBlending {
+    fn default() -> LayerBlending {
+        LayerBlending {
+            enabled: true,
+            constant_alpha: 255,
+            factor_1: BlendingFactor::PixelAlphaTimesConstantAlpha,
+            factor_2: BlendingFactor::PixelAlphaTimesConstantAlpha,
+        }
+    }
+}
+
+impl LayerBlending {
+    /// Blends an ARGB1555 pixel of the layer over the color below it.
+    pub fn blend(&self, pixel: u16, below: Color) -> Color {
+        self.blend_color(Color::from_argb1555(pixel), below)
+    }
+
+    /// Blends a pixel color of the layer over the color below it.
+    pub fn blend_color(&self, color: Color, below: Color) -> Color {
+        if !self.enabled {
+            return below;
+        }
+        let alpha_1 = self.factor_1.alpha(color.alpha, self.constant_alpha);
+        let alpha_2 = self.factor_2.alpha(color.alpha, self.constant_alpha);
+        let channel = |top: u8, bottom: u8| {
+            let value = u32::from(top) * alpha_1 + u32::from(bottom) * (255 - alpha_2);
+            // both factors can be 255 if they differ, so the sum may overflow
+            if value >= 255 * 255 {
+                255
+            } else {
+                div_255(value) as u8
+            }
+        };
+        Color::rgb(channel(color.red, below.red),
+                   channel(color.green, below.green),
+                   channel(color.blue, below.blue))
+    }
+}
+
+/// The pixels of a layer framebuffer, stored row by row.
+#[derive(Debug, Clone, Copy)]
+pub enum LayerPixels<'a> {
+    Argb1555(&'a [u16]),
+    /// Indices into the lookup table of the layer, whose colors are opaque.
+    L8(&'a [u8], &'a [Color]),
+}
+
+impl<'a> LayerPixels<'a> {
+    fn get(&self, index: usize) -> Option<Color> {
+        match *self {
+            LayerPixels::Argb1555(pixels) => pixels.get(index).map(|&pixel| {
+                Color::from_argb1555(pixel)
+            }),
+            LayerPixels::L8(indices, clut) => indices.get(index).map(|&entry| {
+                Color { alpha: 255, ..Color::from_l8(entry, clut) }
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayerSource<'a> {
+    pub pixels: LayerPixels<'a>,
+    pub blending: LayerBlending,
+}
+
+/// Composes the layers over the background color into `output`, three bytes per pixel in the
+/// order red, green, blue.
+///
+/// The layers are ordered from bottom to top. Pixels that are missing in a short layer slice
+/// are treated as transparent. The alpha of the background color is ignored.
+///
+/// Returns the number of written pixels, which is limited by the length of `output`.
+pub fn compose(background: Color, layers: &[LayerSource], output: &mut [u8]) -> usize {
+    let background = Color::rgb(background.red, background.green, background.blue);
+    let mut count = 0;
+    for (i, rgb) in output.chunks_mut(BYTES_PER_PIXEL).enumerate() {
+        if rgb.len() < BYTES_PER_PIXEL {
+            break;
+        }
+        let color = layers.iter().fold(background, |below, layer| match layer.pixels.get(i) {
+            Some(color) => layer.blending.blend_color(color, below),
+            None => below,
+        });
+        rgb[0] = color.red;
+        rgb[1] = color.green;
+        rgb[2] = color.blue;
+        count += 1;
+    }
+    count
+}
+
+fn div_255(value: u32) -> u32 {
+    (value + 127) / 255
+}
+
+/// A composed RGB888 image, stored row by row from the top.
+#[derive(Debug)]
+pub struct Screenshot<'a> {
+    width: u16,
+    height: u16,
+    data: &'a [u8],
+}
+
+impl<'a> Screenshot<'a> {
+    /// Returns `None` if the image is empty or `data` is shorter than `width * height` pixels.
+    pub fn new(width: u16, height: u16, data: &'a [u8]) -> Option<Screenshot<'a>> {
+        let len = usize::from(width) * usize::from(height) * BYTES_PER_PIXEL;
+        if len == 0 || data.len() < len {
+            return None;
+        }
+        Some(Screenshot {
+                 width: width,
+                 height: height,
+                 data: &data[..len],
+             })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The red, green and blue bytes of all pixels.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn pixel(&self, x: u16, y: u16) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let offset = (usize::from(y) * usize::from(self.width) + usize::from(x)) *
+                     BYTES_PER_PIXEL;
+        let rgb = &self.data[offset..offset + BYTES_PER_PIXEL];
+        Some(Color::rgb(rgb[0], rgb[1], rgb[2]))
+    }
+
+    /// Size of the image encoded by `write_bmp`.
+    pub fn bmp_len(&self) -> usize {
+        BMP_HEADER_LEN + self.bmp_stride() * usize::from(self.height)
+    }
+
+    /// Encodes the image as a 24 bit BMP and passes it to `write` in pieces: first the header,
+    /// then one row after the other.
+    ///
+    /// Only a single row is buffered, so the pieces can be sent directly, e.g. with
+    /// `EthernetDevice::send_udp_stream`. The first error returned by `write` is returned.
+    pub fn write_bmp<F, E>(&self, mut write: F) -> Result<(), E>
+        where F: FnMut(&[u8]) -> Result<(), E>
+    {
+        write(&self.bmp_header()[..])?;
+
+        let width = usize::from(self.width);
+        let mut row = vec![0; self.bmp_stride()];
+        // the rows are stored top to bottom, which the negative height in the header indicates
+        for source in self.data.chunks(width * BYTES_PER_PIXEL) {
+            let pixels = row.chunks_mut(BYTES_PER_PIXEL).zip(source.chunks(BYTES_PER_PIXEL));
+            for (bgr, rgb) in pixels {
+                bgr[0] = rgb[2];
+                bgr[1] = rgb[1];
+                bgr[2] = rgb[0];
+            }
+            write(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Rows are padded to a multiple of four bytes.
+    fn bmp_stride(&self) -> usize {
+        (usize::from(self.width) * BYTES_PER_PIXEL + 3) / 4 * 4
+    }
+
+    fn bmp_header(&self) -> [u8; BMP_HEADER_LEN] {
+        let mut header = [0; BMP_HEADER_LEN];
+        let image_len = self.bmp_len() - BMP_HEADER_LEN;
+
+        // file header
+        header[0..2].copy_from_slice(b"BM");
+        LittleEndian::write_u32(&mut header[2..6], self.bmp_len() as u32);
+        LittleEndian::write_u32(&mut header[10..14], BMP_HEADER_LEN as u32); // pixel offset
+
+        // BITMAPINFOHEADER, uncompressed
+        LittleEndian::write_u32(&mut header[14..18], 40);
+        LittleEndian::write_i32(&mut header[18..22], i32::from(self.width));
+        LittleEndian::write_i32(&mut header[22..26], -i32::from(self.height));
+        LittleEndian::write_u16(&mut header[26..28], 1); // planes
+        LittleEndian::write_u16(&mut header[28..30], 24); // bits per pixel
+        LittleEndian::write_u32(&mut header[34..38], image_len as u32);
+        // 72 dpi
+        LittleEndian::write_u32(&mut header[38..42], 2835);
+        LittleEndian::write_u32(&mut header[42..46], 2835);
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+    use lcd::color::{BLACK, BLUE, RED, WHITE};
+
+    const OPAQUE_RED: u16 = 0xfc00;
+    const TRANSPARENT_RED: u16 = 0x7c00;
+
+    fn layer(pixels: LayerPixels) -> LayerSource {
+        LayerSource {
+            pixels: pixels,
+            blending: LayerBlending::default(),
+        }
+    }
+
+    fn compose_pixels(background: Color, layers: &[LayerSource], count: usize) -> Vec<Color> {
+        let mut output = vec![0; count * BYTES_PER_PIXEL];
+        assert_eq!(compose(background, layers, &mut output), count);
+        output.chunks(BYTES_PER_PIXEL).map(|rgb| Color::rgb(rgb[0], rgb[1], rgb[2])).collect()
+    }
+
+    #[test]
+    fn blend_default() {
+        let blending = LayerBlending::default();
+        assert_eq!(blending.blend(OPAQUE_RED, BLUE), RED);
+        assert_eq!(blending.blend(TRANSPARENT_RED, BLUE), BLUE);
+        let disabled = LayerBlending { enabled: false, ..blending };
+        assert_eq!(disabled.blend(OPAQUE_RED, BLUE), BLUE);
+    }
+
+    #[test]
+    fn blend_constant_alpha() {
+        let blending = LayerBlending {
+            constant_alpha: 128,
+            ..LayerBlending::default()
+        };
+        assert_eq!(blending.blend(OPAQUE_RED, BLUE), Color::rgb(128, 0, 127));
+        let constant = LayerBlending {
+            constant_alpha: 51,
+            factor_1: BlendingFactor::ConstantAlpha,
+            factor_2: BlendingFactor::ConstantAlpha,
+            ..blending
+        };
+        // the pixel alpha is ignored
+        assert_eq!(constant.blend(TRANSPARENT_RED, BLACK), Color::rgb(51, 0, 0));
+    }
+
+    #[test]
+    fn blend_saturates() {
+        // the layer gets the full weight, and the color below too because the pixel alpha is 0
+        let blending = LayerBlending {
+            factor_1: BlendingFactor::ConstantAlpha,
+            ..LayerBlending::default()
+        };
+        assert_eq!(blending.blend_color(Color::rgba(200, 0, 0, 0), Color::rgb(100, 0, 7)),
+                   Color::rgb(255, 0, 7));
+    }
+
+    #[test]
+    fn blending_factor_bits() {
+        assert_eq!(BlendingFactor::from_bits(0b100), BlendingFactor::ConstantAlpha);
+        assert_eq!(BlendingFactor::from_bits(0b101), BlendingFactor::ConstantAlpha);
+        assert_eq!(BlendingFactor::from_bits(0b110),
+                   BlendingFactor::PixelAlphaTimesConstantAlpha);
+        assert_eq!(BlendingFactor::from_bits(0b111),
+                   BlendingFactor::PixelAlphaTimesConstantAlpha);
+    }
+
+    #[test]
+    fn compose_layers() {
+        let background = Color::rgba(0, 0, 255, 0);
+        let bottom = [OPAQUE_RED, TRANSPARENT_RED, OPAQUE_RED, 0];
+        let clut = [WHITE, Color::rgba(0, 255, 0, 0)];
+        // the top layer is shorter and its lookup table colors are opaque
+        let top = [1, 0];
+        let mut top_layer = layer(LayerPixels::L8(&top, &clut));
+        top_layer.blending.constant_alpha = 0;
+        let pixels = compose_pixels(background,
+                                    &[layer(LayerPixels::Argb1555(&bottom)), top_layer],
+                                    4);
+        assert_eq!(pixels, [RED, BLUE, RED, BLUE]);
+
+        let pixels = compose_pixels(background,
+                                    &[layer(LayerPixels::Argb1555(&bottom)),
+                                      layer(LayerPixels::L8(&top, &clut))],
+                                    4);
+        assert_eq!(pixels, [Color::rgb(0, 255, 0), WHITE, RED, BLUE]);
+        assert_eq!(compose_pixels(WHITE, &[], 2), [WHITE, WHITE]);
+    }
+
+    #[test]
+    fn compose_limits_to_output() {
+        let pixels = [OPAQUE_RED; 4];
+        let mut output = [0; 2 * BYTES_PER_PIXEL + 2];
+        let count = compose(BLUE, &[layer(LayerPixels::Argb1555(&pixels))], &mut output);
+        assert_eq!(count, 2);
+        assert_eq!(output, [255, 0, 0, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn screenshot_pixels() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
+        assert!(Screenshot::new(2, 3, &data).is_none());
+        assert!(Screenshot::new(0, 2, &data).is_none());
+        let screenshot = Screenshot::new(2, 2, &data).unwrap();
+        assert_eq!(screenshot.data().len(), 12);
+        assert_eq!(screenshot.pixel(1, 1), Some(Color::rgb(10, 11, 12)));
+        assert_eq!(screenshot.pixel(2, 0), None);
+    }
+
+    #[test]
+    fn bmp_layout() {
+        // 2 × 2 pixels, so each row has 2 bytes of padding
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let screenshot = Screenshot::new(2, 2, &data).unwrap();
+        assert_eq!(screenshot.bmp_len(), 54 + 2 * 8);
+
+        let mut pieces = Vec::new();
+        screenshot.write_bmp(|piece| -> Result<(), ()> {
+                pieces.push(piece.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(pieces.len(), 3);
+        let header = &pieces[0];
+        assert_eq!(header.len(), 54);
+        assert_eq!(&header[0..2], b"BM");
+        assert_eq!(LittleEndian::read_u32(&header[2..6]), 70);
+        assert_eq!(LittleEndian::read_u32(&header[10..14]), 54);
+        assert_eq!(LittleEndian::read_u32(&header[14..18]), 40);
+        assert_eq!(LittleEndian::read_i32(&header[18..22]), 2);
+        // negative height: top-down rows
+        assert_eq!(LittleEndian::read_i32(&header[22..26]), -2);
+        assert_eq!(LittleEndian::read_u16(&header[28..30]), 24);
+        assert_eq!(LittleEndian::read_u32(&header[30..34]), 0);
+        assert_eq!(LittleEndian::read_u32(&header[34..38]), 16);
+        assert_eq!(pieces[1], [3, 2, 1, 6, 5, 4, 0, 0]);
+        assert_eq!(pieces[2], [9, 8, 7, 12, 11, 10, 0, 0]);
+        assert_eq!(pieces.iter().map(|piece| piece.len()).sum::<usize>(),
+                   screenshot.bmp_len());
+    }
+
+    #[test]
+    fn bmp_write_error() {
+        let data = [0; 4 * 3 * BYTES_PER_PIXEL];
+        let screenshot = Screenshot::new(4, 3, &data).unwrap();
+        let mut calls = 0;
+        let result = screenshot.write_bmp(|_| {
+            calls += 1;
+            if calls == 2 { Err("full") } else { Ok(()) }
+        });
+        assert_eq!(result, Err("full"));
+        assert_eq!(calls, 2);
+        // no padding for a width of 4
+        assert_eq!(screenshot.bmp_len(), 54 + 3 * 12);
+    }
+}
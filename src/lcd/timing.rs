@@ -0,0 +1,404 @@
+//! Timing descriptions of LCD panels and the LTDC and PLLSAI settings derived from them.
+//!
+//! A panel is described by its resolution, the widths of the synchronization pulses, the
+//! porches and the pixel clock, as found in its datasheet. `PanelTiming::ltdc_timing` and
+//! `PanelTiming::pll_sai_config` compute the register values, so they also run on the host.
+
+/// Largest supported panel. The framebuffers are sized for it.
+pub const MAX_WIDTH: u16 = 800;
+pub const MAX_HEIGHT: u16 = 480;
+
+// ranges of the PLLSAI, see the reference manual
+const PLL_SAI_N: (u16, u16) = (50, 432);
+const PLL_SAI_R: (u8, u8) = (2, 7);
+const VCO_OUTPUT_HZ: (u32, u32) = (100_000_000, 432_000_000);
+/// Dividers of the PLLSAIR output for the LCD clock, indexed by the `pllsaidivr` bits.
+const LCD_CLOCK_DIVIDERS: [u32; 4] = [2, 4, 8, 16];
+
+/// Largest values of the `totalw` and `totalh` fields.
+const MAX_TOTAL_WIDTH: u32 = 0xfff;
+const MAX_TOTAL_HEIGHT: u32 = 0x7ff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+impl Polarity {
+    fn bit(&self) -> bool {
+        *self == Polarity::ActiveHigh
+    }
+}
+
+/// Timing parameters of a panel with a parallel RGB interface. Horizontal values are in pixel
+/// clocks, vertical values in lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanelTiming {
+    pub width: u16,
+    pub height: u16,
+    pub hsync_width: u16,
+    pub horizontal_back_porch: u16,
+    pub horizontal_front_porch: u16,
+    pub vsync_height: u16,
+    pub vertical_back_porch: u16,
+    pub vertical_front_porch: u16,
+    pub hsync_polarity: Polarity,
+    pub vsync_polarity: Polarity,
+    pub data_enable_polarity: Polarity,
+    /// Whether the panel samples the data at the falling edge of the pixel clock.
+    pub pixel_clock_inverted: bool,
+    pub pixel_clock_hz: u32,
+}
+
+/// The 4.3" 480×272 panel of the STM32F746G discovery board.
+pub const RK043FN48H: PanelTiming = PanelTiming {
+    width: 480,
+    height: 272,
+    hsync_width: 41,
+    horizontal_back_porch: 13,
+    horizontal_front_porch: 32,
+    vsync_height: 10,
+    vertical_back_porch: 2,
+    vertical_front_porch: 2,
+    hsync_polarity: Polarity::ActiveLow,
+    vsync_polarity: Polarity::ActiveLow,
+    data_enable_polarity: Polarity::ActiveLow,
+    pixel_clock_inverted: false,
+    pixel_clock_hz: 9_600_000,
+};
+
+/// The 4" 800×480 panel with the OTM8009A controller of the STM32F769I discovery board, in
+/// landscape orientation.
+///
+/// The panel is connected through the DSI host, which has to be configured for video mode
+/// with the same timing; `lcd::init_panel` only sets up the LTDC side.
+pub const OTM8009A: PanelTiming = PanelTiming {
+    width: 800,
+    height: 480,
+    hsync_width: 2,
+    horizontal_back_porch: 34,
+    horizontal_front_porch: 34,
+    vsync_height: 1,
+    vertical_back_porch: 15,
+    vertical_front_porch: 16,
+    hsync_polarity: Polarity::ActiveHigh,
+    vsync_polarity: Polarity::ActiveHigh,
+    data_enable_polarity: Polarity::ActiveLow,
+    pixel_clock_inverted: false,
+    pixel_clock_hz: 27_429_000,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingError {
+    /// The width or height is 0 or larger than `MAX_WIDTH` or `MAX_HEIGHT`.
+    InvalidSize,
+    /// A sync width is 0, or the total width or height doesn't fit into the LTDC registers.
+    InvalidSync,
+    /// The PLLSAI can't generate the pixel clock within 1%.
+    UnreachablePixelClock,
+}
+
+/// The values of the LTDC timing registers, which are accumulated and minus one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LtdcTiming {
+    pub hsw: u16,
+    pub vsh: u16,
+    pub ahbp: u16,
+    pub avbp: u16,
+    pub aaw: u16,
+    pub aah: u16,
+    pub totalw: u16,
+    pub totalh: u16,
+    pub hspol: bool,
+    pub vspol: bool,
+    pub depol: bool,
+    pub pcpol: bool,
+}
+
+impl LtdcTiming {
+    /// The first and the last column of the active area, for the layer windows.
+    pub fn horizontal_window(&self) -> (u16, u16) {
+        (self.ahbp + 1, self.aaw)
+    }
+
+    /// The first and the last line of the active area, for the layer windows.
+    pub fn vertical_window(&self) -> (u16, u16) {
+        (self.avbp + 1, self.aah)
+    }
+}
+
+/// The PLLSAI settings for the LCD clock, which is `input / pllm * n / r / divider`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllSaiConfig {
+    pub n: u16,
+    pub r: u8,
+    /// The `pllsaidivr` bits of `DKCFGR1`.
+    pub divr_bits: u8,
+}
+
+impl PllSaiConfig {
+    /// The LCD clock for a PLL input frequency, i.e. the HSE frequency divided by `pllm`.
+    pub fn pixel_clock(&self, input_hz: u32) -> u32 {
+        let divider = LCD_CLOCK_DIVIDERS[usize::from(self.divr_bits)];
+        (u64::from(input_hz) * u64::from(self.n) / u64::from(self.r) / u64::from(divider)) as u32
+    }
+}
+
+impl PanelTiming {
+    pub fn validate(&self) -> Result<(), TimingError> {
+        if self.width == 0 || self.height == 0 || self.width > MAX_WIDTH ||
+           self.height > MAX_HEIGHT {
+            return Err(TimingError::InvalidSize);
+        }
+        if self.hsync_width == 0 || self.vsync_height == 0 ||
+           self.total_width() - 1 > MAX_TOTAL_WIDTH ||
+           self.total_height() - 1 > MAX_TOTAL_HEIGHT {
+            return Err(TimingError::InvalidSync);
+        }
+        Ok(())
+    }
+
+    /// Pixel clocks per line, including the blanking period.
+    pub fn total_width(&self) -> u32 {
+        u32::from(self.hsync_width) + u32::from(self.horizontal_back_porch) +
+        u32::from(self.width) + u32::from(self.horizontal_front_porch)
+    }
+
+    /// Lines per frame, including the blanking period.
+    pub fn total_height(&self) -> u32 {
+        u32::from(self.vsync_height) + u32::from(self.vertical_back_porch) +
+        u32::from(self.height) + u32::from(self.vertical_front_porch)
+    }
+
+    /// Frames per second in millihertz.
+    pub fn refresh_rate_mhz(&self) -> u32 {
+        let pixels = u64::from(self.total_width()) * u64::from(self.total_height());
+        (u64::from(self.pixel_clock_hz) * 1000 / pixels) as u32
+    }
+
+    pub fn ltdc_timing(&self) -> Result<LtdcTiming, TimingError> {
+        self.validate()?;
+        let back_porch_x = self.hsync_width + self.horizontal_back_porch;
+        let back_porch_y = self.vsync_height + self.vertical_back_porch;
+        Ok(LtdcTiming {
+               hsw: self.hsync_width - 1,
+               vsh: self.vsync_height - 1,
+               ahbp: back_porch_x - 1,
+               avbp: back_porch_y - 1,
+               aaw: back_porch_x + self.width - 1,
+               aah: back_porch_y + self.height - 1,
+               totalw: (self.total_width() - 1) as u16,
+               totalh: (self.total_height() - 1) as u16,
+               hspol: self.hsync_polarity.bit(),
+               vspol: self.vsync_polarity.bit(),
+               depol: self.data_enable_polarity.bit(),
+               pcpol: self.pixel_clock_inverted,
+           })
+    }
+
+    /// Searches the PLLSAI settings whose LCD clock is closest to the pixel clock, for a PLL
+    /// input frequency (HSE / `pllm`). Smaller `n` are preferred among equally close ones.
+    pub fn pll_sai_config(&self, input_hz: u32) -> Result<PllSaiConfig, TimingError> {
+        let target = u64::from(self.pixel_clock_hz);
+        let mut best: Option<(u64, PllSaiConfig)> = None;
+        for n in PLL_SAI_N.0..(PLL_SAI_N.1 + 1) {
+            let vco = u64::from(input_hz) * u64::from(n);
+            if vco < u64::from(VCO_OUTPUT_HZ.0) || vco > u64::from(VCO_OUTPUT_HZ.1) {
+                continue;
+            }
+            for r in PLL_SAI_R.0..(PLL_SAI_R.1 + 1) {
+                for (bits, &divider) in LCD_CLOCK_DIVIDERS.iter().enumerate() {
+                    let clock = vco / u64::from(r) / u64::from(divider);
+                    let error = if clock > target {
+                        clock - target
+                    } else {
+                        target - clock
+                    };
+                    let better = match best {
+                        Some((best_error, _)) => error < best_error,
+                        None => true,
+                    };
+                    if better {
+                        best = Some((error,
+                                     PllSaiConfig {
+                                         n: n,
+                                         r: r,
+                                         divr_bits: bits as u8,
+                                     }));
+                    }
+                }
+            }
+        }
+        match best {
+            Some((error, config)) if error * 100 <= target => Ok(config),
+            _ => Err(TimingError::UnreachablePixelClock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The PLL input of the board, the 25 MHz HSE divided by PLLM 25.
+    const PLL_INPUT_HZ: u32 = 1_000_000;
+
+    #[test]
+    fn rk043fn48h_ltdc_timing() {
+        // the values that were hard-coded before the panel description
+        let timing = RK043FN48H.ltdc_timing().unwrap();
+        assert_eq!(timing,
+                   LtdcTiming {
+                       hsw: 41 - 1,
+                       vsh: 10 - 1,
+                       ahbp: 41 + 13 - 1,
+                       avbp: 10 + 2 - 1,
+                       aaw: 480 + 41 + 13 - 1,
+                       aah: 272 + 10 + 2 - 1,
+                       totalw: 480 + 41 + 13 + 32 - 1,
+                       totalh: 272 + 10 + 2 + 2 - 1,
+                       hspol: false,
+                       vspol: false,
+                       depol: false,
+                       pcpol: false,
+                   });
+        assert_eq!(timing.horizontal_window(), (54, 533));
+        assert_eq!(timing.vertical_window(), (12, 283));
+        assert_eq!(RK043FN48H.refresh_rate_mhz(), 59_304);
+    }
+
+    #[test]
+    fn otm8009a_ltdc_timing() {
+        let timing = OTM8009A.ltdc_timing().unwrap();
+        assert_eq!(timing,
+                   LtdcTiming {
+                       hsw: 2 - 1,
+                       vsh: 1 - 1,
+                       ahbp: 2 + 34 - 1,
+                       avbp: 1 + 15 - 1,
+                       aaw: 800 + 2 + 34 - 1,
+                       aah: 480 + 1 + 15 - 1,
+                       totalw: 800 + 2 + 34 + 34 - 1,
+                       totalh: 480 + 1 + 15 + 16 - 1,
+                       hspol: true,
+                       vspol: true,
+                       depol: false,
+                       pcpol: false,
+                   });
+        assert_eq!(timing.horizontal_window(), (36, 835));
+        assert_eq!(timing.vertical_window(), (16, 495));
+        assert_eq!(OTM8009A.refresh_rate_mhz(), 61_577);
+    }
+
+    #[test]
+    fn polarities() {
+        let panel = PanelTiming {
+            hsync_polarity: Polarity::ActiveHigh,
+            data_enable_polarity: Polarity::ActiveHigh,
+            pixel_clock_inverted: true,
+            ..RK043FN48H
+        };
+        let timing = panel.ltdc_timing().unwrap();
+        assert_eq!((timing.hspol, timing.vspol, timing.depol, timing.pcpol),
+                   (true, false, true, true));
+    }
+
+    #[test]
+    fn rk043fn48h_pll_sai_config() {
+        // 192 / 5 / 4, the settings that were hard-coded before
+        let config = RK043FN48H.pll_sai_config(PLL_INPUT_HZ).unwrap();
+        assert_eq!(config,
+                   PllSaiConfig {
+                       n: 192,
+                       r: 5,
+                       divr_bits: 0b01,
+                   });
+        assert_eq!(config.pixel_clock(PLL_INPUT_HZ), 9_600_000);
+    }
+
+    #[test]
+    fn otm8009a_pll_sai_config() {
+        // 384 / 7 / 2, the settings of the ST board support package
+        let config = OTM8009A.pll_sai_config(PLL_INPUT_HZ).unwrap();
+        assert_eq!(config,
+                   PllSaiConfig {
+                       n: 384,
+                       r: 7,
+                       divr_bits: 0b00,
+                   });
+        assert_eq!(config.pixel_clock(PLL_INPUT_HZ), 27_428_571);
+    }
+
+    #[test]
+    fn pll_sai_config_within_one_percent() {
+        for &input_hz in &[PLL_INPUT_HZ, 2_000_000] {
+            for pixel_clock_hz in (1..80).map(|mhz| mhz * 1_000_000 + 123_456) {
+                let panel = PanelTiming { pixel_clock_hz: pixel_clock_hz, ..RK043FN48H };
+                let config = panel.pll_sai_config(input_hz).unwrap();
+                let vco = input_hz * u32::from(config.n);
+                assert!(vco >= VCO_OUTPUT_HZ.0 && vco <= VCO_OUTPUT_HZ.1);
+                assert!(config.n >= PLL_SAI_N.0 && config.n <= PLL_SAI_N.1);
+                assert!(config.r >= PLL_SAI_R.0 && config.r <= PLL_SAI_R.1);
+                let error = (i64::from(config.pixel_clock(input_hz)) -
+                             i64::from(pixel_clock_hz))
+                    .abs();
+                assert!(error * 100 <= i64::from(pixel_clock_hz),
+                        "{} Hz: {:?}",
+                        pixel_clock_hz,
+                        config);
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_pixel_clock() {
+        // the slowest clock is 100 MHz / 7 / 16 and the fastest 432 MHz / 2 / 2
+        for &pixel_clock_hz in &[500_000, 120_000_000] {
+            let panel = PanelTiming { pixel_clock_hz: pixel_clock_hz, ..RK043FN48H };
+            assert_eq!(panel.pll_sai_config(PLL_INPUT_HZ),
+                       Err(TimingError::UnreachablePixelClock));
+        }
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for &(width, height) in &[(0, 272), (480, 0), (MAX_WIDTH + 1, 272), (480, MAX_HEIGHT + 1)] {
+            let panel = PanelTiming {
+                width: width,
+                height: height,
+                ..RK043FN48H
+            };
+            assert_eq!(panel.ltdc_timing(), Err(TimingError::InvalidSize));
+        }
+        let largest = PanelTiming {
+            width: MAX_WIDTH,
+            height: MAX_HEIGHT,
+            ..RK043FN48H
+        };
+        assert!(largest.ltdc_timing().is_ok());
+    }
+
+    #[test]
+    fn invalid_sync() {
+        let no_hsync = PanelTiming { hsync_width: 0, ..RK043FN48H };
+        let no_vsync = PanelTiming { vsync_height: 0, ..RK043FN48H };
+        // the registers hold the totals minus one, so 0x1000 and 0x800 are the largest totals
+        let too_wide = PanelTiming {
+            horizontal_front_porch: 0x1000 - 480 - 41 - 13 + 1,
+            ..RK043FN48H
+        };
+        let too_high = PanelTiming {
+            vertical_front_porch: 0x800 - 272 - 10 - 2 + 1,
+            ..RK043FN48H
+        };
+        for panel in &[no_hsync, no_vsync, too_wide, too_high] {
+            assert_eq!(panel.ltdc_timing(), Err(TimingError::InvalidSync));
+        }
+        let widest = PanelTiming {
+            horizontal_front_porch: 0x1000 - 480 - 41 - 13,
+            ..RK043FN48H
+        };
+        assert_eq!(widest.ltdc_timing().unwrap().totalw, 0xfff);
+    }
+}
@@ -40,6 +40,12 @@ pub mod touch;
 pub mod ethernet;
 pub mod heap;
 pub mod random;
+pub mod math;
+pub mod flash;
+pub mod graphics;
+pub mod gui;
+pub mod image;
+pub mod raster;
 
 #[cfg(not(test))]
 #[lang = "panic_fmt"]
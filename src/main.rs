@@ -16,7 +16,8 @@ extern crate alloc;
 extern crate collections;
 
 // hardware register structs with accessor methods
-use stm32f7::{system_clock, sdram, lcd, i2c, audio, touch, board, ethernet, embedded};
+use stm32f7::{system_clock, sdram, lcd, i2c, audio, touch, board, ethernet, embedded, graphics,
+              gui};
 
 
 #[no_mangle]
@@ -82,7 +83,10 @@ fn main(hw: board::Hardware) -> ! {
         gpio_k,
         i2c_3,
         sai_2,
+        dma_2,
         syscfg,
+        exti,
+        tim_7,
         ethernet_mac,
         ethernet_dma,
         ..
@@ -138,6 +142,7 @@ fn main(hw: board::Hardware) -> ! {
 
     // lcd controller
     let mut lcd = lcd::init(ltdc, rcc, &mut gpio);
+    lcd.enable_backlight_pwm(tim_7, rcc);
 
     // i2c
     i2c::init_pins_and_clocks(rcc, &mut gpio);
@@ -147,8 +152,21 @@ fn main(hw: board::Hardware) -> ! {
 
     // sai and stereo microphone
     audio::init_sai_2_pins(&mut gpio);
-    audio::init_sai_2(sai_2, rcc);
-    assert!(audio::init_wm8994(&mut i2c_3).is_ok());
+    let audio_config = audio::AudioConfig::default();
+    audio::init_sai_2(sai_2, rcc, &audio_config).expect("invalid audio config");
+    let _wm8994 = audio::Wm8994::init(&mut i2c_3, &audio_config)
+        .expect("wm8994 init failed");
+    audio::init_dma(dma_2, rcc);
+    let mut audio_input = audio::input::init(sai_2);
+    let mut audio_output = audio::output::init(sai_2);
+    let mut audio_buffer = [0; 2 * 32];
+    let mut audio_samples = [0; 2 * 32];
+    let mut spectrum = audio::SpectrumAnalyzer::new(audio::SpectrumMode::Bars,
+                                                    lcd::Layer::Layer1);
+    let mut audio_analyzer = audio::Analyzer::new(audio::AnalyzerConfig {
+                                                      sample_rate: audio_config.sample_rate.hz(),
+                                                      ..Default::default()
+                                                  });
 
     // ethernet
     let mut eth_device = ethernet::EthernetDevice::new(Default::default(),
@@ -164,14 +182,59 @@ fn main(hw: board::Hardware) -> ! {
 
     lcd.clear_screen();
 
-    touch::check_family_id(&mut i2c_3).unwrap();
+    let touch_config = touch::Ft5336Config {
+        interrupt_mode: touch::InterruptMode::Trigger,
+        ..Default::default()
+    };
+    let mut ft5336 = touch::Ft5336::init(&mut i2c_3, &touch_config).expect("ft5336 init failed");
+    ft5336.enable_interrupt(rcc, syscfg, exti, &mut gpio)
+        .expect("touch interrupt pin already in use");
+    let mut touch_tracker = touch::TouchTracker::new(Default::default());
+    // the demo only dims the backlight and never turns the display off
+    let idle_config = lcd::IdleConfig { off_timeout: None, ..Default::default() };
+    let mut idle_dimmer = lcd::IdleDimmer::new(idle_config, system_clock::ticks());
+
+    // hold the button during startup to recalibrate the touch screen
+    let calibration = if button.get() {
+        let calibration = touch::calibrate(&mut lcd, &mut i2c_3, touch::CalibrationPoints::Five)
+            .expect("touch calibration failed");
+        if let Err(e) = calibration.save(flash) {
+            println!("saving the touch calibration failed: {:?}", e);
+        }
+        calibration
+    } else {
+        touch::Calibration::load().unwrap_or_else(touch::Calibration::identity)
+    };
+    touch_tracker.set_transform(touch::TouchTransform {
+                                    calibration: calibration,
+                                    rotation: lcd.rotation(),
+                                    panel_size: lcd.panel_size(),
+                                });
+
+    // toolbar on top of layer 2
+    let mut ui = gui::Ui::new(graphics::Rect::new(0, 0, lcd.width(), 40), Default::default());
+    let root = ui.root();
+    let toolbar = ui.add(root,
+                         gui::Widget::Container(gui::Container {
+                                                    padding: 0,
+                                                    ..gui::Container::row()
+                                                }));
+    let spectrogram_toggle = ui.add(toolbar,
+                                    gui::Widget::Toggle(gui::Toggle::new("Spectrogram", false)));
+    let clear_button = ui.add(toolbar, gui::Widget::Button(gui::Button::new("Clear")));
+    // plays the microphone input on the headphones while on
+    let loopback_toggle = ui.add(toolbar, gui::Widget::Toggle(gui::Toggle::new("Loopback", false)));
 
     let mut last_led_toggle = system_clock::ticks();
     let mut last_color_change = system_clock::ticks();
     let mut button_pressed_old = false;
+    let mut loopback = false;
     loop {
         let ticks = system_clock::ticks();
 
+        // count LTDC errors and reload the layers after them
+        lcd.handle_interrupts();
+
         // every 0.5 seconds
         if ticks - last_led_toggle >= 500 {
             // toggle the led
@@ -183,23 +246,93 @@ fn main(hw: board::Hardware) -> ! {
         let button_pressed = button.get();
         if (button_pressed && !button_pressed_old) || ticks - last_color_change >= 1000 {
             // choose a new background color
-            let new_color = ((system_clock::ticks() as u32).wrapping_mul(19801)) % 0x1000000;
-            lcd.set_background_color(lcd::Color::from_hex(new_color));
+            let hue = (system_clock::ticks() as u32).wrapping_mul(19801) % 360;
+            lcd.set_background_color(lcd::Color::from_hsv(hue as f32, 1.0, 1.0));
             last_color_change = ticks;
         }
 
-        // poll for new audio data
-        while !sai_2.bsr.read().freq() {} // fifo_request_flag
-        let data0 = sai_2.bdr.read().data();
-        while !sai_2.bsr.read().freq() {} // fifo_request_flag
-        let data1 = sai_2.bdr.read().data();
-
-        lcd.set_next_col(data0, data1);
+        // draw new audio data
+        let samples = audio_input.read(&mut audio_buffer);
+        if loopback {
+            audio_output.write(&audio_buffer[..samples]);
+        }
+        // the spectrum and the analyzer work on the upper 16 bits
+        for (sample, &word) in audio_samples.iter_mut().zip(&audio_buffer[..samples]) {
+            *sample = (word >> 16) as i16;
+        }
+        spectrum.push_samples(&audio_samples[..samples]);
+        audio_analyzer.process(&audio_samples[..samples]);
+        while let Some(event) = audio_analyzer.next_event() {
+            // a clap switches between bar graph and spectrogram
+            if let audio::analysis::Event::Onset { .. } = event {
+                let mode = match spectrum.mode() {
+                    audio::SpectrumMode::Bars => audio::SpectrumMode::Spectrogram,
+                    audio::SpectrumMode::Spectrogram => audio::SpectrumMode::Bars,
+                };
+                spectrum.set_mode(mode);
+                lcd.clear_screen();
+                if let gui::Widget::Toggle(ref mut toggle) = *ui.widget_mut(spectrogram_toggle) {
+                    toggle.on = mode == audio::SpectrumMode::Spectrogram;
+                }
+                ui.invalidate_all();
+            }
+        }
+        spectrum.draw(&mut lcd);
+
+        // read new touch data
+        let touched = ft5336.interrupt_pending();
+        if touched {
+            match touch_tracker.poll(&mut i2c_3) {
+                Ok(()) => {}
+                Err(i2c::Error::Timeout) |
+                Err(i2c::Error::BusError) => {
+                    // a stuck bus would stop all touch input
+                    i2c_3.recover(&mut gpio, &i2c::I2C3_PINS).expect("i2c bus stuck");
+                }
+                Err(err) => panic!("touch poll failed: {:?}", err),
+            }
+        }
+        // a touch on the dark display only turns it on
+        let display_was_on = lcd.is_display_on();
+        idle_dimmer.poll(&mut lcd, rcc, touched);
+        while let Some(event) = touch_tracker.next_event() {
+            if !display_was_on {
+                continue;
+            }
+            ui.handle_touch(&event);
+            match event {
+                touch::Event::Down { x, y, .. } |
+                touch::Event::Move { x, y, .. } => {
+                    if !ui.bounds().contains(x, y) {
+                        lcd.print_point_at(x, y);
+                    }
+                }
+                touch::Event::Up { .. } |
+                touch::Event::Gesture(_) => {}
+            }
+        }
 
-        // poll for new touch data
-        for touch in &touch::touches(&mut i2c_3).unwrap() {
-            lcd.print_point_at(touch.x, touch.y);
+        // handle toolbar input
+        while let Some(event) = ui.next_event() {
+            match event {
+                gui::Event::Toggled(id, on) if id == spectrogram_toggle => {
+                    spectrum.set_mode(if on {
+                                          audio::SpectrumMode::Spectrogram
+                                      } else {
+                                          audio::SpectrumMode::Bars
+                                      });
+                    lcd.clear_screen();
+                    ui.invalidate_all();
+                }
+                gui::Event::Toggled(id, on) if id == loopback_toggle => loopback = on,
+                gui::Event::Clicked(id) if id == clear_button => {
+                    lcd.clear_screen();
+                    ui.invalidate_all();
+                }
+                _ => {}
+            }
         }
+        ui.render(&mut graphics::LayerCanvas::new(&mut lcd, lcd::Layer::Layer2));
 
         // handle new ethernet packets
         if let Ok(ref mut eth_device) = eth_device {
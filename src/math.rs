@@ -0,0 +1,300 @@
+//! Floating point functions that `core` does not provide without `std`.
+//!
+//! The approximations are accurate to about 1e-5, which is enough for signal processing and
+//! graphics. The error is absolute for the angle functions and relative for the others.
+
+use core::f32::consts::{PI, FRAC_PI_2, LN_2, LN_10};
+use core::mem;
+
+fn to_bits(x: f32) -> u32 {
+    unsafe { mem::transmute(x) }
+}
+
+fn from_bits(x: u32) -> f32 {
+    unsafe { mem::transmute(x) }
+}
+
+/// All floats from this magnitude on are integers.
+const INTEGER_THRESHOLD: f32 = 8_388_608.0;
+
+pub fn abs(x: f32) -> f32 {
+    if x < 0.0 { -x } else { x }
+}
+
+/// The larger value, `b` if either is NaN.
+pub fn max(a: f32, b: f32) -> f32 {
+    if a > b { a } else { b }
+}
+
+/// The smaller value, `b` if either is NaN.
+pub fn min(a: f32, b: f32) -> f32 {
+    if a < b { a } else { b }
+}
+
+/// Converts to an integer, limited to `-limit..=limit`. NaN gives `-limit`. Casting a value
+/// that doesn't fit directly is undefined behavior.
+pub fn to_i32(x: f32, limit: i32) -> i32 {
+    let bound = limit as f32;
+    if x > -bound && x < bound {
+        x as i32
+    } else if x >= bound {
+        limit
+    } else {
+        -limit
+    }
+}
+
+/// Whether `x` is neither infinite nor NaN.
+pub fn is_finite(x: f32) -> bool {
+    x - x == 0.0
+}
+
+/// Rounds to the nearest integer, away from zero on ties. Large values, infinities and NaN
+/// are returned unchanged.
+pub fn round(x: f32) -> f32 {
+    // the casts are undefined for values that don't fit
+    if !(abs(x) < INTEGER_THRESHOLD) {
+        x
+    } else if x < 0.0 {
+        -((-x + 0.5) as i64 as f32)
+    } else {
+        (x + 0.5) as i64 as f32
+    }
+}
+
+/// Large values, infinities and NaN are returned unchanged.
+pub fn floor(x: f32) -> f32 {
+    if !(abs(x) < INTEGER_THRESHOLD) {
+        return x;
+    }
+    let truncated = x as i64 as f32;
+    if truncated > x {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+pub fn sin(x: f32) -> f32 {
+    // reduce to [-pi, pi]
+    let x = x - 2.0 * PI * round(x / (2.0 * PI));
+    // reduce to [-pi/2, pi/2]
+    let x = if x > FRAC_PI_2 {
+        PI - x
+    } else if x < -FRAC_PI_2 {
+        -PI - x
+    } else {
+        x
+    };
+
+    // taylor series up to x^11
+    let x2 = x * x;
+    x *
+    (1.0 -
+     x2 / 6.0 *
+     (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0 * (1.0 - x2 / 110.0)))))
+}
+
+pub fn cos(x: f32) -> f32 {
+    sin(x + FRAC_PI_2)
+}
+
+pub fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // initial guess by halving the exponent, then newton iterations
+    let mut y = from_bits((to_bits(x) >> 1) + 0x1fbd_1df5);
+    for _ in 0..3 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/// Natural logarithm. Returns negative infinity for zero and negative values.
+pub fn ln(x: f32) -> f32 {
+    if x <= 0.0 {
+        return ::core::f32::NEG_INFINITY;
+    }
+    // x = m * 2^e with m in [1, 2)
+    let bits = to_bits(x);
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+
+    // ln(m) = 2 * atanh((m - 1) / (m + 1))
+    let t = (mantissa - 1.0) / (mantissa + 1.0);
+    let t2 = t * t;
+    let ln_mantissa = 2.0 * t *
+                      (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 * (1.0 / 7.0 + t2 / 9.0))));
+
+    ln_mantissa + exponent as f32 * LN_2
+}
+
+pub fn log10(x: f32) -> f32 {
+    ln(x) / LN_10
+}
+
+pub fn exp(x: f32) -> f32 {
+    // e^x = 2^k * e^r with |r| <= ln(2)/2
+    let k = round(x / LN_2);
+    let r = x - k * LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for i in 1..10 {
+        term *= r / i as f32;
+        sum += term;
+    }
+    let k = k as i32;
+    if k < -126 {
+        0.0
+    } else if k > 127 {
+        ::core::f32::INFINITY
+    } else {
+        sum * from_bits(((k + 127) as u32) << 23)
+    }
+}
+
+pub fn powf(base: f32, exponent: f32) -> f32 {
+    if base <= 0.0 {
+        return 0.0;
+    }
+    exp(exponent * ln(base))
+}
+
+pub fn atan2(y: f32, x: f32) -> f32 {
+    if x == 0.0 && y == 0.0 {
+        return 0.0;
+    }
+    let (ax, ay) = (abs(x), abs(y));
+    // atan of a value in [0, 1]
+    let (t, swapped) = if ay > ax { (ax / ay, true) } else { (ay / ax, false) };
+    let t2 = t * t;
+    // minimax approximation, max error about 1e-5
+    let mut angle = t *
+                    (0.999_866 +
+                     t2 * (-0.330_299_5 + t2 * (0.180_141 + t2 * (-0.085_133 + t2 * 0.020_835))));
+    if swapped {
+        angle = FRAC_PI_2 - angle;
+    }
+    if x < 0.0 {
+        angle = PI - angle;
+    }
+    if y < 0.0 { -angle } else { angle }
+}
+
+pub fn asin(x: f32) -> f32 {
+    let x = if x > 1.0 {
+        1.0
+    } else if x < -1.0 {
+        -1.0
+    } else {
+        x
+    };
+    atan2(x, sqrt(1.0 - x * x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 1e-5;
+    /// The error bound of the atan approximation plus rounding.
+    const ANGLE_TOLERANCE: f32 = 1.2e-5;
+
+    /// `count + 1` evenly spaced values from `start` to `end`.
+    fn range(start: f32, end: f32, count: usize) -> ::collections::Vec<f32> {
+        (0..count + 1).map(|i| start + (end - start) * i as f32 / count as f32).collect()
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32, what: &str, x: f32) {
+        assert!(abs(actual - expected) <= tolerance,
+                "{}({}) = {}, expected {}",
+                what,
+                x,
+                actual,
+                expected);
+    }
+
+    #[test]
+    fn sin_cos() {
+        for x in range(-20.0, 20.0, 4000) {
+            assert_close(sin(x), (x as f64).sin() as f32, TOLERANCE, "sin", x);
+            assert_close(cos(x), (x as f64).cos() as f32, TOLERANCE, "cos", x);
+        }
+    }
+
+    #[test]
+    fn sqrt_relative() {
+        for x in range(1e-6, 1e6, 10000) {
+            let expected = (x as f64).sqrt() as f32;
+            assert_close(sqrt(x), expected, TOLERANCE * expected, "sqrt", x);
+        }
+        assert_eq!(sqrt(0.0), 0.0);
+        assert_eq!(sqrt(-1.0), 0.0);
+    }
+
+    #[test]
+    fn ln_log10_exp_powf() {
+        for x in range(1e-3, 1e4, 10000) {
+            let expected = (x as f64).ln() as f32;
+            assert_close(ln(x), expected, TOLERANCE * abs(expected).max(1.0), "ln", x);
+            let expected = (x as f64).log10() as f32;
+            assert_close(log10(x), expected, TOLERANCE * abs(expected).max(1.0), "log10", x);
+        }
+        for x in range(-20.0, 20.0, 4000) {
+            let expected = (x as f64).exp() as f32;
+            assert_close(exp(x), expected, TOLERANCE * expected, "exp", x);
+        }
+        let expected = (2f64).powf(0.5) as f32;
+        assert_close(powf(2.0, 0.5), expected, TOLERANCE * expected, "powf", 2.0);
+        assert_eq!(ln(0.0), ::core::f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn atan2_asin() {
+        for angle in range(-3.1, 3.1, 1000) {
+            let (y, x) = ((angle as f64).sin() as f32, (angle as f64).cos() as f32);
+            assert_close(atan2(y, x), angle, ANGLE_TOLERANCE, "atan2", angle);
+            assert_close(atan2(3.0 * y, 3.0 * x), angle, ANGLE_TOLERANCE, "atan2", angle);
+        }
+        for x in range(-1.0, 1.0, 1000) {
+            assert_close(asin(x), (x as f64).asin() as f32, ANGLE_TOLERANCE, "asin", x);
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round(2.5), 3.0);
+        assert_eq!(round(-2.5), -3.0);
+        assert_eq!(round(-2.4), -2.0);
+        assert_eq!(floor(-0.5), -1.0);
+        assert_eq!(floor(1.5), 1.0);
+        assert_eq!(abs(-3.0), 3.0);
+        assert_eq!(floor(1e30), 1e30);
+        assert_eq!(round(-1e30), -1e30);
+        assert_eq!(floor(::core::f32::INFINITY), ::core::f32::INFINITY);
+        assert!(round(::core::f32::NAN).is_nan());
+        assert_eq!(floor(-8_388_607.5), -8_388_608.0);
+    }
+
+    #[test]
+    fn min_max_finite() {
+        assert_eq!(max(1.0, 2.0), 2.0);
+        assert_eq!(min(1.0, 2.0), 1.0);
+        assert_eq!(max(::core::f32::NAN, 2.0), 2.0);
+        assert_eq!(min(::core::f32::NAN, 2.0), 2.0);
+        assert!(is_finite(-1e30));
+        assert!(!is_finite(::core::f32::NEG_INFINITY));
+        assert!(!is_finite(::core::f32::NAN));
+    }
+
+    #[test]
+    fn to_i32_limits() {
+        assert_eq!(to_i32(-2.7, 10), -2);
+        assert_eq!(to_i32(9.9, 10), 9);
+        assert_eq!(to_i32(10.0, 10), 10);
+        assert_eq!(to_i32(1e30, 1_000_000_000), 1_000_000_000);
+        assert_eq!(to_i32(::core::f32::NEG_INFINITY, 5), -5);
+        assert_eq!(to_i32(::core::f32::NAN, 5), -5);
+    }
+}
@@ -0,0 +1,191 @@
+//! Circles, ellipses, arcs and rounded rectangles with the midpoint algorithm.
+
+use core::cmp;
+use core::f32::consts::PI;
+use graphics::{Canvas, Rect};
+use lcd::Color;
+use math;
+use super::Rasterizer;
+
+/// Larger radii would overflow the midpoint algorithm, such shapes are not drawn.
+pub const MAX_RADIUS: i32 = 16_383;
+
+impl<'a, C: Canvas> Rasterizer<'a, C> {
+    pub fn circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: Color) {
+        self.ellipse(center_x, center_y, radius, radius, color);
+    }
+
+    pub fn fill_circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: Color) {
+        self.fill_ellipse(center_x, center_y, radius, radius, color);
+    }
+
+    /// Draws the outline of an axis-aligned ellipse with the radii `radius_x` and `radius_y`.
+    pub fn ellipse(&mut self,
+                   center_x: i32,
+                   center_y: i32,
+                   radius_x: i32,
+                   radius_y: i32,
+                   color: Color) {
+        if !self.is_box_visible(center_x, center_y, radius_x, radius_y) {
+            return;
+        }
+        quadrant_points(radius_x, radius_y, |x, y| {
+            self.pixel(center_x + x, center_y + y, color);
+            self.pixel(center_x - x, center_y + y, color);
+            self.pixel(center_x + x, center_y - y, color);
+            self.pixel(center_x - x, center_y - y, color);
+        });
+    }
+
+    pub fn fill_ellipse(&mut self,
+                        center_x: i32,
+                        center_y: i32,
+                        radius_x: i32,
+                        radius_y: i32,
+                        color: Color) {
+        if !self.is_box_visible(center_x, center_y, radius_x, radius_y) {
+            return;
+        }
+        quadrant_points(radius_x, radius_y, |x, y| {
+            self.span(center_y + y, center_x - x, center_x + x, color);
+            self.span(center_y - y, center_x - x, center_x + x, color);
+        });
+    }
+
+    /// Draws the part of a circle from `start_angle` to `end_angle`, in degrees clockwise from
+    /// the positive x axis. Angles of 360 degrees or more apart draw the whole circle.
+    pub fn arc(&mut self,
+               center_x: i32,
+               center_y: i32,
+               radius: i32,
+               start_angle: f32,
+               end_angle: f32,
+               color: Color) {
+        if !self.is_box_visible(center_x, center_y, radius, radius) {
+            return;
+        }
+        let sweep = end_angle - start_angle;
+        let start = normalize_angle(start_angle);
+        let sweep = if sweep >= 360.0 {
+            360.0
+        } else {
+            normalize_angle(sweep)
+        };
+        let mut plot = |x: i32, y: i32| {
+            let angle = math::atan2(y as f32, x as f32) * 180.0 / PI;
+            if normalize_angle(angle - start) <= sweep {
+                self.pixel(center_x + x, center_y + y, color);
+            }
+        };
+        quadrant_points(radius, radius, |x, y| {
+            plot(x, y);
+            plot(-x, y);
+            plot(x, -y);
+            plot(-x, -y);
+        });
+    }
+
+    /// Draws the outline of a rectangle with corners of the given radius. The radius is
+    /// limited to half of the shorter side.
+    pub fn rounded_rect(&mut self, rect: Rect, radius: u16, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+        let (left, top, right, bottom, radius) = corner_geometry(rect, radius);
+        self.span(top, left + radius, right - radius, color);
+        self.span(bottom, left + radius, right - radius, color);
+        self.fill_box(left, top + radius, left, bottom - radius, color);
+        self.fill_box(right, top + radius, right, bottom - radius, color);
+        quadrant_points(radius, radius, |x, y| {
+            self.pixel(right - radius + x, bottom - radius + y, color);
+            self.pixel(left + radius - x, bottom - radius + y, color);
+            self.pixel(right - radius + x, top + radius - y, color);
+            self.pixel(left + radius - x, top + radius - y, color);
+        });
+    }
+
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radius: u16, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+        let (left, top, right, bottom, radius) = corner_geometry(rect, radius);
+        self.fill_box(left, top + radius + 1, right, bottom - radius - 1, color);
+        quadrant_points(radius, radius, |x, y| {
+            self.span(top + radius - y, left + radius - x, right - radius + x, color);
+            self.span(bottom - radius + y, left + radius - x, right - radius + x, color);
+        });
+    }
+
+    fn is_box_visible(&self, center_x: i32, center_y: i32, radius_x: i32, radius_y: i32) -> bool {
+        let (left, top, right, bottom) = self.bounds();
+        radius_x >= 0 && radius_y >= 0 && radius_x <= MAX_RADIUS && radius_y <= MAX_RADIUS &&
+        center_x.saturating_add(radius_x) >= left &&
+        center_x.saturating_sub(radius_x) < right &&
+        center_y.saturating_add(radius_y) >= top &&
+        center_y.saturating_sub(radius_y) < bottom
+    }
+}
+
+/// Left, top, right and bottom (all inclusive) and the limited radius.
+fn corner_geometry(rect: Rect, radius: u16) -> (i32, i32, i32, i32, i32) {
+    let max_radius = cmp::min((i32::from(cmp::min(rect.width, rect.height)) - 1) / 2,
+                              MAX_RADIUS);
+    (i32::from(rect.x),
+     i32::from(rect.y),
+     i32::from(rect.right()) - 1,
+     i32::from(rect.bottom()) - 1,
+     cmp::min(i32::from(radius), max_radius))
+}
+
+/// Maps an angle in degrees to 0 to 360.
+fn normalize_angle(angle: f32) -> f32 {
+    angle - 360.0 * math::floor(angle / 360.0)
+}
+
+/// Calls `point(x, y)` for the outline of the ellipse quadrant with non-negative x and y,
+/// from (0, `radius_y`) to (`radius_x`, 0). Some points are reported more than once.
+fn quadrant_points<F>(radius_x: i32, radius_y: i32, mut point: F)
+    where F: FnMut(i32, i32)
+{
+    if radius_y == 0 {
+        for x in 0..radius_x + 1 {
+            point(x, 0);
+        }
+        return;
+    }
+    // midpoint algorithm, the decision variables are scaled by 4 to stay integers
+    let (a2, b2) = (i64::from(radius_x) * i64::from(radius_x),
+                    i64::from(radius_y) * i64::from(radius_y));
+    let (mut x, mut y) = (0i64, i64::from(radius_y));
+    let (mut dx, mut dy) = (0, 2 * a2 * y);
+
+    // the slope is flatter than -1
+    let mut decision = 4 * b2 - 4 * a2 * y + a2;
+    while dx < dy {
+        point(x as i32, y as i32);
+        x += 1;
+        dx += 2 * b2;
+        if decision < 0 {
+            decision += 4 * (dx + b2);
+        } else {
+            y -= 1;
+            dy -= 2 * a2;
+            decision += 4 * (dx - dy + b2);
+        }
+    }
+
+    // the slope is steeper than -1
+    let mut decision = b2 * (2 * x + 1) * (2 * x + 1) + 4 * a2 * (y - 1) * (y - 1) - 4 * a2 * b2;
+    while y >= 0 {
+        point(x as i32, y as i32);
+        y -= 1;
+        dy -= 2 * a2;
+        if decision > 0 {
+            decision += 4 * (a2 - dy);
+        } else {
+            x += 1;
+            dx += 2 * b2;
+            decision += 4 * (dx - dy + a2);
+        }
+    }
+}
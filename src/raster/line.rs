@@ -0,0 +1,184 @@
+//! Bresenham and antialiased (Xiaolin Wu) lines.
+
+use graphics::Canvas;
+use lcd::Color;
+use math;
+use super::{to_i32, Point, Rasterizer};
+
+const INSIDE: u8 = 0;
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const TOP: u8 = 4;
+const BOTTOM: u8 = 8;
+
+impl<'a, C: Canvas> Rasterizer<'a, C> {
+    /// Draws a line from (`x0`, `y0`) to (`x1`, `y1`), including both end points.
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let (left, top, right, bottom) = self.bounds();
+        // clip first, so that far away end points don't cost time
+        let min = Point::new((left - 1) as f32, (top - 1) as f32);
+        let max = Point::new(right as f32, bottom as f32);
+        let (from, to) = match clip_line(Point::new(x0 as f32, y0 as f32),
+                                         Point::new(x1 as f32, y1 as f32),
+                                         min,
+                                         max) {
+            Some(line) => line,
+            None => return,
+        };
+        let (mut x, mut y) = (to_i32(math::round(from.x)), to_i32(math::round(from.y)));
+        let (x1, y1) = (to_i32(math::round(to.x)), to_i32(math::round(to.y)));
+
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        loop {
+            self.pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws lines between consecutive points, each point in the pixel that contains it.
+    pub fn polyline(&mut self, points: &[Point], color: Color) {
+        for pair in points.windows(2) {
+            self.line(to_i32(math::floor(pair[0].x)),
+                      to_i32(math::floor(pair[0].y)),
+                      to_i32(math::floor(pair[1].x)),
+                      to_i32(math::floor(pair[1].y)),
+                      color);
+        }
+    }
+
+    /// Draws an antialiased line. The pixels are blended with the canvas, so the canvas should
+    /// support `Canvas::pixel`.
+    pub fn line_aa(&mut self, from: Point, to: Point, color: Color) {
+        let (left, top, right, bottom) = self.bounds();
+        // in these coordinates the pixel centers are at integers
+        let from = Point::new(from.x - 0.5, from.y - 0.5);
+        let to = Point::new(to.x - 0.5, to.y - 0.5);
+        let min = Point::new((left - 2) as f32, (top - 2) as f32);
+        let max = Point::new((right + 1) as f32, (bottom + 1) as f32);
+        let (from, to) = match clip_line(from, to, min, max) {
+            Some(line) => line,
+            None => return,
+        };
+
+        // iterate along the major axis
+        let steep = math::abs(to.y - from.y) > math::abs(to.x - from.x);
+        let (from, to) = if steep {
+            (Point::new(from.y, from.x), Point::new(to.y, to.x))
+        } else {
+            (from, to)
+        };
+        let (from, to) = if from.x > to.x { (to, from) } else { (from, to) };
+        let dx = to.x - from.x;
+        let gradient = if dx == 0.0 {
+            1.0
+        } else {
+            (to.y - from.y) / dx
+        };
+
+        let mut plot = |x: i32, y: i32, coverage: f32| if steep {
+            self.blend(y, x, color, coverage)
+        } else {
+            self.blend(x, y, color, coverage)
+        };
+
+        // the end points cover their pixels partially along the major axis
+        let x_start = math::round(from.x);
+        let y_start = from.y + gradient * (x_start - from.x);
+        let gap = 1.0 - fraction(from.x + 0.5);
+        let (first_x, first_y) = (to_i32(x_start), to_i32(math::floor(y_start)));
+        plot(first_x, first_y, (1.0 - fraction(y_start)) * gap);
+        plot(first_x, first_y + 1, fraction(y_start) * gap);
+
+        let x_end = math::round(to.x);
+        let y_end = to.y + gradient * (x_end - to.x);
+        let gap = fraction(to.x + 0.5);
+        let (last_x, last_y) = (to_i32(x_end), to_i32(math::floor(y_end)));
+        plot(last_x, last_y, (1.0 - fraction(y_end)) * gap);
+        plot(last_x, last_y + 1, fraction(y_end) * gap);
+
+        let mut y = y_start + gradient;
+        for x in first_x + 1..last_x {
+            let pixel_y = to_i32(math::floor(y));
+            plot(x, pixel_y, 1.0 - fraction(y));
+            plot(x, pixel_y + 1, fraction(y));
+            y += gradient;
+        }
+    }
+
+    /// Draws antialiased lines between consecutive points.
+    pub fn polyline_aa(&mut self, points: &[Point], color: Color) {
+        for pair in points.windows(2) {
+            self.line_aa(pair[0], pair[1], color);
+        }
+    }
+}
+
+fn fraction(value: f32) -> f32 {
+    value - math::floor(value)
+}
+
+fn outcode(point: Point, min: Point, max: Point) -> u8 {
+    let mut code = INSIDE;
+    if point.x < min.x {
+        code |= LEFT;
+    } else if point.x > max.x {
+        code |= RIGHT;
+    }
+    if point.y < min.y {
+        code |= TOP;
+    } else if point.y > max.y {
+        code |= BOTTOM;
+    }
+    code
+}
+
+/// Clips a line to the rectangle from `min` to `max` with the Cohen-Sutherland algorithm.
+/// Returns `None` if the line is completely outside.
+fn clip_line(mut from: Point, mut to: Point, min: Point, max: Point) -> Option<(Point, Point)> {
+    let mut from_code = outcode(from, min, max);
+    let mut to_code = outcode(to, min, max);
+    loop {
+        if from_code | to_code == INSIDE {
+            return Some((from, to));
+        }
+        if from_code & to_code != INSIDE {
+            return None;
+        }
+        let code = if from_code != INSIDE {
+            from_code
+        } else {
+            to_code
+        };
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let point = if code & TOP != 0 {
+            Point::new(from.x + dx * (min.y - from.y) / dy, min.y)
+        } else if code & BOTTOM != 0 {
+            Point::new(from.x + dx * (max.y - from.y) / dy, max.y)
+        } else if code & LEFT != 0 {
+            Point::new(min.x, from.y + dy * (min.x - from.x) / dx)
+        } else {
+            Point::new(max.x, from.y + dy * (max.x - from.x) / dx)
+        };
+        if code == from_code {
+            from = point;
+            from_code = outcode(from, min, max);
+        } else {
+            to = point;
+            to_code = outcode(to, min, max);
+        }
+    }
+}
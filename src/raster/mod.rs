@@ -0,0 +1,141 @@
+//! Rasterization of lines, circles, ellipses, arcs, polygons and Bézier paths.
+//!
+//! `Rasterizer` draws to any `graphics::Canvas`, e.g. a `graphics::LayerCanvas` for a layer
+//! of the LCD or a `graphics::MemoryCanvas` on the host. All shapes are clipped to a rectangle,
+//! so coordinates may be negative or far outside of the canvas.
+//!
+//! Integer coordinates address pixels. Floating point coordinates, as used by the antialiased
+//! lines, polygons and paths, have the center of pixel (x, y) at (x + 0.5, y + 0.5).
+
+pub use self::path::Path;
+
+use core::cmp;
+use graphics::{Canvas, Rect};
+use lcd::Color;
+use math;
+
+mod ellipse;
+mod line;
+mod path;
+mod polygon;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub fn new(x: f32, y: f32) -> Point {
+        Point { x: x, y: y }
+    }
+}
+
+/// Decides which areas of a self-intersecting polygon are inside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillRule {
+    /// A point is inside if a ray from it crosses the outline an odd number of times.
+    EvenOdd,
+    /// A point is inside if the outline winds around it at least once.
+    NonZero,
+}
+
+impl FillRule {
+    fn is_inside(&self, winding: i32) -> bool {
+        match *self {
+            FillRule::EvenOdd => winding % 2 != 0,
+            FillRule::NonZero => winding != 0,
+        }
+    }
+}
+
+/// Draws shapes to a canvas, clipped to a rectangle.
+pub struct Rasterizer<'a, C: Canvas + 'a> {
+    canvas: &'a mut C,
+    clip: Rect,
+}
+
+impl<'a, C: Canvas> Rasterizer<'a, C> {
+    /// Only pixels inside `clip` and inside the canvas are drawn.
+    pub fn new(canvas: &'a mut C, clip: Rect) -> Rasterizer<'a, C> {
+        let (width, height) = canvas.size();
+        let clip = clip.intersection(&Rect::new(0, 0, width, height));
+        Rasterizer {
+            canvas: canvas,
+            clip: clip,
+        }
+    }
+
+    /// A rasterizer for the whole canvas.
+    pub fn full(canvas: &'a mut C) -> Rasterizer<'a, C> {
+        let (width, height) = canvas.size();
+        Rasterizer::new(canvas, Rect::new(0, 0, width, height))
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    pub fn pixel(&mut self, x: i32, y: i32, color: Color) {
+        if self.is_visible(x, y) {
+            self.canvas.set_pixel(x as u16, y as u16, color);
+        }
+    }
+
+    /// Blends a pixel that is covered by `coverage` (0 to 1) with the canvas.
+    fn blend(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if coverage <= 0.0 || !self.is_visible(x, y) {
+            return;
+        }
+        let alpha = if coverage >= 1.0 {
+            color.alpha
+        } else {
+            (f32::from(color.alpha) * coverage + 0.5) as u8
+        };
+        if alpha == 255 {
+            self.canvas.set_pixel(x as u16, y as u16, color);
+        } else {
+            self.canvas.blend_pixel(x as u16, y as u16, Color { alpha: alpha, ..color });
+        }
+    }
+
+    /// Fills the pixels from `x0` to `x1`, both inclusive, in row `y`. Nothing is drawn if
+    /// `x1 < x0`.
+    fn span(&mut self, y: i32, x0: i32, x1: i32, color: Color) {
+        self.fill_box(x0, y, x1, y, color);
+    }
+
+    /// Fills the pixels from (`x0`, `y0`) to (`x1`, `y1`), both inclusive.
+    fn fill_box(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let (left, top, right, bottom) = self.bounds();
+        let (x0, y0) = (cmp::max(x0, left), cmp::max(y0, top));
+        let (x1, y1) = (cmp::min(x1, right - 1), cmp::min(y1, bottom - 1));
+        if x0 <= x1 && y0 <= y1 {
+            let (width, height) = ((x1 - x0 + 1) as u16, (y1 - y0 + 1) as u16);
+            self.canvas.fill_rect(Rect::new(x0 as u16, y0 as u16, width, height), color);
+        }
+    }
+
+    fn is_visible(&self, x: i32, y: i32) -> bool {
+        let (left, top, right, bottom) = self.bounds();
+        x >= left && x < right && y >= top && y < bottom
+    }
+
+    /// Left, top, right and bottom of the clip rectangle, right and bottom are exclusive.
+    fn bounds(&self) -> (i32, i32, i32, i32) {
+        (i32::from(self.clip.x),
+         i32::from(self.clip.y),
+         i32::from(self.clip.right()),
+         i32::from(self.clip.bottom()))
+    }
+}
+
+/// Converts to an integer, limited to a range that is far larger than any canvas but small
+/// enough that the rasterizers can't overflow.
+fn to_i32(value: f32) -> i32 {
+    math::to_i32(value, 1_000_000)
+}
+
+fn ceil(value: f32) -> f32 {
+    -math::floor(-value)
+}
@@ -0,0 +1,171 @@
+//! Paths of lines and quadratic and cubic Bézier curves.
+
+use collections::Vec;
+use graphics::Canvas;
+use lcd::Color;
+use math;
+use super::{polygon, FillRule, Point, Rasterizer};
+
+/// Maximum distance of the flattened lines from the curve, in pixels.
+const TOLERANCE: f32 = 0.25;
+const MAX_CURVE_SEGMENTS: u32 = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Contour {
+    start: usize,
+    closed: bool,
+}
+
+/// A shape that consists of one or more contours.
+///
+/// Curves are flattened into line segments when they are added, so a path only stores points.
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    points: Vec<Point>,
+    contours: Vec<Contour>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Starts a new contour.
+    pub fn move_to(&mut self, point: Point) {
+        self.contours.push(Contour {
+                               start: self.points.len(),
+                               closed: false,
+                           });
+        self.points.push(point);
+    }
+
+    /// Adds a line from the current point. Without a current point, this starts a contour.
+    pub fn line_to(&mut self, point: Point) {
+        self.start_if_needed(point);
+        self.points.push(point);
+    }
+
+    /// Adds a quadratic Bézier curve from the current point.
+    pub fn quad_to(&mut self, control: Point, to: Point) {
+        let from = self.start_if_needed(control);
+        // the second differences of the control points bound the flattening error
+        let second_difference = length(from.x - 2.0 * control.x + to.x,
+                                       from.y - 2.0 * control.y + to.y);
+        let segments = segment_count(second_difference / 4.0);
+        for i in 1..segments + 1 {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            self.points.push(Point::new(u * u * from.x + 2.0 * u * t * control.x + t * t * to.x,
+                                        u * u * from.y + 2.0 * u * t * control.y + t * t * to.y));
+        }
+    }
+
+    /// Adds a cubic Bézier curve from the current point.
+    pub fn cubic_to(&mut self, control_1: Point, control_2: Point, to: Point) {
+        let from = self.start_if_needed(control_1);
+        let difference_1 = length(from.x - 2.0 * control_1.x + control_2.x,
+                                  from.y - 2.0 * control_1.y + control_2.y);
+        let difference_2 = length(control_1.x - 2.0 * control_2.x + to.x,
+                                  control_1.y - 2.0 * control_2.y + to.y);
+        let max_difference = if difference_1 > difference_2 {
+            difference_1
+        } else {
+            difference_2
+        };
+        let segments = segment_count(max_difference * 3.0 / 4.0);
+        for i in 1..segments + 1 {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.points.push(Point::new(a * from.x + b * control_1.x + c * control_2.x + d * to.x,
+                                        a * from.y + b * control_1.y + c * control_2.y + d * to.y));
+        }
+    }
+
+    /// Connects the current point to the start of the contour. The next segment starts a new
+    /// contour at the same start point.
+    pub fn close(&mut self) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.closed = true;
+        }
+    }
+
+    /// Returns the current point, after starting a contour at `point` if there is none.
+    fn start_if_needed(&mut self, point: Point) -> Point {
+        let last = self.contours.last().cloned();
+        match last {
+            None => self.move_to(point),
+            Some(contour) if contour.closed => {
+                let start = self.points[contour.start];
+                self.move_to(start);
+            }
+            Some(_) => {}
+        }
+        self.points[self.points.len() - 1]
+    }
+
+    /// The points of a contour and whether it is closed.
+    fn contour(&self, index: usize) -> (&[Point], bool) {
+        let contour = self.contours[index];
+        let end = match self.contours.get(index + 1) {
+            Some(next) => next.start,
+            None => self.points.len(),
+        };
+        (&self.points[contour.start..end], contour.closed)
+    }
+}
+
+impl<'a, C: Canvas> Rasterizer<'a, C> {
+    /// Draws the outline of a path with one pixel wide lines.
+    pub fn stroke_path(&mut self, path: &Path, color: Color) {
+        for index in 0..path.contours.len() {
+            let (points, closed) = path.contour(index);
+            self.polyline(points, color);
+            if closed && points.len() > 1 {
+                self.polyline(&[points[points.len() - 1], points[0]], color);
+            }
+        }
+    }
+
+    /// Draws the outline of a path with antialiased lines.
+    pub fn stroke_path_aa(&mut self, path: &Path, color: Color) {
+        for index in 0..path.contours.len() {
+            let (points, closed) = path.contour(index);
+            self.polyline_aa(points, color);
+            if closed && points.len() > 1 {
+                self.line_aa(points[points.len() - 1], points[0], color);
+            }
+        }
+    }
+
+    /// Fills a path. All contours are closed for filling, whether `Path::close` was called or
+    /// not.
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule, color: Color) {
+        let mut edges = Vec::new();
+        for index in 0..path.contours.len() {
+            polygon::push_edges(&mut edges, path.contour(index).0);
+        }
+        polygon::fill_edges(self, &edges, rule, color);
+    }
+}
+
+fn length(x: f32, y: f32) -> f32 {
+    math::sqrt(x * x + y * y)
+}
+
+/// Number of segments for a curve, following Wang's formula for the given multiple of the
+/// largest second difference of the control points.
+fn segment_count(scaled_difference: f32) -> u32 {
+    let count = math::sqrt(scaled_difference / TOLERANCE);
+    if count >= MAX_CURVE_SEGMENTS as f32 {
+        MAX_CURVE_SEGMENTS
+    } else if count > 1.0 {
+        count as u32 + 1
+    } else {
+        1
+    }
+}
@@ -0,0 +1,97 @@
+//! Scanline filling of polygons.
+
+use collections::Vec;
+use core::cmp::{self, Ordering};
+use graphics::Canvas;
+use lcd::Color;
+use super::{ceil, to_i32, FillRule, Point, Rasterizer};
+
+/// A non-horizontal polygon edge from top to bottom.
+pub struct Edge {
+    top: Point,
+    bottom: Point,
+    /// 1 if the edge points down, -1 if it points up.
+    winding: i32,
+}
+
+impl<'a, C: Canvas> Rasterizer<'a, C> {
+    /// Draws the outline of a polygon. The last point is connected to the first.
+    pub fn polygon(&mut self, points: &[Point], color: Color) {
+        self.polyline(points, color);
+        if points.len() > 2 {
+            self.polyline(&[points[points.len() - 1], points[0]], color);
+        }
+    }
+
+    /// Fills a polygon, the last point is connected to the first. A pixel is filled if its
+    /// center is inside.
+    pub fn fill_polygon(&mut self, points: &[Point], rule: FillRule, color: Color) {
+        let mut edges = Vec::new();
+        push_edges(&mut edges, points);
+        fill_edges(self, &edges, rule, color);
+    }
+}
+
+/// Adds the edges of a closed polygon.
+pub fn push_edges(edges: &mut Vec<Edge>, points: &[Point]) {
+    for (i, &from) in points.iter().enumerate() {
+        let to = points[(i + 1) % points.len()];
+        if from.y < to.y {
+            edges.push(Edge {
+                           top: from,
+                           bottom: to,
+                           winding: 1,
+                       });
+        } else if from.y > to.y {
+            edges.push(Edge {
+                           top: to,
+                           bottom: from,
+                           winding: -1,
+                       });
+        }
+    }
+}
+
+pub fn fill_edges<C: Canvas>(rasterizer: &mut Rasterizer<C>,
+                             edges: &[Edge],
+                             rule: FillRule,
+                             color: Color) {
+    if edges.is_empty() {
+        return;
+    }
+    let (_, top, _, bottom) = rasterizer.bounds();
+    let min_y = edges.iter()
+        .map(|edge| edge.top.y)
+        .fold(edges[0].top.y, |min, y| if y < min { y } else { min });
+    let max_y = edges.iter()
+        .map(|edge| edge.bottom.y)
+        .fold(edges[0].bottom.y, |max, y| if y > max { y } else { max });
+    // the rows whose centers are covered
+    let first = cmp::max(to_i32(ceil(min_y - 0.5)), top);
+    let last = cmp::min(to_i32(ceil(max_y - 0.5)), bottom);
+
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    for y in first..last {
+        let center = y as f32 + 0.5;
+        crossings.clear();
+        for edge in edges {
+            if edge.top.y <= center && center < edge.bottom.y {
+                let t = (center - edge.top.y) / (edge.bottom.y - edge.top.y);
+                let x = edge.top.x + t * (edge.bottom.x - edge.top.x);
+                crossings.push((x, edge.winding));
+            }
+        }
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+            if rule.is_inside(winding) {
+                // the pixels whose centers are in [left, right)
+                let left = to_i32(ceil(pair[0].0 - 0.5));
+                let right = to_i32(ceil(pair[1].0 - 0.5)) - 1;
+                rasterizer.span(y, left, right, color);
+            }
+        }
+    }
+}
@@ -2,6 +2,28 @@ use board::rcc::Rcc;
 use board::fmc::Fmc;
 use system_clock;
 use embedded::interfaces::gpio::Gpio;
+use graphics;
+use lcd;
+
+/// Start of the 8 MB SDRAM in the address space of the FMC.
+pub const START: u32 = 0xC000_0000;
+/// Size of the SDRAM in bytes.
+pub const SIZE: u32 = 8 * 1024 * 1024;
+/// End of the SDRAM.
+pub const END: u32 = START + SIZE;
+
+// The regions follow each other in this order, new regions go in front of `FREE_START`.
+
+/// The framebuffers of the two LCD layers, `lcd::FRAMEBUFFERS_LEN` bytes.
+pub const FRAMEBUFFERS_START: u32 = START;
+/// The buffer of `lcd::screenshot_buffer`, `lcd::SCREENSHOT_LEN` bytes.
+pub const SCREENSHOT_BUFFER_START: u32 = FRAMEBUFFERS_START + lcd::FRAMEBUFFERS_LEN;
+/// The buffer of `graphics::offscreen_buffer`, `graphics::OFFSCREEN_BUFFER_LEN` bytes.
+pub const OFFSCREEN_BUFFER_START: u32 = SCREENSHOT_BUFFER_START + lcd::SCREENSHOT_LEN as u32;
+/// Start of the SDRAM that is not reserved by any module, e.g. for `audio::wav::sdram_buffer`.
+pub const FREE_START: u32 = OFFSCREEN_BUFFER_START + graphics::OFFSCREEN_BUFFER_LEN as u32;
+/// Length of the free SDRAM, this fails to compile if the regions don't fit.
+pub const FREE_LEN: u32 = END - FREE_START;
 
 pub fn init(rcc: &mut Rcc, fmc: &mut Fmc, gpio: &mut Gpio) {
     config_pins(gpio);
@@ -67,9 +89,9 @@ pub fn init(rcc: &mut Rcc, fmc: &mut Fmc, gpio: &mut Gpio) {
     // test sdram
     use core::ptr;
 
-    let ptr1 = 0xC000_0000 as *mut u32;
+    let ptr1 = START as *mut u32;
     let ptr2 = 0xC053_6170 as *mut u32;
-    let ptr3 = 0xC07F_FFFC as *mut u32;
+    let ptr3 = (END - 4) as *mut u32;
 
     unsafe {
         ptr::write_volatile(ptr1, 0xcafebabe);
@@ -28,6 +28,28 @@ pub fn wait(ms: usize) {
     }
 }
 
+/// Busy waits for at least `us` microseconds, for delays below the resolution of `wait`.
+///
+/// The core clock cycles are counted with the SysTick counter, so `init` must have been
+/// called before.
+pub fn wait_us(us: usize) {
+    let systick = unsafe { peripheral::syst_mut() };
+    let reload = systick.rvr.read() + 1;
+    let mut remaining = us as u64 * u64::from(get_frequency()) / 1_000_000;
+    let mut last = systick.cvr.read();
+    while remaining > 0 {
+        let current = systick.cvr.read();
+        // the counter counts down and restarts at `reload - 1`
+        let elapsed = if current <= last {
+            last - current
+        } else {
+            last + reload - current
+        };
+        remaining = remaining.saturating_sub(u64::from(elapsed));
+        last = current;
+    }
+}
+
 pub fn init(rcc: &mut Rcc, pwr: &mut Pwr, flash: &mut Flash) {
     // Enable Power Control clock
     rcc.apb1enr.update(|r| r.set_pwren(true));
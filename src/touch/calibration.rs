@@ -0,0 +1,306 @@
+//! Mapping of raw touch coordinates to display coordinates.
+//!
+//! A `Calibration` is an affine transform from the raw FT5336 coordinates to framebuffer
+//! coordinates. It is computed by `calibrate`, which asks the user to touch 3 or 5 targets,
+//! and can be stored in the data sector of the flash. `TouchTransform` additionally applies
+//! the display rotation, so the resulting coordinates match the drawing functions of `Lcd`.
+
+use arrayvec::ArrayVec;
+use core::mem;
+use flash::{self, Flash};
+use i2c::{self, I2C};
+use lcd::{self, Layer, Lcd, Rotation};
+use math;
+use system_clock;
+use super::{touch_points, PointEvent};
+
+/// "TCAL"
+const MAGIC: u32 = 0x5443_414C;
+const VERSION: u32 = 1;
+const STORED_WORDS: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    // x = a * raw_x + b * raw_y + c
+    // y = d * raw_x + e * raw_y + f
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+    f: f32,
+}
+
+impl Calibration {
+    /// Uses the raw coordinates unchanged.
+    pub fn identity() -> Calibration {
+        Calibration {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 0.0,
+            e: 1.0,
+            f: 0.0,
+        }
+    }
+
+    /// Computes the least squares fit for pairs of raw and framebuffer coordinates.
+    ///
+    /// Returns `None` for less than three pairs or if the points are on a line.
+    pub fn from_points(raw: &[(f32, f32)], screen: &[(f32, f32)]) -> Option<Calibration> {
+        if raw.len() < 3 || raw.len() != screen.len() {
+            return None;
+        }
+
+        // normal equations: m * [a, b, c] = rhs_x and m * [d, e, f] = rhs_y
+        let mut m = [[0.0; 3]; 3];
+        let mut rhs_x = [0.0; 3];
+        let mut rhs_y = [0.0; 3];
+        for (&(x, y), &(screen_x, screen_y)) in raw.iter().zip(screen) {
+            let row = [x, y, 1.0];
+            for i in 0..3 {
+                for j in 0..3 {
+                    m[i][j] += row[i] * row[j];
+                }
+                rhs_x[i] += row[i] * screen_x;
+                rhs_y[i] += row[i] * screen_y;
+            }
+        }
+
+        let (a, b, c) = match solve(&m, &rhs_x) {
+            Some(solution) => solution,
+            None => return None,
+        };
+        let (d, e, f) = match solve(&m, &rhs_y) {
+            Some(solution) => solution,
+            None => return None,
+        };
+        Some(Calibration {
+                 a: a,
+                 b: b,
+                 c: c,
+                 d: d,
+                 e: e,
+                 f: f,
+             })
+    }
+
+    /// Maps raw coordinates to (unclamped) framebuffer coordinates.
+    pub fn map(&self, raw_x: f32, raw_y: f32) -> (f32, f32) {
+        (self.a * raw_x + self.b * raw_y + self.c, self.d * raw_x + self.e * raw_y + self.f)
+    }
+
+    fn to_words(&self) -> [u32; STORED_WORDS] {
+        let values = [self.a, self.b, self.c, self.d, self.e, self.f];
+        let mut words = [0; STORED_WORDS];
+        words[0] = MAGIC;
+        words[1] = VERSION;
+        for (word, &value) in words[2..8].iter_mut().zip(values.iter()) {
+            *word = unsafe { mem::transmute(value) };
+        }
+        words[8] = checksum(&words[..8]);
+        words
+    }
+
+    fn from_words(words: &[u32; STORED_WORDS]) -> Option<Calibration> {
+        if words[0] != MAGIC || words[1] != VERSION || words[8] != checksum(&words[..8]) {
+            return None;
+        }
+        let value = |i: usize| -> f32 { unsafe { mem::transmute(words[2 + i]) } };
+        Some(Calibration {
+                 a: value(0),
+                 b: value(1),
+                 c: value(2),
+                 d: value(3),
+                 e: value(4),
+                 f: value(5),
+             })
+    }
+
+    /// Reads the calibration from the flash data sector.
+    pub fn load() -> Option<Calibration> {
+        let mut words = [0; STORED_WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = flash::read_word(flash::DATA_SECTOR_START + i * 4);
+        }
+        Calibration::from_words(&words)
+    }
+
+    /// Stores the calibration in the flash data sector. This erases the whole sector.
+    pub fn save(&self, flash: &mut Flash) -> Result<(), flash::Error> {
+        flash::erase_data_sector(flash)?;
+        flash::program_words(flash, flash::DATA_SECTOR_START, &self.to_words())
+    }
+}
+
+fn checksum(words: &[u32]) -> u32 {
+    words.iter().fold(0xFFFF_FFFF, |sum, &w| sum.rotate_left(5) ^ w)
+}
+
+/// Solves the 3×3 system `m * x = rhs` with Cramer's rule.
+///
+/// Returns `None` if the system is (nearly) singular, i.e. the touched points are (nearly) on
+/// a line.
+fn solve(m: &[[f32; 3]; 3], rhs: &[f32; 3]) -> Option<(f32, f32, f32)> {
+    let det = determinant(m);
+    let scale = m[0][0] * m[1][1] * m[2][2];
+    if scale <= 0.0 || math::abs(det) < 1e-3 * scale {
+        return None;
+    }
+    let replaced = |column: usize| -> f32 {
+        let mut copy = *m;
+        for row in 0..3 {
+            copy[row][column] = rhs[row];
+        }
+        determinant(&copy) / det
+    };
+    Some((replaced(0), replaced(1), replaced(2)))
+}
+
+fn determinant(m: &[[f32; 3]; 3]) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+    m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+    m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Calibration and display rotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchTransform {
+    pub calibration: Calibration,
+    pub rotation: Rotation,
+    /// Physical size of the panel, see `Lcd::panel_size`.
+    pub panel_size: (u16, u16),
+}
+
+impl Default for TouchTransform {
+    fn default() -> TouchTransform {
+        TouchTransform {
+            calibration: Calibration::identity(),
+            rotation: Rotation::Deg0,
+            panel_size: (lcd::WIDTH, lcd::HEIGHT),
+        }
+    }
+}
+
+impl TouchTransform {
+    /// Maps raw coordinates to logical display coordinates. Coordinates outside of the screen
+    /// are clamped to the nearest edge.
+    pub fn map(&self, raw_x: u16, raw_y: u16) -> (u16, u16) {
+        let (x, y) = self.calibration.map(f32::from(raw_x), f32::from(raw_y));
+        let (width, height) = self.panel_size;
+        let x = clamp(x, width);
+        let y = clamp(y, height);
+        self.rotation.to_logical(self.panel_size, x, y)
+    }
+}
+
+fn clamp(value: f32, len: u16) -> u16 {
+    if value < 0.0 {
+        0
+    } else if value > f32::from(len - 1) {
+        len - 1
+    } else {
+        (value + 0.5) as u16
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationPoints {
+    /// Three targets, enough for rotation, scaling and shearing.
+    Three,
+    /// Four corners and the center, which averages out touch inaccuracy.
+    Five,
+}
+
+impl CalibrationPoints {
+    /// Framebuffer coordinates of the targets, at 10% and 90% of the panel size and in the
+    /// center.
+    fn targets(&self, panel: (u16, u16)) -> ArrayVec<[(u16, u16); 5]> {
+        let at = |len: u16, tenths: u32| ((u32::from(len) * tenths + 5) / 10) as u16;
+        let (left, center_x, right) = (at(panel.0, 1), at(panel.0, 5), at(panel.0, 9));
+        let (top, center_y, bottom) = (at(panel.1, 1), at(panel.1, 5), at(panel.1, 9));
+        let mut targets = ArrayVec::new();
+        match *self {
+            CalibrationPoints::Three => {
+                targets.push((left, top));
+                targets.push((right, center_y));
+                targets.push((center_x, bottom));
+            }
+            CalibrationPoints::Five => {
+                targets.push((left, top));
+                targets.push((right, top));
+                targets.push((right, bottom));
+                targets.push((left, bottom));
+                targets.push((center_x, center_y));
+            }
+        }
+        targets
+    }
+}
+
+/// Minimum number of samples that are averaged for a target.
+const MIN_SAMPLES: u32 = 4;
+const TARGET_SIZE: u16 = 10;
+
+/// Shows targets on layer 2 and computes the calibration from the touched positions.
+///
+/// Blocks until all targets were touched. Touches that are too short are repeated, and the
+/// whole sequence is repeated if the touched positions are on a line.
+pub fn calibrate(lcd: &mut Lcd,
+                 i2c_3: &mut I2C,
+                 points: CalibrationPoints)
+                 -> Result<Calibration, i2c::Error> {
+    let targets = points.targets(lcd.panel_size());
+    let mut raw = [(0.0, 0.0); 5];
+    let mut screen = [(0.0, 0.0); 5];
+
+    loop {
+        for (i, &(x, y)) in targets.iter().enumerate() {
+            draw_target(lcd, x, y, 0xffff);
+            raw[i] = wait_for_touch(i2c_3)?;
+            screen[i] = (f32::from(x), f32::from(y));
+            draw_target(lcd, x, y, 0);
+        }
+
+        let len = targets.len();
+        if let Some(calibration) = Calibration::from_points(&raw[..len], &screen[..len]) {
+            return Ok(calibration);
+        }
+    }
+}
+
+/// Waits for a touch and returns the average raw position while the finger was down.
+fn wait_for_touch(i2c_3: &mut I2C) -> Result<(f32, f32), i2c::Error> {
+    loop {
+        let (mut sum_x, mut sum_y, mut samples) = (0.0, 0.0, 0);
+        loop {
+            let points = touch_points(i2c_3)?;
+            match points.iter().find(|p| p.event != PointEvent::LiftUp) {
+                Some(point) => {
+                    sum_x += f32::from(point.x);
+                    sum_y += f32::from(point.y);
+                    samples += 1;
+                }
+                None if samples > 0 => break,
+                None => {}
+            }
+            let start = system_clock::ticks();
+            while system_clock::ticks() - start < 10 {}
+        }
+        if samples >= MIN_SAMPLES {
+            return Ok((sum_x / samples as f32, sum_y / samples as f32));
+        }
+    }
+}
+
+/// Draws a cross at framebuffer coordinates.
+fn draw_target(lcd: &mut Lcd, x: u16, y: u16, color: u16) {
+    let (rotation, panel_size) = (lcd.rotation(), lcd.panel_size());
+    for offset in 0..(2 * TARGET_SIZE + 1) {
+        let (horizontal_x, vertical_y) = (x + offset - TARGET_SIZE, y + offset - TARGET_SIZE);
+        let (lx, ly) = rotation.to_logical(panel_size, horizontal_x, y);
+        lcd.set_pixel(Layer::Layer2, lx, ly, color);
+        let (lx, ly) = rotation.to_logical(panel_size, x, vertical_y);
+        lcd.set_pixel(Layer::Layer2, lx, ly, color);
+    }
+}
@@ -0,0 +1,304 @@
+//! Driver for the FT5336 capacitive touch controller of the LCD.
+//!
+//! The controller signals new touch data on its INT pin, which is connected to PI13. In
+//! `InterruptMode::Trigger` the falling edge raises EXTI line 13, so the touch points only
+//! have to be read when `interrupt_pending` returns true.
+
+use arrayvec::ArrayVec;
+use board::exti::{self, Exti};
+use board::rcc::Rcc;
+use board::syscfg::Syscfg;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embedded::interfaces::gpio::{self, Gpio, InputPin};
+use i2c::{self, I2C};
+use interrupts;
+use super::{PointEvent, TouchPoint};
+
+const FT5336_ADDRESS: i2c::Address = i2c::Address::bits_7(0b0111000);
+
+// register map
+const DEV_MODE: u8 = 0x00;
+const GEST_ID: u8 = 0x01;
+const TD_STATUS: u8 = 0x02;
+// the data of touch point n starts at P1_XH + n * POINT_DATA_LEN
+const P1_XH: u8 = 0x03;
+const POINT_DATA_LEN: u8 = 6;
+const TH_GROUP: u8 = 0x80;
+const CTRL: u8 = 0x86;
+const TIME_ENTER_MONITOR: u8 = 0x87;
+const PERIOD_ACTIVE: u8 = 0x88;
+const PERIOD_MONITOR: u8 = 0x89;
+const LIB_VER_H: u8 = 0xA1;
+const CIPHER: u8 = 0xA3;
+const G_MODE: u8 = 0xA4;
+const FIRMWARE_ID: u8 = 0xA6;
+const FOCALTECH_ID: u8 = 0xA8;
+const RELEASE_CODE_ID: u8 = 0xAF;
+
+/// Value of the `FOCALTECH_ID` register.
+const VENDOR_ID: u8 = 0x51;
+
+const MAX_POINTS: u8 = 5;
+
+#[derive(Debug)]
+pub enum Error {
+    I2c(i2c::Error),
+    /// The vendor ID register contained an unexpected value.
+    UnknownVendorId(u8),
+    /// The INT pin is already used by another driver.
+    InterruptPinInUse,
+}
+
+impl From<i2c::Error> for Error {
+    fn from(err: i2c::Error) -> Error {
+        Error::I2c(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptMode {
+    /// The INT pin is low as long as a finger touches the panel.
+    Polling,
+    /// The INT pin pulses low whenever new touch data is available.
+    Trigger,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ft5336Config {
+    /// Touch detection threshold, lower values are more sensitive.
+    pub threshold: u8,
+    /// Report rate in active mode in Hz.
+    pub active_period: u8,
+    /// Report rate in monitor mode in Hz.
+    pub monitor_period: u8,
+    /// Switch to monitor mode when nothing was touched for `monitor_timeout` seconds.
+    pub auto_monitor: bool,
+    pub monitor_timeout: u8,
+    pub interrupt_mode: InterruptMode,
+}
+
+impl Default for Ft5336Config {
+    fn default() -> Ft5336Config {
+        Ft5336Config {
+            threshold: 0x16,
+            active_period: 12,
+            monitor_period: 40,
+            auto_monitor: true,
+            monitor_timeout: 10,
+            interrupt_mode: InterruptMode::Polling,
+        }
+    }
+}
+
+/// Gesture detected by the controller firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerGesture {
+    None,
+    MoveUp,
+    MoveRight,
+    MoveDown,
+    MoveLeft,
+    ZoomIn,
+    ZoomOut,
+    Unknown(u8),
+}
+
+impl ControllerGesture {
+    fn from_id(id: u8) -> ControllerGesture {
+        match id {
+            0x00 => ControllerGesture::None,
+            0x10 => ControllerGesture::MoveUp,
+            0x14 => ControllerGesture::MoveRight,
+            0x18 => ControllerGesture::MoveDown,
+            0x1C => ControllerGesture::MoveLeft,
+            0x48 => ControllerGesture::ZoomIn,
+            0x49 => ControllerGesture::ZoomOut,
+            id => ControllerGesture::Unknown(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub vendor_id: u8,
+    pub chip_id: u8,
+    pub firmware_id: u8,
+    pub library_version: u16,
+    pub release_code: u8,
+}
+
+pub struct Ft5336 {
+    interrupt_pin: Option<InputPin>,
+}
+
+impl Ft5336 {
+    /// Checks the vendor ID and applies the configuration.
+    pub fn init(i2c_3: &mut I2C, config: &Ft5336Config) -> Result<Ft5336, Error> {
+        let mut vendor_id = 0;
+        i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
+                vendor_id = conn.read(FOCALTECH_ID)?;
+                Ok(())
+            })?;
+        if vendor_id != VENDOR_ID {
+            return Err(Error::UnknownVendorId(vendor_id));
+        }
+
+        let mut ft5336 = Ft5336 { interrupt_pin: None };
+        ft5336.configure(i2c_3, config)?;
+        Ok(ft5336)
+    }
+
+    pub fn configure(&mut self, i2c_3: &mut I2C, config: &Ft5336Config) -> Result<(), Error> {
+        i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
+                conn.write(DEV_MODE, 0)?; // working mode
+                conn.write(TH_GROUP, config.threshold)?;
+                conn.write(PERIOD_ACTIVE, config.active_period)?;
+                conn.write(PERIOD_MONITOR, config.monitor_period)?;
+                conn.write(CTRL, if config.auto_monitor { 1 } else { 0 })?;
+                conn.write(TIME_ENTER_MONITOR, config.monitor_timeout)?;
+                Ok(())
+            })?;
+        self.set_interrupt_mode(i2c_3, config.interrupt_mode)
+    }
+
+    pub fn set_interrupt_mode(&mut self,
+                              i2c_3: &mut I2C,
+                              mode: InterruptMode)
+                              -> Result<(), Error> {
+        let value = match mode {
+            InterruptMode::Polling => 0,
+            InterruptMode::Trigger => 1,
+        };
+        i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| conn.write(G_MODE, value))?;
+        Ok(())
+    }
+
+    pub fn device_info(&mut self, i2c_3: &mut I2C) -> Result<DeviceInfo, Error> {
+        let mut info = DeviceInfo {
+            vendor_id: 0,
+            chip_id: 0,
+            firmware_id: 0,
+            library_version: 0,
+            release_code: 0,
+        };
+        i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
+                let mut library_version = [0; 2];
+                conn.read_bytes(LIB_VER_H, &mut library_version)?;
+                info.library_version = u16::from(library_version[0]) << 8 |
+                                       u16::from(library_version[1]);
+                info.vendor_id = conn.read(FOCALTECH_ID)?;
+                info.chip_id = conn.read(CIPHER)?;
+                info.firmware_id = conn.read(FIRMWARE_ID)?;
+                info.release_code = conn.read(RELEASE_CODE_ID)?;
+                Ok(())
+            })?;
+        Ok(info)
+    }
+
+    /// The gesture that the controller firmware detected in the current touch sequence.
+    pub fn gesture(&mut self, i2c_3: &mut I2C) -> Result<ControllerGesture, Error> {
+        let mut id = 0;
+        i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
+                id = conn.read(GEST_ID)?;
+                Ok(())
+            })?;
+        Ok(ControllerGesture::from_id(id))
+    }
+
+    /// Reads all currently reported touch points.
+    pub fn touch_points(&mut self,
+                        i2c_3: &mut I2C)
+                        -> Result<ArrayVec<[TouchPoint; 5]>, Error> {
+        Ok(read_touch_points(i2c_3)?)
+    }
+
+    /// Configures PI13 as EXTI line 13 that is triggered by the falling edge of the INT pin.
+    ///
+    /// Combine with `InterruptMode::Trigger` and poll `interrupt_pending`.
+    pub fn enable_interrupt(&mut self,
+                            rcc: &mut Rcc,
+                            syscfg: &mut Syscfg,
+                            exti: &'static mut Exti,
+                            gpio: &mut Gpio)
+                            -> Result<(), Error> {
+        let pin = gpio.to_input((gpio::Port::PortI, gpio::Pin::Pin13), gpio::Resistor::PullUp)
+            .map_err(|_| Error::InterruptPinInUse)?;
+        self.interrupt_pin = Some(pin);
+
+        // enable syscfg clock
+        rcc.apb2enr.update(|r| r.set_syscfgen(true));
+        // connect exti line 13 to port i
+        syscfg.exticr4.update(|r| r.set_exti13(0b1000));
+
+        exti.ftsr.update(|r| r.set_tr13(true)); // falling_trigger
+        exti.rtsr.update(|r| r.set_tr13(false)); // rising_trigger
+        exti.pr.write(clear_line_13());
+        exti.imr.update(|r| r.set_mr13(true)); // interrupt_mask
+
+        unsafe { EXTI = Some(exti) };
+        INTERRUPT_PENDING.store(false, Ordering::Relaxed);
+        interrupts::enable(interrupts::EXTI15_10);
+        Ok(())
+    }
+
+    /// Whether the INT pin signaled new data since the last call.
+    pub fn interrupt_pending(&mut self) -> bool {
+        INTERRUPT_PENDING.swap(false, Ordering::AcqRel)
+    }
+
+    /// The current level of the INT pin. In `InterruptMode::Polling` it is low while the panel
+    /// is touched.
+    pub fn interrupt_pin_low(&self) -> Option<bool> {
+        self.interrupt_pin.as_ref().map(|pin| !pin.get())
+    }
+}
+
+/// Reads the status register and the data of the reported points.
+pub fn read_touch_points(i2c_3: &mut I2C) -> Result<ArrayVec<[TouchPoint; 5]>, i2c::Error> {
+    let mut points = ArrayVec::new();
+    i2c_3.connect::<u8, _>(FT5336_ADDRESS, |mut conn| {
+            let status = conn.read(TD_STATUS)?;
+            let mut number_of_touches = status & 0x0F;
+            if number_of_touches > MAX_POINTS {
+                number_of_touches = 0;
+            }
+
+            for i in 0..number_of_touches {
+                let mut data = [0; POINT_DATA_LEN as usize];
+                conn.read_bytes(P1_XH + i * POINT_DATA_LEN, &mut data)?;
+                // the x and y registers are swapped relative to the lcd orientation
+                let y = (u16::from(data[0] & 0x0F) << 8) | u16::from(data[1]);
+                let x = (u16::from(data[2] & 0x0F) << 8) | u16::from(data[3]);
+                points.push(TouchPoint {
+                                id: data[2] >> 4,
+                                x: x,
+                                y: y,
+                                event: PointEvent::from_bits(data[0] >> 6),
+                                weight: data[4],
+                                area: data[5] >> 4,
+                            });
+            }
+            Ok(())
+        })?;
+
+    Ok(points)
+}
+
+static mut EXTI: Option<&'static mut Exti> = None;
+static INTERRUPT_PENDING: AtomicBool = AtomicBool::new(false);
+
+fn clear_line_13() -> exti::Pr {
+    let mut clear = exti::Pr::default();
+    clear.set_pr13(true); // pending, cleared by writing 1
+    clear
+}
+
+/// Interrupt handler for EXTI lines 10 to 15.
+pub unsafe extern "C" fn exti15_10() {
+    if let Some(exti) = EXTI.as_mut() {
+        if exti.pr.read().pr13() {
+            exti.pr.write(clear_line_13());
+            INTERRUPT_PENDING.store(true, Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,610 @@
+//! Turns the touch points of the FT5336 into down/move/up events and gestures.
+//!
+//! `TouchTracker::update` only depends on the reported points and a timestamp, so recorded
+//! point sequences can be replayed without the hardware. `TouchTracker::poll` reads the
+//! points from the controller, maps them to display coordinates and uses the system clock.
+
+use arrayvec::ArrayVec;
+use collections::VecDeque;
+use i2c::{self, I2C};
+use math;
+use system_clock;
+use super::{touch_points, PointEvent, TouchPoint, TouchTransform};
+
+/// Maximum number of queued events. The oldest events are dropped first.
+const MAX_EVENTS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GestureConfig {
+    /// Maximum movement in pixels that still counts as a tap or long press.
+    pub tap_slop: u16,
+    /// Maximum duration of a tap in milliseconds.
+    pub tap_timeout: usize,
+    /// Maximum time between two taps of a double tap in milliseconds.
+    pub double_tap_timeout: usize,
+    /// Time a finger has to rest until a long press is reported in milliseconds.
+    pub long_press_timeout: usize,
+    /// Minimum distance of a swipe in pixels.
+    pub swipe_min_distance: u16,
+    /// Maximum duration of a swipe in milliseconds.
+    pub swipe_timeout: usize,
+    /// Minimum change of the pinch scale until a new pinch event is reported.
+    pub pinch_threshold: f32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> GestureConfig {
+        GestureConfig {
+            tap_slop: 10,
+            tap_timeout: 300,
+            double_tap_timeout: 300,
+            long_press_timeout: 600,
+            swipe_min_distance: 60,
+            swipe_timeout: 500,
+            pinch_threshold: 0.02,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap { x: u16, y: u16 },
+    /// Reported after the `Tap` of the second tap.
+    DoubleTap { x: u16, y: u16 },
+    LongPress { x: u16, y: u16 },
+    Swipe { direction: SwipeDirection },
+    /// Two finger pinch (`scale < 1`) or zoom (`scale > 1`). The scale is the current distance
+    /// between the fingers relative to their distance when exactly two fingers started touching,
+    /// either because a second finger touched down or because a third finger was lifted.
+    Pinch {
+        scale: f32,
+        center_x: u16,
+        center_y: u16,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Down { id: u8, x: u16, y: u16 },
+    Move { id: u8, x: u16, y: u16 },
+    Up { id: u8, x: u16, y: u16 },
+    Gesture(Gesture),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Finger {
+    id: u8,
+    start: (u16, u16),
+    position: (u16, u16),
+    down_at: usize,
+    moved: bool,
+    long_press_reported: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pinch {
+    start_distance: f32,
+    last_scale: f32,
+}
+
+pub struct TouchTracker {
+    config: GestureConfig,
+    transform: TouchTransform,
+    fingers: ArrayVec<[Finger; 5]>,
+    // maximum number of fingers since the first finger touched down
+    max_fingers: usize,
+    pinch: Option<Pinch>,
+    last_tap: Option<(u16, u16, usize)>,
+    events: VecDeque<Event>,
+}
+
+impl TouchTracker {
+    pub fn new(config: GestureConfig) -> TouchTracker {
+        TouchTracker {
+            config: config,
+            transform: TouchTransform::default(),
+            fingers: ArrayVec::new(),
+            max_fingers: 0,
+            pinch: None,
+            last_tap: None,
+            events: VecDeque::with_capacity(MAX_EVENTS),
+        }
+    }
+
+    /// Sets the mapping from raw controller coordinates to display coordinates that `poll`
+    /// applies. The default uses the raw coordinates of the unrotated display.
+    pub fn set_transform(&mut self, transform: TouchTransform) {
+        self.transform = transform;
+    }
+
+    /// Reads the current touch points from the FT5336 and updates the state.
+    ///
+    /// In `InterruptMode::Trigger` this should only be called when `Ft5336::interrupt_pending`
+    /// returns true, because a read without points lifts all fingers.
+    pub fn poll(&mut self, i2c_3: &mut I2C) -> Result<(), i2c::Error> {
+        let mut points = touch_points(i2c_3)?;
+        for point in points.iter_mut() {
+            let (x, y) = self.transform.map(point.x, point.y);
+            point.x = x;
+            point.y = y;
+        }
+        self.update(&points, system_clock::ticks());
+        Ok(())
+    }
+
+    /// Returns the oldest unhandled event.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Updates the state with the points of one read. `now` is a timestamp in milliseconds.
+    pub fn update(&mut self, points: &[TouchPoint], now: usize) {
+        // fingers that are no longer reported were lifted, even if the lift event was missed
+        let mut i = 0;
+        while i < self.fingers.len() {
+            let id = self.fingers[i].id;
+            let reported = points
+                .iter()
+                .any(|p| p.id == id && (p.event == PointEvent::PressDown ||
+                                        p.event == PointEvent::Contact));
+            if reported {
+                i += 1;
+            } else {
+                let finger = self.fingers.remove(i).expect("index is in bounds");
+                self.lift(finger, now);
+            }
+        }
+
+        for point in points {
+            match point.event {
+                PointEvent::PressDown | PointEvent::Contact => self.touch(point, now),
+                PointEvent::LiftUp | PointEvent::None => {}
+            }
+        }
+
+        self.check_long_press(now);
+        self.check_pinch();
+    }
+
+    fn touch(&mut self, point: &TouchPoint, now: usize) {
+        let position = (point.x, point.y);
+        let slop = self.config.tap_slop;
+
+        if let Some(index) = self.fingers.iter().position(|f| f.id == point.id) {
+            {
+                let finger = &mut self.fingers[index];
+                if finger.position == position {
+                    return;
+                }
+                finger.position = position;
+                if distance(finger.start, position) > f32::from(slop) {
+                    finger.moved = true;
+                }
+            }
+            self.push_event(Event::Move {
+                                id: point.id,
+                                x: point.x,
+                                y: point.y,
+                            });
+            return;
+        }
+
+        let finger = Finger {
+            id: point.id,
+            start: position,
+            position: position,
+            down_at: now,
+            moved: false,
+            long_press_reported: false,
+        };
+        if self.fingers.push(finger).is_some() {
+            // the controller reports at most five points
+            return;
+        }
+        if self.fingers.len() > self.max_fingers {
+            self.max_fingers = self.fingers.len();
+        }
+        // `check_pinch` starts a new pinch once all points of this read are updated
+        self.pinch = None;
+        self.push_event(Event::Down {
+                            id: point.id,
+                            x: point.x,
+                            y: point.y,
+                        });
+    }
+
+    fn lift(&mut self, finger: Finger, now: usize) {
+        let (x, y) = finger.position;
+        self.push_event(Event::Up {
+                            id: finger.id,
+                            x: x,
+                            y: y,
+                        });
+        // if two fingers remain, `check_pinch` starts a new pinch from their positions
+        self.pinch = None;
+
+        if self.max_fingers == 1 {
+            self.single_finger_gesture(&finger, now);
+        }
+        if self.fingers.is_empty() {
+            self.max_fingers = 0;
+        }
+    }
+
+    fn single_finger_gesture(&mut self, finger: &Finger, now: usize) {
+        let duration = now - finger.down_at;
+        let (x, y) = finger.position;
+
+        if !finger.moved && !finger.long_press_reported && duration <= self.config.tap_timeout {
+            self.push_event(Event::Gesture(Gesture::Tap { x: x, y: y }));
+            let double_tap = match self.last_tap {
+                Some((last_x, last_y, last_time)) => {
+                    now - last_time <= self.config.double_tap_timeout &&
+                    distance((last_x, last_y), (x, y)) <= f32::from(self.config.tap_slop)
+                }
+                None => false,
+            };
+            if double_tap {
+                self.push_event(Event::Gesture(Gesture::DoubleTap { x: x, y: y }));
+                self.last_tap = None;
+            } else {
+                self.last_tap = Some((x, y, now));
+            }
+        } else if finger.moved && duration <= self.config.swipe_timeout {
+            let dx = i32::from(x) - i32::from(finger.start.0);
+            let dy = i32::from(y) - i32::from(finger.start.1);
+            let min_distance = f32::from(self.config.swipe_min_distance);
+            if distance(finger.start, finger.position) >= min_distance {
+                let direction = if dx.abs() >= dy.abs() {
+                    if dx > 0 {
+                        SwipeDirection::Right
+                    } else {
+                        SwipeDirection::Left
+                    }
+                } else if dy > 0 {
+                    SwipeDirection::Down
+                } else {
+                    SwipeDirection::Up
+                };
+                self.push_event(Event::Gesture(Gesture::Swipe { direction: direction }));
+            }
+        }
+    }
+
+    fn check_long_press(&mut self, now: usize) {
+        if self.max_fingers != 1 || self.fingers.len() != 1 {
+            return;
+        }
+        let timeout = self.config.long_press_timeout;
+        let report = {
+            let finger = &mut self.fingers[0];
+            if !finger.moved && !finger.long_press_reported && now - finger.down_at >= timeout {
+                finger.long_press_reported = true;
+                Some(finger.position)
+            } else {
+                None
+            }
+        };
+        if let Some((x, y)) = report {
+            self.push_event(Event::Gesture(Gesture::LongPress { x: x, y: y }));
+        }
+    }
+
+    fn check_pinch(&mut self) {
+        if self.fingers.len() != 2 {
+            return;
+        }
+        let (a, b) = (self.fingers[0].position, self.fingers[1].position);
+        if self.pinch.is_none() {
+            // the number of fingers changed, so the scale is relative to the new distance
+            self.pinch = Some(Pinch {
+                                  start_distance: distance(a, b),
+                                  last_scale: 1.0,
+                              });
+            return;
+        }
+        let threshold = self.config.pinch_threshold;
+        let scale = match self.pinch {
+            Some(ref mut pinch) if pinch.start_distance > 0.0 => {
+                let scale = distance(a, b) / pinch.start_distance;
+                if math::abs(scale - pinch.last_scale) < threshold {
+                    return;
+                }
+                pinch.last_scale = scale;
+                scale
+            }
+            _ => return,
+        };
+        self.push_event(Event::Gesture(Gesture::Pinch {
+                                           scale: scale,
+                                           center_x: (a.0 + b.0) / 2,
+                                           center_y: (a.1 + b.1) / 2,
+                                       }));
+    }
+
+    fn push_event(&mut self, event: Event) {
+        self.events.push_back(event);
+        self.limit_events();
+    }
+
+    fn limit_events(&mut self) {
+        while self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+}
+
+fn distance(a: (u16, u16), b: (u16, u16)) -> f32 {
+    let dx = f32::from(a.0) - f32::from(b.0);
+    let dy = f32::from(a.1) - f32::from(b.1);
+    math::sqrt(dx * dx + dy * dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::Vec;
+
+    fn point(id: u8, x: u16, y: u16, event: PointEvent) -> TouchPoint {
+        TouchPoint {
+            id: id,
+            x: x,
+            y: y,
+            event: event,
+            weight: 0,
+            area: 0,
+        }
+    }
+
+    fn down(id: u8, x: u16, y: u16) -> TouchPoint {
+        point(id, x, y, PointEvent::PressDown)
+    }
+
+    fn contact(id: u8, x: u16, y: u16) -> TouchPoint {
+        point(id, x, y, PointEvent::Contact)
+    }
+
+    fn lift(id: u8, x: u16, y: u16) -> TouchPoint {
+        point(id, x, y, PointEvent::LiftUp)
+    }
+
+    fn events(tracker: &mut TouchTracker) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(event) = tracker.next_event() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn gestures(tracker: &mut TouchTracker) -> Vec<Gesture> {
+        events(tracker)
+            .into_iter()
+            .filter_map(|e| match e {
+                            Event::Gesture(gesture) => Some(gesture),
+                            _ => None,
+                        })
+            .collect()
+    }
+
+    fn tap(tracker: &mut TouchTracker, x: u16, y: u16, now: usize) {
+        tracker.update(&[down(0, x, y)], now);
+        tracker.update(&[lift(0, x, y)], now + 50);
+    }
+
+    fn pinch_scales(gestures: &[Gesture]) -> Vec<f32> {
+        gestures
+            .iter()
+            .filter_map(|g| match *g {
+                            Gesture::Pinch { scale, .. } => Some(scale),
+                            _ => None,
+                        })
+            .collect()
+    }
+
+    #[test]
+    fn tap_reports_down_up_and_tap() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 50)], 0);
+        tracker.update(&[contact(0, 103, 52)], 20);
+        tracker.update(&[lift(0, 103, 52)], 80);
+        assert_eq!(events(&mut tracker),
+                   vec![Event::Down { id: 0, x: 100, y: 50 },
+                        Event::Move { id: 0, x: 103, y: 52 },
+                        Event::Up { id: 0, x: 103, y: 52 },
+                        Event::Gesture(Gesture::Tap { x: 103, y: 52 })]);
+    }
+
+    #[test]
+    fn unchanged_positions_report_no_move() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 10, 10)], 0);
+        tracker.update(&[contact(0, 10, 10)], 10);
+        tracker.update(&[contact(0, 10, 10)], 20);
+        assert_eq!(events(&mut tracker), vec![Event::Down { id: 0, x: 10, y: 10 }]);
+    }
+
+    #[test]
+    fn slow_or_moved_touches_are_no_taps() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 10, 10)], 0);
+        tracker.update(&[lift(0, 10, 10)], 400);
+        assert_eq!(gestures(&mut tracker), vec![]);
+
+        // moved beyond the slop, but not far enough for a swipe
+        tracker.update(&[down(0, 10, 10)], 1000);
+        tracker.update(&[contact(0, 30, 10)], 1020);
+        tracker.update(&[lift(0, 30, 10)], 1040);
+        assert_eq!(gestures(&mut tracker), vec![]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tap(&mut tracker, 200, 100, 0);
+        tap(&mut tracker, 204, 103, 200);
+        assert_eq!(gestures(&mut tracker),
+                   vec![Gesture::Tap { x: 200, y: 100 },
+                        Gesture::Tap { x: 204, y: 103 },
+                        Gesture::DoubleTap { x: 204, y: 103 }]);
+
+        // a third tap starts a new double tap instead of completing another one
+        tap(&mut tracker, 204, 103, 400);
+        assert_eq!(gestures(&mut tracker), vec![Gesture::Tap { x: 204, y: 103 }]);
+    }
+
+    #[test]
+    fn no_double_tap_when_too_late_or_too_far() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tap(&mut tracker, 200, 100, 0);
+        tap(&mut tracker, 200, 100, 1000);
+        tap(&mut tracker, 300, 100, 1200);
+        assert_eq!(gestures(&mut tracker),
+                   vec![Gesture::Tap { x: 200, y: 100 },
+                        Gesture::Tap { x: 200, y: 100 },
+                        Gesture::Tap { x: 300, y: 100 }]);
+    }
+
+    #[test]
+    fn long_press_is_reported_once_and_suppresses_the_tap() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 50, 60)], 0);
+        tracker.update(&[contact(0, 50, 60)], 599);
+        assert_eq!(gestures(&mut tracker), vec![]);
+        tracker.update(&[contact(0, 50, 60)], 600);
+        tracker.update(&[contact(0, 51, 60)], 900);
+        assert_eq!(gestures(&mut tracker), vec![Gesture::LongPress { x: 50, y: 60 }]);
+        tracker.update(&[lift(0, 51, 60)], 950);
+        assert_eq!(gestures(&mut tracker), vec![]);
+    }
+
+    #[test]
+    fn swipes() {
+        let cases = [((100, 100), (200, 110), SwipeDirection::Right),
+                     ((200, 100), (100, 90), SwipeDirection::Left),
+                     ((100, 100), (110, 200), SwipeDirection::Down),
+                     ((100, 200), (90, 100), SwipeDirection::Up)];
+        for &((x0, y0), (x1, y1), direction) in cases.iter() {
+            let mut tracker = TouchTracker::new(GestureConfig::default());
+            tracker.update(&[down(0, x0, y0)], 0);
+            tracker.update(&[contact(0, (x0 + x1) / 2, (y0 + y1) / 2)], 50);
+            tracker.update(&[contact(0, x1, y1)], 100);
+            tracker.update(&[lift(0, x1, y1)], 150);
+            assert_eq!(gestures(&mut tracker), vec![Gesture::Swipe { direction: direction }]);
+        }
+
+        // too slow
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 100)], 0);
+        tracker.update(&[contact(0, 200, 100)], 100);
+        tracker.update(&[lift(0, 200, 100)], 600);
+        assert_eq!(gestures(&mut tracker), vec![]);
+    }
+
+    #[test]
+    fn pinch_and_zoom() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 100)], 0);
+        tracker.update(&[contact(0, 100, 100), down(1, 200, 100)], 10);
+        // below the threshold
+        tracker.update(&[contact(0, 100, 100), contact(1, 201, 100)], 20);
+        tracker.update(&[contact(0, 100, 100), contact(1, 300, 100)], 30);
+        tracker.update(&[contact(0, 125, 100), contact(1, 175, 100)], 40);
+        assert_eq!(gestures(&mut tracker),
+                   vec![Gesture::Pinch {
+                            scale: 2.0,
+                            center_x: 200,
+                            center_y: 100,
+                        },
+                        Gesture::Pinch {
+                            scale: 0.5,
+                            center_x: 150,
+                            center_y: 100,
+                        }]);
+
+        // no tap, swipe or long press after a multi finger touch
+        tracker.update(&[contact(1, 175, 100)], 1000);
+        tracker.update(&[], 1010);
+        assert_eq!(events(&mut tracker),
+                   vec![Event::Up { id: 0, x: 125, y: 100 }, Event::Up { id: 1, x: 175, y: 100 }]);
+    }
+
+    #[test]
+    fn lifting_one_of_three_fingers_starts_a_new_pinch() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 100), down(1, 200, 100)], 0);
+        tracker.update(&[contact(0, 100, 100), contact(1, 200, 100), down(2, 300, 300)], 10);
+        // no pinch with three fingers
+        tracker.update(&[contact(0, 100, 100), contact(1, 150, 100), contact(2, 300, 300)], 20);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![]);
+
+        // the new pinch is relative to the distance of the remaining fingers when the third
+        // finger lifted
+        tracker.update(&[contact(0, 100, 100), contact(1, 150, 100), lift(2, 300, 300)], 30);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![]);
+        tracker.update(&[contact(0, 100, 100), contact(1, 200, 100)], 40);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![2.0]);
+    }
+
+    #[test]
+    fn lifting_one_finger_of_a_pinch_and_touching_again_starts_a_new_pinch() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 100), down(1, 200, 100)], 0);
+        tracker.update(&[contact(0, 100, 100), contact(1, 300, 100)], 10);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![2.0]);
+
+        tracker.update(&[contact(0, 100, 100), lift(1, 300, 100)], 20);
+        tracker.update(&[contact(0, 100, 100), down(2, 140, 100)], 30);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![]);
+        tracker.update(&[contact(0, 100, 100), contact(2, 120, 100)], 40);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![0.5]);
+    }
+
+    #[test]
+    fn replacing_a_finger_in_one_read_starts_a_new_pinch() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 100, 100), down(1, 200, 100)], 0);
+        tracker.update(&[contact(0, 100, 100), down(2, 400, 100)], 10);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![]);
+        tracker.update(&[contact(0, 100, 100), contact(2, 700, 100)], 20);
+        assert_eq!(pinch_scales(&gestures(&mut tracker)), vec![2.0]);
+    }
+
+    #[test]
+    fn fingers_that_are_no_longer_reported_are_lifted() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 10, 20), down(1, 30, 40)], 0);
+        tracker.update(&[contact(1, 30, 40)], 10);
+        tracker.update(&[], 20);
+        assert_eq!(events(&mut tracker),
+                   vec![Event::Down { id: 0, x: 10, y: 20 },
+                        Event::Down { id: 1, x: 30, y: 40 },
+                        Event::Up { id: 0, x: 10, y: 20 },
+                        Event::Up { id: 1, x: 30, y: 40 }]);
+
+        // the missed lift still completes a tap
+        tracker.update(&[down(3, 10, 20)], 1000);
+        tracker.update(&[], 1050);
+        assert_eq!(gestures(&mut tracker), vec![Gesture::Tap { x: 10, y: 20 }]);
+    }
+
+    #[test]
+    fn event_queue_keeps_the_newest_events() {
+        let mut tracker = TouchTracker::new(GestureConfig::default());
+        tracker.update(&[down(0, 0, 0)], 0);
+        for i in 1..100 {
+            tracker.update(&[contact(0, i, 0)], usize::from(i));
+        }
+        let events = events(&mut tracker);
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0], Event::Move { id: 0, x: 68, y: 0 });
+        assert_eq!(events[MAX_EVENTS - 1], Event::Move { id: 0, x: 99, y: 0 });
+    }
+}
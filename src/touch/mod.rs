@@ -0,0 +1,65 @@
+use i2c::{self, I2C};
+use arrayvec::ArrayVec;
+
+pub use self::ft5336::{Ft5336, Ft5336Config, InterruptMode, ControllerGesture, DeviceInfo};
+pub use self::gesture::{TouchTracker, GestureConfig, Event, Gesture, SwipeDirection};
+pub use self::calibration::{Calibration, TouchTransform, CalibrationPoints, calibrate};
+
+pub mod ft5336;
+mod calibration;
+mod gesture;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Touch {
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Event flag that the FT5336 reports for each touch point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointEvent {
+    PressDown,
+    LiftUp,
+    Contact,
+    None,
+}
+
+impl PointEvent {
+    fn from_bits(bits: u8) -> PointEvent {
+        match bits & 0b11 {
+            0b00 => PointEvent::PressDown,
+            0b01 => PointEvent::LiftUp,
+            0b10 => PointEvent::Contact,
+            _ => PointEvent::None,
+        }
+    }
+}
+
+/// A touch point with the ID that identifies the finger over multiple reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchPoint {
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+    pub event: PointEvent,
+    /// Touch pressure.
+    pub weight: u8,
+    /// Size of the touched area.
+    pub area: u8,
+}
+
+pub fn touches(i2c_3: &mut I2C) -> Result<ArrayVec<[Touch; 5]>, i2c::Error> {
+    Ok(touch_points(i2c_3)?
+           .iter()
+           .map(|point| {
+                    Touch {
+                        x: point.x,
+                        y: point.y,
+                    }
+                })
+           .collect())
+}
+
+pub fn touch_points(i2c_3: &mut I2C) -> Result<ArrayVec<[TouchPoint; 5]>, i2c::Error> {
+    ft5336::read_touch_points(i2c_3)
+}